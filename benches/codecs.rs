@@ -0,0 +1,61 @@
+//! Encoding and decoding CBOR and MessagePack, without the copy to and
+//! from a Buffer. testing/bench/codecs.js compares them with marshalling
+//! objects, which needs Node.
+//!   cargo bench --features cbor,msgpack
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use neon_utils::marshalling::codecs::{cbor, msgpack};
+use serde::{Deserialize, Serialize};
+
+// As in testing/src/lib.rs
+#[derive(Serialize, Deserialize)]
+struct Trade {
+    id: u64,
+    price: f64,
+    side: String,
+    tags: Vec<String>,
+}
+
+fn trades(n: u64) -> Vec<Trade> {
+    (0..n)
+        .map(|id| Trade {
+            id,
+            price: id as f64 / 4.0,
+            side: if id % 2 == 0 { "buy" } else { "sell" }.to_string(),
+            tags: vec!["spot".to_string(), format!("batch-{}", id / 100)],
+        })
+        .collect()
+}
+
+// SafeErr isn't Debug, so can't be unwrapped
+fn ok<T>(result: Result<T, neon_utils::errors::SafeErr>) -> T {
+    result.unwrap_or_else(|e| panic!("{}", e))
+}
+
+fn codecs(c: &mut Criterion) {
+    let mut group = c.benchmark_group("codecs");
+    for n in [1_000, 100_000] {
+        let trades = trades(n);
+        group.throughput(Throughput::Elements(n));
+        group.bench_with_input(BenchmarkId::new("cbor::to_vec", n), &trades, |b, t| {
+            b.iter(|| ok(cbor::to_vec(t)))
+        });
+        group.bench_with_input(BenchmarkId::new("msgpack::to_vec", n), &trades, |b, t| {
+            b.iter(|| ok(msgpack::to_vec(t)))
+        });
+        let encoded = ok(cbor::to_vec(&trades));
+        group.bench_with_input(BenchmarkId::new("cbor::from_slice", n), &encoded, |b, e| {
+            b.iter(|| ok(cbor::from_slice::<Vec<Trade>>(e)))
+        });
+        let encoded = ok(msgpack::to_vec(&trades));
+        group.bench_with_input(
+            BenchmarkId::new("msgpack::from_slice", n),
+            &encoded,
+            |b, e| b.iter(|| ok(msgpack::from_slice::<Vec<Trade>>(e))),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, codecs);
+criterion_main!(benches);
@@ -0,0 +1,48 @@
+//! The parts of the marshalling hot paths which don't need Node: hex of
+//! a few sizes with each Backend, and U256 to and from strings.
+//! testing/bench/marshalling.js times the rest.
+//!   cargo bench -p neon-utils-core
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use neon_utils_core::codecs::{decode, encode_hex, hex::Backend, Encode as _};
+use primitive_types::U256;
+
+const BACKENDS: [Backend; 3] = [Backend::FasterHex, Backend::Scalar, Backend::Table];
+
+fn hex(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hex");
+    for size in [20, 32, 1024, 65536] {
+        let bytes = vec![0xa5; size];
+        group.throughput(Throughput::Bytes(size as u64));
+        for backend in BACKENDS {
+            let name = format!("encode {:?}", backend);
+            group.bench_with_input(BenchmarkId::new(name, size), &bytes, |b, bytes| {
+                b.iter(|| backend.encode(bytes))
+            });
+        }
+        let encoded = format!("0x{}", "a5".repeat(size));
+        group.bench_with_input(BenchmarkId::new("decode", size), &encoded, |b, s| {
+            b.iter(|| decode::<str, Vec<u8>>(s.as_str()).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn u256(c: &mut Criterion) {
+    let mut group = c.benchmark_group("U256");
+    let decimal = "123456789012345678901234567890";
+    let hex = format!("0x{}", "f".repeat(64));
+    group.bench_function("decode decimal", |b| {
+        b.iter(|| decode::<str, U256>(decimal).unwrap())
+    });
+    group.bench_function("decode hex", |b| {
+        b.iter(|| decode::<str, U256>(hex.as_str()).unwrap())
+    });
+    let n = U256::MAX;
+    group.bench_function("encode decimal", |b| b.iter(|| n.encode()));
+    group.bench_function("encode hex", |b| b.iter(|| encode_hex(&n)));
+    group.finish();
+}
+
+criterion_group!(benches, hex, u256);
+criterion_main!(benches);
@@ -0,0 +1,446 @@
+use crate::{profile_method, Address, Bytes32};
+use alloc::{format, string::String, vec::Vec};
+use core::convert::TryInto;
+use primitive_types::{H160, H256, U128, U256};
+
+pub mod hex;
+#[cfg(feature = "std")]
+pub mod interned;
+
+pub trait Decode<T: ?Sized> {
+    #[allow(clippy::result_unit_err)]
+    fn decode(s: &T) -> Result<Self, ()>
+    where
+        Self: Sized;
+}
+
+/// Like Decode, from raw bytes, eg: the contents of a Buffer. A separate
+/// trait, so that decode(s) on a &str is never ambiguous.
+pub trait DecodeBytes {
+    #[allow(clippy::result_unit_err)]
+    fn decode_bytes(bytes: &[u8]) -> Result<Self, ()>
+    where
+        Self: Sized;
+}
+
+pub trait Encode {
+    fn encode(&self) -> String;
+}
+
+impl<const N: usize> Decode<str> for [u8; N] {
+    fn decode(s: &str) -> Result<Self, ()>
+    where
+        Self: Sized,
+    {
+        profile_method!(decode);
+
+        let mut result = [0; N];
+        let mut bytes = s.as_bytes();
+        if bytes.starts_with(b"0x") {
+            bytes = &bytes[2..];
+        }
+        faster_hex::hex_decode(bytes, &mut result[..]).map_err(|_| ())?;
+        Ok(result)
+    }
+}
+
+/// From the raw bytes, which must be exactly N of them
+impl<const N: usize> DecodeBytes for [u8; N] {
+    fn decode_bytes(bytes: &[u8]) -> Result<Self, ()> {
+        bytes.try_into().map_err(|_| ())
+    }
+}
+
+/// Writes the 0x prefixed hex to the start of a buffer, to encode without
+/// allocating. Address needs 42 bytes and Bytes32 needs 66.
+pub trait EncodeTo {
+    #[allow(clippy::result_unit_err)]
+    fn encode_to<'o>(&self, out: &'o mut [u8]) -> Result<&'o mut str, ()>;
+}
+
+impl<const N: usize> EncodeTo for [u8; N] {
+    fn encode_to<'o>(&self, out: &'o mut [u8]) -> Result<&'o mut str, ()> {
+        hex::encode_prefixed_to(&self[..], out)
+    }
+}
+
+impl Encode for Address {
+    fn encode(&self) -> String {
+        profile_method!(encode);
+
+        let mut buffer = [0; 42];
+        String::from(&*self.encode_to(&mut buffer).unwrap())
+    }
+}
+
+impl Encode for Bytes32 {
+    fn encode(&self) -> String {
+        profile_method!(encode);
+
+        let mut buffer = [0; 66];
+        String::from(&*self.encode_to(&mut buffer).unwrap())
+    }
+}
+
+impl Encode for U256 {
+    fn encode(&self) -> String {
+        profile_method!(encode);
+
+        format!("{}", self)
+    }
+}
+
+/// Accepts decimal, or a 0x prefixed hex quantity as in Ethereum JSON-RPC
+impl Decode<str> for U256 {
+    fn decode(s: &str) -> Result<Self, ()> {
+        profile_method!(decode);
+
+        match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            // from_str_radix would accept an empty string or a sign
+            Some(hex) if !hex.is_empty() && hex.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                U256::from_str_radix(hex, 16).map_err(|_| ())
+            }
+            Some(_) => Err(()),
+            None => U256::from_dec_str(s).map_err(|_| ()),
+        }
+    }
+}
+
+/// From big-endian bytes, as in ABI encoding. Fewer than 32 are zero
+/// extended, so a Buffer of a small number need not be padded.
+impl DecodeBytes for U256 {
+    fn decode_bytes(bytes: &[u8]) -> Result<Self, ()> {
+        if bytes.len() > 32 {
+            return Err(());
+        }
+        Ok(U256::from_big_endian(bytes))
+    }
+}
+
+/// Encodes as a 0x prefixed hex quantity with no leading zeros, eg: "0x1f".
+/// Encode gives decimal instead, which is the default for U256.
+pub fn encode_hex(n: &U256) -> String {
+    format!("{:#x}", n)
+}
+
+impl Encode for H160 {
+    fn encode(&self) -> String {
+        self.0.encode()
+    }
+}
+
+impl Decode<str> for H160 {
+    fn decode(s: &str) -> Result<Self, ()> {
+        Decode::decode(s).map(H160)
+    }
+}
+
+impl DecodeBytes for H160 {
+    fn decode_bytes(bytes: &[u8]) -> Result<Self, ()> {
+        DecodeBytes::decode_bytes(bytes).map(H160)
+    }
+}
+
+impl Encode for H256 {
+    fn encode(&self) -> String {
+        self.0.encode()
+    }
+}
+
+impl Decode<str> for H256 {
+    fn decode(s: &str) -> Result<Self, ()> {
+        Decode::decode(s).map(H256)
+    }
+}
+
+impl DecodeBytes for H256 {
+    fn decode_bytes(bytes: &[u8]) -> Result<Self, ()> {
+        DecodeBytes::decode_bytes(bytes).map(H256)
+    }
+}
+
+impl Encode for U128 {
+    fn encode(&self) -> String {
+        profile_method!(encode);
+
+        format!("{}", self)
+    }
+}
+
+/// Accepts decimal, or a 0x prefixed hex quantity, as U256 does
+impl Decode<str> for U128 {
+    fn decode(s: &str) -> Result<Self, ()> {
+        profile_method!(decode);
+
+        match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            // from_str_radix would accept an empty string or a sign
+            Some(hex) if !hex.is_empty() && hex.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                U128::from_str_radix(hex, 16).map_err(|_| ())
+            }
+            Some(_) => Err(()),
+            None => U128::from_dec_str(s).map_err(|_| ()),
+        }
+    }
+}
+
+impl Encode for u128 {
+    fn encode(&self) -> String {
+        format!("{}", self)
+    }
+}
+
+impl Decode<str> for u128 {
+    fn decode(s: &str) -> Result<Self, ()> {
+        s.parse().map_err(|_| ())
+    }
+}
+
+impl Encode for i128 {
+    fn encode(&self) -> String {
+        format!("{}", self)
+    }
+}
+
+impl Decode<str> for i128 {
+    fn decode(s: &str) -> Result<Self, ()> {
+        s.parse().map_err(|_| ())
+    }
+}
+
+/// Decodes hex incrementally into an output allocated once up front.
+/// Input may be fed in chunks of any size, including chunks which
+/// split a byte's two digits, so that very large payloads never
+/// need an intermediate copy or a growing Vec.
+pub struct HexDecoder {
+    output: Vec<u8>,
+    // The high nibble of a byte whose low nibble is in the next chunk
+    pending: Option<u8>,
+}
+
+impl HexDecoder {
+    /// Capacity is the expected number of decoded bytes
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            output: Vec::with_capacity(capacity),
+            pending: None,
+        }
+    }
+
+    #[allow(clippy::result_unit_err)]
+    pub fn update(&mut self, mut chunk: &[u8]) -> Result<(), ()> {
+        if let Some(high) = self.pending.take() {
+            match chunk.split_first() {
+                Some((low, rest)) => {
+                    self.output.push((high << 4) | unhex(*low)?);
+                    chunk = rest;
+                }
+                None => {
+                    self.pending = Some(high);
+                    return Ok(());
+                }
+            }
+        }
+
+        let mut pairs = chunk.chunks_exact(2);
+        for pair in &mut pairs {
+            self.output.push((unhex(pair[0])? << 4) | unhex(pair[1])?);
+        }
+        if let [high] = pairs.remainder() {
+            self.pending = Some(unhex(*high)?);
+        }
+        Ok(())
+    }
+
+    /// Fails if an odd number of digits was given
+    #[allow(clippy::result_unit_err)]
+    pub fn finish(self) -> Result<Vec<u8>, ()> {
+        match self.pending {
+            Some(_) => Err(()),
+            None => Ok(self.output),
+        }
+    }
+}
+
+fn unhex(digit: u8) -> Result<u8, ()> {
+    match digit {
+        b'0'..=b'9' => Ok(digit - b'0'),
+        b'a'..=b'f' => Ok(digit - b'a' + 10),
+        b'A'..=b'F' => Ok(digit - b'A' + 10),
+        _ => Err(()),
+    }
+}
+
+impl Decode<str> for Vec<u8> {
+    fn decode(s: &str) -> Result<Self, ()> {
+        profile_method!(decode);
+
+        let bytes = strip_hex_prefix(s).as_bytes();
+        let mut decoder = HexDecoder::with_capacity(bytes.len() / 2);
+        decoder.update(bytes)?;
+        decoder.finish()
+    }
+}
+
+/// Removes the 0x prefix from a hex string, if any
+pub fn strip_hex_prefix(s: &str) -> &str {
+    s.strip_prefix("0x").unwrap_or(s)
+}
+
+#[allow(clippy::result_unit_err)]
+pub fn decode<T: ?Sized, D: Decode<T>>(s: impl AsRef<T>) -> Result<D, ()> {
+    Decode::decode(s.as_ref())
+}
+
+#[allow(clippy::result_unit_err)]
+pub fn decode_bytes<D: DecodeBytes>(bytes: impl AsRef<[u8]>) -> Result<D, ()> {
+    DecodeBytes::decode_bytes(bytes.as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use rustc_hex::ToHex as _;
+
+    #[test]
+    fn round_trip_hex() {
+        let mut bytes = Bytes32::default();
+        bytes[0] = 1;
+        bytes[2] = 2;
+        let encoded = bytes.encode();
+
+        assert_eq!(
+            "0x0100020000000000000000000000000000000000000000000000000000000000",
+            &encoded
+        );
+        assert_eq!(decode(encoded.as_str()), Ok(bytes));
+    }
+
+    #[test]
+    fn hex_decoder_chunks() {
+        let hex = b"00ff10a0B1";
+        for chunk_size in 1..hex.len() {
+            let mut decoder = HexDecoder::with_capacity(5);
+            for chunk in hex.chunks(chunk_size) {
+                decoder.update(chunk).unwrap();
+            }
+            assert_eq!(decoder.finish(), Ok(vec![0x00, 0xff, 0x10, 0xa0, 0xb1]));
+        }
+
+        assert_eq!(decode::<_, Vec<u8>>("0x0102"), Ok(vec![1, 2]));
+        assert_eq!(decode::<_, Vec<u8>>(""), Ok(vec![]));
+        assert_eq!(decode::<_, Vec<u8>>("010"), Err(()));
+        assert_eq!(decode::<_, Vec<u8>>("0g"), Err(()));
+    }
+
+    #[test]
+    fn decodes_bytes() {
+        assert_eq!(decode_bytes::<[u8; 2]>([1u8, 2]), Ok([1, 2]));
+        assert_eq!(decode_bytes::<[u8; 2]>([1u8]), Err(()));
+        assert_eq!(decode_bytes::<U256>([1u8, 0]), Ok(U256::from(256)));
+        assert_eq!(decode_bytes::<U256>([]), Ok(U256::zero()));
+        assert_eq!(decode_bytes::<U256>([0u8; 33]), Err(()));
+        assert_eq!(decode_bytes::<H160>([7u8; 20]), Ok(H160([7; 20])));
+        assert_eq!(decode_bytes::<H256>([7u8; 20]), Err(()));
+    }
+
+    #[test]
+    fn round_trip_u256() {
+        for i in 0..10000u32 {
+            if i % 31 != 0 {
+                continue;
+            }
+            let i = U256::from(i);
+            let enc = i.encode();
+            assert_eq!(Ok(i), decode(enc.as_str()));
+        }
+    }
+
+    #[test]
+    fn decode_hex_u256() {
+        assert_eq!(decode("0x1f"), Ok(U256::from(31)));
+        assert_eq!(decode("0X00ff"), Ok(U256::from(255)));
+        assert_eq!(encode_hex(&U256::from(31)), "0x1f");
+        assert_eq!(encode_hex(&U256::zero()), "0x0");
+        assert_eq!(decode::<_, U256>("0x"), Err(()));
+        assert_eq!(decode::<_, U256>("0x-1"), Err(()));
+        assert_eq!(decode::<_, U256>(format!("0x1{}", "0".repeat(64))), Err(()));
+    }
+
+    #[test]
+    fn decode_u128() {
+        assert_eq!(
+            decode("340282366920938463463374607431768211455"),
+            Ok(U128::MAX)
+        );
+        assert_eq!(decode("0x1f"), Ok(U128::from(31)));
+        assert_eq!(decode("0XfF"), Ok(U128::from(255)));
+        assert_eq!(decode::<_, U128>("0x"), Err(()));
+        assert_eq!(decode::<_, U128>("0x+1"), Err(()));
+        assert_eq!(decode::<_, U128>(format!("0x1{}", "0".repeat(32))), Err(()));
+        assert_eq!(
+            decode::<_, U128>("340282366920938463463374607431768211456"),
+            Err(())
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn prop_round_trip_address(bytes: [u8; 20]) {
+            let encoded = bytes.encode();
+            prop_assert_eq!(encoded.len(), 42);
+            prop_assert_eq!(decode(encoded.as_str()), Ok(bytes));
+        }
+
+        #[test]
+        fn prop_round_trip_bytes32(bytes: [u8; 32]) {
+            prop_assert_eq!(decode(bytes.encode().as_str()), Ok(bytes));
+        }
+
+        #[test]
+        fn prop_round_trip_u256(limbs: [u64; 4]) {
+            let n = U256(limbs);
+            prop_assert_eq!(decode(n.encode().as_str()), Ok(n));
+            prop_assert_eq!(decode(encode_hex(&n).as_str()), Ok(n));
+        }
+
+        #[test]
+        fn prop_round_trip_h160(bytes: [u8; 20]) {
+            let h = H160(bytes);
+            prop_assert_eq!(h.encode(), bytes.encode());
+            prop_assert_eq!(decode(h.encode().as_str()), Ok(h));
+        }
+
+        #[test]
+        fn prop_round_trip_h256(bytes: [u8; 32]) {
+            let h = H256(bytes);
+            prop_assert_eq!(decode(h.encode().as_str()), Ok(h));
+        }
+
+        #[test]
+        fn prop_round_trip_u128(n: u128) {
+            prop_assert_eq!(decode(n.encode().as_str()), Ok(n));
+            let wide = U128::from(n);
+            prop_assert_eq!(wide.encode(), n.encode());
+            prop_assert_eq!(decode(wide.encode().as_str()), Ok(wide));
+        }
+
+        #[test]
+        fn prop_round_trip_i128(n: i128) {
+            prop_assert_eq!(decode(n.encode().as_str()), Ok(n));
+        }
+
+        #[test]
+        fn prop_round_trip_vec_u8(bytes: Vec<u8>, chunk_size in 1usize..16) {
+            let hex: String = bytes.to_hex();
+            prop_assert_eq!(decode(hex.as_str()), Ok(bytes.clone()));
+            prop_assert_eq!(decode(format!("0x{}", hex.to_uppercase()).as_str()), Ok(bytes.clone()));
+
+            let mut decoder = HexDecoder::with_capacity(bytes.len());
+            for chunk in hex.as_bytes().chunks(chunk_size) {
+                decoder.update(chunk).unwrap();
+            }
+            prop_assert_eq!(decoder.finish(), Ok(bytes));
+        }
+    }
+}
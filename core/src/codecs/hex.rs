@@ -0,0 +1,149 @@
+//! Lowercase hex encoding, which is on hot paths like returning hashes and
+//! addresses. There's more than one way to do it, and which is fastest
+//! depends on the target and the input size, so the Backend is a choice.
+//! `cargo bench -p neon-utils-core` compares them.
+
+use alloc::string::String;
+use alloc::vec;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// faster_hex, which uses SIMD on x86 when the CPU has it
+    FasterHex,
+    /// A nibble at a time, with no tables
+    Scalar,
+    /// Both digits of a byte at once, from a table built at compile time
+    Table,
+}
+
+impl Default for Backend {
+    /// FasterHex on x86, where it has SIMD, and Table elsewhere
+    fn default() -> Self {
+        if cfg!(any(target_arch = "x86", target_arch = "x86_64")) {
+            Backend::FasterHex
+        } else {
+            Backend::Table
+        }
+    }
+}
+
+const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+const fn table() -> [[u8; 2]; 256] {
+    let mut table = [[0; 2]; 256];
+    let mut i = 0;
+    while i < 256 {
+        table[i] = [DIGITS[i >> 4], DIGITS[i & 0xf]];
+        i += 1;
+    }
+    table
+}
+
+static TABLE: [[u8; 2]; 256] = table();
+
+impl Backend {
+    /// Writes the hex of bytes to the start of out, which must have room
+    /// for twice as many. Returns what was written.
+    #[allow(clippy::result_unit_err)]
+    pub fn encode_to<'o>(self, bytes: &[u8], out: &'o mut [u8]) -> Result<&'o mut str, ()> {
+        let len = bytes.len() * 2;
+        let out = out.get_mut(..len).ok_or(())?;
+        match self {
+            Backend::FasterHex => {
+                faster_hex::hex_encode(bytes, out).map_err(|_| ())?;
+            }
+            Backend::Scalar => {
+                for (byte, pair) in bytes.iter().zip(out.chunks_exact_mut(2)) {
+                    pair[0] = DIGITS[(byte >> 4) as usize];
+                    pair[1] = DIGITS[(byte & 0xf) as usize];
+                }
+            }
+            Backend::Table => {
+                for (byte, pair) in bytes.iter().zip(out.chunks_exact_mut(2)) {
+                    pair.copy_from_slice(&TABLE[*byte as usize]);
+                }
+            }
+        }
+        // Only ASCII digits were written
+        core::str::from_utf8_mut(out).map_err(|_| ())
+    }
+
+    pub fn encode(self, bytes: &[u8]) -> String {
+        let mut out = vec![0; bytes.len() * 2];
+        // There is always room
+        let _ = self.encode_to(bytes, &mut out);
+        String::from_utf8(out).unwrap_or_default()
+    }
+
+    /// As encode_to, after a 0x prefix
+    #[allow(clippy::result_unit_err)]
+    pub fn encode_prefixed_to<'o>(
+        self,
+        bytes: &[u8],
+        out: &'o mut [u8],
+    ) -> Result<&'o mut str, ()> {
+        let len = 2 + bytes.len() * 2;
+        let out = out.get_mut(..len).ok_or(())?;
+        out[..2].copy_from_slice(b"0x");
+        self.encode_to(bytes, &mut out[2..])?;
+        core::str::from_utf8_mut(out).map_err(|_| ())
+    }
+
+    pub fn encode_prefixed(self, bytes: &[u8]) -> String {
+        let mut out = vec![0; 2 + bytes.len() * 2];
+        let _ = self.encode_prefixed_to(bytes, &mut out);
+        String::from_utf8(out).unwrap_or_default()
+    }
+}
+
+/// Backend::default().encode_to(bytes, out)
+#[allow(clippy::result_unit_err)]
+pub fn encode_to<'o>(bytes: &[u8], out: &'o mut [u8]) -> Result<&'o mut str, ()> {
+    Backend::default().encode_to(bytes, out)
+}
+
+pub fn encode(bytes: &[u8]) -> String {
+    Backend::default().encode(bytes)
+}
+
+/// Backend::default().encode_prefixed_to(bytes, out)
+#[allow(clippy::result_unit_err)]
+pub fn encode_prefixed_to<'o>(bytes: &[u8], out: &'o mut [u8]) -> Result<&'o mut str, ()> {
+    Backend::default().encode_prefixed_to(bytes, out)
+}
+
+pub fn encode_prefixed(bytes: &[u8]) -> String {
+    Backend::default().encode_prefixed(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use rustc_hex::ToHex as _;
+
+    const BACKENDS: [Backend; 3] = [Backend::FasterHex, Backend::Scalar, Backend::Table];
+
+    #[test]
+    fn buffers() {
+        let mut out = [0u8; 8];
+        assert_eq!(
+            encode_prefixed_to(&[0xab, 0x01], &mut out).as_deref(),
+            Ok("0xab01")
+        );
+        assert_eq!(&out[6..], [0, 0]);
+        assert_eq!(encode_to(&[0; 5], &mut out), Err(()));
+        assert_eq!(encode(&[]), "");
+    }
+
+    proptest! {
+        #[test]
+        fn prop_backends_agree(bytes: Vec<u8>) {
+            let expected: String = bytes.to_hex();
+            for backend in BACKENDS {
+                prop_assert_eq!(backend.encode(&bytes), expected.clone());
+                prop_assert_eq!(backend.encode_prefixed(&bytes), format!("0x{}", expected));
+            }
+        }
+    }
+}
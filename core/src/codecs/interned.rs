@@ -0,0 +1,173 @@
+//! What byte strings encode to, kept for values which repeat, eg: the same
+//! few hundred addresses throughout a large response, so that each is only
+//! encoded once.
+//!
+//! Eviction is approximately least recently used: entries live in two
+//! generations, and when the recent one fills the older one is dropped
+//! and the recent one takes its place. An entry found in the older one is
+//! moved back to the recent one. That is O(1) without a linked list.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+pub struct InternCache {
+    // Entries per generation, so up to twice this in all
+    generation: AtomicUsize,
+    generations: Mutex<Generations>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Default)]
+struct Generations {
+    recent: HashMap<Box<[u8]>, Arc<str>>,
+    older: HashMap<Box<[u8]>, Arc<str>>,
+}
+
+impl Generations {
+    fn insert(&mut self, generation: usize, key: Box<[u8]>, value: Arc<str>) {
+        if self.recent.len() >= generation {
+            self.older = std::mem::take(&mut self.recent);
+        }
+        self.recent.insert(key, value);
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InternStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+impl InternCache {
+    /// Holds up to capacity entries. A capacity of 0 keeps nothing, and
+    /// encodes every time.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            generation: AtomicUsize::new(capacity.div_ceil(2)),
+            generations: Mutex::new(Generations::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.generation() != 0
+    }
+
+    fn generation(&self) -> usize {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    /// Empties the cache, which then holds up to capacity entries
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut generations = self.lock();
+        self.generation
+            .store(capacity.div_ceil(2), Ordering::Relaxed);
+        *generations = Generations::default();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Generations> {
+        self.generations.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// What key encodes to, from the cache or else from encode. Encoding
+    /// happens without the lock held, so threads don't wait on each other.
+    pub fn get_or_insert_with(&self, key: &[u8], encode: impl FnOnce() -> String) -> Arc<str> {
+        if !self.is_enabled() {
+            return encode().into();
+        }
+        {
+            let mut generations = self.lock();
+            if let Some(value) = generations.recent.get(key) {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return value.clone();
+            }
+            if let Some((key, value)) = generations.older.remove_entry(key) {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                generations.insert(self.generation(), key, value.clone());
+                return value;
+            }
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let value: Arc<str> = encode().into();
+        self.lock()
+            .insert(self.generation(), key.into(), value.clone());
+        value
+    }
+
+    pub fn stats(&self) -> InternStats {
+        let generations = self.lock();
+        InternStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: generations.recent.len() + generations.older.len(),
+        }
+    }
+
+    /// Empties the cache and resets the counts
+    pub fn clear(&self) {
+        *self.lock() = Generations::default();
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(cache: &InternCache, key: u8) -> Arc<str> {
+        cache.get_or_insert_with(&[key], || format!("{:02x}", key))
+    }
+
+    #[test]
+    fn hits_and_evicts() {
+        let cache = InternCache::new(4);
+        assert_eq!(&*encode(&cache, 1), "01");
+        assert_eq!(&*encode(&cache, 1), "01");
+        for key in 2..=4 {
+            encode(&cache, key);
+        }
+        // 1 was moved to the older generation, so it is still there
+        assert_eq!(cache.stats().entries, 4);
+        // Moving it back fills the recent one, so 2 goes
+        encode(&cache, 1);
+        assert_eq!(
+            cache.stats(),
+            InternStats {
+                hits: 2,
+                misses: 4,
+                entries: 3
+            }
+        );
+        // Never more than the capacity
+        for key in 5..=100 {
+            encode(&cache, key);
+        }
+        assert!(cache.stats().entries <= 4);
+        cache.clear();
+        assert_eq!(cache.stats(), InternStats::default());
+    }
+
+    #[test]
+    fn disabled() {
+        let cache = InternCache::new(0);
+        assert_eq!(&*encode(&cache, 7), "07");
+        assert_eq!(cache.stats(), InternStats::default());
+    }
+
+    #[test]
+    fn resized() {
+        let cache = InternCache::new(0);
+        cache.set_capacity(2);
+        encode(&cache, 1);
+        encode(&cache, 1);
+        assert_eq!(cache.stats().hits, 1);
+        cache.set_capacity(0);
+        assert!(!cache.is_enabled());
+        assert_eq!(cache.stats().entries, 0);
+    }
+}
@@ -0,0 +1,53 @@
+use alloc::{boxed::Box, string::String};
+use core::fmt;
+
+pub struct LazyFmt(Box<dyn fmt::Display>);
+
+impl LazyFmt {
+    pub fn new<T>(value: T) -> Self
+    where
+        T: 'static + fmt::Display,
+    {
+        Self(Box::new(value))
+    }
+}
+
+impl fmt::Display for LazyFmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A LazyFmt of a value which is Send, so it can stay unformatted in an
+/// error returned from another thread, eg: a run_async task
+pub struct SendFmt(Box<dyn fmt::Display + Send>);
+
+impl SendFmt {
+    pub fn new<T>(value: T) -> Self
+    where
+        T: 'static + Send + fmt::Display,
+    {
+        Self(Box::new(value))
+    }
+}
+
+impl fmt::Display for SendFmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A value which wasn't of the expected type, described when that was
+/// found rather than when it is thrown, so that it holds no handles and
+/// always reads the same, eg: "Expected object, got string".
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DowncastFailure {
+    pub expected: String,
+    pub got: String,
+}
+
+impl fmt::Display for DowncastFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Expected {}, got {}", self.expected, self.got)
+    }
+}
@@ -0,0 +1,24 @@
+//! The parts of neon-utils which don't need neon, eg: to share Encode and
+//! Decode with a WASM or server-side crate. neon-utils re-exports all of
+//! this, so there's no need to depend on both.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod codecs;
+pub mod errors;
+
+pub type Address = [u8; 20];
+pub type Bytes32 = [u8; 32];
+
+#[cfg(feature = "std")]
+pub(crate) use firestorm::profile_method;
+
+// Profiling needs std
+#[cfg(not(feature = "std"))]
+macro_rules! profile_method {
+    ($($t:tt)*) => {};
+}
+#[cfg(not(feature = "std"))]
+pub(crate) use profile_method;
@@ -0,0 +1,838 @@
+//! Derive macros for neon-utils. These are re-exported from neon_utils::marshalling,
+//! so depend on neon-utils rather than on this crate directly.
+use proc_macro::TokenStream;
+use proc_macro2::{Ident, Span, TokenStream as TokenStream2, TokenTree};
+use quote::quote;
+use syn::ext::IdentExt;
+use syn::parse::{Parse, ParseStream};
+use syn::{
+    braced, bracketed, parse_macro_input, parse_quote, token, Data, DeriveInput, Expr, Field,
+    Fields, FnArg, Generics, ImplItem, ItemImpl, Lit, LitStr, Member, Pat, Path, Token, Type,
+};
+
+/// Decodes a struct with named fields from a JS object. Properties are
+/// the camelCase field names. Every invalid field is reported at once,
+/// with errors: [{ field, message }] on the thrown Error. Struct attributes:
+///   #[neon(rename_all = "...")] spells properties as "camelCase" (the default),
+///     "snake_case", "PascalCase", "lowercase", "UPPERCASE", "SCREAMING_SNAKE_CASE"
+///     or "kebab-case" instead
+///   #[neon(ts)] also implements decls::TsType, declaring an interface with
+///     the properties, which are optional where they have a default
+///   #[neon(transparent)] on a struct with one field, eg: BlockNumber(u64),
+///     decodes it as that field would be, from the whole value. With ts,
+///     the TsType is the field's too
+///   #[neon(validate = path)] calls path(&value) once decoded, which returns
+///     a Result whose error is a SafeErr (or a String or &str)
+/// Field attributes:
+///   #[neon(default)] uses Default::default() for a missing or undefined property
+///   #[neon(default = expr)] uses expr for a missing or undefined property
+///   #[neon(rename = "...")] reads the property with this exact name
+#[proc_macro_derive(FromHandle, attributes(neon))]
+pub fn derive_from_handle(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    from_handle(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Writes a struct with named fields as a JS object, with the same
+/// properties #[derive(FromHandle)] reads, so rename_all and rename work
+/// the same, and #[neon(transparent)] writes the one field as itself.
+/// Other attributes are for FromHandle, and are ignored. Also:
+///   #[neon(skip_none)] on the struct or a field leaves out None Options
+///     rather than writing null. A SkipIfNone field is always left out when None
+#[proc_macro_derive(IntoHandle, attributes(neon))]
+pub fn derive_into_handle(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    into_handle(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Goes on an impl block for a type T wrapped in a class as Proxy<T>, and
+/// on each method in it to export. For each of those, eg:
+///   #[neon_method]
+///   fn deposit(&self, amount: U256, memo: Option<String>) -> SafeResult<U256>
+/// this adds an associated function js_deposit for the class to call, eg:
+///   method deposit(cx) { Wallet::js_deposit(cx) }
+/// which takes the Proxy from this, decodes each argument in turn with
+/// FromHandle (reading one left out as undefined, so memo may be), calls
+/// the method and finishes with the result. Methods
+/// take &self and return a Result whose Ok is IntoHandle.
+#[proc_macro_attribute]
+pub fn neon_method(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        return syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "neon_method takes no arguments",
+        )
+        .into_compile_error()
+        .into();
+    }
+    let item = parse_macro_input!(item as ItemImpl);
+    neon_methods(item)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// The fields of js_object!, which expands to this with its arguments
+/// first: $crate; cx, object, casing, skip_none; fields. Each field is set
+/// in turn, without the recursion a macro_rules muncher would need for
+/// every field.
+#[doc(hidden)]
+#[proc_macro]
+pub fn __js_object_fields(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as ObjectFields);
+    let object = input.object.clone();
+    input.fields.expand(&input, &object, 0).into()
+}
+
+fn neon_methods(mut item: ItemImpl) -> syn::Result<TokenStream2> {
+    let mut wrappers = Vec::new();
+    for method in item.items.iter_mut().filter_map(|item| match item {
+        ImplItem::Fn(method) => Some(method),
+        _ => None,
+    }) {
+        let before = method.attrs.len();
+        method.attrs.retain(|a| !a.path().is_ident("neon_method"));
+        if method.attrs.len() == before {
+            continue;
+        }
+
+        let sig = &method.sig;
+        let mut inputs = sig.inputs.iter();
+        match inputs.next() {
+            Some(FnArg::Receiver(receiver))
+                if receiver.reference.is_some() && receiver.mutability.is_none() => {}
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    sig,
+                    "neon_method methods must take &self, since a Proxy is shared",
+                ))
+            }
+        }
+        let mut decoded = Vec::new();
+        let mut args = Vec::new();
+        for (i, input) in inputs.enumerate() {
+            let ty = match input {
+                FnArg::Typed(typed) if matches!(*typed.pat, Pat::Ident(_)) => &typed.ty,
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        input,
+                        "neon_method arguments must be plain names",
+                    ))
+                }
+            };
+            let local = quote::format_ident!("__arg{}", i);
+            let index = i as i32;
+            // One left out is read as undefined, so that an Option may be
+            decoded.push(quote! {
+                let #local: #ty = {
+                    let arg = match cx.argument_opt(#index) {
+                        ::core::option::Option::Some(arg) => arg,
+                        ::core::option::Option::None => ::neon::context::Context::undefined(&mut cx).upcast(),
+                    };
+                    ::neon_utils::marshalling::FromHandle::from_handle(arg, &mut cx)?
+                };
+            });
+            args.push(local);
+        }
+
+        let name = &sig.ident;
+        let export = format!("{}.{}", type_name(&item.self_ty), name);
+        let wrapper = quote::format_ident!("js_{}", name);
+        let doc = format!("Calls {} with this and the arguments from JS", name);
+        wrappers.push(quote! {
+            #[doc = #doc]
+            pub fn #wrapper<'a, K>(
+                mut cx: ::neon::context::MethodContext<'a, K>,
+            ) -> ::neon::result::JsResult<'a, ::neon::types::JsValue>
+            where
+                K: ::neon::object::Class<Internals = ::neon_utils::proxy::Proxy<Self>>,
+            {
+                let result = ::neon_utils::profiling::in_export(#export, || -> ::neon_utils::errors::SafeResult<_> {
+                    let this = ::neon_utils::proxy::Proxy::<Self>::from_this::<K, K>(&mut cx)?;
+                    #(#decoded)*
+                    this.#name(#(#args),*)
+                        .map_err(::core::convert::Into::<::neon_utils::errors::MaybeThrown>::into)
+                });
+                let handle = ::neon_utils::errors::Terminal::finish(result, cx)?;
+                Ok(handle.upcast())
+            }
+        });
+    }
+
+    if wrappers.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &item.self_ty,
+            "Mark the methods to export with #[neon_method]",
+        ));
+    }
+    let (impl_generics, _, where_clause) = item.generics.split_for_impl();
+    let self_ty = &item.self_ty;
+    Ok(quote! {
+        #item
+
+        impl #impl_generics #self_ty #where_clause {
+            #(#wrappers)*
+        }
+    })
+}
+
+enum FieldDefault {
+    None,
+    Trait,
+    Expr(Expr),
+}
+
+#[derive(Clone, Copy)]
+enum RenameAll {
+    Camel,
+    Snake,
+    Pascal,
+    Lower,
+    Upper,
+    ScreamingSnake,
+    Kebab,
+}
+
+struct StructAttrs {
+    rename_all: RenameAll,
+    ts: bool,
+    skip_none: bool,
+    transparent: bool,
+    validate: Option<Path>,
+}
+
+struct FieldAttrs {
+    default: FieldDefault,
+    rename: Option<String>,
+    skip_none: bool,
+}
+
+// The name JS knows the class by, eg: Tally for crate::Tally<T>, rather
+// than the tokens, which quote spaces out as "Tally < T >"
+fn type_name(ty: &Type) -> String {
+    match ty {
+        Type::Path(path) => match path.path.segments.last() {
+            Some(segment) => segment.ident.unraw().to_string(),
+            None => String::new(),
+        },
+        _ => quote!(#ty).to_string().replace(' ', ""),
+    }
+}
+
+fn named_fields<'a>(
+    input: &'a DeriveInput,
+    derive: &str,
+) -> syn::Result<&'a syn::punctuated::Punctuated<Field, syn::Token![,]>> {
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => Ok(&fields.named),
+            _ => Err(unsupported(input, derive)),
+        },
+        _ => Err(unsupported(input, derive)),
+    }
+}
+
+// The one field of a #[neon(transparent)] struct, named or not
+fn transparent_field(input: &DeriveInput) -> syn::Result<(Member, &Type)> {
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => return Err(transparent_unsupported(input)),
+    };
+    let mut iter = fields.iter();
+    match (iter.next(), iter.next()) {
+        (Some(field), None) => {
+            let member = match &field.ident {
+                Some(ident) => Member::Named(ident.clone()),
+                None => Member::Unnamed(0.into()),
+            };
+            Ok((member, &field.ty))
+        }
+        _ => Err(transparent_unsupported(input)),
+    }
+}
+
+fn transparent_unsupported(input: &DeriveInput) -> syn::Error {
+    syn::Error::new_spanned(
+        &input.ident,
+        "neon(transparent) needs a struct with exactly one field",
+    )
+}
+
+// Generic newtypes need the field's type bound, eg: Wrapper<T>(T)
+fn bound(generics: &Generics, ty: &Type, bound: TokenStream2) -> Generics {
+    let mut generics = generics.clone();
+    if !generics.params.is_empty() {
+        generics
+            .make_where_clause()
+            .predicates
+            .push(parse_quote!(#ty: #bound));
+    }
+    generics
+}
+
+fn validated(validate: &Option<Path>) -> TokenStream2 {
+    match validate {
+        Some(path) => quote! { #path(&value)?; },
+        None => quote! {},
+    }
+}
+
+fn from_handle_transparent(input: &DeriveInput, attrs: StructAttrs) -> syn::Result<TokenStream2> {
+    let (member, ty) = transparent_field(input)?;
+    let name = &input.ident;
+    let ts_impl = if attrs.ts {
+        let generics = bound(&input.generics, ty, quote!(::neon_utils::decls::TsType));
+        let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+        quote! {
+            impl #impl_generics ::neon_utils::decls::TsType for #name #ty_generics #where_clause {
+                fn ts_input() -> ::std::string::String {
+                    <#ty as ::neon_utils::decls::TsType>::ts_input()
+                }
+
+                fn ts_output() -> ::std::string::String {
+                    <#ty as ::neon_utils::decls::TsType>::ts_output()
+                }
+
+                fn ts_optional() -> bool {
+                    <#ty as ::neon_utils::decls::TsType>::ts_optional()
+                }
+
+                fn ts_declare(decls: &mut ::neon_utils::decls::Decls) {
+                    <#ty as ::neon_utils::decls::TsType>::ts_declare(decls);
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+    let validate = validated(&attrs.validate);
+    let generics = bound(
+        &input.generics,
+        ty,
+        quote!(::neon_utils::marshalling::FromHandle),
+    );
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        #ts_impl
+
+        impl #impl_generics ::neon_utils::marshalling::FromHandle for #name #ty_generics #where_clause {
+            fn from_handle<'a, V: ::neon::types::Value>(
+                handle: ::neon::handle::Handle<V>,
+                cx: &mut impl ::neon::context::Context<'a>,
+            ) -> ::neon_utils::errors::SafeResult<Self>
+            where
+                Self: Sized,
+            {
+                let value = Self {
+                    #member: <#ty as ::neon_utils::marshalling::FromHandle>::from_handle(handle, cx)?,
+                };
+                #validate
+                Ok(value)
+            }
+        }
+    })
+}
+
+fn from_handle(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let attrs = struct_attrs(&input)?;
+    if attrs.transparent {
+        return from_handle_transparent(&input, attrs);
+    }
+    let fields = named_fields(&input, "FromHandle")?;
+    let validate = validated(&attrs.validate);
+    let StructAttrs { rename_all, ts, .. } = attrs;
+    let mut decoded = Vec::new();
+    let mut assigned = Vec::new();
+    let mut declared = Vec::new();
+    for field in fields {
+        let ident = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let attrs = field_attrs(field)?;
+        let key = match attrs.rename {
+            Some(key) => key,
+            None => rename(&ident.to_string(), rename_all),
+        };
+        let optional = !matches!(attrs.default, FieldDefault::None);
+        declared.push(quote! {
+            (
+                #key,
+                <#ty as ::neon_utils::decls::TsType>::ts_input(),
+                #optional || <#ty as ::neon_utils::decls::TsType>::ts_optional(),
+            )
+        });
+        let decode = match attrs.default {
+            FieldDefault::None => quote! {
+                <#ty as ::neon_utils::marshalling::FromHandle>::from_handle(value, cx)
+            },
+            FieldDefault::Trait => quote! {
+                <#ty as ::neon_utils::marshalling::FromHandleWithDefault>::from_handle_or_default(value, cx)
+            },
+            FieldDefault::Expr(expr) => quote! {
+                <#ty as ::neon_utils::marshalling::FromHandleWithDefault>::from_handle_or_else(value, cx, || #expr)
+            },
+        };
+        // Each field is decoded into a numbered local, so that
+        // field names can't shadow the locals used here.
+        let local = quote::format_ident!("__field{}", decoded.len());
+        decoded.push(quote! {
+            let #local = {
+                let value: ::neon::handle::Handle<::neon::types::JsValue> =
+                    ::neon::object::Object::get(&*object, cx, #key)?;
+                errors.field(#key, #decode)?
+            };
+        });
+        // Only reached if every field decoded
+        assigned.push(quote! { #ident: #local.unwrap() });
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let ts_impl = if ts {
+        if !input.generics.params.is_empty() {
+            return Err(syn::Error::new_spanned(
+                &input.generics,
+                "neon(ts) can't declare an interface for a generic struct",
+            ));
+        }
+        let interface = name.to_string();
+        let types = fields.iter().map(|field| &field.ty);
+        quote! {
+            impl ::neon_utils::decls::TsType for #name {
+                fn ts_input() -> ::std::string::String {
+                    ::std::string::String::from(#interface)
+                }
+
+                fn ts_declare(decls: &mut ::neon_utils::decls::Decls) {
+                    let fields = [#(#declared),*];
+                    if decls.interface(#interface, &fields) {
+                        #(<#types as ::neon_utils::decls::TsType>::ts_declare(decls);)*
+                    }
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+    Ok(quote! {
+        #ts_impl
+
+        impl #impl_generics ::neon_utils::marshalling::FromHandle for #name #ty_generics #where_clause {
+            fn from_handle<'a, V: ::neon::types::Value>(
+                handle: ::neon::handle::Handle<V>,
+                cx: &mut impl ::neon::context::Context<'a>,
+            ) -> ::neon_utils::errors::SafeResult<Self>
+            where
+                Self: Sized,
+            {
+                let object: ::neon::handle::Handle<::neon::types::JsObject> =
+                    ::neon_utils::marshalling::expect(handle, cx, "object")?;
+                let mut errors = ::neon_utils::errors::FieldErrors::new();
+                #(#decoded)*
+                errors.finish()?;
+                let value = Self {
+                    #(#assigned,)*
+                };
+                #validate
+                Ok(value)
+            }
+        }
+    })
+}
+
+fn into_handle_transparent(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let (member, ty) = transparent_field(input)?;
+    let name = &input.ident;
+    let generics = bound(
+        &input.generics,
+        ty,
+        quote!(::neon_utils::marshalling::IntoHandle),
+    );
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::neon_utils::marshalling::IntoHandle for #name #ty_generics #where_clause {
+            type Handle = <#ty as ::neon_utils::marshalling::IntoHandle>::Handle;
+
+            fn into_handle<'c>(
+                &self,
+                cx: &mut impl ::neon::context::Context<'c>,
+            ) -> ::neon_utils::errors::SafeJsResult<'c, Self::Handle> {
+                ::neon_utils::marshalling::IntoHandle::into_handle(&self.#member, cx)
+            }
+        }
+    })
+}
+
+fn into_handle(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let StructAttrs {
+        rename_all,
+        skip_none,
+        transparent,
+        ..
+    } = struct_attrs(&input)?;
+    if transparent {
+        return into_handle_transparent(&input);
+    }
+    let fields = named_fields(&input, "IntoHandle")?;
+    let mut set = Vec::new();
+    for field in fields {
+        let ident = field.ident.as_ref().unwrap();
+        let attrs = field_attrs(field)?;
+        let key = match attrs.rename {
+            Some(key) => key,
+            None => rename(&ident.to_string(), rename_all),
+        };
+        let skip_none = skip_none || attrs.skip_none;
+        set.push(quote! {
+            if !(&::neon_utils::marshalling::Field(&self.#ident)).left_out(#skip_none) {
+                let value = ::neon_utils::marshalling::IntoHandle::into_handle(&self.#ident, cx)?;
+                ::neon::object::Object::set(&*object, cx, #key, value)?;
+            }
+        });
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::neon_utils::marshalling::IntoHandle for #name #ty_generics #where_clause {
+            type Handle = ::neon::types::JsObject;
+
+            fn into_handle<'c>(
+                &self,
+                cx: &mut impl ::neon::context::Context<'c>,
+            ) -> ::neon_utils::errors::SafeJsResult<'c, Self::Handle> {
+                #[allow(unused_imports)]
+                use ::neon_utils::marshalling::{Keep as _, LeaveOut as _};
+                let object = ::neon::types::JsObject::new(cx);
+                #(#set)*
+                Ok(object)
+            }
+        }
+    })
+}
+
+fn struct_attrs(input: &DeriveInput) -> syn::Result<StructAttrs> {
+    let mut attrs = StructAttrs {
+        rename_all: RenameAll::Camel,
+        ts: false,
+        skip_none: false,
+        transparent: false,
+        validate: None,
+    };
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("neon")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename_all") {
+                let value: LitStr = meta.value()?.parse()?;
+                attrs.rename_all = match value.value().as_str() {
+                    "camelCase" => RenameAll::Camel,
+                    "snake_case" => RenameAll::Snake,
+                    "PascalCase" => RenameAll::Pascal,
+                    "lowercase" => RenameAll::Lower,
+                    "UPPERCASE" => RenameAll::Upper,
+                    "SCREAMING_SNAKE_CASE" => RenameAll::ScreamingSnake,
+                    "kebab-case" => RenameAll::Kebab,
+                    _ => return Err(meta.error("Unknown rename_all casing")),
+                };
+                Ok(())
+            } else if meta.path.is_ident("ts") {
+                attrs.ts = true;
+                Ok(())
+            } else if meta.path.is_ident("skip_none") {
+                attrs.skip_none = true;
+                Ok(())
+            } else if meta.path.is_ident("transparent") {
+                attrs.transparent = true;
+                Ok(())
+            } else if meta.path.is_ident("validate") {
+                attrs.validate = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("Unknown neon attribute"))
+            }
+        })?;
+    }
+    Ok(attrs)
+}
+
+fn field_attrs(field: &Field) -> syn::Result<FieldAttrs> {
+    let mut attrs = FieldAttrs {
+        default: FieldDefault::None,
+        rename: None,
+        skip_none: false,
+    };
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("neon")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("default") {
+                attrs.default = if meta.input.peek(syn::Token![=]) {
+                    FieldDefault::Expr(meta.value()?.parse()?)
+                } else {
+                    FieldDefault::Trait
+                };
+                Ok(())
+            } else if meta.path.is_ident("rename") {
+                let value: LitStr = meta.value()?.parse()?;
+                attrs.rename = Some(value.value());
+                Ok(())
+            } else if meta.path.is_ident("skip_none") {
+                attrs.skip_none = true;
+                Ok(())
+            } else {
+                Err(meta.error("Unknown neon attribute"))
+            }
+        })?;
+    }
+    Ok(attrs)
+}
+
+fn unsupported(input: &DeriveInput, derive: &str) -> syn::Error {
+    syn::Error::new_spanned(
+        &input.ident,
+        format!(
+            "{} can only be derived for structs with named fields",
+            derive
+        ),
+    )
+}
+
+// Fields are snake_case, so this is all that's needed of Casing
+fn rename(ident: &str, rename_all: RenameAll) -> String {
+    let ident = ident.strip_prefix("r#").unwrap_or(ident);
+    let words = ident.split('_').filter(|w| !w.is_empty());
+    match rename_all {
+        RenameAll::Camel => camel_case(ident),
+        RenameAll::Snake => words.collect::<Vec<_>>().join("_"),
+        RenameAll::Pascal => words
+            .map(|w| {
+                let mut chars = w.chars();
+                let first = chars.next().map(|c| c.to_uppercase().collect::<String>());
+                first.unwrap_or_default() + chars.as_str()
+            })
+            .collect(),
+        RenameAll::Lower => words.collect(),
+        RenameAll::Upper => words.map(str::to_uppercase).collect(),
+        RenameAll::ScreamingSnake => words.map(str::to_uppercase).collect::<Vec<_>>().join("_"),
+        RenameAll::Kebab => words.collect::<Vec<_>>().join("-"),
+    }
+}
+
+fn camel_case(ident: &str) -> String {
+    let ident = ident.strip_prefix("r#").unwrap_or(ident);
+    let mut result = String::with_capacity(ident.len());
+    let mut upper = false;
+    for c in ident.chars() {
+        if c == '_' {
+            upper = !result.is_empty();
+        } else if upper {
+            result.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+// The arguments of __js_object_fields
+struct ObjectFields {
+    // $crate, passed through so that paths resolve wherever js_object! is used
+    krate: TokenStream2,
+    cx: Expr,
+    object: Ident,
+    casing: Expr,
+    skip_none: Expr,
+    fields: FieldList,
+}
+
+struct FieldList(Vec<ObjectField>);
+
+enum ObjectField {
+    // ..source
+    Spread(Expr),
+    // key?: value
+    Optional(TokenStream2, Expr),
+    // key: { fields }
+    Nested(TokenStream2, FieldList),
+    // key: value
+    Value(TokenStream2, Expr),
+}
+
+impl Parse for ObjectFields {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut krate = TokenStream2::new();
+        while !input.peek(Token![;]) {
+            krate.extend(Some(input.parse::<TokenTree>()?));
+        }
+        input.parse::<Token![;]>()?;
+        let cx = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let object = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let casing = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let skip_none = input.parse()?;
+        input.parse::<Token![;]>()?;
+        let fields = input.parse()?;
+        Ok(Self {
+            krate,
+            cx,
+            object,
+            casing,
+            skip_none,
+            fields,
+        })
+    }
+}
+
+impl Parse for FieldList {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut fields = Vec::new();
+        while !input.is_empty() {
+            fields.push(input.parse()?);
+            if input.is_empty() {
+                break;
+            }
+            input.parse::<Token![,]>()?;
+        }
+        Ok(Self(fields))
+    }
+}
+
+impl Parse for ObjectField {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(Token![..]) {
+            input.parse::<Token![..]>()?;
+            return Ok(ObjectField::Spread(input.parse()?));
+        }
+        // An ident (which may be a keyword, eg: type), a literal or [expr],
+        // as __js_object_key! takes them
+        let key = if input.peek(token::Bracket) {
+            let content;
+            let brackets = bracketed!(content in input);
+            let expr: Expr = content.parse()?;
+            let mut key = TokenStream2::new();
+            brackets.surround(&mut key, |key| key.extend(quote!(#expr)));
+            key
+        } else if input.peek(Lit) {
+            let lit: Lit = input.parse()?;
+            quote!(#lit)
+        } else {
+            let ident = Ident::parse_any(input)?;
+            quote!(#ident)
+        };
+        if input.peek(Token![?]) {
+            input.parse::<Token![?]>()?;
+            input.parse::<Token![:]>()?;
+            return Ok(ObjectField::Optional(key, input.parse()?));
+        }
+        input.parse::<Token![:]>()?;
+        // A braced value is a nested object rather than a block
+        if input.peek(token::Brace) {
+            let content;
+            braced!(content in input);
+            return Ok(ObjectField::Nested(key, content.parse()?));
+        }
+        Ok(ObjectField::Value(key, input.parse()?))
+    }
+}
+
+impl FieldList {
+    // depth tells apart the objects of nested fields, which are all in scope at once
+    fn expand(&self, args: &ObjectFields, object: &Ident, depth: usize) -> TokenStream2 {
+        let ObjectFields {
+            krate,
+            cx,
+            casing,
+            skip_none,
+            ..
+        } = args;
+        // Mixed site, like the locals of a macro_rules, so that values can't see them
+        let local = |name: &str| Ident::new(name, Span::mixed_site());
+        let (value, handle, key) = (local("value"), local("handle"), local("key"));
+        let set = |key_tokens: &TokenStream2| {
+            quote! {
+                let #key = #krate::__js_object_key!(#cx, #casing, #key_tokens);
+                #object.set(#cx, #key, #handle)?;
+            }
+        };
+        let fields = self.0.iter().map(|field| match field {
+            ObjectField::Spread(source) => quote! {{
+                let #handle = #krate::marshalling::IntoHandle::into_handle(&#source, #cx)?;
+                #krate::marshalling::spread(#cx, #object, #handle.upcast())?;
+            }},
+            ObjectField::Optional(key_tokens, v) => {
+                let set = set(key_tokens);
+                quote! {
+                    if let Some(#value) = &#v {
+                        let #handle = #krate::marshalling::IntoHandle::into_handle(#value, #cx)?;
+                        #set
+                    }
+                }
+            }
+            ObjectField::Nested(key_tokens, inner) => {
+                let nested = local(&format!("nested{}", depth));
+                let fields = inner.expand(args, &nested, depth + 1);
+                let set = set(key_tokens);
+                quote! {{
+                    let #nested = JsObject::new(#cx);
+                    #fields
+                    let #handle = #nested;
+                    #set
+                }}
+            }
+            ObjectField::Value(key_tokens, v) => {
+                let set = set(key_tokens);
+                quote! {{
+                    let #value = &#v;
+                    #[allow(unused_imports)]
+                    use #krate::marshalling::{Keep as _, LeaveOut as _};
+                    if !(&#krate::marshalling::Field(#value)).left_out(#skip_none) {
+                        let #handle = #krate::marshalling::IntoHandle::into_handle(#value, #cx)?;
+                        #set
+                    }
+                }}
+            }
+        });
+        quote!(#(#fields)*)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_keys() {
+        assert_eq!(camel_case("timeout"), "timeout");
+        assert_eq!(camel_case("max_fee_per_gas"), "maxFeePerGas");
+        assert_eq!(camel_case("r#type"), "type");
+        assert_eq!(camel_case("_private"), "private");
+    }
+
+    #[test]
+    fn type_names() {
+        assert_eq!(type_name(&parse_quote!(Tally)), "Tally");
+        assert_eq!(type_name(&parse_quote!(crate::Pool<'a, T>)), "Pool");
+        assert_eq!(type_name(&parse_quote!([u8; 4])), "[u8;4]");
+    }
+
+    #[test]
+    fn rename_all_keys() {
+        assert_eq!(
+            rename("max_fee_per_gas", RenameAll::Snake),
+            "max_fee_per_gas"
+        );
+        assert_eq!(rename("max_fee_per_gas", RenameAll::Pascal), "MaxFeePerGas");
+        assert_eq!(rename("max_fee_per_gas", RenameAll::Lower), "maxfeepergas");
+        assert_eq!(
+            rename("max_fee_per_gas", RenameAll::ScreamingSnake),
+            "MAX_FEE_PER_GAS"
+        );
+        assert_eq!(
+            rename("max_fee_per_gas", RenameAll::Kebab),
+            "max-fee-per-gas"
+        );
+        assert_eq!(rename("r#type", RenameAll::Upper), "TYPE");
+    }
+}
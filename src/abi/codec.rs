@@ -0,0 +1,302 @@
+use super::{ParamType, Token};
+use crate::errors::SafeErr;
+use primitive_types::U256;
+use std::iter;
+
+impl Token {
+    fn is_dynamic(&self) -> bool {
+        match self {
+            Token::Bytes(_) | Token::String(_) | Token::Array(_) => true,
+            Token::FixedArray(tokens) | Token::Tuple(tokens) => {
+                tokens.iter().any(Token::is_dynamic)
+            }
+            _ => false,
+        }
+    }
+
+    fn head_size(&self) -> usize {
+        match self {
+            _ if self.is_dynamic() => 32,
+            Token::FixedArray(tokens) | Token::Tuple(tokens) => {
+                tokens.iter().map(Token::head_size).sum()
+            }
+            _ => 32,
+        }
+    }
+}
+
+/// Encodes values as the parameters of a function, ie: calldata without
+/// the 4 byte selector. Tokens made by token_from_handle or decode_params
+/// always match their types. Others are encoded as they are, eg: an Int
+/// is not checked to fit in the intN it is meant for.
+pub fn encode_params(tokens: &[Token]) -> Vec<u8> {
+    encode_sequence(tokens)
+}
+
+fn encode_sequence(tokens: &[Token]) -> Vec<u8> {
+    let heads_len: usize = tokens.iter().map(Token::head_size).sum();
+    let mut head = Vec::with_capacity(heads_len);
+    let mut tail = Vec::new();
+    for token in tokens {
+        if token.is_dynamic() {
+            head.extend_from_slice(&word_usize(heads_len + tail.len()));
+            encode_token(token, &mut tail);
+        } else {
+            encode_token(token, &mut head);
+        }
+    }
+    head.extend(tail);
+    head
+}
+
+fn encode_token(token: &Token, out: &mut Vec<u8>) {
+    match token {
+        Token::Address(address) => {
+            out.extend_from_slice(&[0; 12]);
+            out.extend_from_slice(address);
+        }
+        Token::Bool(b) => out.extend_from_slice(&word_usize(*b as usize)),
+        Token::Uint(n) | Token::Int(n) => {
+            let mut word = [0; 32];
+            n.to_big_endian(&mut word);
+            out.extend_from_slice(&word);
+        }
+        Token::FixedBytes(bytes) => pad_right(bytes, out),
+        Token::Bytes(bytes) => {
+            out.extend_from_slice(&word_usize(bytes.len()));
+            pad_right(bytes, out);
+        }
+        Token::String(s) => {
+            out.extend_from_slice(&word_usize(s.len()));
+            pad_right(s.as_bytes(), out);
+        }
+        Token::Array(tokens) => {
+            out.extend_from_slice(&word_usize(tokens.len()));
+            out.extend(encode_sequence(tokens));
+        }
+        Token::FixedArray(tokens) | Token::Tuple(tokens) => out.extend(encode_sequence(tokens)),
+    }
+}
+
+fn word_usize(n: usize) -> [u8; 32] {
+    let mut word = [0; 32];
+    word[24..].copy_from_slice(&(n as u64).to_be_bytes());
+    word
+}
+
+fn pad_right(bytes: &[u8], out: &mut Vec<u8>) {
+    out.extend_from_slice(bytes);
+    let padding = (32 - bytes.len() % 32) % 32;
+    out.resize(out.len() + padding, 0);
+}
+
+/// Decodes the parameters of a function, ie: calldata without the
+/// 4 byte selector, or the data returned by a call.
+pub fn decode_params(types: &[ParamType], data: impl AsRef<[u8]>) -> Result<Vec<Token>, SafeErr> {
+    decode_sequence(types.iter(), data.as_ref(), "parameter")
+}
+
+fn decode_sequence<'t>(
+    types: impl Iterator<Item = &'t ParamType>,
+    data: &[u8],
+    what: &str,
+) -> Result<Vec<Token>, SafeErr> {
+    let mut offset = 0;
+    let mut tokens = Vec::new();
+    for (i, param) in types.enumerate() {
+        let token = if param.is_dynamic() {
+            read_offset(data, offset).and_then(|at| decode_token(param, &data[at..]))
+        } else {
+            data.get(offset..)
+                .ok_or_else(too_short)
+                .and_then(|data| decode_token(param, data))
+        };
+        tokens.push(token.map_err(|e| e.with_context(format!("Invalid {} at index {}", what, i)))?);
+        offset = offset.saturating_add(param.head_size());
+    }
+    Ok(tokens)
+}
+
+fn decode_token(param: &ParamType, data: &[u8]) -> Result<Token, SafeErr> {
+    param.check_size()?;
+    Ok(match param {
+        ParamType::Address => {
+            let word = read_word(data, 0)?;
+            if word[..12].iter().any(|b| *b != 0) {
+                return Err("Invalid address padding".into());
+            }
+            let mut address = [0; 20];
+            address.copy_from_slice(&word[12..]);
+            Token::Address(address)
+        }
+        ParamType::Bool => {
+            let word = read_word(data, 0)?;
+            match (word[..31].iter().all(|b| *b == 0), word[31]) {
+                (true, 0) => Token::Bool(false),
+                (true, 1) => Token::Bool(true),
+                _ => return Err("Invalid bool".into()),
+            }
+        }
+        ParamType::Uint(bits) => {
+            let n = U256::from_big_endian(read_word(data, 0)?);
+            if *bits < 256 && !(n >> *bits).is_zero() {
+                return Err(format!("Value out of range for uint{}", bits).into());
+            }
+            Token::Uint(n)
+        }
+        ParamType::Int(bits) => {
+            let n = U256::from_big_endian(read_word(data, 0)?);
+            // Everything above the sign bit must match it
+            let high = n >> (*bits - 1);
+            if *bits < 256 && !high.is_zero() && high != U256::MAX >> (*bits - 1) {
+                return Err(format!("Value out of range for int{}", bits).into());
+            }
+            Token::Int(n)
+        }
+        ParamType::FixedBytes(len) => Token::FixedBytes(read_word(data, 0)?[..*len].to_vec()),
+        ParamType::Bytes => Token::Bytes(read_bytes(data)?.to_vec()),
+        ParamType::String => {
+            let s = String::from_utf8(read_bytes(data)?.to_vec()).map_err(|_| "Invalid UTF-8")?;
+            Token::String(s)
+        }
+        ParamType::Array(param) => {
+            let len = read_usize(data, 0)?;
+            // Every element takes at least a word, which bounds len by the data
+            if len > (data.len() - 32) / 32 {
+                return Err(too_short());
+            }
+            let tokens = decode_sequence(iter::repeat_n(&**param, len), &data[32..], "element")?;
+            Token::Array(tokens)
+        }
+        ParamType::FixedArray(param, len) => Token::FixedArray(decode_sequence(
+            iter::repeat_n(&**param, *len),
+            data,
+            "element",
+        )?),
+        ParamType::Tuple(params) => Token::Tuple(decode_sequence(params.iter(), data, "field")?),
+    })
+}
+
+fn too_short() -> SafeErr {
+    "Data too short".into()
+}
+
+fn read_word(data: &[u8], at: usize) -> Result<&[u8], SafeErr> {
+    data.get(at..at.saturating_add(32)).ok_or_else(too_short)
+}
+
+fn read_usize(data: &[u8], at: usize) -> Result<usize, SafeErr> {
+    let n = U256::from_big_endian(read_word(data, at)?);
+    if n > U256::from(usize::MAX) {
+        return Err("Offset or length out of range".into());
+    }
+    Ok(n.as_usize())
+}
+
+fn read_offset(data: &[u8], at: usize) -> Result<usize, SafeErr> {
+    let offset = read_usize(data, at)?;
+    if offset > data.len() {
+        return Err("Offset out of range".into());
+    }
+    Ok(offset)
+}
+
+fn read_bytes(data: &[u8]) -> Result<&[u8], SafeErr> {
+    let len = read_usize(data, 0)?;
+    data.get(32..32usize.saturating_add(len))
+        .ok_or_else(too_short)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parse_params;
+    use super::*;
+    use crate::marshalling::codecs::decode;
+
+    fn hex(s: &str) -> Vec<u8> {
+        decode(s.split_whitespace().collect::<String>()).unwrap()
+    }
+
+    #[test]
+    fn static_and_dynamic() {
+        // From the examples in the Solidity docs: f(uint256,uint32[],bytes10,bytes)
+        // with (0x123, [0x456, 0x789], "1234567890", "Hello, world!")
+        let types =
+            parse_params("uint256,uint32[],bytes10,bytes").unwrap_or_else(|e| panic!("{}", e));
+        let tokens = vec![
+            Token::Uint(0x123.into()),
+            Token::Array(vec![Token::Uint(0x456.into()), Token::Uint(0x789.into())]),
+            Token::FixedBytes(b"1234567890".to_vec()),
+            Token::Bytes(b"Hello, world!".to_vec()),
+        ];
+        let encoded = hex(
+            "0000000000000000000000000000000000000000000000000000000000000123
+             0000000000000000000000000000000000000000000000000000000000000080
+             3132333435363738393000000000000000000000000000000000000000000000
+             00000000000000000000000000000000000000000000000000000000000000e0
+             0000000000000000000000000000000000000000000000000000000000000002
+             0000000000000000000000000000000000000000000000000000000000000456
+             0000000000000000000000000000000000000000000000000000000000000789
+             000000000000000000000000000000000000000000000000000000000000000d
+             48656c6c6f2c20776f726c642100000000000000000000000000000000000000",
+        );
+        assert_eq!(encode_params(&tokens), encoded);
+        assert_eq!(
+            decode_params(&types, &encoded).unwrap_or_else(|e| panic!("{}", e)),
+            tokens
+        );
+    }
+
+    #[test]
+    fn nested() {
+        let types =
+            parse_params("(string,int8)[2],bool,address").unwrap_or_else(|e| panic!("{}", e));
+        let tokens = vec![
+            Token::FixedArray(vec![
+                Token::Tuple(vec![Token::String("a".into()), Token::Int(U256::MAX)]),
+                Token::Tuple(vec![Token::String("".into()), Token::Int(127.into())]),
+            ]),
+            Token::Bool(true),
+            Token::Address([0x11; 20]),
+        ];
+        let encoded = encode_params(&tokens);
+        assert_eq!(
+            decode_params(&types, &encoded).unwrap_or_else(|e| panic!("{}", e)),
+            tokens
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        let int8 = parse_params("int8").unwrap_or_else(|e| panic!("{}", e));
+        let mut word = [0u8; 32];
+        word[31] = 128;
+        assert!(decode_params(&int8, word).is_err());
+        let bool_ = parse_params("bool").unwrap_or_else(|e| panic!("{}", e));
+        word[31] = 2;
+        assert!(decode_params(&bool_, word).is_err());
+        let array = parse_params("uint256[]").unwrap_or_else(|e| panic!("{}", e));
+        // An offset to a length far larger than the data
+        let mut data = word_usize(32).to_vec();
+        data.extend_from_slice(&word_usize(1 << 40));
+        assert!(decode_params(&array, &data).is_err());
+        assert!(decode_params(&array, &data[..16]).is_err());
+    }
+
+    #[test]
+    fn invalid_sizes() {
+        let word = [0u8; 32];
+        for param in [
+            ParamType::Int(0),
+            ParamType::Int(7),
+            ParamType::Uint(264),
+            ParamType::FixedBytes(33),
+        ] {
+            let e = decode_params(&[param.clone()], word).err().unwrap();
+            assert_eq!(
+                e.to_string(),
+                format!("Invalid parameter at index 0: Invalid type {}", param)
+            );
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use super::{decode_params, encode_params, parse_params, ParamType, Token};
+use crate::errors::{SafeJsResult, SafeResult, SafeResultExt, Terminal};
+use crate::marshalling::codecs::decode;
+use crate::marshalling::{with_str, Arg, FromHandle, HexBytes, IntoHandle};
+use neon::prelude::*;
+use primitive_types::U256;
+use rustc_hex::ToHex as _;
+
+// Addresses and bytes are 0x prefixed hex, integers are decimal strings
+// (as for U256), and arrays and tuples are Arrays.
+impl IntoHandle for Token {
+    type Handle = JsValue;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        Ok(match self {
+            Token::Address(address) => address.into_handle(cx)?.upcast(),
+            Token::Bool(b) => cx.boolean(*b).upcast(),
+            Token::Uint(n) => n.into_handle(cx)?.upcast(),
+            Token::Int(n) => cx.string(signed(*n)).upcast(),
+            Token::FixedBytes(bytes) | Token::Bytes(bytes) => {
+                let hex: String = bytes.to_hex();
+                cx.string(format!("0x{}", hex)).upcast()
+            }
+            Token::String(s) => cx.string(s).upcast(),
+            Token::Array(tokens) | Token::FixedArray(tokens) | Token::Tuple(tokens) => {
+                tokens.into_handle(cx)?.upcast()
+            }
+        })
+    }
+}
+
+/// Accepts one type, eg: "uint256"
+impl FromHandle for ParamType {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        let s = String::from_handle(handle, cx)?;
+        Ok(ParamType::parse(&s)?)
+    }
+}
+
+/// Reads a JS value as a Token of the given type. Integers may be
+/// numbers or strings, and bytes anything HexBytes accepts.
+pub fn token_from_handle<'a, V: Value>(
+    param: &ParamType,
+    handle: Handle<V>,
+    cx: &mut impl Context<'a>,
+) -> SafeResult<Token> {
+    param.check_size()?;
+    Ok(match param {
+        ParamType::Address => Token::Address(FromHandle::from_handle(handle, cx)?),
+        ParamType::Bool => Token::Bool(FromHandle::from_handle(handle, cx)?),
+        ParamType::Uint(bits) => {
+            let n = U256::from_handle(handle, cx)?;
+            if *bits < 256 && !(n >> *bits).is_zero() {
+                return Err(format!("Value out of range for uint{}", bits).into());
+            }
+            Token::Uint(n)
+        }
+        ParamType::Int(bits) => Token::Int(int_from_handle(handle, cx, *bits)?),
+        ParamType::FixedBytes(len) => {
+            let bytes = HexBytes::from_handle(handle, cx)?.0;
+            if bytes.len() != *len {
+                return Err(format!("Expected {} bytes, got {}", len, bytes.len()).into());
+            }
+            Token::FixedBytes(bytes)
+        }
+        ParamType::Bytes => Token::Bytes(HexBytes::from_handle(handle, cx)?.0),
+        ParamType::String => Token::String(FromHandle::from_handle(handle, cx)?),
+        ParamType::Array(param) => {
+            let items: Vec<Handle<JsValue>> = items(handle, cx, None)?;
+            Token::Array(tokens_from_handles(param, items, cx, "element")?)
+        }
+        ParamType::FixedArray(param, len) => {
+            let items = items(handle, cx, Some(*len))?;
+            Token::FixedArray(tokens_from_handles(param, items, cx, "element")?)
+        }
+        ParamType::Tuple(params) => {
+            let items = items(handle, cx, Some(params.len()))?;
+            let mut tokens = Vec::with_capacity(items.len());
+            for (i, (param, item)) in params.iter().zip(items).enumerate() {
+                let token = token_from_handle(param, item, cx)
+                    .with_context(|| format!("Invalid field at index {}", i))?;
+                tokens.push(token);
+            }
+            Token::Tuple(tokens)
+        }
+    })
+}
+
+fn items<'a, 'h, V: Value>(
+    handle: Handle<'h, V>,
+    cx: &mut impl Context<'a>,
+    len: Option<usize>,
+) -> SafeResult<Vec<Handle<'a, JsValue>>> {
+    let array = handle.downcast::<JsArray>().map_err(|_| "Expected array")?;
+    let items = array.to_vec(cx)?;
+    match len {
+        Some(len) if items.len() != len => {
+            Err(format!("Expected {} items, got {}", len, items.len()).into())
+        }
+        _ => Ok(items),
+    }
+}
+
+fn tokens_from_handles<'a>(
+    param: &ParamType,
+    items: Vec<Handle<JsValue>>,
+    cx: &mut impl Context<'a>,
+    what: &str,
+) -> SafeResult<Vec<Token>> {
+    let mut tokens = Vec::with_capacity(items.len());
+    for (i, item) in items.into_iter().enumerate() {
+        let token = token_from_handle(param, item, cx)
+            .with_context(|| format!("Invalid {} at index {}", what, i))?;
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+// Accepts what i128 does, but with the range of intN
+fn int_from_handle<'a, V: Value>(
+    handle: Handle<V>,
+    cx: &mut impl Context<'a>,
+    bits: usize,
+) -> SafeResult<U256> {
+    let (negative, magnitude) = if handle.is_a::<JsString>() {
+        let (negative, magnitude): (bool, Result<U256, ()>) =
+            with_str(handle, cx, |s| match s.strip_prefix('-') {
+                Some(digits) => (true, decode(digits)),
+                None => (false, decode(s)),
+            })?;
+        let magnitude = magnitude.map_err(|_| format!("Failed to parse int{}", bits))?;
+        (negative, magnitude)
+    } else {
+        let n = i128::from_handle(handle, cx)?;
+        (n < 0, U256::from(n.unsigned_abs()))
+    };
+    // The magnitude may be up to 2^(bits - 1), or one less if positive
+    let limit = U256::one() << (bits - 1);
+    if magnitude > limit || (!negative && magnitude == limit) {
+        return Err(format!("Value out of range for int{}", bits).into());
+    }
+    Ok(if negative {
+        (!magnitude).overflowing_add(U256::one()).0
+    } else {
+        magnitude
+    })
+}
+
+// Two's complement to a decimal string
+fn signed(n: U256) -> String {
+    if n.bit(255) {
+        format!("-{}", (!n).overflowing_add(U256::one()).0)
+    } else {
+        n.to_string()
+    }
+}
+
+// The types for the JS functions may be a comma separated string, or an Array of types
+fn params_arg(cx: &mut FunctionContext) -> SafeResult<Vec<ParamType>> {
+    let types = cx.argument::<JsValue>(0)?;
+    if let Ok(types) = types.downcast::<JsString>() {
+        return Ok(parse_params(&types.value())?);
+    }
+    Vec::<ParamType>::from_handle(types, cx)
+}
+
+fn encode(cx: &mut FunctionContext) -> SafeResult<String> {
+    let params = params_arg(cx)?;
+    let values = cx.argument::<JsValue>(1)?;
+    let values = items(values, cx, Some(params.len()))?;
+    let mut tokens = Vec::with_capacity(values.len());
+    for (i, (param, value)) in params.iter().zip(values).enumerate() {
+        let token = token_from_handle(param, value, cx)
+            .with_context(|| format!("Invalid parameter at index {}", i))?;
+        tokens.push(token);
+    }
+    let hex: String = encode_params(&tokens).to_hex();
+    Ok(format!("0x{}", hex))
+}
+
+fn decode_js(cx: &mut FunctionContext) -> SafeResult<Vec<Token>> {
+    let params = params_arg(cx)?;
+    let data: HexBytes = cx.arg(1)?;
+    Ok(decode_params(&params, &data.0)?)
+}
+
+// The Neon functions below can be exported as is, eg:
+// cx.export_function("encodeParams", abi::js_encode_params)
+
+/// encodeParams(types, values) returns the encoding as 0x prefixed hex.
+/// types is eg: "address,uint256" or ["address", "uint256"].
+pub fn js_encode_params(mut cx: FunctionContext) -> JsResult<JsString> {
+    let encoded = encode(&mut cx);
+    encoded.finish(cx)
+}
+
+/// decodeParams(types, data) returns an Array of values. data is
+/// anything HexBytes accepts.
+pub fn js_decode_params(mut cx: FunctionContext) -> JsResult<JsArray> {
+    let decoded = decode_js(&mut cx);
+    decoded.finish(cx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signed_decimal() {
+        assert_eq!(signed(U256::MAX), "-1");
+        assert_eq!(signed(U256::from(5)), "5");
+        assert_eq!(
+            signed(U256::one() << 255),
+            format!("-{}", U256::one() << 255)
+        );
+    }
+}
@@ -0,0 +1,291 @@
+//! Solidity ABI encoding of function parameters and return values, eg: for
+//! calldata. See https://docs.soliditylang.org/en/latest/abi-spec.html
+
+mod codec;
+mod js;
+
+pub use codec::{decode_params, encode_params};
+pub use js::{js_decode_params, js_encode_params, token_from_handle};
+
+use crate::errors::SafeErr;
+use primitive_types::U256;
+use std::fmt;
+
+/// A Solidity type, as written in a function signature
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ParamType {
+    Address,
+    Bool,
+    /// uintN, with the size in bits
+    Uint(usize),
+    /// intN, with the size in bits
+    Int(usize),
+    /// bytesN, with the size in bytes
+    FixedBytes(usize),
+    Bytes,
+    String,
+    /// T[]
+    Array(Box<ParamType>),
+    /// T[N]
+    FixedArray(Box<ParamType>, usize),
+    /// (T1,T2,...)
+    Tuple(Vec<ParamType>),
+}
+
+/// A value of some ParamType
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Token {
+    Address([u8; 20]),
+    Bool(bool),
+    Uint(U256),
+    /// In two's complement
+    Int(U256),
+    FixedBytes(Vec<u8>),
+    Bytes(Vec<u8>),
+    String(String),
+    Array(Vec<Token>),
+    FixedArray(Vec<Token>),
+    Tuple(Vec<Token>),
+}
+
+impl ParamType {
+    /// Parses one type, eg: "uint256" or "(address,bytes)[]"
+    pub fn parse(s: &str) -> Result<Self, SafeErr> {
+        let mut parser = Parser { s, at: 0 };
+        let param = parser.param()?;
+        parser.end()?;
+        Ok(param)
+    }
+
+    /// Whether values are encoded after the static part, with an offset in its place
+    pub fn is_dynamic(&self) -> bool {
+        match self {
+            ParamType::Bytes | ParamType::String | ParamType::Array(_) => true,
+            ParamType::FixedArray(param, _) => param.is_dynamic(),
+            ParamType::Tuple(params) => params.iter().any(ParamType::is_dynamic),
+            _ => false,
+        }
+    }
+
+    // ParamType can be built by hand with sizes parse would reject, eg:
+    // Int(0), which would underflow when finding the sign bit
+    fn check_size(&self) -> Result<(), SafeErr> {
+        let valid = match self {
+            ParamType::Uint(bits) | ParamType::Int(bits) => valid_bits(*bits),
+            ParamType::FixedBytes(len) => valid_len(*len),
+            _ => true,
+        };
+        if !valid {
+            return Err(format!("Invalid type {}", self).into());
+        }
+        Ok(())
+    }
+
+    // The bytes taken in the static part
+    fn head_size(&self) -> usize {
+        match self {
+            _ if self.is_dynamic() => 32,
+            ParamType::FixedArray(param, len) => param.head_size().saturating_mul(*len),
+            ParamType::Tuple(params) => params.iter().map(ParamType::head_size).sum(),
+            _ => 32,
+        }
+    }
+}
+
+/// Parses a comma separated list of types, eg: "address,uint256[]".
+/// An empty string is an empty list.
+pub fn parse_params(s: &str) -> Result<Vec<ParamType>, SafeErr> {
+    let mut parser = Parser { s, at: 0 };
+    let params = parser.list(None)?;
+    parser.end()?;
+    Ok(params)
+}
+
+/// Writes the canonical form, as used to compute function selectors
+impl fmt::Display for ParamType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamType::Address => write!(f, "address"),
+            ParamType::Bool => write!(f, "bool"),
+            ParamType::Uint(bits) => write!(f, "uint{}", bits),
+            ParamType::Int(bits) => write!(f, "int{}", bits),
+            ParamType::FixedBytes(len) => write!(f, "bytes{}", len),
+            ParamType::Bytes => write!(f, "bytes"),
+            ParamType::String => write!(f, "string"),
+            ParamType::Array(param) => write!(f, "{}[]", param),
+            ParamType::FixedArray(param, len) => write!(f, "{}[{}]", param, len),
+            ParamType::Tuple(params) => {
+                write!(f, "(")?;
+                for (i, param) in params.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", param)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+struct Parser<'s> {
+    s: &'s str,
+    at: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&mut self) -> Option<char> {
+        self.s[self.at..].trim_start().chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let rest = self.s[self.at..].trim_start();
+        if rest.starts_with(c) {
+            self.at = self.s.len() - rest.len() + c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn word(&mut self, f: fn(char) -> bool) -> &str {
+        let rest = self.s[self.at..].trim_start();
+        let start = self.s.len() - rest.len();
+        let len = rest.find(|c| !f(c)).unwrap_or(rest.len());
+        self.at = start + len;
+        &self.s[start..self.at]
+    }
+
+    fn unexpected(&mut self) -> SafeErr {
+        match self.peek() {
+            Some(c) => format!("Unexpected '{}' in type '{}'", c, self.s).into(),
+            None => format!("Unexpected end of type '{}'", self.s).into(),
+        }
+    }
+
+    fn end(&mut self) -> Result<(), SafeErr> {
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(self.unexpected()),
+        }
+    }
+
+    // Types separated by commas, up to close (which is consumed) or the end
+    fn list(&mut self, close: Option<char>) -> Result<Vec<ParamType>, SafeErr> {
+        let mut params = Vec::new();
+        if self.peek() != close {
+            loop {
+                params.push(self.param()?);
+                if !self.eat(',') {
+                    break;
+                }
+            }
+        }
+        match close {
+            Some(close) if !self.eat(close) => Err(self.unexpected()),
+            _ => Ok(params),
+        }
+    }
+
+    fn param(&mut self) -> Result<ParamType, SafeErr> {
+        let word = self.word(|c| c.is_ascii_alphanumeric()).to_owned();
+        let mut param = if (word.is_empty() || word == "tuple") && self.eat('(') {
+            let params = self.list(Some(')'))?;
+            if params.is_empty() {
+                return Err("Empty tuples are not allowed".into());
+            }
+            ParamType::Tuple(params)
+        } else if word.is_empty() {
+            return Err(self.unexpected());
+        } else {
+            elementary(&word)?
+        };
+        while self.eat('[') {
+            let len = self.word(|c| c.is_ascii_digit());
+            param = if len.is_empty() {
+                ParamType::Array(Box::new(param))
+            } else {
+                match len.parse() {
+                    Ok(len) if len != 0 => ParamType::FixedArray(Box::new(param), len),
+                    _ => return Err(format!("Invalid array length '{}'", len).into()),
+                }
+            };
+            if !self.eat(']') {
+                return Err(self.unexpected());
+            }
+        }
+        Ok(param)
+    }
+}
+
+// Of intN and uintN: whole bytes, up to a word
+fn valid_bits(n: usize) -> bool {
+    n.is_multiple_of(8) && (8..=256).contains(&n)
+}
+
+// Of bytesN
+fn valid_len(n: usize) -> bool {
+    (1..=32).contains(&n)
+}
+
+fn elementary(word: &str) -> Result<ParamType, SafeErr> {
+    // Eg: "256" but not "0256"
+    let sized = |digits: &str, valid: fn(usize) -> bool| match digits.parse::<usize>() {
+        Ok(size) if valid(size) && size.to_string() == digits => Some(size),
+        _ => None,
+    };
+    let param = match word {
+        "address" => Some(ParamType::Address),
+        "bool" => Some(ParamType::Bool),
+        "string" => Some(ParamType::String),
+        "bytes" => Some(ParamType::Bytes),
+        "uint" => Some(ParamType::Uint(256)),
+        "int" => Some(ParamType::Int(256)),
+        // An address and selector
+        "function" => Some(ParamType::FixedBytes(24)),
+        _ => {
+            if let Some(size) = word.strip_prefix("uint") {
+                sized(size, valid_bits).map(ParamType::Uint)
+            } else if let Some(size) = word.strip_prefix("int") {
+                sized(size, valid_bits).map(ParamType::Int)
+            } else if let Some(size) = word.strip_prefix("bytes") {
+                sized(size, valid_len).map(ParamType::FixedBytes)
+            } else {
+                None
+            }
+        }
+    };
+    param.ok_or_else(|| format!("Unknown type '{}'", word).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let params = parse_params("address, uint[] ,(bytes32,string)[2], tuple(bool)")
+            .unwrap_or_else(|e| panic!("{}", e));
+        let canonical: Vec<String> = params.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            canonical,
+            ["address", "uint256[]", "(bytes32,string)[2]", "(bool)"]
+        );
+        assert!(params[2].is_dynamic());
+        assert!(!params[3].is_dynamic());
+        assert_eq!(parse_params("").unwrap_or_else(|e| panic!("{}", e)), vec![]);
+        assert_eq!(
+            ParamType::parse("int8").unwrap_or_else(|e| panic!("{}", e)),
+            ParamType::Int(8)
+        );
+
+        for invalid in [
+            "uint7", "uint264", "uint08", "bytes0", "bytes33", "()", "uint[0]", "(uint", "uint]",
+            "address,", "foo",
+        ]
+        .iter()
+        {
+            assert!(parse_params(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! A JS function which Rust can call from any thread, eg: for pull style
+//! integrations where a background thread asks JS for the next page.
+
+use crate::channel::{self, Slot};
+use crate::errors::MaybeThrown;
+#[cfg(feature = "tokio")]
+use crate::marshalling::FromHandle;
+#[cfg(feature = "tokio")]
+use crate::promise::{self, PromiseFuture};
+use crate::registry::IntoArgs;
+use crate::uncaught;
+use neon::prelude::*;
+use std::sync::Arc;
+
+/// Keeps a JS function alive until every clone is dropped. Calls run on
+/// the JS thread in the order made, like everything else sent back to JS.
+#[derive(Clone)]
+pub struct JsCallback {
+    slot: Arc<Slot>,
+}
+
+impl JsCallback {
+    pub fn new<'a>(cx: &mut impl Context<'a>, f: Handle<JsFunction>) -> NeonResult<Self> {
+        Ok(Self {
+            slot: Arc::new(channel::hold(cx, f)?),
+        })
+    }
+
+    /// Calls it without waiting, eg: callback.call((block, hash))
+    /// What it throws is logged, since there is nowhere else for it to go.
+    pub fn call<A: 'static + Send + IntoArgs>(&self, args: A) {
+        self.slot.schedule(move |cx, f| {
+            let called = cx.try_catch(|cx| match args.into_args(cx) {
+                Ok(args) => {
+                    let f: Handle<JsFunction> = f.downcast_or_throw(cx)?;
+                    let this = cx.undefined();
+                    f.call(cx, this, args)
+                }
+                Err(MaybeThrown::Thrown(t)) => Err(t),
+                Err(MaybeThrown::Unthrown(e)) => {
+                    tracing::warn!(error = %e, "Dropped callback arguments");
+                    Ok(cx.undefined().upcast())
+                }
+            });
+            if let Err(thrown) = called {
+                uncaught::report(cx, thrown, "Callback");
+            }
+        });
+    }
+
+    /// Calls it and gets back what it returns, decoded as T. A returned
+    /// Promise is waited for, so JS may answer with an async function.
+    /// Await the result, eg: in task::run_future.
+    ///
+    /// Throwing or rejecting is an error with the message, eg:
+    /// "Callback threw: No more pages". If Node shuts down before the
+    /// call is made that is an error too, rather than waiting forever.
+    #[cfg(feature = "tokio")]
+    pub fn call_for<T, A>(&self, args: A) -> PromiseFuture<T>
+    where
+        T: 'static + Send + FromHandle,
+        A: 'static + Send + IntoArgs,
+    {
+        let (future, settler) = promise::oneshot();
+        self.slot.schedule(move |cx, f| {
+            let returned = cx.try_catch(|cx| {
+                let args = match args.into_args(cx) {
+                    Ok(args) => args,
+                    Err(MaybeThrown::Thrown(t)) => return Err(t),
+                    Err(MaybeThrown::Unthrown(e)) => {
+                        return Ok(Err(format!("Failed to encode callback arguments: {}", e)))
+                    }
+                };
+                let f: Handle<JsFunction> = f.downcast_or_throw(cx)?;
+                let this = cx.undefined();
+                f.call(cx, this, args).map(Ok)
+            });
+            match returned {
+                Ok(Ok(value)) => {
+                    // Throws only if Promise itself is broken, which drops settler
+                    let _ = cx.try_catch(|cx| promise::settle_when_resolved(cx, value, settler));
+                }
+                Ok(Err(e)) => settler.settle(Err(e)),
+                Err(thrown) => {
+                    let message = promise::reason_message(cx, thrown);
+                    settler.settle(Err(format!("Callback threw: {}", message)));
+                }
+            }
+        });
+        future
+    }
+}
@@ -0,0 +1,177 @@
+//! One EventHandler shared by everything that calls back into JS from
+//! other threads, instead of one per call.
+//!
+//! The legacy runtime has no Channel. The closest thing is an EventHandler,
+//! and each one costs a uv_async handle plus persistent handles to create
+//! and tear down. Under heavy call rates that churn adds up, so calls hold
+//! a Slot in a shared handler instead. The JS values a call needs later
+//! (eg: its callback) are kept in a registry object until the Slot drops.
+//!
+//! A live EventHandler keeps Node running, so the shared one is dropped
+//! once no Slots are left and made again when next needed.
+//!
+//! Scheduling on an EventHandler after its environment is torn down aborts,
+//! so the handler is closed when the environment shuts down. Anything
+//! scheduled after that is dropped.
+
+use crate::shutdown;
+use neon::event::EventHandler;
+use neon::prelude::*;
+use std::cell::RefCell;
+use std::sync::{Arc, RwLock};
+
+struct Shared {
+    line: Arc<Line>,
+    slots: usize,
+    next_id: u32,
+}
+
+// The shared handler, or None once closed
+struct Line(RwLock<Option<EventHandler>>);
+
+impl Line {
+    fn schedule<F>(&self, f: F)
+    where
+        F: 'static + Send + FnOnce(&mut TaskContext, Handle<JsValue>, Handle<JsFunction>),
+    {
+        // Held while scheduling, so that close waits for sends in progress
+        let handler = self.0.read().unwrap_or_else(|e| e.into_inner());
+        if let Some(handler) = handler.as_ref() {
+            handler.schedule_with(f);
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).is_none()
+    }
+}
+
+thread_local! {
+    // Only used from the JS thread
+    static SHARED: RefCell<Option<Shared>> = const { RefCell::new(None) };
+}
+
+// The registry hangs off the global object so that a Slot can be filled in
+// from the JS thread without a handle to it. Symbol.for keeps it out of the way.
+fn registry<'a>(cx: &mut impl Context<'a>) -> NeonResult<Handle<'a, JsObject>> {
+    let global = cx.global();
+    let symbol: Handle<JsObject> = global.get(cx, "Symbol")?;
+    let symbol_for: Handle<JsFunction> = symbol.get(cx, "for")?;
+    let name = cx.string("neon-utils.channel").upcast();
+    let key: Handle<JsValue> = symbol_for.call(cx, symbol, vec![name])?;
+    let existing: Handle<JsValue> = global.get(cx, key)?;
+    if let Ok(registry) = existing.downcast::<JsObject>() {
+        return Ok(registry);
+    }
+    let registry = cx.empty_object();
+    global.set(cx, key, registry)?;
+    Ok(registry)
+}
+
+pub(crate) fn ignore(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    Ok(cx.undefined())
+}
+
+/// Keeps value alive until the Slot drops, and gets back to the
+/// JS thread with it from any thread.
+pub(crate) fn hold<'a, V: Value>(cx: &mut impl Context<'a>, value: Handle<V>) -> NeonResult<Slot> {
+    // Also starts listening for shutdown, before there is anything to close
+    shutdown::token(cx)?;
+    if shutdown::is_shutting_down() {
+        return cx.throw_error("Cannot start work while shutting down");
+    }
+    let registry = registry(cx)?;
+    if SHARED.with(|shared| shared.borrow().is_none()) {
+        let ignore = JsFunction::new(cx, ignore)?;
+        let handler = EventHandler::new(&*cx, registry, ignore);
+        SHARED.with(|shared| {
+            *shared.borrow_mut() = Some(Shared {
+                line: Arc::new(Line(RwLock::new(Some(handler)))),
+                slots: 0,
+                next_id: 0,
+            })
+        });
+    }
+    let (line, id) = SHARED.with(|shared| {
+        let mut shared = shared.borrow_mut();
+        let shared = shared.as_mut().unwrap();
+        let id = shared.next_id;
+        shared.next_id = shared.next_id.wrapping_add(1);
+        shared.slots += 1;
+        (shared.line.clone(), id)
+    });
+    registry.set(cx, id, value)?;
+    Ok(Slot { line, id })
+}
+
+/// Closes the shared handler for good. Called on the JS thread as the
+/// environment shuts down.
+pub(crate) fn close() {
+    let shared = SHARED.with(|shared| shared.borrow_mut().take());
+    if let Some(shared) = shared {
+        shared
+            .line
+            .0
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+    }
+}
+
+pub(crate) struct Slot {
+    line: Arc<Line>,
+    id: u32,
+}
+
+impl Slot {
+    /// Calls f on the JS thread with the held value. Calls run in the
+    /// order they were scheduled, across every Slot. If the environment
+    /// has shut down f is dropped instead.
+    pub fn schedule<F>(&self, f: F)
+    where
+        F: 'static + Send + for<'a> FnOnce(&mut TaskContext<'a>, Handle<'a, JsValue>),
+    {
+        let id = self.id;
+        self.line.schedule(move |cx, registry, _ignore| {
+            let value = match registry.downcast::<JsObject>() {
+                Ok(registry) => registry.get(cx, id),
+                Err(_) => Ok(cx.undefined().upcast()),
+            };
+            if let Ok(value) = value {
+                f(cx, value);
+            }
+        });
+    }
+}
+
+impl Slot {
+    /// Whether the environment has shut down, so work for this
+    /// Slot may as well stop since nothing can be sent back.
+    pub fn is_closed(&self) -> bool {
+        self.line.is_closed()
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let id = self.id;
+        self.line.schedule(move |cx, registry, _ignore| {
+            let _ = cx.try_catch(|cx| {
+                let global = cx.global();
+                let reflect: Handle<JsObject> = global.get(cx, "Reflect")?;
+                let delete: Handle<JsFunction> = reflect.get(cx, "deleteProperty")?;
+                let id = cx.number(id).upcast();
+                delete.call(cx, reflect, vec![registry, id])
+            });
+            SHARED.with(|shared| {
+                let mut shared = shared.borrow_mut();
+                if let Some(s) = shared.as_mut() {
+                    s.slots -= 1;
+                    if s.slots == 0 {
+                        *shared = None;
+                    }
+                }
+            });
+        });
+    }
+}
@@ -0,0 +1,220 @@
+//! Settings for the crate as a whole, loaded once from environment
+//! variables and optionally overridden by JS calling configure(options)
+//! before anything else. Eg: NEON_UTILS_MAX_BYTES=1048576 node app.js
+//!
+//! The size of the libuv pool which run_async uses isn't here: libuv reads
+//! UV_THREADPOOL_SIZE once, and changing the environment of a running
+//! process isn't safe. Set it before Node starts, eg:
+//! UV_THREADPOOL_SIZE=8 node app.js
+
+use crate::errors::{SafeErr, SafeResult, Terminal};
+use crate::marshalling::{HexFormat, MarshalLimits, ObjectExt};
+use crate::profiling;
+use neon::prelude::*;
+use std::sync::OnceLock;
+use tracing_subscriber::filter::LevelFilter;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NeonUtilsConfig {
+    /// What each JS thread starts with, see marshalling::limits
+    pub limits: MarshalLimits,
+    /// What logging::init starts with
+    pub log_level: LevelFilter,
+    /// How HexBytes are written
+    pub hex_format: HexFormat,
+    /// How many hex encodings of byte arrays (eg: H256) and of checksummed
+    /// Addresses to keep, for values which repeat. 0 keeps none.
+    pub intern_capacity: usize,
+    /// Whether to count values of the wrong type, see profiling::marshal_stats.
+    /// This and intern_capacity can be changed later, see profiling.
+    pub marshal_stats: bool,
+}
+
+impl Default for NeonUtilsConfig {
+    fn default() -> Self {
+        Self {
+            limits: MarshalLimits::UNLIMITED,
+            log_level: LevelFilter::WARN,
+            hex_format: HexFormat::Plain,
+            intern_capacity: 0,
+            marshal_stats: false,
+        }
+    }
+}
+
+impl NeonUtilsConfig {
+    /// The defaults, overridden by whichever of NEON_UTILS_MAX_DEPTH, NEON_UTILS_MAX_ITEMS, NEON_UTILS_MAX_BYTES,
+    /// NEON_UTILS_LOG_LEVEL, NEON_UTILS_HEX_FORMAT,
+    /// NEON_UTILS_INTERN_CAPACITY and NEON_UTILS_MARSHAL_STATS are set
+    pub fn from_env() -> Result<Self, SafeErr> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, SafeErr> {
+        fn parse<T: std::str::FromStr>(
+            name: &str,
+            value: Option<String>,
+        ) -> Result<Option<T>, SafeErr> {
+            match value {
+                None => Ok(None),
+                Some(value) => match value.trim().parse() {
+                    Ok(value) => Ok(Some(value)),
+                    Err(_) => Err(format!("Invalid {} '{}'", name, value).into()),
+                },
+            }
+        }
+
+        let mut config = Self::default();
+        if let Some(depth) = parse("NEON_UTILS_MAX_DEPTH", var("NEON_UTILS_MAX_DEPTH"))? {
+            config.limits.max_depth = depth;
+        }
+        if let Some(items) = parse("NEON_UTILS_MAX_ITEMS", var("NEON_UTILS_MAX_ITEMS"))? {
+            config.limits.max_items = items;
+        }
+        if let Some(bytes) = parse("NEON_UTILS_MAX_BYTES", var("NEON_UTILS_MAX_BYTES"))? {
+            config.limits.max_bytes = bytes;
+        }
+        if let Some(level) = parse("NEON_UTILS_LOG_LEVEL", var("NEON_UTILS_LOG_LEVEL"))? {
+            config.log_level = level;
+        }
+        if let Some(format) = parse("NEON_UTILS_HEX_FORMAT", var("NEON_UTILS_HEX_FORMAT"))? {
+            config.hex_format = format;
+        }
+        if let Some(capacity) = parse(
+            "NEON_UTILS_INTERN_CAPACITY",
+            var("NEON_UTILS_INTERN_CAPACITY"),
+        )? {
+            config.intern_capacity = capacity;
+        }
+        if let Some(stats) = parse("NEON_UTILS_MARSHAL_STATS", var("NEON_UTILS_MARSHAL_STATS"))? {
+            config.marshal_stats = stats;
+        }
+        Ok(config)
+    }
+}
+
+static CONFIG: OnceLock<NeonUtilsConfig> = OnceLock::new();
+
+/// The config in use. The first call fixes it, loading it from the
+/// environment unless set was called first. Invalid environment variables
+/// are logged and the defaults used instead, since there is nobody to
+/// return an error to.
+pub fn get() -> &'static NeonUtilsConfig {
+    CONFIG.get_or_init(|| {
+        NeonUtilsConfig::from_env().unwrap_or_else(|e| {
+            tracing::warn!("Ignoring neon-utils environment: {}", e);
+            NeonUtilsConfig::default()
+        })
+    })
+}
+
+/// Fixes the config, which must happen before anything reads it
+pub fn set(config: NeonUtilsConfig) -> Result<(), SafeErr> {
+    let mut applied = false;
+    CONFIG.get_or_init(|| {
+        applied = true;
+        config
+    });
+    if applied {
+        Ok(())
+    } else {
+        Err("neon-utils is already configured. Call configure before anything else uses it".into())
+    }
+}
+
+/// configure({ maxDepth, maxItems, maxBytes, logLevel, hexFormat,
+/// internCapacity, marshalStats }).
+/// Anything left out is taken from the environment as usual. After the
+/// config is fixed, internCapacity and marshalStats can still be given on
+/// their own, which changes them from then on, eg: around only the tests
+/// which need them.
+pub fn js_configure(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let configured = (|| -> SafeResult<()> {
+        let mut config = NeonUtilsConfig::from_env()?;
+        let options: Handle<JsObject> = cx.argument(0)?;
+        let cx = &mut cx;
+        // Whether anything was given which can only be set once
+        let mut fixed = false;
+        if let Some(depth) = options.get_field::<Option<u64>>(cx, "maxDepth")? {
+            config.limits.max_depth = depth as usize;
+            fixed = true;
+        }
+        if let Some(items) = options.get_field::<Option<u64>>(cx, "maxItems")? {
+            config.limits.max_items = items as usize;
+            fixed = true;
+        }
+        if let Some(bytes) = options.get_field::<Option<u64>>(cx, "maxBytes")? {
+            config.limits.max_bytes = bytes as usize;
+            fixed = true;
+        }
+        if let Some(level) = options.get_field::<Option<String>>(cx, "logLevel")? {
+            config.log_level = level
+                .parse()
+                .map_err(|_| format!("Invalid log level '{}'", level))?;
+            fixed = true;
+        }
+        if let Some(format) = options.get_field::<Option<HexFormat>>(cx, "hexFormat")? {
+            config.hex_format = format;
+            fixed = true;
+        }
+        let capacity = options.get_field::<Option<u32>>(cx, "internCapacity")?;
+        if let Some(capacity) = capacity {
+            config.intern_capacity = capacity as usize;
+        }
+        let stats = options.get_field::<Option<bool>>(cx, "marshalStats")?;
+        if let Some(stats) = stats {
+            config.marshal_stats = stats;
+        }
+        match set(config) {
+            Ok(()) => Ok(()),
+            Err(e) if fixed || (capacity.is_none() && stats.is_none()) => Err(e.into()),
+            Err(_) => {
+                if let Some(capacity) = capacity {
+                    profiling::set_intern_capacity(capacity as usize);
+                }
+                if let Some(stats) = stats {
+                    profiling::set_marshal_stats(stats);
+                }
+                Ok(())
+            }
+        }
+    })();
+    configured.finish(cx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn from(vars: &[(&str, &str)]) -> Result<NeonUtilsConfig, SafeErr> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        NeonUtilsConfig::from_vars(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn from_vars() {
+        assert_eq!(from(&[]).ok(), Some(NeonUtilsConfig::default()));
+
+        let config = from(&[
+            ("NEON_UTILS_MAX_BYTES", "1024"),
+            ("NEON_UTILS_LOG_LEVEL", "debug"),
+            ("NEON_UTILS_HEX_FORMAT", "prefixed"),
+            ("NEON_UTILS_INTERN_CAPACITY", "512"),
+            ("NEON_UTILS_MARSHAL_STATS", "true"),
+        ])
+        .unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(config.limits.max_bytes, 1024);
+        assert_eq!(config.limits.max_items, usize::MAX);
+        assert_eq!(config.log_level, LevelFilter::DEBUG);
+        assert_eq!(config.hex_format, HexFormat::Prefixed);
+        assert_eq!(config.intern_capacity, 512);
+        assert!(config.marshal_stats);
+
+        let e = from(&[("NEON_UTILS_MAX_DEPTH", "deep")]).err().unwrap();
+        assert_eq!(e.to_string(), "Invalid NEON_UTILS_MAX_DEPTH 'deep'");
+    }
+}
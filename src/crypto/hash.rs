@@ -0,0 +1,76 @@
+use crate::errors::{SafeResult, Terminal};
+use crate::marshalling::{Arg, HexBytes};
+use crate::prelude::*;
+#[cfg(feature = "crypto")]
+use blake2::{digest::consts::U32, Blake2b};
+use neon::prelude::*;
+#[cfg(feature = "crypto")]
+use sha2::{Digest, Sha256};
+use tiny_keccak::{Hasher, Keccak};
+
+pub fn keccak256(data: &[u8]) -> Bytes32 {
+    let mut hasher = Keccak::v256();
+    hasher.update(data);
+    let mut output = [0; 32];
+    hasher.finalize(&mut output);
+    output
+}
+
+#[cfg(feature = "crypto")]
+pub fn sha256(data: &[u8]) -> Bytes32 {
+    Sha256::digest(data).into()
+}
+
+/// Blake2b with a 32 byte digest, not the default of 64
+#[cfg(feature = "crypto")]
+pub fn blake2b(data: &[u8]) -> Bytes32 {
+    Blake2b::<U32>::digest(data).into()
+}
+
+// The Neon functions below take one argument of anything HexBytes
+// accepts (hex string, Buffer, ArrayBuffer) and return a hex string.
+// They can be exported as is, eg: cx.export_function("keccak256", js_keccak256)
+
+fn js_hash(mut cx: FunctionContext, f: fn(&[u8]) -> Bytes32) -> JsResult<JsString> {
+    let data: SafeResult<HexBytes> = cx.arg(0);
+    data.map(|data| f(&data.0)).finish(cx)
+}
+
+pub fn js_keccak256(cx: FunctionContext) -> JsResult<JsString> {
+    js_hash(cx, keccak256)
+}
+
+#[cfg(feature = "crypto")]
+pub fn js_sha256(cx: FunctionContext) -> JsResult<JsString> {
+    js_hash(cx, sha256)
+}
+
+#[cfg(feature = "crypto")]
+pub fn js_blake2b(cx: FunctionContext) -> JsResult<JsString> {
+    js_hash(cx, blake2b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_hex::ToHex as _;
+
+    #[test]
+    fn empty_input() {
+        assert_eq!(
+            keccak256(b"").to_hex::<String>(),
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+        #[cfg(feature = "crypto")]
+        {
+            assert_eq!(
+                sha256(b"").to_hex::<String>(),
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+            );
+            assert_eq!(
+                blake2b(b"").to_hex::<String>(),
+                "0e5751c026e543b2e8ab2eb06099daa1d1e5df47778f7787faab45cdf12fe3a8"
+            );
+        }
+    }
+}
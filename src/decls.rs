@@ -0,0 +1,404 @@
+//! TypeScript declarations generated from the marshalling impls, so that
+//! an addon's .d.ts can't drift from what its functions accept. Eg:
+//!   let decls = Decls::new()
+//!       .function::<(Address, Option<U256>), U256>("balance", &["owner", "block"])
+//!       .function::<(Options,), ()>("configure", &["options"]);
+//!   decls::export_schema(&mut cx, decls)?;
+//! Then `node -e 'process.stdout.write(require(".").__schema())' > index.d.ts`
+//!
+//! #[derive(FromHandle)] declares an interface for structs marked #[neon(ts)].
+
+use crate::marshalling::{AsUndefined, PlainObject, SkipIfNone};
+use neon::prelude::*;
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
+use std::fmt;
+use std::sync::OnceLock;
+
+/// The TypeScript type of a Rust type on the JS side
+pub trait TsType {
+    /// What FromHandle accepts
+    fn ts_input() -> String;
+
+    /// What IntoHandle returns, where that is narrower
+    fn ts_output() -> String {
+        Self::ts_input()
+    }
+
+    /// Whether a parameter or property of this type may be left out
+    fn ts_optional() -> bool {
+        false
+    }
+
+    /// Adds the declarations the type refers to, eg: its interface
+    fn ts_declare(_decls: &mut Decls) {}
+}
+
+/// The declarations for a .d.ts, which Display writes out
+#[derive(Clone, Debug, Default)]
+pub struct Decls {
+    // By name, so that each is declared once and the output is stable
+    types: BTreeMap<String, String>,
+    functions: Vec<String>,
+}
+
+impl Decls {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declares `export function name(...): R`, with a name for each
+    /// of the arguments A, which is a tuple.
+    pub fn function<A: TsArgs, R: TsType>(mut self, name: &str, params: &[&str]) -> Self {
+        let params = A::ts_params(params);
+        A::ts_declare(&mut self);
+        R::ts_declare(&mut self);
+        self.functions.push(format!(
+            "export function {}({}): {};",
+            name,
+            params,
+            R::ts_output()
+        ));
+        self
+    }
+
+    /// Declares T on its own, eg: a type only passed to a callback
+    pub fn declare<T: TsType>(mut self) -> Self {
+        T::ts_declare(&mut self);
+        self
+    }
+
+    /// Adds `export interface name` with (property, type, optional) fields,
+    /// unless there already is one. Returns whether it was added, so that
+    /// a recursive type declares what it refers to only once.
+    pub fn interface(&mut self, name: &str, fields: &[(&str, String, bool)]) -> bool {
+        if self.types.contains_key(name) {
+            return false;
+        }
+        let mut declaration = format!("export interface {} {{\n", name);
+        for (key, ty, optional) in fields {
+            let optional = if *optional { "?" } else { "" };
+            declaration += &format!("  {}{}: {};\n", property(key), optional, ty);
+        }
+        declaration.push('}');
+        self.types.insert(name.to_string(), declaration);
+        true
+    }
+}
+
+impl fmt::Display for Decls {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "// Generated by neon-utils. Do not edit.")?;
+        for declaration in self.types.values() {
+            writeln!(f, "\n{}", declaration)?;
+        }
+        if !self.functions.is_empty() {
+            writeln!(f)?;
+        }
+        for function in &self.functions {
+            writeln!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
+
+static SCHEMA: OnceLock<String> = OnceLock::new();
+
+/// Exports __schema(), which returns the .d.ts for decls. Only the
+/// first instantiation's decls are kept, as they are the same each time.
+pub fn export_schema(cx: &mut ModuleContext, decls: Decls) -> NeonResult<()> {
+    SCHEMA.get_or_init(|| decls.to_string());
+    cx.export_function("__schema", js_schema)
+}
+
+fn js_schema(mut cx: FunctionContext) -> JsResult<JsString> {
+    let schema = SCHEMA.get().map(String::as_str).unwrap_or_default();
+    Ok(cx.string(schema))
+}
+
+// Quoted unless it is a plain identifier, eg: "kebab-case"
+fn property(key: &str) -> String {
+    let mut chars = key.chars();
+    let plain = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+    if plain {
+        key.to_string()
+    } else {
+        format!("{:?}", key)
+    }
+}
+
+// Parenthesized where it is a union, eg: for an array of it
+fn group(ty: String) -> String {
+    if ty.contains(" | ") {
+        format!("({})", ty)
+    } else {
+        ty
+    }
+}
+
+/// The arguments of a function as a tuple, eg: (Address, Option<U256>)
+pub trait TsArgs {
+    fn ts_params(names: &[&str]) -> String;
+    fn ts_declare(decls: &mut Decls);
+}
+
+macro_rules! ts_args {
+    ($(($($t:ident),*),)*) => {
+        $(
+            impl<$($t: TsType),*> TsArgs for ($($t,)*) {
+                #[allow(unused_mut, unused_variables)]
+                fn ts_params(names: &[&str]) -> String {
+                    let mut params: Vec<String> = Vec::new();
+                    // Optional only if everything after is too
+                    let mut trailing = true;
+                    let types: Vec<(String, bool)> = vec![$(($t::ts_input(), $t::ts_optional())),*];
+                    for (i, (ty, optional)) in types.into_iter().enumerate().rev() {
+                        trailing &= optional;
+                        let name = names.get(i).map_or_else(|| format!("arg{}", i), |n| n.to_string());
+                        let optional = if trailing { "?" } else { "" };
+                        params.push(format!("{}{}: {}", name, optional, ty));
+                    }
+                    params.reverse();
+                    params.join(", ")
+                }
+
+                #[allow(unused_variables)]
+                fn ts_declare(decls: &mut Decls) {
+                    $($t::ts_declare(decls);)*
+                }
+            }
+        )*
+    };
+}
+
+ts_args! {
+    (),
+    (A),
+    (A, B),
+    (A, B, C),
+    (A, B, C, D),
+    (A, B, C, D, E),
+    (A, B, C, D, E, F),
+}
+
+/// Implements TsType for a type which is always the same TS type, given
+/// what FromHandle accepts and what IntoHandle returns. It's used right
+/// after the type's marshalling impls, so that the two change together.
+macro_rules! ts_type {
+    ($t:ty => $input:expr, $output:expr) => {
+        impl $crate::decls::TsType for $t {
+            fn ts_input() -> String {
+                $input.to_string()
+            }
+
+            fn ts_output() -> String {
+                $output.to_string()
+            }
+        }
+    };
+}
+
+pub(crate) use ts_type;
+
+impl<T: TsType + ?Sized> TsType for &'_ T {
+    fn ts_input() -> String {
+        T::ts_input()
+    }
+
+    fn ts_output() -> String {
+        T::ts_output()
+    }
+
+    fn ts_declare(decls: &mut Decls) {
+        T::ts_declare(decls);
+    }
+}
+
+impl<T: TsType> TsType for PlainObject<T> {
+    fn ts_input() -> String {
+        T::ts_input()
+    }
+
+    fn ts_output() -> String {
+        T::ts_output()
+    }
+
+    fn ts_declare(decls: &mut Decls) {
+        T::ts_declare(decls);
+    }
+}
+
+impl<T: TsType> TsType for Option<T> {
+    fn ts_input() -> String {
+        format!("{} | null | undefined", T::ts_input())
+    }
+
+    fn ts_output() -> String {
+        format!("{} | null", T::ts_output())
+    }
+
+    fn ts_optional() -> bool {
+        true
+    }
+
+    fn ts_declare(decls: &mut Decls) {
+        T::ts_declare(decls);
+    }
+}
+
+macro_rules! ts_undefined_options {
+    ($($t:ident),*) => {
+        $(
+            impl<T: TsType> TsType for $t<T> {
+                fn ts_input() -> String {
+                    Option::<T>::ts_input()
+                }
+
+                fn ts_output() -> String {
+                    format!("{} | undefined", T::ts_output())
+                }
+
+                fn ts_optional() -> bool {
+                    true
+                }
+
+                fn ts_declare(decls: &mut Decls) {
+                    T::ts_declare(decls);
+                }
+            }
+        )*
+    };
+}
+
+ts_undefined_options!(AsUndefined, SkipIfNone);
+
+impl<T: TsType> TsType for Vec<T> {
+    fn ts_input() -> String {
+        format!("{}[]", group(T::ts_input()))
+    }
+
+    fn ts_output() -> String {
+        format!("{}[]", group(T::ts_output()))
+    }
+
+    fn ts_declare(decls: &mut Decls) {
+        T::ts_declare(decls);
+    }
+}
+
+impl<T: TsType> TsType for VecDeque<T> {
+    fn ts_input() -> String {
+        Vec::<T>::ts_input()
+    }
+
+    fn ts_output() -> String {
+        Vec::<T>::ts_output()
+    }
+
+    fn ts_declare(decls: &mut Decls) {
+        T::ts_declare(decls);
+    }
+}
+
+#[cfg(feature = "smallvec")]
+impl<A> TsType for smallvec::SmallVec<A>
+where
+    A: smallvec::Array,
+    A::Item: TsType,
+{
+    fn ts_input() -> String {
+        Vec::<A::Item>::ts_input()
+    }
+
+    fn ts_output() -> String {
+        Vec::<A::Item>::ts_output()
+    }
+
+    fn ts_declare(decls: &mut Decls) {
+        A::Item::ts_declare(decls);
+    }
+}
+
+// Sets are read from a Set or an Array, but written as an Array
+macro_rules! ts_sets {
+    ($($t:ident),*) => {
+        $(
+            impl<T: TsType> TsType for $t<T> {
+                fn ts_input() -> String {
+                    format!("Set<{0}> | {1}[]", T::ts_input(), group(T::ts_input()))
+                }
+
+                fn ts_output() -> String {
+                    Vec::<T>::ts_output()
+                }
+
+                fn ts_declare(decls: &mut Decls) {
+                    T::ts_declare(decls);
+                }
+            }
+        )*
+    };
+}
+
+ts_sets!(HashSet, BTreeSet);
+
+impl<T: TsType> TsType for [T] {
+    fn ts_input() -> String {
+        format!("{}[]", group(T::ts_input()))
+    }
+
+    fn ts_output() -> String {
+        format!("{}[]", group(T::ts_output()))
+    }
+
+    fn ts_declare(decls: &mut Decls) {
+        T::ts_declare(decls);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use primitive_types::U256;
+
+    struct Point;
+
+    impl TsType for Point {
+        fn ts_input() -> String {
+            "Point".to_string()
+        }
+
+        fn ts_declare(decls: &mut Decls) {
+            let fields = [
+                ("x", f64::ts_input(), false),
+                ("max-y", Option::<f64>::ts_input(), true),
+            ];
+            if decls.interface("Point", &fields) {
+                // Would recurse forever without the check
+                Point::ts_declare(decls);
+            }
+        }
+    }
+
+    #[test]
+    fn declares() {
+        let decls = Decls::new()
+            .function::<(Vec<Point>, Option<U256>), Vec<Option<U256>>>("sum", &["points"])
+            .function::<(Option<u32>, bool), ()>("flag", &["count", "on"]);
+        assert_eq!(
+            decls.to_string(),
+            "// Generated by neon-utils. Do not edit.
+
+export interface Point {
+  x: number;
+  \"max-y\"?: number | null | undefined;
+}
+
+export function sum(points: Point[], arg1?: string | number | Buffer | ArrayBuffer | null | undefined): (string | null)[];
+export function flag(count: number | null | undefined, on: boolean): undefined;
+"
+        );
+    }
+}
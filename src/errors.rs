@@ -1,4 +1,6 @@
 use crate::marshalling::*;
+use crate::profiling;
+use crate::registry;
 use neon::{
     handle::{DowncastError, Managed},
     prelude::*,
@@ -6,6 +8,12 @@ use neon::{
 };
 use never::Never;
 use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Neon-free, so that codecs outside of an addon can use them
+pub use neon_utils_core::errors::{DowncastFailure, LazyFmt, SendFmt};
 
 /// This type is to handle a problem that neon creates.
 /// First, if you return Err(Throw) from a function without calling throw() neon will segfault.
@@ -37,7 +45,14 @@ pub type SafeResult<Ok> = Result<Ok, MaybeThrown>;
 pub type SafeJsResult<'c, Ok> = SafeResult<Handle<'c, Ok>>;
 
 pub trait IntoError {
+    #[allow(clippy::wrong_self_convention)]
     fn into_error<'c>(&self, cx: &mut impl Context<'c>) -> JsResult<'c, JsError>;
+
+    /// The SafeErr to pass the error observer, if this is one. Anything
+    /// else is observed as its message.
+    fn as_safe_err(&self) -> Option<&SafeErr> {
+        None
+    }
 }
 
 impl IntoError for Never {
@@ -66,6 +81,61 @@ where
     }
 }
 
+/// Errors which JS can tell apart by their code, eg: an enum of the ways
+/// a transfer can fail. The JS error has code set, and is an instance of
+/// the class registered for the code if there is one, eg: RateLimitError.
+/// See registry::register_error.
+///
+/// These are IntoError, and convert to a MaybeThrown with ? like a String.
+pub trait ErrorCode: fmt::Display {
+    fn code(&self) -> &'static str;
+
+    /// As a SafeErr with the code, eg: to return from a run_async task
+    /// and be thrown the same as it would be from the JS thread
+    fn into_safe_err(self) -> SafeErr
+    where
+        Self: Sized,
+    {
+        SafeErr::from(self.to_string()).with_code(self.code())
+    }
+}
+
+impl<E: ErrorCode> From<E> for SafeErr {
+    fn from(e: E) -> Self {
+        e.into_safe_err()
+    }
+}
+
+impl<E: ErrorCode> IntoError for E {
+    fn into_error<'c>(&self, cx: &mut impl Context<'c>) -> JsResult<'c, JsError> {
+        coded_error(cx, self.code(), self.to_string())
+    }
+}
+
+/// An error with code set, of the class registered for the code or else Error
+pub fn coded_error<'c>(
+    cx: &mut impl Context<'c>,
+    code: &str,
+    message: impl AsRef<str>,
+) -> JsResult<'c, JsError> {
+    let message = message.as_ref();
+    let error = match registry::error_class(cx, code)? {
+        Some(class) => {
+            let js_message = cx.string(message);
+            let error: Handle<JsError> = class
+                .construct(cx, vec![js_message.upcast()])?
+                .downcast_or_throw(cx)?;
+            // Whatever the constructor did with it
+            error.set(cx, "message", js_message)?;
+            error
+        }
+        None => cx.error(message)?,
+    };
+    let code = cx.string(code);
+    error.set(cx, "code", code)?;
+    Ok(error)
+}
+
 impl<T: Value, F: Value> IntoError for DowncastError<T, F> {
     fn into_error<'c>(&self, cx: &mut impl Context<'c>) -> JsResult<'c, JsError> {
         let msg = format!("{}", self);
@@ -85,6 +155,31 @@ impl IntoError for &'_ str {
     }
 }
 
+static OBSERVER: Mutex<Option<fn(&SafeErr)>> = Mutex::new(None);
+
+/// Sets a function to call with every error that finish throws, eg: to
+/// report them to telemetry. Errors which aren't a SafeErr are passed as
+/// their message. JS exceptions passed through (eg: from a callback) are
+/// not observed, but are counted by profiling::call_stats.
+pub fn set_error_observer(observer: fn(&SafeErr)) {
+    *OBSERVER.lock().unwrap_or_else(|e| e.into_inner()) = Some(observer);
+}
+
+pub fn clear_error_observer() {
+    *OBSERVER.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+fn observer() -> Option<fn(&SafeErr)> {
+    *OBSERVER.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn observe(observer: fn(&SafeErr), e: &SafeErr) {
+    // A panic can't unwind into JS
+    if panic::catch_unwind(AssertUnwindSafe(|| observer(e))).is_err() {
+        tracing::warn!("Error observer panicked");
+    }
+}
+
 pub trait Terminal {
     type Handle: Value;
     // This takes Context by value to prevent interacting with JS
@@ -96,13 +191,28 @@ pub trait Terminal {
 }
 
 impl MaybeThrown {
+    /// Adds context to an Unthrown error. Thrown errors are passed through as is.
+    pub fn with_context(self, context: impl Into<String>) -> Self {
+        match self {
+            MaybeThrown::Thrown(t) => MaybeThrown::Thrown(t),
+            MaybeThrown::Unthrown(e) => MaybeThrown::Unthrown(e.with_context(context)),
+        }
+    }
+
     pub fn finish<'c, Any: Managed>(self, mut cx: impl Context<'c>) -> JsResult<'c, Any> {
+        profiling::count_throw();
         match self {
             MaybeThrown::Thrown(t) => Err(t),
-            MaybeThrown::Unthrown(e) => match e.into_error(&mut cx) {
-                Ok(ok) => cx.throw(ok),
-                Err(err) => Err(err),
-            },
+            MaybeThrown::Unthrown(e) => {
+                tracing::debug!(error = %e, "Throwing");
+                if let Some(observer) = observer() {
+                    observe(observer, &e);
+                }
+                match e.into_error(&mut cx) {
+                    Ok(ok) => cx.throw(ok),
+                    Err(err) => Err(err),
+                }
+            }
         }
     }
 }
@@ -113,6 +223,8 @@ where
 {
     type Handle = <Ok as IntoHandle>::Handle;
     fn finish<'c>(self, mut cx: impl Context<'c>) -> JsResult<'c, Self::Handle> {
+        let _span = tracing::trace_span!("finish", output = std::any::type_name::<Ok>()).entered();
+        profiling::count_call();
         match self {
             Ok(ok) => match ok.into_handle(&mut cx) {
                 Ok(ok) => Ok(ok),
@@ -130,19 +242,73 @@ where
 {
     type Handle = <Ok as IntoHandle>::Handle;
     fn finish<'c>(self, mut cx: impl Context<'c>) -> JsResult<'c, Self::Handle> {
+        let _span = tracing::trace_span!("finish", output = std::any::type_name::<Ok>()).entered();
+        profiling::count_call();
         match self {
             Ok(ok) => match ok.into_handle(&mut cx) {
                 Ok(ok) => Ok(ok),
                 Err(e) => e.finish(cx),
             },
             Err(e) => {
-                let e = e.into_error(&mut cx)?;
-                cx.throw(e)
+                profiling::count_throw();
+                let error = e.into_error(&mut cx)?;
+                if let Some(observer) = observer() {
+                    match e.as_safe_err() {
+                        Some(e) => observe(observer, e),
+                        None => {
+                            let message: Handle<JsValue> = error.get(&mut cx, "message")?;
+                            let message = match message.downcast::<JsString>() {
+                                Ok(message) => message.value(),
+                                Err(_) => String::new(),
+                            };
+                            observe(observer, &SafeErr::String(message));
+                        }
+                    }
+                }
+                cx.throw(error)
             }
         }
     }
 }
 
+/// Declares a function neon can export, written against SafeResult
+/// (or any Result which is Terminal) instead of JsResult. The body is
+/// run as a closure, so ? works on both Throw and Unthrown errors, and
+/// the result is finished at the end. Eg:
+///   terminal_fn! {
+///       fn balance(cx: FunctionContext) -> SafeResult<U256> {
+///           let owner: Address = cx.arg(0)?;
+///           Ok(lookup(owner)?)
+///       }
+///   }
+///   cx.export_function("balance", balance)?;
+#[macro_export]
+macro_rules! terminal_fn {
+    ($(#[$attr:meta])* $vis:vis fn $name:ident($cx:ident: $context:ty) -> $ret:ty $body:block) => {
+        $(#[$attr])*
+        $vis fn $name(
+            mut $cx: $context,
+        ) -> ::neon::result::JsResult<'_, <$ret as $crate::errors::Terminal>::Handle> {
+            let result: $ret = $crate::profiling::in_export(stringify!($name), || $body);
+            $crate::errors::Terminal::finish(result, $cx)
+        }
+    };
+}
+
+/// The other way around from terminal_fn!: in a function returning
+/// JsResult, unwraps a SafeResult or else throws the error and returns.
+/// Eg: let owner: Address = try_js!(cx, cx.arg(0));
+/// The error may be anything a MaybeThrown is From, eg: a String.
+#[macro_export]
+macro_rules! try_js {
+    ($cx:ident, $result:expr) => {
+        match $result {
+            Ok(ok) => ok,
+            Err(e) => return $crate::errors::MaybeThrown::from(e).finish($cx),
+        }
+    };
+}
+
 // After much consternation, I am enumerating all errors instead of using generics.
 // It's a fair amount of work to get this module to compile with generic errors,
 // but it works. Then you run into an issue on the usage end where calls to .arg
@@ -152,7 +318,36 @@ where
 pub enum SafeErr {
     StaticStr(&'static str),
     String(String),
-    LazyFmt(LazyFmt),
+    LazyFmt(SendFmt),
+    Downcast(DowncastFailure),
+    Context(String, Box<SafeErr>),
+    /// Every invalid field of an object, by name. Nested objects are
+    /// flattened into paths like "network.rpc".
+    Fields(Vec<(String, SafeErr)>),
+    /// An error with a code for JS to tell it apart by, eg: "RATE_LIMITED"
+    Coded(&'static str, Box<SafeErr>),
+}
+
+impl SafeErr {
+    /// Wraps the error with a description of what was being done when it happened.
+    /// Eg: "Invalid element at index 2: Failed to parse U256"
+    pub fn with_context(self, context: impl Into<String>) -> Self {
+        SafeErr::Context(context.into(), Box::new(self))
+    }
+
+    /// Gives the error a code, which is set as code on the JS error
+    pub fn with_code(self, code: &'static str) -> Self {
+        SafeErr::Coded(code, Box::new(self))
+    }
+
+    /// The code of this error, or of the error it adds context to
+    pub fn code(&self) -> Option<&'static str> {
+        match self {
+            SafeErr::Coded(code, _) => Some(code),
+            SafeErr::Context(_, source) => source.code(),
+            _ => None,
+        }
+    }
 }
 
 impl From<&'static str> for SafeErr {
@@ -165,42 +360,249 @@ impl From<String> for SafeErr {
         Self::String(v)
     }
 }
+// SafeErr is Send, so that a task can fail with one, and a LazyFmt isn't.
+// So it's formatted here, and only a SendFmt stays lazy.
 impl From<LazyFmt> for SafeErr {
     fn from(v: LazyFmt) -> Self {
+        Self::String(v.to_string())
+    }
+}
+impl From<SendFmt> for SafeErr {
+    fn from(v: SendFmt) -> Self {
         Self::LazyFmt(v)
     }
 }
 
+impl From<DowncastFailure> for SafeErr {
+    fn from(v: DowncastFailure) -> Self {
+        Self::Downcast(v)
+    }
+}
+
+impl fmt::Display for SafeErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SafeErr::StaticStr(s) => s.fmt(f),
+            SafeErr::String(s) => s.fmt(f),
+            SafeErr::LazyFmt(l) => l.fmt(f),
+            SafeErr::Downcast(d) => d.fmt(f),
+            SafeErr::Context(context, source) => write!(f, "{}: {}", context, source),
+            SafeErr::Coded(_, source) => source.fmt(f),
+            SafeErr::Fields(fields) => {
+                for (i, (field, e)) in fields.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "Invalid field '{}': {}", field, e)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+pub trait SafeResultExt<T> {
+    fn context(self, context: impl Into<String>) -> SafeResult<T>;
+    /// Like context, but only formats the context on error
+    fn with_context<C: Into<String>>(self, f: impl FnOnce() -> C) -> SafeResult<T>;
+}
+
+impl<T> SafeResultExt<T> for SafeResult<T> {
+    fn context(self, context: impl Into<String>) -> SafeResult<T> {
+        self.map_err(|e| e.with_context(context))
+    }
+
+    fn with_context<C: Into<String>>(self, f: impl FnOnce() -> C) -> SafeResult<T> {
+        self.map_err(|e| e.with_context(f()))
+    }
+}
+
+/// Gathers the errors from decoding each field of an object, so that all
+/// of them can be reported at once instead of only the first. This is
+/// what #[derive(FromHandle)] uses.
+#[derive(Default)]
+pub struct FieldErrors(Vec<(String, SafeErr)>);
+
+impl FieldErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ok(None) if the field was invalid, which is recorded. A thrown error
+    /// is returned as is, since nothing more can be decoded while it's pending.
+    pub fn field<T>(&mut self, name: &str, result: SafeResult<T>) -> SafeResult<Option<T>> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(MaybeThrown::Thrown(t)) => Err(MaybeThrown::Thrown(t)),
+            Err(MaybeThrown::Unthrown(SafeErr::Fields(fields))) => {
+                for (path, e) in fields {
+                    self.0.push((format!("{}.{}", name, path), e));
+                }
+                Ok(None)
+            }
+            Err(MaybeThrown::Unthrown(e)) => {
+                self.0.push((name.to_owned(), e));
+                Ok(None)
+            }
+        }
+    }
+
+    /// Fails with every recorded error, if there are any
+    pub fn finish(self) -> SafeResult<()> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(SafeErr::Fields(self.0).into())
+        }
+    }
+}
+
 impl IntoError for SafeErr {
     fn into_error<'c>(&self, cx: &mut impl Context<'c>) -> JsResult<'c, JsError> {
         match self {
             SafeErr::StaticStr(s) => s.into_error(cx),
             SafeErr::String(s) => s.into_error(cx),
             SafeErr::LazyFmt(l) => l.into_error(cx),
+            SafeErr::Downcast(d) => cx.error(d.to_string()),
+            SafeErr::Coded(code, _) => coded_error(cx, code, format!("{}", self)),
+            SafeErr::Context(_, source) => {
+                // Thrown as the class of the error it adds context to
+                let error = match source.code() {
+                    Some(code) => coded_error(cx, code, format!("{}", self))?,
+                    None => cx.error(format!("{}", self))?,
+                };
+                let cause = source.into_error(cx)?;
+                error.set(cx, "cause", cause)?;
+                Ok(error)
+            }
+            // Also lists the errors as errors: [{ field, message }] for
+            // callers which show them next to each field.
+            SafeErr::Fields(fields) => {
+                let error = cx.error(format!("{}", self))?;
+                let errors = JsArray::new(cx, fields.len() as u32);
+                for (i, (field, e)) in fields.iter().enumerate() {
+                    let entry = cx.empty_object();
+                    let field = cx.string(field);
+                    entry.set(cx, "field", field)?;
+                    let message = cx.string(format!("{}", e));
+                    entry.set(cx, "message", message)?;
+                    errors.set(cx, i as u32, entry)?;
+                }
+                error.set(cx, "errors", errors)?;
+                Ok(error)
+            }
         }
     }
+
+    fn as_safe_err(&self) -> Option<&SafeErr> {
+        Some(self)
+    }
 }
 
-pub struct LazyFmt(Box<dyn fmt::Display>);
+impl IntoError for LazyFmt {
+    fn into_error<'c>(&self, cx: &mut impl Context<'c>) -> JsResult<'c, JsError> {
+        let s = format!("{}", self);
+        cx.error(s)
+    }
+}
 
-impl LazyFmt {
-    pub fn new<T>(value: T) -> Self
-    where
-        T: 'static + fmt::Display,
-    {
-        Self(Box::new(value))
+impl IntoError for SendFmt {
+    fn into_error<'c>(&self, cx: &mut impl Context<'c>) -> JsResult<'c, JsError> {
+        let s = format!("{}", self);
+        cx.error(s)
     }
 }
 
-impl fmt::Display for LazyFmt {
+/// The error given to the callback when a task does not complete
+/// before its deadline. The resulting JS error has name `TimeoutError`
+/// and code `ETIMEDOUT` so that it can be told apart from task failures.
+pub struct TimeoutError {
+    pub timeout: Duration,
+}
+
+impl fmt::Display for TimeoutError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.0.fmt(f)
+        write!(f, "Task timed out after {}ms", self.timeout.as_millis())
     }
 }
 
-impl IntoError for LazyFmt {
+impl IntoError for TimeoutError {
     fn into_error<'c>(&self, cx: &mut impl Context<'c>) -> JsResult<'c, JsError> {
-        let s = format!("{}", self);
-        cx.error(s)
+        let error = cx.error(format!("{}", self))?;
+        let name = cx.string("TimeoutError");
+        error.set(cx, "name", name)?;
+        let code = cx.string("ETIMEDOUT");
+        error.set(cx, "code", code)?;
+        Ok(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct RateLimited;
+
+    impl fmt::Display for RateLimited {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Slow down")
+        }
+    }
+
+    impl ErrorCode for RateLimited {
+        fn code(&self) -> &'static str {
+            "RATE_LIMITED"
+        }
+    }
+
+    #[test]
+    fn keeps_code() {
+        let failed = || -> SafeResult<()> { Err(RateLimited)? };
+        match failed().context("Fetching blocks") {
+            Err(MaybeThrown::Unthrown(e)) => {
+                assert_eq!(e.code(), Some("RATE_LIMITED"));
+                assert_eq!(e.to_string(), "Fetching blocks: Slow down");
+            }
+            _ => panic!("Expected a coded error"),
+        }
+    }
+
+    #[test]
+    fn crosses_threads() {
+        let e = std::thread::spawn(|| RateLimited.into_safe_err().with_context("Fetching blocks"))
+            .join()
+            .unwrap_or_else(|_| panic!("Thread panicked"));
+        assert_eq!(e.code(), Some("RATE_LIMITED"));
+        assert!(e.as_safe_err().is_some());
+        assert!("Slow down".as_safe_err().is_none());
+    }
+
+    #[test]
+    fn lazy_fmt_of_anything() {
+        // eg: neon's DowncastError, which isn't Send either
+        let e = SafeErr::from(LazyFmt::new(std::rc::Rc::new(5)));
+        assert_eq!(e.to_string(), "5");
+        let e = SafeErr::from(SendFmt::new(5));
+        assert!(matches!(e, SafeErr::LazyFmt(_)));
+    }
+
+    #[test]
+    fn accumulates_fields() {
+        let mut inner = FieldErrors::new();
+        assert!(inner.field::<u8>("rpc", Err("Missing".into())).is_ok());
+        let mut outer = FieldErrors::new();
+        assert!(matches!(outer.field("name", Ok(1)), Ok(Some(1))));
+        assert!(matches!(
+            outer.field::<u8>("retries", Err("Bad".into())),
+            Ok(None)
+        ));
+        assert!(matches!(outer.field("network", inner.finish()), Ok(None)));
+        match outer.finish() {
+            Err(MaybeThrown::Unthrown(e)) => assert_eq!(
+                e.to_string(),
+                "Invalid field 'retries': Bad; Invalid field 'network.rpc': Missing"
+            ),
+            _ => panic!("Expected field errors"),
+        }
     }
 }
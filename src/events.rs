@@ -0,0 +1,114 @@
+//! Sends to JS in batches, for events which come too often to wake the
+//! event loop for each one, eg: a log line or a progress tick per item.
+
+use crate::errors::SafeResult;
+use crate::marshalling::{expect, FromHandle, IntoHandle, ObjectExt};
+use crate::tsfn::{BoundedTsfn, Overflow, TsfnOptions};
+use crossbeam_channel::{RecvTimeoutError, Sender};
+use neon::prelude::*;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// When a batch is sent: once window has passed since its first item,
+/// or as soon as it has max_batch items, whichever comes first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BatchOptions {
+    pub window: Duration,
+    pub max_batch: usize,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            // About a frame, so that a UI fed by this still looks live
+            window: Duration::from_millis(16),
+            max_batch: 1000,
+        }
+    }
+}
+
+/// From { window?, maxBatch? }, with the window in milliseconds
+impl FromHandle for BatchOptions {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        let object: Handle<JsObject> = expect(handle, cx, "object")?;
+        let defaults = Self::default();
+        let window: Option<Duration> = object.get_field(cx, "window")?;
+        let max_batch: Option<u32> = object.get_field(cx, "maxBatch")?;
+        if max_batch == Some(0) {
+            return Err("Invalid field 'maxBatch': Expected at least 1".into());
+        }
+        Ok(Self {
+            window: window.unwrap_or(defaults.window),
+            max_batch: max_batch.map_or(defaults.max_batch, |n| n as usize),
+        })
+    }
+}
+
+/// Sends items to a JS callback from any thread, which is called with an
+/// Array of them at a time. Items arrive in the order sent.
+///
+/// Batches are put together on a thread of their own, which stops once
+/// every BatchSender is dropped, after sending what was left. Until then
+/// Node is kept running. If JS falls behind, that thread waits for it
+/// rather than queue up batches without bound.
+pub struct BatchSender<T> {
+    items: Sender<T>,
+}
+
+impl<T> Clone for BatchSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            items: self.items.clone(),
+        }
+    }
+}
+
+impl<T> BatchSender<T> {
+    /// False if the environment has shut down, so nothing more can be sent
+    pub fn send(&self, item: T) -> bool {
+        self.items.send(item).is_ok()
+    }
+}
+
+/// Creates a BatchSender which calls callback with each batch
+pub fn batched<'a, T>(
+    cx: &mut impl Context<'a>,
+    callback: Handle<JsFunction>,
+    options: BatchOptions,
+) -> NeonResult<BatchSender<T>>
+where
+    T: 'static + Send + IntoHandle,
+{
+    // A few batches in flight is enough to keep JS busy
+    let tsfn_options = TsfnOptions {
+        max_queue: 4,
+        overflow: Overflow::Block,
+    };
+    let batches: BoundedTsfn<Vec<T>> = BoundedTsfn::new(cx, callback, tsfn_options)?;
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    thread::spawn(move || {
+        // Blocks for the first item of each batch, then waits out the window
+        while let Ok(first) = receiver.recv() {
+            let deadline = Instant::now() + options.window;
+            let mut batch = vec![first];
+            let mut disconnected = false;
+            while batch.len() < options.max_batch {
+                match receiver.recv_deadline(deadline) {
+                    Ok(item) => batch.push(item),
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        disconnected = true;
+                        break;
+                    }
+                }
+            }
+            if batches.call(batch).is_err() || disconnected {
+                return;
+            }
+        }
+    });
+    Ok(BatchSender { items: sender })
+}
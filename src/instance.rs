@@ -0,0 +1,59 @@
+//! State for one instance of the addon, ie: the main thread or one
+//! worker_thread, each of which runs the module's register function.
+//!
+//! Under the legacy runtime every instance has a JS thread of its own, so
+//! what only the JS thread touches (channel, shutdown, limits) is already
+//! per instance by being thread local. What other threads touch as well
+//! lives here instead, and work for an instance holds on to it. Eg: tasks
+//! serialized by key in one worker don't wait on the same key in another.
+//!
+//! What is per process stays that way: config, the libuv pool, logging
+//! and module::init state.
+
+use crate::task::lanes::Lanes;
+use crate::task::Job;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    // Only set on JS threads
+    static CURRENT: RefCell<Option<Arc<Instance>>> = const { RefCell::new(None) };
+}
+
+pub struct Instance {
+    id: u64,
+    // The pending jobs for each key which has a worker running
+    pub(crate) serialized: Mutex<HashMap<String, VecDeque<Job>>>,
+    pub(crate) lanes: Mutex<Lanes>,
+}
+
+impl Instance {
+    /// Unique within the process, starting from 1 in the order made
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+/// The instance the current JS thread belongs to. Made by module::init,
+/// or when first needed by a module which doesn't call that.
+///
+/// Only call this from a JS thread. Anything else gets an instance of its
+/// own, which nothing else will share.
+pub fn current() -> Arc<Instance> {
+    CURRENT.with(|current| {
+        current
+            .borrow_mut()
+            .get_or_insert_with(|| {
+                Arc::new(Instance {
+                    id: NEXT_ID.fetch_add(1, Ordering::SeqCst),
+                    serialized: Mutex::new(HashMap::new()),
+                    lanes: Mutex::new(Lanes::default()),
+                })
+            })
+            .clone()
+    })
+}
@@ -0,0 +1,285 @@
+//! JSON parsed off of the JS thread, for responses too large to JSON.parse
+//! without blocking the event loop. See stream for feeding it in chunks.
+
+use crate::errors::{SafeErr, SafeJsResult};
+use crate::marshalling::limits::{check_bytes, check_items, Nested};
+use crate::marshalling::IntoHandle;
+use neon::prelude::*;
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use std::cell::Cell;
+use std::convert::TryInto;
+use std::fmt;
+
+mod stream;
+pub use stream::{js_json_stream, json_stream, JsJsonStream, Records, StreamParser};
+
+/// A parsed JSON value. Numbers are f64, like JSON.parse.
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    /// In the order written, duplicates included
+    Object(Vec<(String, JsonValue)>),
+}
+
+/// Parses exactly one JSON value, which may be surrounded by whitespace.
+/// The MarshalLimits of this thread bound its size, nesting and items.
+/// Past a depth of 128 serde_json refuses it anyway, to keep off the end
+/// of the stack, whatever the limits allow.
+pub fn parse(bytes: &[u8]) -> Result<JsonValue, SafeErr> {
+    parse_at(bytes, 0)
+}
+
+// Where bytes starts in the whole input, for errors
+fn parse_at(bytes: &[u8], base: usize) -> Result<JsonValue, SafeErr> {
+    check_bytes(bytes.len())?;
+    let exceeded = Cell::new(None);
+    let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+    let parsed = Seed {
+        exceeded: &exceeded,
+    }
+    .deserialize(&mut deserializer)
+    .and_then(|value| deserializer.end().map(|()| value));
+    parsed.map_err(|e| match exceeded.take() {
+        // Valid JSON, only too much of it
+        Some(exceeded) => exceeded,
+        None => invalid(bytes, base, &e),
+    })
+}
+
+// serde_json says where by line and column, where the rest of this
+// module says by byte
+fn invalid(bytes: &[u8], base: usize, e: &serde_json::Error) -> SafeErr {
+    let line_start = match e.line() {
+        0 | 1 => 0,
+        line => bytes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| **b == b'\n')
+            .nth(line - 2)
+            .map_or(0, |(i, _)| i + 1),
+    };
+    let at = base + line_start + e.column().saturating_sub(1);
+    let message = e.to_string();
+    // Without the " at line 1 column 5" serde_json ends it with
+    let message = message.split(" at line ").next().unwrap_or_default();
+    format!("Invalid JSON at byte {}: {}", at, message).into()
+}
+
+// Deserializes straight into JsonValue, checking the limits on the way
+#[derive(Clone, Copy)]
+struct Seed<'e> {
+    // The limit exceeded, if any, to return instead of serde_json's error
+    exceeded: &'e Cell<Option<SafeErr>>,
+}
+
+impl Seed<'_> {
+    fn check<E: de::Error, T>(&self, checked: Result<T, SafeErr>) -> Result<T, E> {
+        checked.map_err(|e| {
+            let message = e.to_string();
+            self.exceeded.set(Some(e));
+            E::custom(message)
+        })
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for Seed<'_> {
+    type Value = JsonValue;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<JsonValue, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Seed<'_> {
+    type Value = JsonValue;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a JSON value")
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<JsonValue, E> {
+        Ok(JsonValue::Null)
+    }
+
+    fn visit_bool<E: de::Error>(self, b: bool) -> Result<JsonValue, E> {
+        Ok(JsonValue::Bool(b))
+    }
+
+    fn visit_i64<E: de::Error>(self, n: i64) -> Result<JsonValue, E> {
+        Ok(JsonValue::Number(n as f64))
+    }
+
+    fn visit_u64<E: de::Error>(self, n: u64) -> Result<JsonValue, E> {
+        Ok(JsonValue::Number(n as f64))
+    }
+
+    fn visit_f64<E: de::Error>(self, n: f64) -> Result<JsonValue, E> {
+        Ok(JsonValue::Number(n))
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<JsonValue, E> {
+        Ok(JsonValue::String(s.to_owned()))
+    }
+
+    fn visit_string<E: de::Error>(self, s: String) -> Result<JsonValue, E> {
+        Ok(JsonValue::String(s))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<JsonValue, A::Error> {
+        let _nested = self.check(Nested::enter())?;
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element_seed(self)? {
+            items.push(item);
+            self.check(check_items(items.len()))?;
+        }
+        Ok(JsonValue::Array(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<JsonValue, A::Error> {
+        let _nested = self.check(Nested::enter())?;
+        let mut fields = Vec::new();
+        while let Some(key) = map.next_key::<String>()? {
+            let value = map.next_value_seed(self)?;
+            fields.push((key, value));
+            self.check(check_items(fields.len()))?;
+        }
+        Ok(JsonValue::Object(fields))
+    }
+}
+
+impl IntoHandle for JsonValue {
+    type Handle = JsValue;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        Ok(match self {
+            JsonValue::Null => cx.null().upcast(),
+            JsonValue::Bool(b) => cx.boolean(*b).upcast(),
+            JsonValue::Number(n) => cx.number(*n).upcast(),
+            JsonValue::String(s) => cx.string(s).upcast(),
+            JsonValue::Array(items) => {
+                let len: u32 = items
+                    .len()
+                    .try_into()
+                    .map_err(|_| "Array to large for JavaScript")?;
+                let array = JsArray::new(cx, len);
+                for (i, item) in items.iter().enumerate() {
+                    let item = item.into_handle(cx)?;
+                    array.set(cx, i as u32, item)?;
+                }
+                array.upcast()
+            }
+            JsonValue::Object(fields) => {
+                let object = cx.empty_object();
+                for (key, value) in fields {
+                    let value = value.into_handle(cx)?;
+                    if key == "__proto__" {
+                        define_own(cx, object, key, value)?;
+                    } else {
+                        object.set(cx, key.as_str(), value)?;
+                    }
+                }
+                object.upcast()
+            }
+        })
+    }
+}
+
+// Setting "__proto__" would change the prototype, where JSON.parse makes
+// an ordinary property instead
+fn define_own<'c>(
+    cx: &mut impl Context<'c>,
+    object: Handle<'c, JsObject>,
+    key: &str,
+    value: Handle<'c, JsValue>,
+) -> NeonResult<()> {
+    let global = cx.global();
+    let constructor: Handle<JsObject> = global.get(cx, "Object")?;
+    let define: Handle<JsFunction> = constructor.get(cx, "defineProperty")?;
+    let descriptor = cx.empty_object();
+    descriptor.set(cx, "value", value)?;
+    for flag in ["writable", "enumerable", "configurable"].iter() {
+        let yes = cx.boolean(true);
+        descriptor.set(cx, *flag, yes)?;
+    }
+    let key = cx.string(key).upcast();
+    define.call(
+        cx,
+        constructor,
+        vec![object.upcast(), key, descriptor.upcast()],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::marshalling::{limits, MarshalLimits};
+
+    fn ok(json: &str) -> JsonValue {
+        parse(json.as_bytes()).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn err(json: &str) -> String {
+        parse(json.as_bytes()).err().unwrap().to_string()
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(ok(" null "), JsonValue::Null);
+        assert_eq!(ok("-1.5e2"), JsonValue::Number(-150.0));
+        assert_eq!(ok(r#""a\né😀""#), JsonValue::String("a\né😀".into()));
+        assert_eq!(
+            ok(r#"{"a": [1, true], "b": {}}"#),
+            JsonValue::Object(vec![
+                (
+                    "a".into(),
+                    JsonValue::Array(vec![JsonValue::Number(1.0), JsonValue::Bool(true)])
+                ),
+                ("b".into(), JsonValue::Object(vec![])),
+            ])
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(err("[1,]"), "Invalid JSON at byte 3: trailing comma");
+        assert_eq!(err("01"), "Invalid JSON at byte 1: invalid number");
+        assert_eq!(err("1 2"), "Invalid JSON at byte 2: trailing characters");
+        assert_eq!(err("{\"a\" 1}"), "Invalid JSON at byte 5: expected `:`");
+        assert_eq!(err("[\n1,\n x]"), "Invalid JSON at byte 6: expected value");
+        assert_eq!(
+            err("\"abc"),
+            "Invalid JSON at byte 3: EOF while parsing a string"
+        );
+        let deep = "[".repeat(200);
+        assert!(err(&deep).ends_with("recursion limit exceeded"));
+    }
+
+    #[test]
+    fn limits() {
+        let strict = MarshalLimits {
+            max_depth: 2,
+            max_items: 3,
+            max_bytes: 16,
+        };
+        limits::with_limits(strict, || {
+            assert!(parse(b"[[1, 2, 3], {}]").is_ok());
+            assert_eq!(err("[[[]]]"), "Nested deeper than the limit of 2");
+            assert_eq!(
+                err("{\"a\": {\"b\": []}}"),
+                "Nested deeper than the limit of 2"
+            );
+            assert_eq!(
+                err("[1, 2, 3, 4]"),
+                "Got 4 items, which exceeds the limit of 3"
+            );
+            assert_eq!(
+                err(r#""0123456789abcdef""#),
+                "Got 18 bytes, which exceeds the limit of 16"
+            );
+        });
+    }
+}
@@ -0,0 +1,469 @@
+use super::{parse_at, JsonValue};
+use crate::errors::{SafeErr, SafeJsResult, SafeResult};
+use crate::keep_alive::Refs;
+use crate::marshalling::limits::{self, check_bytes};
+use crate::marshalling::{with_bytes, with_str_as, FromHandle};
+use crate::promise::JsOneshot;
+use crossbeam_channel::Sender;
+use neon::prelude::*;
+use neon::types::{JsArrayBuffer, JsBuffer};
+use std::mem;
+use std::thread;
+
+crate::js_enum! {
+    /// What a JSON stream yields as records. Values is each top level value,
+    /// eg: newline delimited JSON. Array is each element of a single top
+    /// level array, eg: a large JSON-RPC result.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Records { Values, Array }
+}
+
+// Where an Array stream is between its elements
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Between {
+    Open,
+    First,
+    Comma,
+    Element,
+    Closed,
+}
+
+/// Splits JSON fed in chunks into records, parsing each as soon as the
+/// last of it arrives. Only the record in progress is kept in memory.
+pub struct StreamParser {
+    records: Records,
+    buffer: Vec<u8>,
+    // How much has been dropped from the front of the buffer, for errors
+    offset: usize,
+    pos: usize,
+    // Where the record being scanned starts, if any
+    start: Option<usize>,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    between: Between,
+}
+
+fn is_whitespace(b: u8) -> bool {
+    matches!(b, b' ' | b'\t' | b'\n' | b'\r')
+}
+
+impl StreamParser {
+    pub fn new(records: Records) -> Self {
+        Self {
+            records,
+            buffer: Vec::new(),
+            offset: 0,
+            pos: 0,
+            start: None,
+            depth: 0,
+            in_string: false,
+            escaped: false,
+            between: Between::Open,
+        }
+    }
+
+    fn error(&self, message: &str) -> SafeErr {
+        let at = self.offset + self.pos;
+        format!("Invalid JSON at byte {}: {}", at, message).into()
+    }
+
+    /// The records completed by chunk
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<Vec<JsonValue>, SafeErr> {
+        self.buffer.extend_from_slice(chunk);
+        let max_depth = limits::limits().max_depth;
+        let mut records = Vec::new();
+        while self.pos < self.buffer.len() {
+            let b = self.buffer[self.pos];
+            match self.start {
+                None => self.between(b)?,
+                Some(start) => {
+                    if let Some(end) = self.scan(start, b) {
+                        records.push(self.complete(start, end)?);
+                    }
+                    // Like max_bytes below, before the record is complete
+                    if self.depth > max_depth {
+                        return Err(format!("Nested deeper than the limit of {}", max_depth).into());
+                    }
+                }
+            }
+        }
+        let keep = self.start.unwrap_or(self.pos);
+        self.buffer.drain(..keep);
+        self.offset += keep;
+        self.pos -= keep;
+        self.start = self.start.map(|start| start - keep);
+        // The record in progress, which max_bytes bounds before it is complete
+        check_bytes(self.buffer.len())?;
+        Ok(records)
+    }
+
+    /// The last record, if the input ended with a number (or true, etc),
+    /// or an error if it ended part way through.
+    pub fn finish(&mut self) -> Result<Vec<JsonValue>, SafeErr> {
+        if self.in_string || self.depth > 0 {
+            return Err(self.error("Unexpected end of JSON"));
+        }
+        let mut records = Vec::new();
+        if let Some(start) = self.start {
+            records.push(self.complete(start, self.buffer.len())?);
+        }
+        if self.records == Records::Array && self.between != Between::Closed {
+            return Err(self.error("Unexpected end of JSON"));
+        }
+        Ok(records)
+    }
+
+    // Outside of any record, either starts the next or steps over a separator
+    fn between(&mut self, b: u8) -> Result<(), SafeErr> {
+        if is_whitespace(b) {
+            self.pos += 1;
+            return Ok(());
+        }
+        if self.records == Records::Values {
+            self.start = Some(self.pos);
+            return Ok(());
+        }
+        match (self.between, b) {
+            (Between::Open, b'[') => self.between = Between::First,
+            (Between::Open, _) => return Err(self.error("Expected an array")),
+            (Between::First, b']') | (Between::Comma, b']') => self.between = Between::Closed,
+            (Between::Comma, b',') => self.between = Between::Element,
+            (Between::Comma, _) => return Err(self.error("Expected ',' or ']'")),
+            (Between::Element, b']') => return Err(self.error("Expected a value")),
+            (Between::First, _) | (Between::Element, _) => {
+                self.start = Some(self.pos);
+                return Ok(());
+            }
+            (Between::Closed, _) => return Err(self.error("Unexpected data after the array")),
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    // Steps over b, returning where the record ends if it is complete
+    fn scan(&mut self, start: usize, b: u8) -> Option<usize> {
+        if self.in_string {
+            self.pos += 1;
+            if self.escaped {
+                self.escaped = false;
+            } else if b == b'\\' {
+                self.escaped = true;
+            } else if b == b'"' {
+                self.in_string = false;
+                if self.depth == 0 {
+                    return Some(self.pos);
+                }
+            }
+            return None;
+        }
+        // A number, true, etc ends at whatever can't be part of it
+        let in_scalar = self.depth == 0 && self.pos > start;
+        if in_scalar && (is_whitespace(b) || b"[]{},\"".contains(&b)) {
+            return Some(self.pos);
+        }
+        self.pos += 1;
+        match b {
+            b'"' => self.in_string = true,
+            b'[' | b'{' => self.depth += 1,
+            b']' | b'}' if self.depth > 0 => {
+                self.depth -= 1;
+                if self.depth == 0 {
+                    return Some(self.pos);
+                }
+            }
+            // Not the start of a value, which parsing will say
+            b']' | b'}' => return Some(self.pos),
+            _ => {}
+        }
+        None
+    }
+
+    fn complete(&mut self, start: usize, end: usize) -> Result<JsonValue, SafeErr> {
+        self.start = None;
+        self.between = Between::Comma;
+        parse_at(&self.buffer[start..end], self.offset + start)
+    }
+}
+
+enum Command {
+    Write(Vec<u8>, JsOneshot),
+    End(JsOneshot),
+}
+
+/// The internals of a JsJsonStream. Empty unless created by json_stream,
+/// and once ended.
+pub struct StreamInternals(Option<Sender<Command>>, Refs);
+
+// The bytes of a Buffer, ArrayBuffer, Uint8Array or string
+fn chunk<'a>(handle: Handle<JsValue>, cx: &mut impl Context<'a>) -> SafeResult<Vec<u8>> {
+    if let Ok(buffer) = handle.downcast::<JsArrayBuffer>() {
+        return with_bytes(buffer, cx, <[u8]>::to_vec);
+    }
+    if let Ok(buffer) = handle.downcast::<JsBuffer>() {
+        return with_bytes(buffer, cx, <[u8]>::to_vec);
+    }
+    with_str_as(handle, cx, "Buffer or string", 1, |s| s.as_bytes().to_vec())
+}
+
+declare_types! {
+    /// The JS side of json_stream. write(chunk) and end() return Promises
+    /// for the records they complete, which arrive in the order written.
+    pub class JsJsonStream for StreamInternals {
+        init(_cx) {
+            Ok(StreamInternals(None, Refs::new()))
+        }
+
+        method write(mut cx) {
+            let this = cx.this();
+            let sender = {
+                let guard = cx.lock();
+                let internals = this.borrow(&guard);
+                internals.0.clone()
+            };
+            let handle = cx.argument::<JsValue>(0)?;
+            let written = (|| -> SafeJsResult<JsObject> {
+                let sender = sender.ok_or("JSON stream has ended")?;
+                let chunk = chunk(handle, &mut cx)?;
+                let (promise, deferred) = JsOneshot::new(&mut cx)?;
+                sender
+                    .send(Command::Write(chunk, deferred))
+                    .map_err(|_| "JSON stream has ended")?;
+                Ok(promise)
+            })();
+            match written {
+                Ok(promise) => Ok(promise.upcast()),
+                Err(e) => e.finish(cx),
+            }
+        }
+
+        method end(mut cx) {
+            let mut this = cx.this();
+            let sender = cx.borrow_mut(&mut this, |mut internals| {
+                // The last write holds Node open until it's done
+                internals.1 = Refs::new();
+                internals.0.take()
+            });
+            let ended = (|| -> SafeJsResult<JsObject> {
+                let sender = sender.ok_or("JSON stream has ended")?;
+                let (promise, deferred) = JsOneshot::new(&mut cx)?;
+                sender
+                    .send(Command::End(deferred))
+                    .map_err(|_| "JSON stream has ended")?;
+                Ok(promise)
+            })();
+            match ended {
+                Ok(promise) => Ok(promise.upcast()),
+                Err(e) => e.finish(cx),
+            }
+        }
+    }
+}
+
+/// Creates a stream for JS to write chunks of JSON to, which are parsed
+/// on a thread of its own. Eg, to yield each record from an async iterable:
+///   for await (const chunk of body)
+///     yield* await stream.write(chunk);
+///   yield* await stream.end();
+/// The first invalid JSON rejects that write, and every call after it.
+/// The MarshalLimits of the calling thread apply to each record.
+pub fn json_stream<'a>(cx: &mut impl Context<'a>, records: Records) -> JsResult<'a, JsJsonStream> {
+    let (sender, commands) = crossbeam_channel::unbounded();
+    // Limits are per thread, so the parsing thread takes this one's
+    let limits = limits::limits();
+    thread::spawn(move || {
+        limits::set_limits(limits);
+        let mut parser = StreamParser::new(records);
+        // SafeErr can't be sent, so errors go back as their message
+        let mut failed: Option<String> = None;
+        for command in commands {
+            let (deferred, result) = match command {
+                Command::Write(chunk, deferred) => (deferred, parser.feed(&chunk)),
+                Command::End(deferred) => (deferred, parser.finish()),
+            };
+            let result = match (&failed, result) {
+                (Some(e), _) => Err(e.clone()),
+                (None, Ok(records)) => Ok(records),
+                (None, Err(e)) => {
+                    failed = Some(e.to_string());
+                    Err(e.to_string())
+                }
+            };
+            deferred.settle(result);
+        }
+    });
+    let mut stream = JsJsonStream::new::<_, JsValue, _>(cx, vec![])?;
+    cx.borrow_mut(&mut stream, |mut internals| internals.0 = Some(sender));
+    // ref and unref are keywords, so can't be methods in declare_types!
+    let constructor = JsJsonStream::constructor(cx)?;
+    let prototype: Handle<JsObject> = constructor.get(cx, "prototype")?;
+    let existing: Handle<JsValue> = prototype.get(cx, "ref")?;
+    if existing.is_a::<JsUndefined>() {
+        let js_ref = JsFunction::new(cx, stream_ref)?;
+        let js_unref = JsFunction::new(cx, stream_unref)?;
+        prototype.set(cx, "ref", js_ref)?;
+        prototype.set(cx, "unref", js_unref)?;
+    }
+    Ok(stream)
+}
+
+/// stream.ref(): holds Node open until as many stream.unref() calls or
+/// the stream ends, eg: while waiting on a source of chunks which doesn't
+/// hold it open itself. A write in progress always does. Returns the count.
+fn stream_ref(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    let mut this = cx.this().downcast_or_throw::<JsJsonStream, _>(&mut cx)?;
+    // Taken out while the hold is made, which needs cx
+    let (ended, mut refs) = cx.borrow_mut(&mut this, |mut internals| {
+        (internals.0.is_none(), mem::take(&mut internals.1))
+    });
+    if ended {
+        return cx.throw_error("JSON stream has ended");
+    }
+    let count = refs.add(&mut cx);
+    cx.borrow_mut(&mut this, |mut internals| internals.1 = refs);
+    Ok(cx.number(count?))
+}
+
+/// stream.unref(): undoes a stream.ref(). Returns the count.
+fn stream_unref(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    let mut this = cx.this().downcast_or_throw::<JsJsonStream, _>(&mut cx)?;
+    let count = cx.borrow_mut(&mut this, |mut internals| internals.1.remove());
+    Ok(cx.number(count))
+}
+
+/// jsonStream(records = "values")
+pub fn js_json_stream(mut cx: FunctionContext) -> JsResult<JsJsonStream> {
+    let records = match cx.argument_opt(0) {
+        Some(records) => Option::<Records>::from_handle(records, &mut cx),
+        None => Ok(None),
+    };
+    match records {
+        Ok(records) => json_stream(&mut cx, records.unwrap_or(Records::Values)),
+        Err(e) => e.finish(cx),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::marshalling::MarshalLimits;
+
+    fn feed(records: Records, chunks: &[&str]) -> Result<Vec<JsonValue>, String> {
+        let mut parser = StreamParser::new(records);
+        let mut all = Vec::new();
+        for chunk in chunks {
+            all.extend(parser.feed(chunk.as_bytes()).map_err(|e| e.to_string())?);
+        }
+        all.extend(parser.finish().map_err(|e| e.to_string())?);
+        Ok(all)
+    }
+
+    fn numbers(values: Vec<JsonValue>) -> Vec<f64> {
+        values
+            .into_iter()
+            .map(|v| match v {
+                JsonValue::Number(n) => n,
+                other => panic!("Expected a number, got {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn splits_values() {
+        let values = feed(Records::Values, &["1 2", "3\n", "45", "\n6"]).unwrap();
+        assert_eq!(numbers(values), vec![1.0, 23.0, 45.0, 6.0]);
+
+        let values = feed(Records::Values, &["{\"a\":\"}\\\"", "\"}[1,", "2]\"x\""]).unwrap();
+        assert_eq!(values.len(), 3);
+        assert_eq!(values[2], JsonValue::String("x".into()));
+        assert_eq!(feed(Records::Values, &["", " "]).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn splits_array() {
+        let values = feed(Records::Array, &[" [1", "0, 2 ,", "3]  "]).unwrap();
+        assert_eq!(numbers(values), vec![10.0, 2.0, 3.0]);
+        assert_eq!(feed(Records::Array, &["[", "]"]).unwrap(), vec![]);
+
+        // Elements split across chunks are only parsed once complete
+        let mut parser = StreamParser::new(Records::Array);
+        assert_eq!(
+            parser
+                .feed(b"[{\"a\": [1, 2")
+                .unwrap_or_else(|e| panic!("{}", e))
+                .len(),
+            0
+        );
+        assert_eq!(
+            parser
+                .feed(b"]}, {")
+                .unwrap_or_else(|e| panic!("{}", e))
+                .len(),
+            1
+        );
+        assert_eq!(parser.buffer, b"{");
+    }
+
+    #[test]
+    fn errors() {
+        let err = |records, chunks| feed(records, chunks).err().unwrap();
+        assert_eq!(
+            err(Records::Array, &["{}"]),
+            "Invalid JSON at byte 0: Expected an array"
+        );
+        assert_eq!(
+            err(Records::Array, &["[1,", "]"]),
+            "Invalid JSON at byte 3: Expected a value"
+        );
+        assert_eq!(
+            err(Records::Array, &["[1 2]"]),
+            "Invalid JSON at byte 3: Expected ',' or ']'"
+        );
+        assert_eq!(
+            err(Records::Array, &["[1]", " 2"]),
+            "Invalid JSON at byte 4: Unexpected data after the array"
+        );
+        assert_eq!(
+            err(Records::Array, &["[1, 2"]),
+            "Invalid JSON at byte 5: Unexpected end of JSON"
+        );
+        assert_eq!(
+            err(Records::Values, &["1 ", "{\"a\" 1}"]),
+            "Invalid JSON at byte 7: expected `:`"
+        );
+        assert_eq!(
+            err(Records::Values, &["tru"]),
+            "Invalid JSON at byte 2: EOF while parsing a value"
+        );
+    }
+
+    #[test]
+    fn limits() {
+        let strict = MarshalLimits {
+            max_bytes: 8,
+            ..MarshalLimits::UNLIMITED
+        };
+        limits::with_limits(strict, || {
+            let values = feed(Records::Array, &["[1234", "5678, 1234", "5678, 1]"]).unwrap();
+            assert_eq!(numbers(values), vec![12345678.0, 12345678.0, 1.0]);
+            // Never completed, so only the buffer's size gives it away
+            assert_eq!(
+                feed(Records::Values, &["[1, 2", ", 3, 4", "]"])
+                    .err()
+                    .unwrap(),
+                "Got 11 bytes, which exceeds the limit of 8"
+            );
+        });
+        let shallow = MarshalLimits {
+            max_depth: 2,
+            ..MarshalLimits::UNLIMITED
+        };
+        limits::with_limits(shallow, || {
+            assert_eq!(feed(Records::Array, &["[[1], [[2]]]"]).unwrap().len(), 2);
+            assert_eq!(
+                feed(Records::Values, &["[[", "[", "]]]"]).err().unwrap(),
+                "Nested deeper than the limit of 2"
+            );
+        });
+    }
+}
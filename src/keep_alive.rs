@@ -0,0 +1,119 @@
+//! Holding Node open for native work which has nothing outstanding yet,
+//! eg: a service thread which will emit events later but hasn't started
+//! a task or batch to do it with. Without a hold the process may exit
+//! first. JS does the same as Rust's KeepAlive with ref() and unref().
+//! The task pool and each JSON stream have a ref() and unref() of their
+//! own as well, which hold Node open until that thing is unref'd. A
+//! batched emitter holds Node open for as long as any of its BatchSenders
+//! live, so it has none.
+//!
+//! Under the legacy runtime every callback still pending (a task, a batch
+//! sender, a promise) keeps Node running through the shared EventHandler,
+//! which can't be unref'd. So unref only lets go of what ref held: it
+//! can't let the process exit out from under pending work.
+
+use crate::channel::{self, Slot};
+use neon::prelude::*;
+use std::cell::RefCell;
+use std::thread::LocalKey;
+
+/// Keeps Node running until dropped or released, from any thread
+pub struct KeepAlive(Option<Slot>);
+
+impl KeepAlive {
+    pub fn new<'a>(cx: &mut impl Context<'a>) -> NeonResult<Self> {
+        let nothing = cx.undefined();
+        Ok(Self(Some(channel::hold(cx, nothing)?)))
+    }
+
+    /// Lets Node exit once nothing else holds it
+    pub fn release(&mut self) {
+        self.0 = None;
+    }
+
+    pub fn is_held(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+/// Nesting ref() and unref() for one thing, eg: a stream. Holds Node
+/// open from the first ref until as many unrefs, or until dropped.
+#[derive(Default)]
+pub struct Refs {
+    count: u32,
+    hold: Option<KeepAlive>,
+}
+
+impl Refs {
+    pub const fn new() -> Self {
+        Self {
+            count: 0,
+            hold: None,
+        }
+    }
+
+    /// Returns the count
+    pub fn add<'a>(&mut self, cx: &mut impl Context<'a>) -> NeonResult<u32> {
+        if self.hold.is_none() {
+            self.hold = Some(KeepAlive::new(cx)?);
+        }
+        self.count += 1;
+        Ok(self.count)
+    }
+
+    /// Extra calls do nothing. Returns the count.
+    pub fn remove(&mut self) -> u32 {
+        self.count = self.count.saturating_sub(1);
+        if self.count == 0 {
+            self.hold = None;
+        }
+        self.count
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+}
+
+thread_local! {
+    // For the global ref() and unref() from JS
+    static REFS: RefCell<Refs> = const { RefCell::new(Refs::new()) };
+}
+
+/// ref(): holds Node open until as many unref() calls. Returns the count.
+pub fn js_ref(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    let count = local_ref(&REFS, &mut cx)?;
+    Ok(cx.number(count))
+}
+
+/// unref(): undoes a ref(). Extra calls do nothing. Returns the count.
+pub fn js_unref(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    let count = local_unref(&REFS);
+    Ok(cx.number(count))
+}
+
+// Refs::add for Refs in a thread local, which isn't borrowed while the
+// hold is made in case that runs JS
+pub(crate) fn local_ref<'a>(
+    refs: &'static LocalKey<RefCell<Refs>>,
+    cx: &mut impl Context<'a>,
+) -> NeonResult<u32> {
+    let held = refs.with(|refs| refs.borrow().hold.is_some());
+    let hold = if held {
+        None
+    } else {
+        Some(KeepAlive::new(cx)?)
+    };
+    Ok(refs.with(|refs| {
+        let mut refs = refs.borrow_mut();
+        refs.count += 1;
+        if hold.is_some() {
+            refs.hold = hold;
+        }
+        refs.count
+    }))
+}
+
+pub(crate) fn local_unref(refs: &'static LocalKey<RefCell<Refs>>) -> u32 {
+    refs.with(|refs| refs.borrow_mut().remove())
+}
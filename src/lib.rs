@@ -1,5 +1,34 @@
+pub mod abi;
+pub mod callback;
+pub(crate) mod channel;
+pub mod config;
+pub mod crypto;
+pub mod decls;
 pub mod errors;
+pub mod events;
+pub mod instance;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod keep_alive;
+pub mod logging;
 pub mod marshalling;
-pub(crate) mod prelude;
+pub mod memory;
+pub mod module;
+pub mod prelude;
+pub mod profiling;
+pub mod promise;
 pub mod proxy;
+pub mod queue;
+pub mod registry;
+#[cfg(feature = "tokio")]
+pub mod runtime;
+pub mod shutdown;
+pub mod singleton;
 pub mod task;
+pub mod templates;
+pub mod tsfn;
+pub mod uncaught;
+
+// Expanded by js_object!, so it has to be reachable as $crate::__js_object_fields
+#[doc(hidden)]
+pub use neon_utils_derive::__js_object_fields;
@@ -0,0 +1,114 @@
+use crate::errors::{SafeResult, Terminal};
+use crate::marshalling::Arg;
+use neon::event::EventHandler;
+use neon::prelude::*;
+use std::io::{self, Write};
+use std::sync::{Mutex, Once, OnceLock};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, reload, Registry};
+
+// Where formatted lines go. None is stderr.
+static OUTPUT: Mutex<Option<EventHandler>> = Mutex::new(None);
+static LEVEL: OnceLock<reload::Handle<LevelFilter, Registry>> = OnceLock::new();
+static INIT: Once = Once::new();
+
+/// Installs a tracing subscriber that writes to stderr at the level
+/// from config::get(), which is WARN unless configured otherwise.
+/// Calling this more than once (eg: from each worker_thread) is fine,
+/// but it will not replace a subscriber installed by someone else.
+///
+/// run_async and Terminal::finish have spans at the TRACE level,
+/// so setting the level to trace shows every call.
+pub fn init() {
+    INIT.call_once(|| {
+        let (filter, handle) = reload::Layer::new(crate::config::get().log_level);
+        let output = fmt::layer().with_ansi(false).with_writer(Line::default);
+        if Registry::default()
+            .with(filter)
+            .with(output)
+            .try_init()
+            .is_ok()
+        {
+            let _ = LEVEL.set(handle);
+        }
+    });
+}
+
+pub fn set_level(level: LevelFilter) {
+    init();
+    if let Some(handle) = LEVEL.get() {
+        let _ = handle.modify(|filter| *filter = level);
+    }
+}
+
+pub fn to_stderr() {
+    init();
+    *OUTPUT.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Sends each line to a JS function, eg: console.log.
+/// Like a setInterval, this keeps Node running until the
+/// function is removed by going back to stderr.
+pub fn to_js<'a>(cx: &mut impl Context<'a>, callback: Handle<JsFunction>) {
+    init();
+    let this = cx.undefined();
+    let handler = EventHandler::new(&*cx, this, callback);
+    *OUTPUT.lock().unwrap_or_else(|e| e.into_inner()) = Some(handler);
+}
+
+/// setLogLevel("off" | "error" | "warn" | "info" | "debug" | "trace")
+pub fn js_set_log_level(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let level: SafeResult<String> = cx.arg(0);
+    level
+        .and_then(|level| {
+            level
+                .parse::<LevelFilter>()
+                .map_err(|_| format!("Invalid log level '{}'", level).into())
+        })
+        .map(set_level)
+        .finish(cx)
+}
+
+/// setLogger(console.log) to log to JS, or setLogger() to go back to stderr
+pub fn js_set_logger(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    match cx.argument_opt(0) {
+        Some(callback) if !callback.is_a::<JsUndefined>() && !callback.is_a::<JsNull>() => {
+            let callback = callback.downcast_or_throw::<JsFunction, _>(&mut cx)?;
+            to_js(&mut cx, callback);
+        }
+        _ => to_stderr(),
+    }
+    Ok(cx.undefined())
+}
+
+// The fmt layer writes each event to a new writer,
+// so the whole line is sent when it is dropped.
+#[derive(Default)]
+struct Line(Vec<u8>);
+
+impl Write for Line {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for Line {
+    fn drop(&mut self) {
+        let output = OUTPUT.lock().unwrap_or_else(|e| e.into_inner());
+        match output.as_ref() {
+            None => {
+                let _ = io::stderr().write_all(&self.0);
+            }
+            Some(handler) => {
+                let line = String::from_utf8_lossy(&self.0).trim_end().to_owned();
+                handler.schedule(move |cx| vec![cx.string(line)]);
+            }
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use super::bytes::with_binary;
+use super::codecs::DecodeBytes;
+use super::*;
+use crate::crypto::hash::keccak256;
+use crate::decls::ts_type;
+use crate::errors::SafeJsResult;
+use primitive_types::H160;
+use rustc_hex::ToHex as _;
+use std::fmt;
+use std::str::FromStr;
+
+/// An Ethereum address. Unlike [u8; 20] this checks the EIP-55 checksum
+/// when parsing mixed case, and is written in checksummed form.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Address(pub [u8; 20]);
+
+impl Address {
+    /// The 0x prefixed EIP-55 checksummed form
+    pub fn to_checksum(&self) -> String {
+        let lower: String = self.0.to_hex();
+        let hash = keccak256(lower.as_bytes());
+        let mut result = String::with_capacity(42);
+        result.push_str("0x");
+        for (i, c) in lower.chars().enumerate() {
+            let nibble = (hash[i / 2] >> (4 * (1 - i % 2))) & 0xf;
+            result.push(if nibble >= 8 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            });
+        }
+        result
+    }
+}
+
+impl FromStr for Address {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.strip_prefix("0x").unwrap_or(s);
+        if hex.len() != 40 {
+            return Err(format!(
+                "Expected an address of 40 hex characters but got {}",
+                hex.len()
+            ));
+        }
+        let mut bytes = [0; 20];
+        for (i, c) in hex.chars().enumerate() {
+            let nibble = c
+                .to_digit(16)
+                .ok_or_else(|| format!("Invalid character '{}' in address at {}", c, i))?;
+            bytes[i / 2] |= (nibble as u8) << (4 * (1 - i % 2));
+        }
+        let address = Address(bytes);
+
+        // All lower or all upper case has no checksum
+        let has_lower = hex.bytes().any(|b| b.is_ascii_lowercase());
+        let has_upper = hex.bytes().any(|b| b.is_ascii_uppercase());
+        if has_lower && has_upper {
+            let checksummed = address.to_checksum();
+            if checksummed[2..] != *hex {
+                return Err(format!(
+                    "Invalid checksum for address {}, expected {}",
+                    s, checksummed
+                ));
+            }
+        }
+        Ok(address)
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_checksum())
+    }
+}
+
+impl From<[u8; 20]> for Address {
+    fn from(bytes: [u8; 20]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<H160> for Address {
+    fn from(h: H160) -> Self {
+        Self(h.0)
+    }
+}
+
+impl From<Address> for H160 {
+    fn from(address: Address) -> Self {
+        H160(address.0)
+    }
+}
+
+impl DecodeBytes for Address {
+    fn decode_bytes(bytes: &[u8]) -> Result<Self, ()> {
+        DecodeBytes::decode_bytes(bytes).map(Address)
+    }
+}
+
+impl FromHandle for Address {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        // Raw bytes have no checksum to check
+        if let Some(address) = with_binary(handle, cx, DecodeBytes::decode_bytes)? {
+            return Ok(address.map_err(|_| "Expected an address of 20 bytes")?);
+        }
+        let s = String::from_handle(handle, cx)?;
+        Ok(s.parse::<Address>()?)
+    }
+}
+
+impl IntoHandle for Address {
+    type Handle = JsString;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        let interned = super::interned::checksums();
+        if interned.is_enabled() {
+            let checksum = interned.get_or_insert_with(&self.0, || self.to_checksum());
+            return (*checksum).into_handle(cx);
+        }
+        self.to_checksum().into_handle(cx)
+    }
+}
+
+ts_type!(Address => "string | Buffer | ArrayBuffer", "string");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // From the EIP-55 spec
+    const CHECKSUMMED: [&str; 4] = [
+        "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+        "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+        "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+        "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+    ];
+
+    #[test]
+    fn checksum() {
+        for s in CHECKSUMMED {
+            let address: Address = s.parse().unwrap();
+            assert_eq!(address.to_checksum(), s);
+            let lower: Address = s.to_lowercase().parse().unwrap();
+            assert_eq!(lower, address);
+            let upper: Address = s[2..].to_uppercase().parse().unwrap();
+            assert_eq!(upper, address);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let bad_checksum = CHECKSUMMED[0].replace('a', "A");
+        assert!(bad_checksum
+            .parse::<Address>()
+            .unwrap_err()
+            .starts_with("Invalid checksum"));
+        assert_eq!(
+            "0x1234".parse::<Address>().unwrap_err(),
+            "Expected an address of 40 hex characters but got 4"
+        );
+        assert_eq!(
+            format!("0x{}g", "0".repeat(39))
+                .parse::<Address>()
+                .unwrap_err(),
+            "Invalid character 'g' in address at 39"
+        );
+    }
+}
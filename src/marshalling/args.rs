@@ -0,0 +1,210 @@
+//! Checks the arguments of a call as a whole before decoding them, so that
+//! a missing argument is reported as missing rather than read as undefined.
+
+use super::*;
+use crate::errors::SafeResultExt;
+use neon::object::This;
+use std::ops::{Bound, RangeBounds};
+
+/// What a function takes: required arguments, then optional ones, then
+/// optionally a plain object of options and a callback, eg:
+///   let args = ArgsSpec::new(1).optional(1).options().callback().check(&mut cx)?;
+///   let to: Address = args.get(&mut cx, 0)?;
+///   let memo: Option<String> = args.get_opt(&mut cx, 1)?;
+///   let options: Option<TransferOptions> = args.options(&mut cx)?;
+///
+/// The options and callback are recognized by type from the end, so
+/// they may be left out even when optional arguments are too.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ArgsSpec {
+    required: usize,
+    optional: usize,
+    options: bool,
+    callback: bool,
+}
+
+/// The arguments of a call, checked against an ArgsSpec
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Args {
+    positional: usize,
+    options: Option<i32>,
+    callback: Option<i32>,
+}
+
+impl ArgsSpec {
+    pub fn new(required: usize) -> Self {
+        Self {
+            required,
+            ..Self::default()
+        }
+    }
+
+    pub fn optional(mut self, optional: usize) -> Self {
+        self.optional = optional;
+        self
+    }
+
+    /// Takes a plain object of options after the other arguments
+    pub fn options(mut self) -> Self {
+        self.options = true;
+        self
+    }
+
+    /// Takes a function last
+    pub fn callback(mut self) -> Self {
+        self.callback = true;
+        self
+    }
+
+    pub fn check<O: This>(&self, cx: &mut CallContext<O>) -> SafeResult<Args> {
+        let mut positional = cx.len() as usize;
+        let mut args = Args {
+            positional,
+            options: None,
+            callback: None,
+        };
+        if self.callback && positional > 0 {
+            let last = cx.argument::<JsValue>(positional as i32 - 1)?;
+            if last.is_a::<JsFunction>() {
+                positional -= 1;
+                args.callback = Some(positional as i32);
+            }
+        }
+        // Only past the required arguments, which may be objects themselves
+        if self.options && positional > self.required {
+            let last = cx.argument::<JsValue>(positional as i32 - 1)?;
+            if type_name_of(last, cx) == "object" {
+                positional -= 1;
+                args.options = Some(positional as i32);
+            }
+        }
+        args.positional = positional;
+
+        let max = self.required + self.optional;
+        if positional > max {
+            let expected = expected_count(self.required, Some(max));
+            return Err(format!("Expected {}, got {}", expected, positional).into());
+        }
+        for i in 0..self.required {
+            let missing = match cx.argument_opt(i as i32) {
+                Some(arg) => arg.is_a::<JsUndefined>(),
+                None => true,
+            };
+            if missing {
+                return Err(format!("Missing argument {}", i + 1).into());
+            }
+        }
+        Ok(args)
+    }
+}
+
+impl Args {
+    /// How many arguments there are, besides the options and callback
+    pub fn len(&self) -> usize {
+        self.positional
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positional == 0
+    }
+
+    /// Decodes argument i, which is missing if it wasn't passed
+    pub fn get<T: FromHandle, O: This>(&self, cx: &mut CallContext<O>, i: usize) -> SafeResult<T> {
+        match self.get_opt(cx, i)? {
+            Some(value) => Ok(value),
+            None => Err(format!("Missing argument {}", i + 1).into()),
+        }
+    }
+
+    /// Decodes argument i, or None if it wasn't passed or is undefined
+    pub fn get_opt<T: FromHandle, O: This>(
+        &self,
+        cx: &mut CallContext<O>,
+        i: usize,
+    ) -> SafeResult<Option<T>> {
+        if i >= self.positional {
+            return Ok(None);
+        }
+        let arg = cx.argument::<JsValue>(i as i32)?;
+        if arg.is_a::<JsUndefined>() {
+            return Ok(None);
+        }
+        T::from_handle(arg, cx)
+            .map(Some)
+            .with_context(|| format!("Invalid argument {}", i + 1))
+    }
+
+    pub fn options<T: FromHandle, O: This>(
+        &self,
+        cx: &mut CallContext<O>,
+    ) -> SafeResult<Option<T>> {
+        let i = match self.options {
+            Some(i) => i,
+            None => return Ok(None),
+        };
+        let options = cx.argument::<JsValue>(i)?;
+        T::from_handle(options, cx)
+            .map(Some)
+            .with_context(|| "Invalid options")
+    }
+
+    pub fn callback<'a, O: This>(
+        &self,
+        cx: &mut CallContext<'a, O>,
+    ) -> NeonResult<Option<Handle<'a, JsFunction>>> {
+        self.callback
+            .map(|i| cx.argument::<JsFunction>(i))
+            .transpose()
+    }
+}
+
+/// cx.expect_args(1..=2), to check only how many arguments there are
+pub trait ExpectArgs {
+    /// The number of arguments, or an error if it's out of range, eg:
+    /// "Expected 1 to 2 arguments, got 3". Trailing undefined counts.
+    fn expect_args(&mut self, range: impl RangeBounds<usize>) -> SafeResult<usize>;
+}
+
+impl<O: This> ExpectArgs for CallContext<'_, O> {
+    fn expect_args(&mut self, range: impl RangeBounds<usize>) -> SafeResult<usize> {
+        let len = self.len() as usize;
+        if range.contains(&len) {
+            return Ok(len);
+        }
+        let min = match range.start_bound() {
+            Bound::Included(&min) => min,
+            Bound::Excluded(&min) => min + 1,
+            Bound::Unbounded => 0,
+        };
+        let max = match range.end_bound() {
+            Bound::Included(&max) => Some(max),
+            Bound::Excluded(&max) => Some(max.saturating_sub(1)),
+            Bound::Unbounded => None,
+        };
+        Err(format!("Expected {}, got {}", expected_count(min, max), len).into())
+    }
+}
+
+fn expected_count(min: usize, max: Option<usize>) -> String {
+    let plural = |n: usize| if n == 1 { "argument" } else { "arguments" };
+    match max {
+        Some(max) if max == min => format!("{} {}", min, plural(min)),
+        Some(max) if min == 0 => format!("at most {} {}", max, plural(max)),
+        Some(max) => format!("{} to {} arguments", min, max),
+        None => format!("at least {} {}", min, plural(min)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expected_counts() {
+        assert_eq!(expected_count(1, Some(1)), "1 argument");
+        assert_eq!(expected_count(0, Some(0)), "0 arguments");
+        assert_eq!(expected_count(0, Some(2)), "at most 2 arguments");
+        assert_eq!(expected_count(1, Some(3)), "1 to 3 arguments");
+        assert_eq!(expected_count(1, None), "at least 1 argument");
+    }
+}
@@ -0,0 +1,77 @@
+use super::*;
+use std::fmt;
+
+/// A number which must be from MIN to MAX inclusive, eg: a port is
+/// Bounded<u16, 1, 65535> and a percentage is Bounded<f64, 0, 100>.
+/// Out of range values fail to decode with the range in the error.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct Bounded<T, const MIN: i64, const MAX: i64>(pub T);
+
+impl<T, const MIN: i64, const MAX: i64> Bounded<T, MIN, MAX> {
+    pub fn get(self) -> T {
+        self.0
+    }
+}
+
+/// The numbers Bounded can hold
+pub trait InRange: Copy + fmt::Display {
+    fn in_range(self, min: i64, max: i64) -> bool;
+}
+
+macro_rules! in_range_int {
+    ($($t:ty),*) => {
+        $(
+            impl InRange for $t {
+                fn in_range(self, min: i64, max: i64) -> bool {
+                    (min as i128..=max as i128).contains(&(self as i128))
+                }
+            }
+        )*
+    };
+}
+
+in_range_int!(u8, u16, u32, u64);
+
+impl InRange for f64 {
+    fn in_range(self, min: i64, max: i64) -> bool {
+        (min as f64..=max as f64).contains(&self)
+    }
+}
+
+impl<T, const MIN: i64, const MAX: i64> FromHandle for Bounded<T, MIN, MAX>
+where
+    T: FromHandle + InRange,
+{
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        let value = T::from_handle(handle, cx)?;
+        if !value.in_range(MIN, MAX) {
+            return Err(format!("Expected a number from {} to {}, got {}", MIN, MAX, value).into());
+        }
+        Ok(Self(value))
+    }
+}
+
+impl<T: IntoHandle, const MIN: i64, const MAX: i64> IntoHandle for Bounded<T, MIN, MAX> {
+    type Handle = T::Handle;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        self.0.into_handle(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges() {
+        assert!(80u16.in_range(1, 65535));
+        assert!(!0u16.in_range(1, 65535));
+        assert!(!u64::MAX.in_range(0, i64::MAX));
+        assert!(100.0.in_range(0, 100));
+        assert!(!100.5.in_range(0, 100));
+        assert!(!f64::NAN.in_range(0, 100));
+    }
+}
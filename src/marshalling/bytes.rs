@@ -0,0 +1,436 @@
+use super::codecs::*;
+use super::handle_impls::with_str_as;
+use super::inspect::expect;
+use super::limits;
+use super::*;
+use crate::config;
+use crate::decls::ts_type;
+use crate::errors::{SafeJsResult, SafeResultExt};
+use neon::types::{BinaryData, JsArrayBuffer, JsBuffer};
+use std::convert::TryInto;
+use std::ops::Deref;
+use std::str::FromStr;
+use std::sync::Arc;
+
+// Vec<u8> is an Array of numbers like any other Vec<T>. Bytes are written
+// through one of these wrappers instead, which say what JS should get.
+
+crate::js_enum! {
+    /// How HexBytes are written, which is set for the crate by config.
+    /// Hex is read with or without a 0x prefix either way.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum HexFormat {
+        #[default]
+        Plain,
+        Prefixed,
+    }
+}
+
+impl HexFormat {
+    pub fn encode(self, bytes: &[u8]) -> String {
+        match self {
+            HexFormat::Plain => hex::encode(bytes),
+            HexFormat::Prefixed => hex::encode_prefixed(bytes),
+        }
+    }
+}
+
+/// For NEON_UTILS_HEX_FORMAT, as in JS: "plain" or "prefixed"
+impl FromStr for HexFormat {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(HexFormat::Plain),
+            "prefixed" => Ok(HexFormat::Prefixed),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Bytes as a hex string, with a 0x prefix if config says HexFormat::Prefixed.
+/// Reads from a hex string, Buffer, ArrayBuffer or Uint8Array.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct HexBytes(pub Vec<u8>);
+
+/// As HexBytes, but always with a 0x prefix whatever the config says,
+/// eg: for ethers which requires it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PrefixedHex(pub Vec<u8>);
+
+/// Bytes as an Array of numbers. This is what Vec<u8> does too.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ByteArray(pub Vec<u8>);
+
+/// Bytes which are shared rather than copied when cloned, eg: a payload
+/// handed to several tasks. Reads like HexBytes, copying a Buffer or
+/// ArrayBuffer once straight into the Arc, and is written like HexBytes.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Bytes(pub Arc<[u8]>);
+
+// Specify that the data should be converted to an ArrayBuffer.
+// Either reads from a Buffer, ArrayBuffer or Uint8Array, but not a string.
+pub struct AsArrayBuffer(pub Vec<u8>);
+
+// Specify that the data should be converted to a Node Buffer.
+// The legacy runtime can't wrap Rust memory in a Buffer, so this is a single copy.
+pub struct AsNodeBuffer(pub Vec<u8>);
+
+impl From<Vec<u8>> for HexBytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<Vec<u8>> for Bytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes.into())
+    }
+}
+
+impl From<&[u8]> for Bytes {
+    fn from(bytes: &[u8]) -> Self {
+        Self(bytes.into())
+    }
+}
+
+impl Deref for Bytes {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for Bytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for PrefixedHex {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<Vec<u8>> for ByteArray {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl IntoHandle for HexBytes {
+    type Handle = JsString;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        config::get().hex_format.encode(&self.0).into_handle(cx)
+    }
+}
+
+impl IntoHandle for PrefixedHex {
+    type Handle = JsString;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        HexFormat::Prefixed.encode(&self.0).into_handle(cx)
+    }
+}
+
+impl FromHandle for PrefixedHex {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        HexBytes::from_handle(handle, cx).map(|bytes| Self(bytes.0))
+    }
+}
+
+ts_type!(PrefixedHex => "string | Buffer | ArrayBuffer", "string");
+
+impl FromHandle for HexBytes {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        if let Some(buffer) = with_binary(handle, cx, <[u8]>::to_vec)? {
+            return Ok(Self(buffer));
+        }
+
+        if !handle.is_a::<JsString>() {
+            return Err(Candidates::binary()
+                .or("hex string")
+                .error(handle, cx)
+                .into());
+        }
+        read_hex(handle, cx).map(Self)
+    }
+}
+
+ts_type!(HexBytes => "string | Buffer | ArrayBuffer", "string");
+
+// Hex strings longer than this are read a chunk at a time, so that decoding
+// one needs the output and a chunk rather than a copy of the whole string too
+const HEX_CHUNK: usize = 64 * 1024;
+
+fn read_hex<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Vec<u8>> {
+    let js_str: Handle<JsString> = expect(handle, cx, "hex string")?;
+    let size = js_str.size() as usize;
+    if size <= HEX_CHUNK {
+        let v = with_str_as(handle, cx, "hex string", 2, |s| decode(s))?;
+        return Ok(v.map_err(|_| "Invalid hex")?);
+    }
+    limits::check_bytes(size / 2)?;
+
+    let global = cx.global();
+    let string: Handle<JsObject> = global.get(cx, "String")?;
+    let prototype: Handle<JsObject> = string.get(cx, "prototype")?;
+    let slice: Handle<JsFunction> = prototype.get(cx, "slice")?;
+    // slice counts UTF-16 code units, which are bytes while the string is
+    // hex. Anything else fails to decode whichever chunk it lands in.
+    let mut decoder = HexDecoder::with_capacity(size / 2);
+    let mut start = 0;
+    while start < size {
+        let end = (start + HEX_CHUNK).min(size);
+        let bounds = vec![cx.number(start as f64), cx.number(end as f64)];
+        let chunk: Handle<JsString> = slice.call(cx, js_str, bounds)?.downcast_or_throw(cx)?;
+        let chunk = chunk.value();
+        let digits = if start == 0 {
+            strip_hex_prefix(&chunk)
+        } else {
+            &chunk
+        };
+        decoder
+            .update(digits.as_bytes())
+            .map_err(|_| "Invalid hex")?;
+        start = end;
+    }
+    Ok(decoder.finish().map_err(|_| "Invalid hex")?)
+}
+
+impl IntoHandle for Bytes {
+    type Handle = JsString;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        config::get().hex_format.encode(&self.0).into_handle(cx)
+    }
+}
+
+impl FromHandle for Bytes {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        if let Some(bytes) = with_binary(handle, cx, |bytes| Arc::<[u8]>::from(bytes))? {
+            return Ok(Self(bytes));
+        }
+        HexBytes::from_handle(handle, cx).map(|bytes| bytes.0.into())
+    }
+}
+
+ts_type!(Bytes => "string | Buffer | ArrayBuffer", "string");
+
+impl IntoHandle for ByteArray {
+    type Handle = JsArray;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        let len: u32 = self
+            .0
+            .len()
+            .try_into()
+            .map_err(|_| "Array to large for JavaScript")?;
+        let arr = JsArray::new(cx, len);
+        for (i, byte) in self.0.iter().enumerate() {
+            let value = cx.number(*byte);
+            arr.set(cx, i as u32, value)?;
+        }
+        Ok(arr)
+    }
+}
+
+impl FromHandle for ByteArray {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        let js_array: Handle<JsArray> = expect(handle, cx, "array")?;
+        limits::check_bytes(js_array.len() as usize)?;
+        let mut bytes = Vec::with_capacity(js_array.len() as usize);
+        for (i, item) in js_array.to_vec(cx)?.into_iter().enumerate() {
+            let byte = expect::<JsNumber, _>(item, cx, "number")
+                .map(|n| n.value())
+                .and_then(|n| {
+                    if n.fract() == 0.0 && (0.0..=255.0).contains(&n) {
+                        Ok(n as u8)
+                    } else {
+                        Err("Got number exceeding limits of u8".into())
+                    }
+                })
+                .with_context(|| format!("Invalid element at index {}", i))?;
+            bytes.push(byte);
+        }
+        Ok(Self(bytes))
+    }
+}
+
+ts_type!(ByteArray => "number[]", "number[]");
+
+impl IntoHandle for AsArrayBuffer {
+    type Handle = JsArrayBuffer;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        let size: u32 = self
+            .0
+            .len()
+            .try_into()
+            .map_err(|_| "Array to large for JavaScript")?;
+        let buffer = cx.array_buffer(size)?;
+        with_bytes_mut(buffer, cx, |bytes| bytes.copy_from_slice(&self.0))?;
+        Ok(buffer)
+    }
+}
+
+impl IntoHandle for AsNodeBuffer {
+    type Handle = JsBuffer;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        let size: u32 = self
+            .0
+            .len()
+            .try_into()
+            .map_err(|_| "Array to large for JavaScript")?;
+        let buffer = cx.buffer(size)?;
+        with_bytes_mut(buffer, cx, |bytes| bytes.copy_from_slice(&self.0))?;
+        Ok(buffer)
+    }
+}
+
+// Copied out only after checking the length against max_bytes, since
+// it comes from the caller
+fn read_owned<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Vec<u8>> {
+    match with_binary(handle, cx, <[u8]>::to_vec)? {
+        Some(bytes) => Ok(bytes),
+        None => Err(Candidates::binary().error(handle, cx).into()),
+    }
+}
+
+impl FromHandle for AsArrayBuffer {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        read_owned(handle, cx).map(Self)
+    }
+}
+
+ts_type!(AsArrayBuffer => "Buffer | ArrayBuffer", "ArrayBuffer");
+
+impl FromHandle for AsNodeBuffer {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        read_owned(handle, cx).map(Self)
+    }
+}
+
+ts_type!(AsNodeBuffer => "Buffer | ArrayBuffer", "Buffer");
+
+/// Runs f on the contents of an ArrayBuffer or Buffer (or any ArrayBufferView)
+/// while they are locked. Fails instead of panicking if they are already
+/// borrowed, or if the ArrayBuffer was detached.
+pub fn with_bytes<'a, V, T>(
+    handle: Handle<V>,
+    cx: &mut impl Context<'a>,
+    f: impl FnOnce(&[u8]) -> T,
+) -> SafeResult<T>
+where
+    V: Value + Object,
+    // https://doc.rust-lang.org/nomicon/hrtb.html
+    for<'x> &'x V: Borrow<Target = BinaryData<'x>>,
+{
+    if is_empty(handle, cx)? {
+        return Ok(f(&[]));
+    }
+    let lock = cx.lock();
+    let binary = (&*handle)
+        .try_borrow(&lock)
+        .map_err(|e| format!("Cannot borrow bytes: {}", e))?;
+    Ok(f(binary.as_slice()))
+}
+
+/// Like with_bytes, but f may write to the contents
+pub fn with_bytes_mut<'a, V, T>(
+    mut handle: Handle<V>,
+    cx: &mut impl Context<'a>,
+    f: impl FnOnce(&mut [u8]) -> T,
+) -> SafeResult<T>
+where
+    V: Value + Object,
+    for<'x> &'x mut V: BorrowMut<Target = BinaryData<'x>>,
+{
+    if is_empty(handle, cx)? {
+        return Ok(f(&mut []));
+    }
+    let lock = cx.lock();
+    let binary = (&mut *handle)
+        .try_borrow_mut(&lock)
+        .map_err(|e| format!("Cannot borrow bytes: {}", e))?;
+    Ok(f(binary.as_mut_slice()))
+}
+
+// A detached ArrayBuffer has no backing store, and borrowing one makes
+// a slice from a null pointer. So, check the length before borrowing
+// and never borrow when it is empty.
+fn is_empty<'a, V: Value + Object>(
+    handle: Handle<V>,
+    cx: &mut impl Context<'a>,
+) -> SafeResult<bool> {
+    let length: Handle<JsNumber> = handle.get(cx, "byteLength")?;
+    if length.value() != 0.0 {
+        return Ok(false);
+    }
+    let array_buffer: Handle<JsValue> = if handle.is_a::<JsArrayBuffer>() {
+        handle.upcast()
+    } else {
+        handle.get(cx, "buffer")?
+    };
+    if is_detached(array_buffer, cx)? {
+        return Err("Cannot read from a detached ArrayBuffer".into());
+    }
+    Ok(true)
+}
+
+/// Like with_bytes, for a handle of any type. Ok(None) if it is not an
+/// ArrayBuffer or Buffer (or any ArrayBufferView), eg: a string.
+pub(crate) fn with_binary<'a, V: Value, T>(
+    handle: Handle<V>,
+    cx: &mut impl Context<'a>,
+    f: impl FnOnce(&[u8]) -> T,
+) -> SafeResult<Option<T>> {
+    if let Ok(buffer) = handle.downcast::<JsArrayBuffer>() {
+        return read_binary(buffer, cx, f).map(Some);
+    }
+    if let Ok(buffer) = handle.downcast::<JsBuffer>() {
+        return read_binary(buffer, cx, f).map(Some);
+    }
+    Ok(None)
+}
+
+fn read_binary<'a, V, T>(
+    buffer: Handle<V>,
+    cx: &mut impl Context<'a>,
+    f: impl FnOnce(&[u8]) -> T,
+) -> SafeResult<T>
+where
+    V: Value + Object,
+    for<'x> &'x V: Borrow<Target = BinaryData<'x>>,
+{
+    let length: Handle<JsNumber> = buffer.get(cx, "byteLength")?;
+    limits::check_bytes(length.value() as usize)?;
+    with_bytes(buffer, cx, f)
+}
+
+// ArrayBuffer.prototype.detached is only in newer Node (22+). Without it
+// a detached buffer can't be told apart from an empty one, and reads as empty.
+fn is_detached<'a>(array_buffer: Handle<JsValue>, cx: &mut impl Context<'a>) -> NeonResult<bool> {
+    let array_buffer: Handle<JsObject> = match array_buffer.downcast() {
+        Ok(array_buffer) => array_buffer,
+        Err(_) => return Ok(false),
+    };
+    let detached: Handle<JsValue> = array_buffer.get(cx, "detached")?;
+    Ok(detached
+        .downcast::<JsBoolean>()
+        .is_ok_and(|detached| detached.value()))
+}
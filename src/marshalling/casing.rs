@@ -0,0 +1,122 @@
+use std::borrow::Cow;
+
+/// How a Rust identifier is spelled on the JS side.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Casing {
+    /// "mainnet", "notfound"
+    Lower,
+    /// "MAINNET", "NOTFOUND"
+    Upper,
+    /// "mainnet", "notFound"
+    Camel,
+    /// "Mainnet", "NotFound"
+    Pascal,
+    /// "mainnet", "not_found"
+    Snake,
+    /// "MAINNET", "NOT_FOUND"
+    ScreamingSnake,
+    /// "mainnet", "not-found"
+    Kebab,
+}
+
+impl Casing {
+    /// Respells an identifier, which may be in PascalCase or snake_case.
+    pub fn apply(&self, ident: &str) -> String {
+        let words = split_words(ident);
+        let mut result = String::with_capacity(ident.len() + words.len());
+        for (i, word) in words.iter().enumerate() {
+            match self {
+                Casing::Lower => result.push_str(&word.to_lowercase()),
+                Casing::Upper => result.push_str(&word.to_uppercase()),
+                Casing::Camel if i == 0 => result.push_str(&word.to_lowercase()),
+                Casing::Camel | Casing::Pascal => push_capitalized(&mut result, word),
+                Casing::Snake | Casing::Kebab | Casing::ScreamingSnake => {
+                    if i != 0 {
+                        result.push(if *self == Casing::Kebab { '-' } else { '_' });
+                    }
+                    if *self == Casing::ScreamingSnake {
+                        result.push_str(&word.to_uppercase());
+                    } else {
+                        result.push_str(&word.to_lowercase());
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Respells an object key, eg: a field name or a HashMap key. Unlike
+/// Casing::apply, keys which aren't identifiers (eg: "0x5aAe...", "a b")
+/// are left as they are, since respelling them would change their meaning.
+pub fn rename_key(key: &str, casing: Casing) -> Cow<'_, str> {
+    let is_ident = key
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if is_ident {
+        Cow::Owned(casing.apply(key))
+    } else {
+        Cow::Borrowed(key)
+    }
+}
+
+fn push_capitalized(result: &mut String, word: &str) {
+    let mut chars = word.chars();
+    if let Some(first) = chars.next() {
+        result.extend(first.to_uppercase());
+        result.push_str(&chars.as_str().to_lowercase());
+    }
+}
+
+/// Splits on underscores and at lower-to-upper transitions. A run of
+/// capitals is kept together as an acronym, eg: "HTTPServer" is "HTTP", "Server".
+fn split_words(ident: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    for part in ident.split('_').filter(|p| !p.is_empty()) {
+        let chars: Vec<(usize, char)> = part.char_indices().collect();
+        let mut start = 0;
+        for i in 1..chars.len() {
+            let (at, c) = chars[i];
+            let prev = chars[i - 1].1;
+            let next_is_lower = chars.get(i + 1).is_some_and(|(_, n)| n.is_lowercase());
+            let boundary = c.is_uppercase()
+                && (prev.is_lowercase()
+                    || prev.is_ascii_digit()
+                    || (prev.is_uppercase() && next_is_lower));
+            if boundary {
+                words.push(&part[start..at]);
+                start = at;
+            }
+        }
+        words.push(&part[start..]);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn respell() {
+        assert_eq!(Casing::Lower.apply("Mainnet"), "mainnet");
+        assert_eq!(Casing::Camel.apply("NotFound"), "notFound");
+        assert_eq!(Casing::Pascal.apply("not_found"), "NotFound");
+        assert_eq!(Casing::Snake.apply("NotFound"), "not_found");
+        assert_eq!(Casing::ScreamingSnake.apply("NotFound"), "NOT_FOUND");
+        assert_eq!(Casing::Kebab.apply("HTTPServer"), "http-server");
+        assert_eq!(Casing::Upper.apply("max_fee_per_gas"), "MAXFEEPERGAS");
+        assert_eq!(Casing::Camel.apply("max_fee_per_gas"), "maxFeePerGas");
+    }
+
+    #[test]
+    fn rename_keys() {
+        assert_eq!(rename_key("chain_id", Casing::Camel), "chainId");
+        assert_eq!(rename_key("chainId", Casing::Snake), "chain_id");
+        assert_eq!(rename_key("0x5aAeb605", Casing::Camel), "0x5aAeb605");
+        assert_eq!(rename_key("not-an-ident", Casing::Snake), "not-an-ident");
+        assert_eq!(rename_key("", Casing::Pascal), "");
+    }
+}
@@ -0,0 +1,209 @@
+//! CBOR (RFC 8949), for passing a large payload as one Buffer instead of
+//! marshalling it object by object. Eg: with cbor-x in JS.
+//!
+//! Reading and writing is by ciborium. Tags are refused, other than those
+//! for integers too large for 64 bits, rather than be silently dropped.
+
+use crate::errors::{SafeErr, SafeJsResult, SafeResult};
+use crate::marshalling::limits::{check_bytes, check_items, Nested};
+use ciborium::value::Value;
+use neon::prelude::*;
+use neon::types::JsBuffer;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, SafeErr> {
+    let mut out = Vec::new();
+    ciborium::ser::into_writer(value, &mut out)
+        .map_err(|e| format!("Failed to encode CBOR: {}", e))?;
+    Ok(out)
+}
+
+/// Deserializes exactly one value. The MarshalLimits of this thread bound
+/// its size, nesting and items. Past a depth of 256 ciborium refuses it
+/// anyway, to keep off the end of the stack, whatever the limits allow.
+pub fn from_slice<T: DeserializeOwned>(bytes: impl AsRef<[u8]>) -> Result<T, SafeErr> {
+    let bytes = bytes.as_ref();
+    check_bytes(bytes.len())?;
+    let mut rest = bytes;
+    let value: Value = ciborium::de::from_reader(&mut rest).map_err(|e| invalid(e, bytes, rest))?;
+    if !rest.is_empty() {
+        let at = bytes.len() - rest.len();
+        return Err(format!(
+            "Invalid CBOR at byte {}: Unexpected data after the value",
+            at
+        )
+        .into());
+    }
+    check(&value)?;
+    value.deserialized().map_err(|e| {
+        // Whose Display is its Debug
+        let ciborium::value::Error::Custom(reason) = e;
+        format!("Failed to decode CBOR: {}", reason).into()
+    })
+}
+
+/// Serializes value into a new Node Buffer
+pub fn to_buffer<'a, T: Serialize + ?Sized>(
+    cx: &mut impl Context<'a>,
+    value: &T,
+) -> SafeJsResult<'a, JsBuffer> {
+    super::to_buffer(cx, to_vec(value)?)
+}
+
+/// Deserializes from a Buffer or ArrayBuffer
+pub fn from_buffer<'a, T: DeserializeOwned, V: neon::types::Value>(
+    handle: Handle<V>,
+    cx: &mut impl Context<'a>,
+) -> SafeResult<T> {
+    super::from_buffer(handle, cx, |bytes| from_slice(bytes))
+}
+
+fn invalid<E>(e: ciborium::de::Error<E>, bytes: &[u8], rest: &[u8]) -> SafeErr {
+    use ciborium::de::Error;
+    let (at, reason) = match e {
+        Error::Io(_) => (
+            bytes.len() - rest.len(),
+            "Unexpected end of data".to_string(),
+        ),
+        Error::Syntax(at) => (at, "Malformed data item".to_string()),
+        Error::Semantic(at, reason) => (at.unwrap_or(bytes.len() - rest.len()), reason),
+        Error::RecursionLimitExceeded => return "Invalid CBOR: Nested too deeply".into(),
+    };
+    format!("Invalid CBOR at byte {}: {}", at, reason).into()
+}
+
+// Applies the MarshalLimits, once the whole value is read. Reading it
+// first is safe as it can't take up more than the bytes it came from.
+fn check(value: &Value) -> SafeResult<()> {
+    match value {
+        Value::Array(items) => {
+            let _nested = Nested::enter()?;
+            check_items(items.len())?;
+            items.iter().try_for_each(check)
+        }
+        Value::Map(entries) => {
+            let _nested = Nested::enter()?;
+            check_items(entries.len())?;
+            entries.iter().try_for_each(|(key, value)| {
+                check(key)?;
+                check(value)
+            })
+        }
+        // Bignums, which ciborium reads as integers
+        Value::Tag(2 | 3, _) => Ok(()),
+        Value::Tag(tag, _) => Err(format!("Invalid CBOR: Tag {} is not supported", tag).into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::marshalling::limits::{with_limits, MarshalLimits};
+    use serde::de::IgnoredAny;
+    use serde::Deserialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Event {
+        Ping,
+        Transfer { to: String, amount: u64 },
+        Burn(i64),
+    }
+
+    fn enc<T: Serialize + ?Sized>(value: &T) -> Vec<u8> {
+        to_vec(value).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn dec<T: DeserializeOwned>(bytes: &[u8]) -> T {
+        from_slice(bytes).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    #[test]
+    fn known_encodings() {
+        // From RFC 8949, Appendix A
+        assert_eq!(enc(&0u8), [0x00]);
+        assert_eq!(enc(&500u16), [0x19, 0x01, 0xf4]);
+        assert_eq!(enc(&-1000i32), [0x39, 0x03, 0xe7]);
+        assert_eq!(
+            enc(&u64::MAX),
+            [0x1b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]
+        );
+        assert_eq!(enc("IETF"), [0x64, 0x49, 0x45, 0x54, 0x46]);
+        assert_eq!(enc(&vec![1u8, 2, 3]), [0x83, 0x01, 0x02, 0x03]);
+        assert_eq!(dec::<f64>(&[0xf9, 0x3c, 0x00]), 1.0);
+        assert_eq!(dec::<f64>(&[0xf9, 0xc4, 0x00]), -4.0);
+        assert_eq!(dec::<f64>(&[0xf9, 0x00, 0x01]), 2f64.powi(-24));
+        assert_eq!(dec::<f64>(&[0xfa, 0x47, 0xc3, 0x50, 0x00]), 100000.0);
+        // Bignums, as tag 2
+        assert_eq!(
+            dec::<u128>(&[0xc2, 0x49, 0x01, 0, 0, 0, 0, 0, 0, 0, 0]),
+            1 << 64
+        );
+    }
+
+    #[test]
+    fn round_trips() {
+        let events = vec![
+            Event::Ping,
+            Event::Transfer {
+                to: "0xabc".to_string(),
+                amount: u64::MAX,
+            },
+            Event::Burn(i64::MIN),
+        ];
+        let bytes = enc(&events);
+        assert_eq!(dec::<Vec<Event>>(&bytes), events);
+
+        let mut map = BTreeMap::new();
+        map.insert("fee".to_string(), Some(1.5));
+        map.insert("tip".to_string(), None);
+        assert_eq!(dec::<BTreeMap<String, Option<f64>>>(&enc(&map)), map);
+
+        assert_eq!(dec::<i128>(&enc(&i128::MIN)), i128::MIN);
+    }
+
+    #[test]
+    fn rejects() {
+        let e = |bytes: &[u8]| from_slice::<IgnoredAny>(bytes).err().unwrap().to_string();
+        assert_eq!(
+            e(&[0x19, 0x01]),
+            "Invalid CBOR at byte 2: Unexpected end of data"
+        );
+        assert_eq!(
+            e(&[0x01, 0x02]),
+            "Invalid CBOR at byte 1: Unexpected data after the value"
+        );
+        assert_eq!(e(&[0x81; 300]), "Invalid CBOR: Nested too deeply");
+        // Tag 1 (epoch time)
+        assert_eq!(
+            e(&[0xc1, 0x1a, 0x51, 0x4b, 0x67, 0xb0]),
+            "Invalid CBOR: Tag 1 is not supported"
+        );
+        let wrong = from_slice::<String>(&[0x01]).err().unwrap().to_string();
+        assert_eq!(
+            wrong,
+            "Failed to decode CBOR: invalid type: integer `1`, expected str"
+        );
+    }
+
+    #[test]
+    fn limits() {
+        let strict = MarshalLimits {
+            max_depth: 2,
+            max_items: 3,
+            max_bytes: 8,
+        };
+        let deep = enc(&[[[1u8]]]);
+        let long = enc(&[1u8, 2, 3, 4]);
+        let big = enc("a string of 16 b");
+        with_limits(strict, || {
+            assert_eq!(dec::<Vec<Vec<u8>>>(&enc(&[[1u8]])), [[1]]);
+            let e = |bytes: &[u8]| from_slice::<IgnoredAny>(bytes).err().unwrap().to_string();
+            assert_eq!(e(&deep), "Nested deeper than the limit of 2");
+            assert_eq!(e(&long), "Got 4 items, which exceeds the limit of 3");
+            assert_eq!(e(&big), "Got 17 bytes, which exceeds the limit of 8");
+        });
+    }
+}
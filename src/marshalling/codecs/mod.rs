@@ -0,0 +1,31 @@
+// These live in neon-utils-core so that they can be used without neon
+pub use neon_utils_core::codecs::*;
+
+#[cfg(feature = "cbor")]
+pub mod cbor;
+#[cfg(feature = "msgpack")]
+pub mod msgpack;
+
+#[cfg(any(feature = "cbor", feature = "msgpack"))]
+fn to_buffer<'a>(
+    cx: &mut impl neon::context::Context<'a>,
+    bytes: Vec<u8>,
+) -> crate::errors::SafeJsResult<'a, neon::types::JsBuffer> {
+    use super::IntoHandle;
+    super::AsNodeBuffer(bytes).into_handle(cx)
+}
+
+#[cfg(any(feature = "cbor", feature = "msgpack"))]
+fn from_buffer<'a, T, V: neon::types::Value>(
+    handle: neon::handle::Handle<V>,
+    cx: &mut impl neon::context::Context<'a>,
+    decode: fn(&[u8]) -> Result<T, crate::errors::SafeErr>,
+) -> crate::errors::SafeResult<T> {
+    match super::bytes::with_binary(handle, cx, decode)? {
+        Some(decoded) => Ok(decoded?),
+        None => {
+            let got = super::type_name_of(handle, cx);
+            Err(format!("Expected buffer, got {}", got).into())
+        }
+    }
+}
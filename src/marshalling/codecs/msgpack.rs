@@ -0,0 +1,207 @@
+//! MessagePack, for passing a large payload as one Buffer instead of
+//! marshalling it object by object. Eg: with msgpackr in JS.
+//!
+//! Reading and writing is by rmp-serde, with structs written as maps so
+//! that they read back as objects in JS. Extension types aren't supported.
+
+use crate::errors::{SafeErr, SafeJsResult, SafeResult};
+use crate::marshalling::limits::{check_bytes, check_items, Nested};
+use neon::prelude::*;
+use neon::types::JsBuffer;
+use rmpv::ValueRef;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, SafeErr> {
+    rmp_serde::to_vec_named(value)
+        .map_err(|e| format!("Failed to encode MessagePack: {}", e).into())
+}
+
+/// Deserializes exactly one value. The MarshalLimits of this thread bound
+/// its size, nesting and items, which are checked by a first pass with
+/// rmpv. Past a depth of 128 that refuses it anyway, as serde_json does
+/// for JSON, to keep off the end of the stack whatever the limits allow.
+pub fn from_slice<T: DeserializeOwned>(bytes: impl AsRef<[u8]>) -> Result<T, SafeErr> {
+    let bytes = bytes.as_ref();
+    check_bytes(bytes.len())?;
+    let mut rest = bytes;
+    // rmpv counts each array or map twice, so this is a depth of 128
+    let value = rmpv::decode::read_value_ref_with_max_depth(&mut rest, 256).map_err(|e| {
+        let at = bytes.len() - rest.len();
+        format!("Invalid MessagePack at byte {}: {}", at, reason(e))
+    })?;
+    if !rest.is_empty() {
+        let at = bytes.len() - rest.len();
+        return Err(format!(
+            "Invalid MessagePack at byte {}: Unexpected data after the value",
+            at
+        )
+        .into());
+    }
+    check(&value)?;
+    rmp_serde::from_slice(bytes).map_err(|e| format!("Failed to decode MessagePack: {}", e).into())
+}
+
+/// Serializes value into a new Node Buffer
+pub fn to_buffer<'a, T: Serialize + ?Sized>(
+    cx: &mut impl Context<'a>,
+    value: &T,
+) -> SafeJsResult<'a, JsBuffer> {
+    super::to_buffer(cx, to_vec(value)?)
+}
+
+/// Deserializes from a Buffer or ArrayBuffer
+pub fn from_buffer<'a, T: DeserializeOwned, V: neon::types::Value>(
+    handle: Handle<V>,
+    cx: &mut impl Context<'a>,
+) -> SafeResult<T> {
+    super::from_buffer(handle, cx, |bytes| from_slice(bytes))
+}
+
+fn reason(e: rmpv::decode::Error) -> String {
+    use rmpv::decode::Error;
+    match e {
+        Error::InvalidMarkerRead(e) | Error::InvalidDataRead(e)
+            if e.kind() == std::io::ErrorKind::UnexpectedEof =>
+        {
+            "Unexpected end of data".to_string()
+        }
+        Error::DepthLimitExceeded => "Nested too deeply".to_string(),
+        e => e.to_string(),
+    }
+}
+
+// Applies the MarshalLimits, once the whole value is read. Reading it
+// first is safe as it can't take up more than the bytes it came from.
+fn check(value: &ValueRef) -> SafeResult<()> {
+    match value {
+        ValueRef::Array(items) => {
+            let _nested = Nested::enter()?;
+            check_items(items.len())?;
+            items.iter().try_for_each(check)
+        }
+        ValueRef::Map(entries) => {
+            let _nested = Nested::enter()?;
+            check_items(entries.len())?;
+            entries.iter().try_for_each(|(key, value)| {
+                check(key)?;
+                check(value)
+            })
+        }
+        ValueRef::Ext(kind, _) => Err(format!(
+            "Invalid MessagePack: Extension type {} is not supported",
+            kind
+        )
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::marshalling::limits::{with_limits, MarshalLimits};
+    use serde::de::IgnoredAny;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Event {
+        Ping,
+        Transfer { to: String, amount: u64 },
+        Burn(i64),
+    }
+
+    fn enc<T: Serialize + ?Sized>(value: &T) -> Vec<u8> {
+        to_vec(value).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn dec<T: DeserializeOwned>(bytes: &[u8]) -> T {
+        from_slice(bytes).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    #[test]
+    fn known_encodings() {
+        assert_eq!(enc(&5u8), [0x05]);
+        assert_eq!(enc(&-5i8), [0xfb]);
+        assert_eq!(enc(&200u8), [0xcc, 200]);
+        assert_eq!(enc(&-200i16), [0xd1, 0xff, 0x38]);
+        assert_eq!(enc("hi"), [0xa2, b'h', b'i']);
+        assert_eq!(enc(&"x".repeat(40))[..2], [0xd9, 40]);
+        assert_eq!(enc(&serde_bytes(&[1, 2])), [0xc4, 2, 1, 2]);
+        assert_eq!(enc(&vec![true, false]), [0x92, 0xc3, 0xc2]);
+        assert_eq!(dec::<f64>(&[0xca, 0x3f, 0xc0, 0x00, 0x00]), 1.5);
+        assert_eq!(dec::<Option<u8>>(&[0xc0]), None);
+    }
+
+    // A byte slice which serializes as bytes rather than as a sequence
+    fn serde_bytes(bytes: &[u8]) -> impl Serialize + '_ {
+        struct Bytes<'b>(&'b [u8]);
+        impl Serialize for Bytes<'_> {
+            fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                s.serialize_bytes(self.0)
+            }
+        }
+        Bytes(bytes)
+    }
+
+    #[test]
+    fn round_trips() {
+        let events = vec![
+            Event::Ping,
+            Event::Transfer {
+                to: "0xabc".to_string(),
+                amount: u64::MAX,
+            },
+            Event::Burn(i64::MIN),
+        ];
+        let bytes = enc(&events);
+        assert_eq!(dec::<Vec<Event>>(&bytes), events);
+
+        let long: Vec<u32> = (0..70_000).collect();
+        assert_eq!(dec::<Vec<u32>>(&enc(&long)), long);
+    }
+
+    #[test]
+    fn rejects() {
+        let e = |bytes: &[u8]| from_slice::<IgnoredAny>(bytes).err().unwrap().to_string();
+        assert_eq!(
+            e(&[0xcd, 0x01]),
+            "Invalid MessagePack at byte 2: Unexpected end of data"
+        );
+        assert_eq!(
+            e(&[0xd4, 0x01, 0x00]),
+            "Invalid MessagePack: Extension type 1 is not supported"
+        );
+        assert_eq!(
+            e(&[0x01, 0x02]),
+            "Invalid MessagePack at byte 1: Unexpected data after the value"
+        );
+        assert_eq!(
+            e(&[0xdd, 0xff, 0xff, 0xff, 0xff]),
+            "Invalid MessagePack at byte 5: Unexpected end of data"
+        );
+        assert_eq!(
+            e(&[0x91; 300]),
+            "Invalid MessagePack at byte 128: Nested too deeply"
+        );
+    }
+
+    #[test]
+    fn limits() {
+        let strict = MarshalLimits {
+            max_depth: 2,
+            max_items: 3,
+            max_bytes: 8,
+        };
+        let deep = enc(&[[[1u8]]]);
+        let long = enc(&[1u8, 2, 3, 4]);
+        let big = enc("a string of 16 b");
+        with_limits(strict, || {
+            assert_eq!(dec::<Vec<Vec<u8>>>(&enc(&[[1u8]])), [[1]]);
+            let e = |bytes: &[u8]| from_slice::<IgnoredAny>(bytes).err().unwrap().to_string();
+            assert_eq!(e(&deep), "Nested deeper than the limit of 2");
+            assert_eq!(e(&long), "Got 4 items, which exceeds the limit of 3");
+            assert_eq!(e(&big), "Got 17 bytes, which exceeds the limit of 8");
+        });
+    }
+}
@@ -0,0 +1,114 @@
+use super::*;
+use std::cell::Cell;
+
+/// Whether numbers and strings are converted into each other while decoding,
+/// the way JS would. Strict, the default, takes only the type asked for.
+/// Lenient lets a number be given as a numeric string, eg: "42" or "0x2a",
+/// and a string be given as a number, which is formatted like String(n).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Coercion {
+    Strict,
+    Lenient,
+}
+
+// Like the limits, this is per JS thread
+thread_local! {
+    static COERCION: Cell<Coercion> = const { Cell::new(Coercion::Strict) };
+}
+
+/// Sets the coercion for everything decoded on this thread from now on
+pub fn set_coercion(coercion: Coercion) {
+    COERCION.with(|c| c.set(coercion));
+}
+
+pub fn coercion() -> Coercion {
+    COERCION.with(Cell::get)
+}
+
+/// Runs f with a different coercion, eg: Strict for one call site in an
+/// addon which is otherwise Lenient. The previous coercion is restored after.
+pub fn with_coercion<T>(coercion: Coercion, f: impl FnOnce() -> T) -> T {
+    struct Restore(Coercion);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            set_coercion(self.0);
+        }
+    }
+
+    let _restore = Restore(self::coercion());
+    set_coercion(coercion);
+    f()
+}
+
+/// Decodes T with Lenient coercion, whatever the coercion is otherwise.
+/// This goes for everything inside T too, eg: each item of a Coerce<Vec<u64>>.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Coerce<T>(pub T);
+
+impl<T: FromHandle> FromHandle for Coerce<T> {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        with_coercion(Coercion::Lenient, || T::from_handle(handle, cx)).map(Coerce)
+    }
+}
+
+// Written out as T, so that Coerce<T> can be round tripped
+impl<T: IntoHandle> IntoHandle for Coerce<T> {
+    type Handle = T::Handle;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        self.0.into_handle(cx)
+    }
+}
+
+/// Parses a numeric string like Number(s) does, except that an empty
+/// string is not 0. Hex is only for integers, as in JS.
+pub(crate) fn parse_number(s: &str) -> Option<f64> {
+    let s = s.trim();
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        return u64::from_str_radix(hex, 16).ok().map(|n| n as f64);
+    }
+    match s {
+        "Infinity" | "+Infinity" => return Some(f64::INFINITY),
+        "-Infinity" => return Some(f64::NEG_INFINITY),
+        _ => {}
+    }
+    // Rust also takes eg: "inf" and "NaN", which JS doesn't
+    if s.is_empty() || !s.bytes().all(|b| b"0123456789+-.eE".contains(&b)) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse_number(" 42 "), Some(42.0));
+        assert_eq!(parse_number("-1.5e3"), Some(-1500.0));
+        assert_eq!(parse_number(".5"), Some(0.5));
+        assert_eq!(parse_number("0x2a"), Some(42.0));
+        assert_eq!(parse_number("-Infinity"), Some(f64::NEG_INFINITY));
+        assert_eq!(parse_number(""), None);
+        assert_eq!(parse_number("inf"), None);
+        assert_eq!(parse_number("NaN"), None);
+        assert_eq!(parse_number("4 2"), None);
+        assert_eq!(parse_number("0x"), None);
+    }
+
+    #[test]
+    fn restores() {
+        assert_eq!(coercion(), Coercion::Strict);
+        with_coercion(Coercion::Lenient, || {
+            assert_eq!(coercion(), Coercion::Lenient);
+            with_coercion(Coercion::Strict, || {
+                assert_eq!(coercion(), Coercion::Strict)
+            });
+            assert_eq!(coercion(), Coercion::Lenient);
+        });
+        assert_eq!(coercion(), Coercion::Strict);
+    }
+}
@@ -0,0 +1,244 @@
+use super::casing::{rename_key, Casing};
+use super::inspect::{expect, type_tag};
+use super::limits::{self, Nested};
+use super::*;
+use crate::errors::SafeResultExt;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+use std::iter::FromIterator;
+
+// Spreads an iterable into an Array with Array.from
+fn array_from<'a, V: Value>(
+    handle: Handle<V>,
+    cx: &mut impl Context<'a>,
+) -> NeonResult<Vec<Handle<'a, JsValue>>> {
+    let global = cx.global();
+    let array: Handle<JsObject> = global.get(cx, "Array")?;
+    let from: Handle<JsFunction> = array.get(cx, "from")?;
+    let items: Handle<JsArray> = from
+        .call(cx, array, vec![handle.upcast::<JsValue>()])?
+        .downcast_or_throw(cx)?;
+    items.to_vec(cx)
+}
+
+fn construct<'a>(cx: &mut impl Context<'a>, name: &str) -> NeonResult<Handle<'a, JsObject>> {
+    let global = cx.global();
+    let constructor: Handle<JsFunction> = global.get(cx, name)?;
+    let args: Vec<Handle<JsValue>> = Vec::new();
+    constructor.construct(cx, args)
+}
+
+/// Accepts a Map, or a plain object keyed by strings
+impl<K, V> FromHandle for HashMap<K, V>
+where
+    K: FromHandle + Eq + Hash,
+    V: FromHandle,
+{
+    fn from_handle<'a, T: Value>(handle: Handle<T>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        let mut map = HashMap::new();
+        let _nested = Nested::enter()?;
+        if type_tag(handle, cx)? == "[object Map]" {
+            let entries = array_from(handle, cx)?;
+            limits::check_items(entries.len())?;
+            for (i, entry) in entries.into_iter().enumerate() {
+                let entry: Handle<JsArray> = expect(entry, cx, "entry array")?;
+                let key: Handle<JsValue> = entry.get(cx, 0)?;
+                let value: Handle<JsValue> = entry.get(cx, 1)?;
+                let key = K::from_handle(key, cx)
+                    .with_context(|| format!("Invalid key at index {}", i))?;
+                let value = V::from_handle(value, cx)
+                    .with_context(|| format!("Invalid value at index {}", i))?;
+                map.insert(key, value);
+            }
+            return Ok(map);
+        }
+
+        if handle.is_a::<JsArray>() {
+            return Err("Expected Map or object, got array".into());
+        }
+        let object: Handle<JsObject> = expect(handle, cx, "Map or object")?;
+        let names = object.get_own_property_names(cx)?;
+        limits::check_items(names.len() as usize)?;
+        let names = names.to_vec(cx)?;
+        for name in names {
+            let value: Handle<JsValue> = object.get(cx, name)?;
+            let field = || match name.downcast::<JsString>() {
+                Ok(name) => format!("Invalid field '{}'", name.value()),
+                Err(_) => "Invalid field".to_owned(),
+            };
+            let key = K::from_handle(name, cx).with_context(field)?;
+            let value = V::from_handle(value, cx).with_context(field)?;
+            map.insert(key, value);
+        }
+        Ok(map)
+    }
+}
+
+// The items of a Set or an Array, decoded
+fn set_items<'a, V: Value, T: FromHandle, C: FromIterator<T>>(
+    handle: Handle<V>,
+    cx: &mut impl Context<'a>,
+) -> SafeResult<C> {
+    let items = if let Ok(array) = handle.downcast::<JsArray>() {
+        limits::check_items(array.len() as usize)?;
+        array.to_vec(cx)?
+    } else if type_tag(handle, cx)? == "[object Set]" {
+        array_from(handle, cx)?
+    } else {
+        let expected = Candidates::default().or("Set").or("array");
+        return Err(expected.error(handle, cx).into());
+    };
+    limits::check_items(items.len())?;
+    let _nested = Nested::enter()?;
+
+    items
+        .into_iter()
+        .enumerate()
+        .map(|(i, handle)| {
+            T::from_handle(handle, cx).with_context(|| format!("Invalid element at index {}", i))
+        })
+        .collect()
+}
+
+/// Accepts a Set, or an Array
+impl<T> FromHandle for HashSet<T>
+where
+    T: FromHandle + Eq + Hash,
+{
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        set_items(handle, cx)
+    }
+}
+
+/// Accepts a Set, or an Array
+impl<T: FromHandle + Ord> FromHandle for BTreeSet<T> {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        set_items(handle, cx)
+    }
+}
+
+impl<T: FromHandle> FromHandle for VecDeque<T> {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        Vec::from_handle(handle, cx).map(VecDeque::from)
+    }
+}
+
+#[cfg(feature = "smallvec")]
+impl<A> FromHandle for smallvec::SmallVec<A>
+where
+    A: smallvec::Array,
+    A::Item: FromHandle,
+{
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        Vec::from_handle(handle, cx).map(smallvec::SmallVec::from_vec)
+    }
+}
+
+// Each collection below is an Array, in its iteration order. A HashSet
+// has no order to keep, so AsJsSet is there for a Set instead.
+fn array_of<'c, 'i, T: 'i + IntoHandle>(
+    items: impl Iterator<Item = &'i T>,
+    cx: &mut impl Context<'c>,
+) -> SafeJsResult<'c, JsArray> {
+    let arr = JsArray::new(cx, 0);
+    super::scope::fill_array(cx, arr, items)?;
+    Ok(arr)
+}
+
+impl<T: IntoHandle> IntoHandle for VecDeque<T> {
+    type Handle = JsArray;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        array_of(self.iter(), cx)
+    }
+}
+
+impl<T: IntoHandle> IntoHandle for BTreeSet<T> {
+    type Handle = JsArray;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        array_of(self.iter(), cx)
+    }
+}
+
+impl<T: IntoHandle> IntoHandle for HashSet<T> {
+    type Handle = JsArray;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        array_of(self.iter(), cx)
+    }
+}
+
+#[cfg(feature = "smallvec")]
+impl<A> IntoHandle for smallvec::SmallVec<A>
+where
+    A: smallvec::Array,
+    A::Item: IntoHandle,
+{
+    type Handle = JsArray;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        self.as_slice().into_handle(cx)
+    }
+}
+
+// Specify that the data should be converted to a JS Map. There is no IntoHandle
+// for HashMap itself since it could as well be a plain object.
+pub struct AsJsMap<K, V>(pub HashMap<K, V>);
+
+impl<K: IntoHandle, V: IntoHandle> IntoHandle for AsJsMap<K, V> {
+    type Handle = JsObject;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        let map = construct(cx, "Map")?;
+        let set: Handle<JsFunction> = map.get(cx, "set")?;
+        for (key, value) in &self.0 {
+            let key = key.into_handle(cx)?.upcast();
+            let value = value.into_handle(cx)?.upcast();
+            set.call(cx, map, vec![key, value])?;
+        }
+        Ok(map)
+    }
+}
+
+// Specify that the data should be converted to a plain object, with each
+// key respelled by rename_key. Eg: AsJsObject(fees, Casing::Camel)
+pub struct AsJsObject<V>(pub HashMap<String, V>, pub Casing);
+
+impl<V: IntoHandle> IntoHandle for AsJsObject<V> {
+    type Handle = JsObject;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        let object = cx.empty_object();
+        for (key, value) in &self.0 {
+            let value = value.into_handle(cx)?;
+            object.set(cx, rename_key(key, self.1).as_ref(), value)?;
+        }
+        Ok(object)
+    }
+}
+
+// Specify that the data should be converted to a JS Set.
+pub struct AsJsSet<T>(pub HashSet<T>);
+
+impl<T: IntoHandle> IntoHandle for AsJsSet<T> {
+    type Handle = JsObject;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        let set = construct(cx, "Set")?;
+        let add: Handle<JsFunction> = set.get(cx, "add")?;
+        for item in &self.0 {
+            let item = item.into_handle(cx)?.upcast::<JsValue>();
+            add.call(cx, set, vec![item])?;
+        }
+        Ok(set)
+    }
+}
@@ -0,0 +1,186 @@
+use super::*;
+use crate::decls::ts_type;
+use primitive_types::U256;
+use std::fmt;
+use std::str::FromStr;
+
+/// An integer amount scaled by 10^decimals, as a decimal string with
+/// exactly that many places, eg: Decimal(1_250_000.into(), 6) is "1.250000".
+/// For token amounts which would lose precision as a JS number.
+///
+/// Reads a decimal string, optionally in scientific notation ("1.5e-3"),
+/// with as many decimals as it has places. Use with_decimals to bring it
+/// to the token's, eg: Decimal::from_str("1.25")?.with_decimals(18)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Decimal(pub U256, pub u8);
+
+// 10^78 is more than U256 can hold
+const MAX_DECIMALS: u8 = 77;
+
+impl Decimal {
+    /// The same amount with another number of decimals. Fails rather than
+    /// round if there are more places than that, or if it overflows.
+    pub fn with_decimals(self, decimals: u8) -> Result<Self, String> {
+        if decimals > MAX_DECIMALS {
+            return Err(format!(
+                "Got {} decimals, which is more than U256 can hold",
+                decimals
+            ));
+        }
+        let Decimal(amount, from) = self;
+        let scaled = if decimals >= from {
+            amount
+                .checked_mul(U256::exp10((decimals - from) as usize))
+                .ok_or_else(|| format!("{} overflows U256 with {} decimals", self, decimals))?
+        } else {
+            let divisor = U256::exp10((from - decimals) as usize);
+            if !(amount % divisor).is_zero() {
+                return Err(format!(
+                    "{} has more than {} decimal places",
+                    self, decimals
+                ));
+            }
+            amount / divisor
+        };
+        Ok(Decimal(scaled, decimals))
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let decimals = self.1 as usize;
+        let digits = self.0.to_string();
+        if decimals == 0 {
+            return f.write_str(&digits);
+        }
+        // At least one digit before the point
+        let digits = format!("{:0>width$}", digits, width = decimals + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - decimals);
+        write!(f, "{}.{}", whole, fraction)
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid decimal '{}'", s);
+        let (mantissa, exponent) = match s.find(['e', 'E']) {
+            Some(i) => {
+                let exponent: i32 = s[i + 1..].parse().map_err(|_| invalid())?;
+                (&s[..i], exponent)
+            }
+            None => (s, 0),
+        };
+        let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let is_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if whole.len() + fraction.len() == 0 || !is_digits(whole) || !is_digits(fraction) {
+            return Err(invalid());
+        }
+        let digits = format!("{}{}", whole, fraction);
+        let amount = U256::from_dec_str(&digits).map_err(|_| format!("{} overflows U256", s))?;
+        // Scientific notation only moves the point
+        let places = fraction.len() as i64 - exponent as i64;
+        if places > MAX_DECIMALS as i64 {
+            return Err(format!(
+                "{} has more than {} decimal places",
+                s, MAX_DECIMALS
+            ));
+        }
+        if places >= 0 {
+            return Ok(Decimal(amount, places as u8));
+        }
+        // eg: 15e2, which needs zeros rather than decimals
+        let zeros = -places;
+        let scaled = if amount.is_zero() {
+            Some(amount)
+        } else if zeros > MAX_DECIMALS as i64 {
+            None
+        } else {
+            amount.checked_mul(U256::exp10(zeros as usize))
+        };
+        scaled
+            .map(|amount| Decimal(amount, 0))
+            .ok_or_else(|| format!("{} overflows U256", s))
+    }
+}
+
+impl FromHandle for Decimal {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        let parsed = with_str_as(handle, cx, "decimal string", 1, Decimal::from_str)?;
+        Ok(parsed?)
+    }
+}
+
+impl IntoHandle for Decimal {
+    type Handle = JsString;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        Ok(cx.string(self.to_string()))
+    }
+}
+
+ts_type!(Decimal => "string", "string");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn parse(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(Decimal(1_250_000.into(), 6).to_string(), "1.250000");
+        assert_eq!(Decimal(5.into(), 3).to_string(), "0.005");
+        assert_eq!(Decimal(0.into(), 2).to_string(), "0.00");
+        assert_eq!(Decimal(42.into(), 0).to_string(), "42");
+        assert_eq!(
+            Decimal(U256::MAX, 18).to_string(),
+            "115792089237316195423570985008687907853269984665640564039457.584007913129639935"
+        );
+    }
+
+    #[test]
+    fn parses() {
+        assert_eq!(parse("1.25"), Decimal(125.into(), 2));
+        assert_eq!(parse(".5"), Decimal(5.into(), 1));
+        assert_eq!(parse("7"), Decimal(7.into(), 0));
+        assert_eq!(parse("1.5e-3"), Decimal(15.into(), 4));
+        assert_eq!(parse("15E2"), Decimal(1500.into(), 0));
+        assert_eq!(parse("0e100"), Decimal(0.into(), 0));
+        for bad in ["", ".", "-1", "1.2.3", "1e", "0x10", " 1", "1,5"] {
+            assert_eq!(
+                bad.parse::<Decimal>(),
+                Err(format!("Invalid decimal '{}'", bad))
+            );
+        }
+        assert_eq!(
+            "1e78".parse::<Decimal>(),
+            Err("1e78 overflows U256".to_string())
+        );
+    }
+
+    #[test]
+    fn rescales() {
+        let eth = parse("1.25").with_decimals(18).unwrap();
+        assert_eq!(eth, Decimal(U256::from(125) * U256::exp10(16), 18));
+        assert_eq!(eth.with_decimals(2), Ok(Decimal(125.into(), 2)));
+        assert_eq!(
+            eth.with_decimals(1),
+            Err("1.250000000000000000 has more than 1 decimal places".to_string())
+        );
+        assert!(Decimal(U256::MAX, 0).with_decimals(1).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn prop_round_trip(limbs: [u64; 4], decimals in 0u8..=MAX_DECIMALS) {
+            let decimal = Decimal(U256(limbs), decimals);
+            prop_assert_eq!(decimal.to_string().parse::<Decimal>(), Ok(decimal));
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use neon::prelude::*;
+
+/// How much of an object Object.freeze is applied to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Freeze {
+    /// Leave it as is
+    No,
+    /// Only the object itself. Objects it holds can still be changed.
+    Shallow,
+    /// The object, and every array and object it holds. Buffers can't be
+    /// frozen and Maps and Sets ignore freezing, so those stay writable.
+    Deep,
+}
+
+/// Freezes handle in place. Values which aren't objects are left alone.
+pub fn freeze<'a, V: Value>(
+    handle: Handle<V>,
+    cx: &mut impl Context<'a>,
+    how: Freeze,
+) -> NeonResult<()> {
+    if how == Freeze::No {
+        return Ok(());
+    }
+    let object: Handle<JsObject> = match handle.downcast() {
+        Ok(object) => object,
+        Err(_) => return Ok(()),
+    };
+    // Object.freeze throws on an ArrayBufferView with elements
+    if handle.is_a::<JsFunction>() || handle.is_a::<JsBuffer>() {
+        return Ok(());
+    }
+
+    let global = cx.global();
+    let constructor: Handle<JsObject> = global.get(cx, "Object")?;
+    // Already frozen objects are skipped, which also stops at cycles
+    let is_frozen: Handle<JsFunction> = constructor.get(cx, "isFrozen")?;
+    let frozen: Handle<JsBoolean> = is_frozen
+        .call(cx, constructor, vec![object.upcast()])?
+        .downcast_or_throw(cx)?;
+    if frozen.value() {
+        return Ok(());
+    }
+    let freeze_fn: Handle<JsFunction> = constructor.get(cx, "freeze")?;
+    freeze_fn.call(cx, constructor, vec![object.upcast()])?;
+
+    if how == Freeze::Deep {
+        let values: Handle<JsFunction> = constructor.get(cx, "values")?;
+        let values: Handle<JsArray> = values
+            .call(cx, constructor, vec![object.upcast()])?
+            .downcast_or_throw(cx)?;
+        for value in values.to_vec(cx)? {
+            freeze(value, cx, Freeze::Deep)?;
+        }
+    }
+    Ok(())
+}
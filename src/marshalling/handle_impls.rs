@@ -1,27 +1,47 @@
-use crate::errors::{LazyFmt, MaybeThrown, SafeJsResult, SafeResult};
+use crate::decls::ts_type;
+use crate::errors::{SafeJsResult, SafeResult, SafeResultExt};
 
+use super::bytes::with_binary;
 use super::codecs::*;
+use super::coercion::{coercion, parse_number, Coercion};
+use super::inspect::expect;
+use super::limits::{self, Nested};
 use super::*;
-use neon::types::{BinaryData, JsArrayBuffer, JsBuffer};
-use primitive_types::U256;
-use rustc_hex::{FromHex as _, ToHex as _};
-use secp256k1::ecdsa::{RecoverableSignature, RecoveryId};
+use primitive_types::{H160, H256, U128, U256};
+#[cfg(feature = "crypto")]
 use secp256k1::SecretKey;
-use std::convert::TryInto;
-use std::time::Duration;
+use std::cell::Cell;
+use std::convert::{TryFrom, TryInto};
+use std::num::{NonZeroU32, NonZeroU64};
+
+// Borrowed data marshals the same as what it points to, eg: &[T] and
+// &Vec<T> are Arrays without cloning out of a Proxy first.
+impl<T: IntoHandle + ?Sized> IntoHandle for &'_ T {
+    type Handle = T::Handle;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        (**self).into_handle(cx)
+    }
+}
 
-impl<T: IntoHandle> IntoHandle for Vec<T> {
+/// There is no IntoHandle for [T; N] in general, since it would overlap
+/// with [u8; N] as a hex string. Marshal &array[..] instead, which is
+/// also an Array and doesn't copy.
+impl<T: IntoHandle> IntoHandle for [T] {
     type Handle = JsArray;
     fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
         let arr = JsArray::new(cx, 0);
-        for i in 0..self.len() {
-            let value = self[i].into_handle(cx)?;
-            arr.set(cx, i as u32, value)?;
-        }
+        super::scope::fill_array(cx, arr, self)?;
         Ok(arr)
     }
 }
 
+impl<T: IntoHandle> IntoHandle for Vec<T> {
+    type Handle = JsArray;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        self.as_slice().into_handle(cx)
+    }
+}
+
 impl<T: FromHandle> FromHandle for Option<T> {
     fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
     where
@@ -40,24 +60,25 @@ impl<T: FromHandle> FromHandle for Vec<T> {
     where
         Self: Sized,
     {
-        let js_array: Handle<JsArray> = handle.downcast().map_err(|e| LazyFmt::new(e))?;
+        let js_array: Handle<JsArray> = expect(handle, cx, "array")?;
+        limits::check_items(js_array.len() as usize)?;
+        let _nested = Nested::enter()?;
         js_array
             .to_vec(cx)?
             .into_iter()
-            .map(|handle| T::from_handle(handle, cx))
+            .enumerate()
+            .map(|(i, handle)| {
+                T::from_handle(handle, cx)
+                    .with_context(|| format!("Invalid element at index {}", i))
+            })
             .collect::<Result<Vec<_>, _>>()
     }
 }
 
-impl<'a, T0: IntoHandle, T1: IntoHandle> IntoHandle for (T0, T1) {
+impl<T0: IntoHandle, T1: IntoHandle> IntoHandle for (T0, T1) {
     type Handle = JsArray;
     fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
-        let arr = JsArray::new(cx, 0);
-        let value = self.0.into_handle(cx)?;
-        arr.set(cx, 0, value)?;
-        let value = self.1.into_handle(cx)?;
-        arr.set(cx, 0, value)?;
-        Ok(arr)
+        crate::js_array![cx => self.0, self.1]
     }
 }
 
@@ -68,45 +89,14 @@ impl IntoHandle for String {
     }
 }
 
-impl IntoHandle for &'_ str {
+impl IntoHandle for str {
     type Handle = JsString;
     fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
         Ok(JsString::new(cx, self))
     }
 }
 
-impl IntoHandle for Vec<u8> {
-    // Better would be Uint8Array, but for our use-cases we are turning them
-    // into hex strings anyway so we might as well just go straight there.
-    type Handle = JsString;
-    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
-        let hex: String = self.to_hex();
-        hex.into_handle(cx)
-    }
-}
-
-// Specify that the data should be converted to an ArrayBuffer instead of the default hex string.
-pub struct AsArrayBuffer(pub Vec<u8>);
-
-impl IntoHandle for AsArrayBuffer {
-    type Handle = JsArrayBuffer;
-    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
-        let size: u32 = self
-            .0
-            .len()
-            .try_into()
-            .map_err(|_| "Array to large for JavaScript")?;
-        let mut buffer = cx.array_buffer(size)?;
-
-        {
-            let lock = cx.lock();
-            let binary = buffer.borrow_mut(&lock);
-            binary.as_mut_slice().copy_from_slice(&self.0);
-        }
-
-        Ok(buffer)
-    }
-}
+ts_type!(str => "string", "string");
 
 impl<T> IntoHandle for Option<T>
 where
@@ -128,6 +118,53 @@ impl IntoHandle for U256 {
     }
 }
 
+// Specify that the number should be converted to a 0x prefixed hex quantity instead of the default decimal string.
+pub struct AsHexQuantity(pub U256);
+
+impl IntoHandle for AsHexQuantity {
+    type Handle = JsString;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        encode_hex(&self.0).into_handle(cx)
+    }
+}
+
+ts_type!(AsHexQuantity => "string", "string");
+
+impl IntoHandle for U128 {
+    type Handle = JsString;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        self.encode().into_handle(cx)
+    }
+}
+
+impl IntoHandle for u128 {
+    type Handle = JsString;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        self.encode().into_handle(cx)
+    }
+}
+
+impl IntoHandle for i128 {
+    type Handle = JsString;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        self.encode().into_handle(cx)
+    }
+}
+
+impl IntoHandle for H160 {
+    type Handle = JsString;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        self.0.into_handle(cx)
+    }
+}
+
+impl IntoHandle for H256 {
+    type Handle = JsString;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        self.0.into_handle(cx)
+    }
+}
+
 impl IntoHandle for f64 {
     type Handle = JsNumber;
     fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
@@ -153,6 +190,20 @@ impl IntoHandle for u32 {
     }
 }
 
+impl IntoHandle for u16 {
+    type Handle = JsNumber;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        (*self as f64).into_handle(cx)
+    }
+}
+
+impl IntoHandle for u8 {
+    type Handle = JsNumber;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        (*self as f64).into_handle(cx)
+    }
+}
+
 impl<const N: usize> IntoHandle for [u8; N]
 where
     [u8; N]: Encode,
@@ -160,54 +211,182 @@ where
     type Handle = JsString;
 
     fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
-        self.encode().into_handle(cx)
+        let interned = super::interned::hex();
+        if interned.is_enabled() {
+            let hex = interned.get_or_insert_with(self, || self.encode());
+            return (*hex).into_handle(cx);
+        }
+        // Room for Bytes32, the largest with Encode, so no String is needed
+        let mut buffer = [0; 66];
+        match self.encode_to(&mut buffer) {
+            Ok(hex) => (*hex).into_handle(cx),
+            Err(()) => self.encode().into_handle(cx),
+        }
     }
 }
 
+/// Reads a hex string, or the bytes themselves from a Buffer or ArrayBuffer.
+/// This is used for the hash and address types too.
+pub(crate) fn from_hex_or_binary<'a, V, T>(
+    handle: Handle<V>,
+    cx: &mut impl Context<'a>,
+    error: &'static str,
+) -> SafeResult<T>
+where
+    V: Value,
+    T: Decode<str> + DecodeBytes,
+{
+    let decoded = match with_binary(handle, cx, DecodeBytes::decode_bytes)? {
+        Some(decoded) => decoded,
+        None if handle.is_a::<JsString>() => {
+            with_str_as(handle, cx, "hex string", 2, |s| Decode::decode(s))?
+        }
+        None => {
+            return Err(Candidates::binary()
+                .or("hex string")
+                .error(handle, cx)
+                .into())
+        }
+    };
+    Ok(decoded.map_err(|_| error)?)
+}
+
 impl<const N: usize> FromHandle for [u8; N]
 where
-    [u8; N]: Decode<str>,
+    [u8; N]: Decode<str> + DecodeBytes,
 {
     fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
     where
         Self: Sized,
     {
-        let s = String::from_handle(handle, cx)?;
-        let a = decode(s.as_str()).map_err(|_| "Failed to parse [u8; N]")?;
-        Ok(a)
+        from_hex_or_binary(handle, cx, "Failed to parse [u8; N]")
+    }
+}
+
+impl<const N: usize> crate::decls::TsType for [u8; N] {
+    fn ts_input() -> String {
+        "string | Buffer | ArrayBuffer".to_string()
+    }
+
+    fn ts_output() -> String {
+        "string".to_string()
     }
 }
 
 impl FromHandle for String {
-    fn from_handle<'a, V: Value>(handle: Handle<V>, _cx: &mut impl Context<'a>) -> SafeResult<Self>
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
     where
         Self: Sized,
     {
-        // TODO: (Performance) Eagerly converting to string is not great.
-        // This is here because DowncastError is generic over To and From
-        // and From is V which would require GAT
-        // See also 66e8073c-dd82-4e8e-a62d-0076a1e02f97
-        let js_str: Handle<JsString> = handle.downcast().map_err(|e| LazyFmt::new(e))?;
-        Ok(js_str.value())
+        if handle.is_a::<JsNumber>() && coercion() == Coercion::Lenient {
+            // Formatting a double like JS does is best left to JS
+            let global = cx.global();
+            let string: Handle<JsFunction> = global.get(cx, "String")?;
+            let undefined = cx.undefined();
+            let s = string.call(cx, undefined, vec![handle.upcast::<JsValue>()])?;
+            return read_str(s, cx, "string", 1);
+        }
+        read_str(handle, cx, "string", 1)
     }
 }
 
+ts_type!(String => "string", "string");
+
+/// Runs f on the contents of a JS string, eg: to parse it. Fails like
+/// String::from_handle if it isn't a string or is over the byte limit.
+///
+/// V8 only hands out a string's UTF-8 by writing it somewhere, so it is
+/// written into a buffer which each JS thread reuses rather than a new
+/// String each time.
+pub fn with_str<'a, V: Value, T>(
+    handle: Handle<V>,
+    cx: &mut impl Context<'a>,
+    f: impl FnOnce(&str) -> T,
+) -> SafeResult<T> {
+    with_str_as(handle, cx, "string", 1, f)
+}
+
+// Like with_str, but says what kind of string is expected in errors, and
+// how many characters make one byte of what it decodes to, eg: 2 for hex.
+pub(crate) fn with_str_as<'a, V: Value, T>(
+    handle: Handle<V>,
+    cx: &mut impl Context<'a>,
+    expected: &str,
+    chars_per_byte: usize,
+    f: impl FnOnce(&str) -> T,
+) -> SafeResult<T> {
+    let js_str: Handle<JsString> = expect(handle, cx, expected)?;
+    limits::check_bytes(js_str.size() as usize / chars_per_byte)?;
+    // Taken while in use, so that f reading another string can't alias it
+    let mut scratch = SCRATCH.with(|scratch| scratch.take());
+    let result = match write_utf8(*js_str, &mut scratch) {
+        Ok(s) => f(s),
+        // A lone surrogate, which V8 writes as invalid UTF-8
+        Err(_) => f(&String::from_utf8_lossy(&scratch)),
+    };
+    if scratch.capacity() <= MAX_SCRATCH {
+        SCRATCH.with(|cell| cell.replace(scratch));
+    }
+    Ok(result)
+}
+
+// Larger strings get a buffer of their own, which isn't kept after
+const MAX_SCRATCH: usize = 64 * 1024;
+
+thread_local! {
+    static SCRATCH: Cell<Vec<u8>> = const { Cell::new(Vec::new()) };
+}
+
+fn write_utf8(js_str: JsString, buffer: &mut Vec<u8>) -> Result<&str, std::str::Utf8Error> {
+    use neon::handle::Managed;
+
+    let raw = js_str.to_raw();
+    buffer.clear();
+    // SAFETY: the runtime writes at most capacity bytes and returns how
+    // many it wrote, as JsString::value relies on
+    unsafe {
+        let capacity = neon_runtime::string::utf8_len(raw);
+        buffer.reserve(capacity as usize);
+        let written = neon_runtime::string::data(buffer.as_mut_ptr(), capacity, raw);
+        buffer.set_len(written as usize);
+    }
+    std::str::from_utf8(buffer)
+}
+
+// Every string is read through here
+pub(crate) fn read_str<'a, V: Value>(
+    handle: Handle<V>,
+    cx: &mut impl Context<'a>,
+    expected: &str,
+    chars_per_byte: usize,
+) -> SafeResult<String> {
+    let js_str: Handle<JsString> = expect(handle, cx, expected)?;
+    limits::check_bytes(js_str.size() as usize / chars_per_byte)?;
+    Ok(js_str.value())
+}
+
 impl FromHandle for f64 {
-    fn from_handle<'a, V: Value>(handle: Handle<V>, _cx: &mut impl Context<'a>) -> SafeResult<Self>
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
     where
         Self: Sized,
     {
-        let js_num: Handle<JsNumber> = handle.downcast().map_err(|e| LazyFmt::new(e))?;
+        if handle.is_a::<JsString>() && coercion() == Coercion::Lenient {
+            let n = with_str(handle, cx, parse_number)?;
+            return Ok(n.ok_or("Got non-numeric string for number")?);
+        }
+        let js_num: Handle<JsNumber> = expect(handle, cx, "number")?;
         Ok(js_num.value())
     }
 }
 
+ts_type!(f64 => "number", "number");
+
 impl FromHandle for bool {
-    fn from_handle<'a, V: Value>(handle: Handle<V>, _cx: &mut impl Context<'a>) -> SafeResult<Self>
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
     where
         Self: Sized,
     {
-        let js_bool: Handle<JsBoolean> = handle.downcast().map_err(|e| LazyFmt::new(e))?;
+        let js_bool: Handle<JsBoolean> = expect(handle, cx, "boolean")?;
         Ok(js_bool.value())
     }
 }
@@ -219,43 +398,7 @@ impl IntoHandle for bool {
     }
 }
 
-fn from_binary<'a, T, V, C>(handle: &Handle<T>, cx: &mut C) -> Result<Vec<u8>, ()>
-where
-    T: Value,
-    V: Value,
-    // https://doc.rust-lang.org/nomicon/hrtb.html
-    for<'x> &'x V: Borrow<Target = BinaryData<'x>>,
-    C: Context<'a>,
-{
-    if let Ok(buffer) = handle.downcast::<V>() {
-        let lock = cx.lock();
-        let binary = buffer.borrow(&lock);
-        return Ok(binary.as_slice().to_owned());
-    }
-    Err(())
-}
-
-impl FromHandle for Vec<u8> {
-    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
-    where
-        Self: Sized,
-    {
-        if let Ok(buffer) = from_binary::<V, JsArrayBuffer, _>(&handle, cx) {
-            return Ok(buffer);
-        }
-
-        if let Ok(buffer) = from_binary::<V, JsBuffer, _>(&handle, cx) {
-            return Ok(buffer);
-        }
-
-        // TODO: We want the error to indicate that either string or buffer
-        // was ok, but this error was handled and only the string error
-        // is seen if an invalid type is specified.
-        let s = String::from_handle(handle, cx)?;
-        let v = s.from_hex().map_err(|_| "Invalid hex")?;
-        Ok(v)
-    }
-}
+ts_type!(bool => "boolean", "boolean");
 
 impl FromHandle for u64 {
     fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
@@ -280,77 +423,239 @@ impl FromHandle for u64 {
     }
 }
 
-impl FromHandle for Duration {
+ts_type!(u64 => "number", "number");
+
+fn narrow<'a, T, V>(
+    handle: Handle<V>,
+    cx: &mut impl Context<'a>,
+    err: &'static str,
+) -> SafeResult<T>
+where
+    T: TryFrom<u64>,
+    V: Value,
+{
+    let n = u64::from_handle(handle, cx)?;
+    Ok(n.try_into().map_err(|_| err)?)
+}
+
+impl FromHandle for u32 {
     fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
     where
         Self: Sized,
     {
-        let ms = f64::from_handle(handle, cx)?;
-
-        if ms.is_nan() {
-            Err("Got NaN for Duration")?;
-        } else if ms.is_infinite() {
-            Err("Got infinite for Duration")?;
-        } else if ms < 0.0 {
-            Err("Got negative number for Duration")?;
-        }
+        narrow(handle, cx, "Got number exceeding limits of u32")
+    }
+}
+
+ts_type!(u32 => "number", "number");
+
+impl FromHandle for u16 {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        narrow(handle, cx, "Got number exceeding limits of u16")
+    }
+}
+
+ts_type!(u16 => "number", "number");
+
+impl FromHandle for u8 {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        narrow(handle, cx, "Got number exceeding limits of u8")
+    }
+}
+
+ts_type!(u8 => "number", "number");
+
+/// A string of exactly one character, which may be more than one UTF-16
+/// code unit in JS, eg: "😀"
+impl FromHandle for char {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        let c = with_str_as(handle, cx, "string", 1, |s| {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(c),
+                _ => Err(format!("Expected a single character, got \"{}\"", s)),
+            }
+        })?;
+        Ok(c?)
+    }
+}
 
-        Ok(Duration::from_secs_f64(ms / 1000.0))
+impl IntoHandle for char {
+    type Handle = JsString;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        let mut utf8 = [0; 4];
+        Ok(cx.string(self.encode_utf8(&mut utf8)))
     }
 }
 
+ts_type!(char => "string", "string");
+
+macro_rules! non_zero_impls {
+    ($($t:ident($inner:ty),)*) => {
+        $(
+            impl FromHandle for $t {
+                fn from_handle<'a, V: Value>(
+                    handle: Handle<V>,
+                    cx: &mut impl Context<'a>,
+                ) -> SafeResult<Self>
+                where
+                    Self: Sized,
+                {
+                    let n = <$inner>::from_handle(handle, cx)?;
+                    Ok($t::new(n).ok_or(concat!("Got zero for ", stringify!($t)))?)
+                }
+            }
+
+            impl IntoHandle for $t {
+                type Handle = JsNumber;
+                fn into_handle<'c>(
+                    &self,
+                    cx: &mut impl Context<'c>,
+                ) -> SafeJsResult<'c, Self::Handle> {
+                    self.get().into_handle(cx)
+                }
+            }
+
+            impl crate::decls::TsType for $t {
+                fn ts_input() -> String {
+                    <$inner as crate::decls::TsType>::ts_input()
+                }
+            }
+        )*
+    };
+}
+
+non_zero_impls! {
+    NonZeroU32(u32),
+    NonZeroU64(u64),
+}
+
+/// Integers which are too wide for f64 are decoded from a string,
+/// but for convenience may also be given as a number when small enough.
+/// tried is what the caller already checked for, to list in the error.
+fn from_string_or_u64<'a, T, V>(
+    handle: Handle<V>,
+    cx: &mut impl Context<'a>,
+    tried: Candidates,
+    err: &'static str,
+) -> SafeResult<T>
+where
+    T: Decode<str> + From<u64>,
+    V: Value,
+{
+    if handle.is_a::<JsString>() {
+        return Ok(with_str(handle, cx, |s| decode(s))?.map_err(|_| err)?);
+    }
+    let n: Handle<JsNumber> = match handle.downcast() {
+        Ok(n) => n,
+        Err(_) => return Err(tried.or("string").or("number").error(handle, cx).into()),
+    };
+    let n = u64::from_handle(n, cx)?;
+    Ok(n.into())
+}
+
 impl FromHandle for U256 {
     fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
     where
         Self: Sized,
     {
-        match String::from_handle(handle, cx) {
-            Ok(s) => return Ok(decode(&s).map_err(|_| "Failed to parse U256")?),
-            // Thrown must never be handled.
-            Err(MaybeThrown::Thrown(t)) => return Err(MaybeThrown::Thrown(t)),
-            // But unthrown can be ignored since we are going to try u64 next.
-            // FIXME: This error will be confusing since this supports cast from
-            // either string or u64 but onlyl the error message from the u64
-            // branch will be seen.
-            _ => {}
-        };
+        // Big-endian, without going through a string
+        if let Some(n) = with_binary(handle, cx, DecodeBytes::decode_bytes)? {
+            return Ok(n.map_err(|_| "Failed to parse U256")?);
+        }
+        from_string_or_u64(handle, cx, Candidates::binary(), "Failed to parse U256")
+    }
+}
 
-        let n = u64::from_handle(handle, cx)?;
-        Ok(n.into())
+ts_type!(U256 => "string | number | Buffer | ArrayBuffer", "string");
+
+impl FromHandle for U128 {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        from_string_or_u64(handle, cx, Candidates::default(), "Failed to parse U128")
     }
 }
 
-impl FromHandle for SecretKey {
+ts_type!(U128 => "string | number", "string");
+
+impl FromHandle for u128 {
     fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
     where
         Self: Sized,
     {
-        let s = String::from_handle(handle, cx)?;
-        let mut s = s.as_str();
-        if s.starts_with("0x") {
-            s = &s[2..]
+        from_string_or_u64(handle, cx, Candidates::default(), "Failed to parse u128")
+    }
+}
+
+ts_type!(u128 => "string | number", "string");
+
+impl FromHandle for i128 {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        if handle.is_a::<JsString>() {
+            return Ok(with_str(handle, cx, |s| decode(s))?.map_err(|_| "Failed to parse i128")?);
+        }
+        let number: Handle<JsNumber> = expect(handle, cx, "string or number")?;
+        let number = number.value();
+        if number.is_nan() {
+            Err("Got NaN for i128")?
+        } else if number.is_infinite() {
+            Err("Got infinite for i128")?
+        } else if number.fract() != 0.0 {
+            Err("Got fractional number for i128")?
+        } else if number.abs() > 9007199254740991.0 {
+            Err("Got number exceeding limits of i128 as f64")?
+        } else {
+            Ok(number as i128)
         }
-        Ok(s.parse().map_err(|_| "Failed to parse secret key")?)
     }
 }
 
-impl FromHandle for RecoverableSignature {
+ts_type!(i128 => "string | number", "string");
+
+impl FromHandle for H160 {
     fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
     where
         Self: Sized,
     {
-        let data = <[u8; 65]>::from_handle(handle, cx)?;
+        from_hex_or_binary(handle, cx, "Failed to parse H160")
+    }
+}
 
-        let recovery_id = data[64];
+ts_type!(H160 => "string | Buffer | ArrayBuffer", "string");
 
-        let recovery_id = match recovery_id {
-            0 | 1 => RecoveryId::from_i32(recovery_id as i32).unwrap(),
-            27 | 28 => RecoveryId::from_i32((recovery_id - 27) as i32).unwrap(),
-            _ => Err("Invalid recovery id")?,
-        };
+impl FromHandle for H256 {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        from_hex_or_binary(handle, cx, "Failed to parse H256")
+    }
+}
+
+ts_type!(H256 => "string | Buffer | ArrayBuffer", "string");
 
-        Ok(RecoverableSignature::from_compact(&data[..64], recovery_id)
-            .map_err(|_| "Failed to parse RecoverableSignature")?)
+#[cfg(feature = "crypto")]
+impl FromHandle for SecretKey {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        // The key itself is the caller's to look after, eg: with SigningKey
+        secret::read_key(handle, cx)
     }
 }
 
@@ -360,3 +665,5 @@ impl IntoHandle for () {
         Ok(cx.undefined())
     }
 }
+
+ts_type!(() => "undefined", "undefined");
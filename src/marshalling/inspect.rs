@@ -0,0 +1,142 @@
+use super::*;
+use crate::errors::{DowncastFailure, SafeErr};
+use crate::profiling;
+use std::fmt;
+
+// Gets eg: "[object Map]" the same way JS would, which unlike instanceof
+// also works for values from another realm (vm, worker, etc).
+pub(super) fn type_tag<'a, V: Value>(
+    handle: Handle<V>,
+    cx: &mut impl Context<'a>,
+) -> NeonResult<String> {
+    let global = cx.global();
+    let object: Handle<JsObject> = global.get(cx, "Object")?;
+    let prototype: Handle<JsObject> = object.get(cx, "prototype")?;
+    let to_string: Handle<JsFunction> = prototype.get(cx, "toString")?;
+    let args: Vec<Handle<JsValue>> = Vec::new();
+    let tag: Handle<JsString> = to_string.call(cx, handle, args)?.downcast_or_throw(cx)?;
+    Ok(tag.value())
+}
+
+/// A short description of what a JS value is, for error messages.
+/// One of string, number, boolean, null, undefined, array, function,
+/// buffer, date, bigint, symbol or object.
+pub fn type_name_of<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> &'static str {
+    if handle.is_a::<JsString>() {
+        "string"
+    } else if handle.is_a::<JsNumber>() {
+        "number"
+    } else if handle.is_a::<JsBoolean>() {
+        "boolean"
+    } else if handle.is_a::<JsNull>() {
+        "null"
+    } else if handle.is_a::<JsUndefined>() {
+        "undefined"
+    } else if handle.is_a::<JsArray>() {
+        "array"
+    } else if handle.is_a::<JsFunction>() {
+        "function"
+    } else if handle.is_a::<JsBuffer>() || handle.is_a::<JsArrayBuffer>() {
+        "buffer"
+    } else {
+        // The legacy runtime has no types for the rest, so ask JS.
+        // This is only for an error message, so a throwing getter
+        // on Symbol.toStringTag is ignored.
+        match cx.try_catch(|cx| type_tag(handle, cx)).as_deref() {
+            Ok("[object Date]") => "date",
+            Ok("[object BigInt]") => "bigint",
+            Ok("[object Symbol]") => "symbol",
+            _ => "object",
+        }
+    }
+}
+
+/// Downcasts handle, or fails with eg: "Expected hex string, got function".
+/// The error is a DowncastFailure, which is described here and now rather
+/// than when thrown, unlike a DowncastError.
+pub fn expect<'h, 'a, T: Value, V: Value>(
+    handle: Handle<'h, V>,
+    cx: &mut impl Context<'a>,
+    expected: &str,
+) -> SafeResult<Handle<'h, T>> {
+    match handle.downcast::<T>() {
+        Ok(handle) => Ok(handle),
+        Err(_) => {
+            let failure = DowncastFailure {
+                expected: expected.to_string(),
+                got: type_name_of(handle, cx).to_string(),
+            };
+            profiling::count_downcast_failure(&failure);
+            Err(SafeErr::from(failure).into())
+        }
+    }
+}
+
+/// The representations a value was tried as in turn, for one error naming
+/// them all when none fit, eg: "Expected Buffer, ArrayBuffer or hex
+/// string, got number". A representation which fit but then failed to
+/// decode should report that failure instead, since it's the one meant.
+#[derive(Clone, Debug, Default)]
+pub struct Candidates(Vec<&'static str>);
+
+impl Candidates {
+    /// Buffer and ArrayBuffer, as read by with_binary
+    pub fn binary() -> Self {
+        Self(vec!["Buffer", "ArrayBuffer"])
+    }
+
+    pub fn or(mut self, name: &'static str) -> Self {
+        self.0.push(name);
+        self
+    }
+
+    pub fn error<'a, V: Value>(&self, handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeErr {
+        let failure = self.failure(type_name_of(handle, cx));
+        profiling::count_downcast_failure(&failure);
+        failure.into()
+    }
+
+    fn failure(&self, got: &str) -> DowncastFailure {
+        DowncastFailure {
+            expected: self.to_string(),
+            got: got.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Candidates {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.split_last() {
+            None => f.write_str("nothing"),
+            Some((last, [])) => f.write_str(last),
+            Some((last, rest)) => write!(f, "{} or {}", rest.join(", "), last),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn candidates() {
+        let hex = Candidates::binary().or("hex string");
+        assert_eq!(
+            hex.failure("number").to_string(),
+            "Expected Buffer, ArrayBuffer or hex string, got number"
+        );
+        let number = Candidates::default().or("string").or("number");
+        assert_eq!(
+            number.failure("boolean").to_string(),
+            "Expected string or number, got boolean"
+        );
+        assert_eq!(Candidates::default().or("string").to_string(), "string");
+        assert_eq!(
+            hex.failure("number"),
+            DowncastFailure {
+                expected: "Buffer, ArrayBuffer or hex string".to_string(),
+                got: "number".to_string(),
+            }
+        );
+    }
+}
@@ -0,0 +1,21 @@
+//! The caches behind IntoHandle for byte arrays and Address, sized by
+//! config::NeonUtilsConfig::intern_capacity. Off unless that is set.
+//!
+//! The legacy runtime can't root a JsString to hand out again, so what is
+//! kept is the Rust string. A hit still makes a JsString, but skips the
+//! hex encoding, and for an Address the keccak hash too.
+
+use super::codecs::interned::InternCache;
+use crate::config;
+use std::sync::OnceLock;
+
+static HEX: OnceLock<InternCache> = OnceLock::new();
+static CHECKSUMS: OnceLock<InternCache> = OnceLock::new();
+
+pub(crate) fn hex() -> &'static InternCache {
+    HEX.get_or_init(|| InternCache::new(config::get().intern_capacity))
+}
+
+pub(crate) fn checksums() -> &'static InternCache {
+    CHECKSUMS.get_or_init(|| InternCache::new(config::get().intern_capacity))
+}
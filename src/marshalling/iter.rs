@@ -0,0 +1,143 @@
+use super::*;
+use crate::proxy::Proxy;
+use std::cell::RefCell;
+use std::convert::TryInto;
+
+/// Like IntoHandle for Vec<T>, but writes the items straight into
+/// a preallocated JsArray instead of collecting them first.
+pub fn marshal_iter<'a, I>(cx: &mut impl Context<'a>, iter: I) -> SafeJsResult<'a, JsArray>
+where
+    I: IntoIterator,
+    I::IntoIter: ExactSizeIterator,
+    I::Item: IntoHandle,
+{
+    let iter = iter.into_iter();
+    let len: u32 = iter
+        .len()
+        .try_into()
+        .map_err(|_| "Array to large for JavaScript")?;
+    let arr = JsArray::new(cx, len);
+    super::scope::fill_array(cx, arr, iter)?;
+    Ok(arr)
+}
+
+/// Type erases the item type so that a single JS class can back every generator
+trait Pull {
+    fn pull<'a>(
+        &mut self,
+        cx: &mut MethodContext<'a, JsGenerator>,
+    ) -> SafeResult<Option<Handle<'a, JsValue>>>;
+}
+
+impl<I> Pull for I
+where
+    I: Iterator,
+    I::Item: IntoHandle,
+{
+    fn pull<'a>(
+        &mut self,
+        cx: &mut MethodContext<'a, JsGenerator>,
+    ) -> SafeResult<Option<Handle<'a, JsValue>>> {
+        match self.next() {
+            Some(item) => Ok(Some(item.into_handle(cx)?.upcast())),
+            None => Ok(None),
+        }
+    }
+}
+
+// None once the iterator is exhausted or closed,
+// or if the generator was not created by JsGenerator::lazy.
+type Source = RefCell<Option<Box<dyn Pull>>>;
+
+/// The internals of a JsGenerator
+pub struct GeneratorInternals(Proxy<Source>);
+
+impl GeneratorInternals {
+    fn source<'a>(cx: &mut impl Context<'a>, generator: Handle<JsGenerator>) -> Proxy<Source> {
+        let guard = cx.lock();
+        let internals = generator.borrow(&guard);
+        internals.0.clone()
+    }
+}
+
+declare_types! {
+    /// A JS iterator which marshals items from a Rust iterator on demand.
+    /// Works with for...of and spread.
+    pub class JsGenerator for GeneratorInternals {
+        init(_cx) {
+            Ok(GeneratorInternals(Proxy::new(RefCell::new(None))))
+        }
+
+        method next(mut cx) {
+            let this = cx.this();
+            let source = GeneratorInternals::source(&mut cx, this);
+            let mut source = source.borrow_mut();
+            let pulled = match source.as_mut() {
+                Some(iter) => iter.pull(&mut cx),
+                None => Ok(None),
+            };
+            let value = match pulled {
+                Ok(value) => value,
+                Err(e) => {
+                    // Don't leave JS to spin on a broken iterator
+                    *source = None;
+                    return e.finish(cx);
+                }
+            };
+            if value.is_none() {
+                *source = None;
+            }
+            result(&mut cx, value)
+        }
+    }
+}
+
+impl JsGenerator {
+    /// Creates a JS iterator which pulls from iter as JS asks for items
+    pub fn lazy<'a, I>(cx: &mut impl Context<'a>, iter: I) -> JsResult<'a, JsGenerator>
+    where
+        I: 'static + IntoIterator,
+        I::IntoIter: 'static,
+        I::Item: IntoHandle,
+    {
+        let mut generator = JsGenerator::new::<_, JsValue, _>(cx, vec![])?;
+        let iter: Box<dyn Pull> = Box::new(iter.into_iter());
+        cx.borrow_mut(&mut generator, |mut internals| {
+            internals.0 = Proxy::new(RefCell::new(Some(iter)))
+        });
+
+        // Make it iterable, and let break in for...of drop the iterator early
+        let global = cx.global();
+        let symbol: Handle<JsObject> = global.get(cx, "Symbol")?;
+        let iterator: Handle<JsValue> = symbol.get(cx, "iterator")?;
+        let iterable = JsFunction::new(cx, iterable)?;
+        generator.set(cx, iterator, iterable)?;
+        let close = JsFunction::new(cx, close)?;
+        generator.set(cx, "return", close)?;
+        Ok(generator)
+    }
+}
+
+fn result<'a>(
+    cx: &mut impl Context<'a>,
+    value: Option<Handle<'a, JsValue>>,
+) -> JsResult<'a, JsValue> {
+    let result = cx.empty_object();
+    let done = cx.boolean(value.is_none());
+    let value = value.unwrap_or_else(|| cx.undefined().upcast());
+    result.set(cx, "value", value)?;
+    result.set(cx, "done", done)?;
+    Ok(result.upcast())
+}
+
+fn iterable(mut cx: FunctionContext) -> JsResult<JsObject> {
+    Ok(cx.this())
+}
+
+fn close(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let this = cx.this();
+    if let Ok(generator) = this.downcast::<JsGenerator>() {
+        GeneratorInternals::source(&mut cx, generator).take();
+    }
+    result(&mut cx, None)
+}
@@ -0,0 +1,121 @@
+use crate::errors::SafeResult;
+use std::cell::Cell;
+
+/// Bounds on what FromHandle will decode, so that a buggy or malicious
+/// caller can't block the JS thread with a huge or deeply nested value.
+/// The default is no limits, and each JS thread starts with config::get().limits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MarshalLimits {
+    /// How deeply arrays, Maps and Sets may nest inside each other
+    pub max_depth: usize,
+    /// The most items in any one array, Map, Set or object
+    pub max_items: usize,
+    /// The largest string or byte array, in bytes
+    pub max_bytes: usize,
+}
+
+impl MarshalLimits {
+    pub const UNLIMITED: Self = Self {
+        max_depth: usize::MAX,
+        max_items: usize::MAX,
+        max_bytes: usize::MAX,
+    };
+}
+
+impl Default for MarshalLimits {
+    fn default() -> Self {
+        Self::UNLIMITED
+    }
+}
+
+// Marshalling only happens on the JS thread, so these are per isolate.
+thread_local! {
+    static LIMITS: Cell<MarshalLimits> = Cell::new(crate::config::get().limits);
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Sets the limits for everything decoded on this thread from now on
+pub fn set_limits(limits: MarshalLimits) {
+    LIMITS.with(|l| l.set(limits));
+}
+
+pub fn limits() -> MarshalLimits {
+    LIMITS.with(Cell::get)
+}
+
+/// Runs f with different limits, eg: while decoding the arguments
+/// of an untrusted caller. The previous limits are restored after.
+pub fn with_limits<T>(limits: MarshalLimits, f: impl FnOnce() -> T) -> T {
+    struct Restore(MarshalLimits);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            set_limits(self.0);
+        }
+    }
+
+    let _restore = Restore(self::limits());
+    set_limits(limits);
+    f()
+}
+
+pub(crate) fn check_items(items: usize) -> SafeResult<()> {
+    let max = limits().max_items;
+    if items > max {
+        return Err(format!("Got {} items, which exceeds the limit of {}", items, max).into());
+    }
+    Ok(())
+}
+
+pub(crate) fn check_bytes(bytes: usize) -> SafeResult<()> {
+    let max = limits().max_bytes;
+    if bytes > max {
+        return Err(format!("Got {} bytes, which exceeds the limit of {}", bytes, max).into());
+    }
+    Ok(())
+}
+
+/// Held while decoding the items of a collection
+pub(crate) struct Nested(());
+
+impl Nested {
+    pub fn enter() -> SafeResult<Self> {
+        let depth = DEPTH.with(|d| d.get()) + 1;
+        let max = limits().max_depth;
+        if depth > max {
+            return Err(format!("Nested deeper than the limit of {}", max).into());
+        }
+        DEPTH.with(|d| d.set(depth));
+        Ok(Nested(()))
+    }
+}
+
+impl Drop for Nested {
+    fn drop(&mut self) {
+        DEPTH.with(|d| d.set(d.get() - 1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scoped_limits() {
+        let strict = MarshalLimits {
+            max_depth: 1,
+            max_items: 2,
+            max_bytes: 3,
+        };
+        with_limits(strict, || {
+            assert!(check_items(2).is_ok());
+            assert!(check_items(3).is_err());
+            assert!(check_bytes(4).is_err());
+            let outer = Nested::enter().ok();
+            assert!(outer.is_some());
+            assert!(Nested::enter().is_err());
+            drop(outer);
+            assert!(Nested::enter().is_ok());
+        });
+        assert_eq!(limits(), MarshalLimits::UNLIMITED);
+    }
+}
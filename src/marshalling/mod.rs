@@ -1,12 +1,80 @@
 use neon::prelude::*;
+mod address;
+mod args;
+mod bounded;
+mod bytes;
+pub mod casing;
 pub mod codecs;
+pub mod coercion;
+mod collections;
+mod decimal;
+mod freeze;
 mod handle_impls;
+mod inspect;
+pub(crate) mod interned;
+mod iter;
+pub mod limits;
+mod named_tuple;
+mod net;
+pub mod normalize;
+mod object_ext;
+mod option_policy;
+mod path;
+mod plain_object;
+mod scope;
+mod secret;
+#[cfg(feature = "crypto")]
+mod signature;
+mod symbols;
+mod time;
+mod try_into;
+#[cfg(feature = "url")]
+mod url;
+#[cfg(feature = "uuid")]
+mod uuid;
+pub mod validators;
 use crate::errors::{SafeJsResult, SafeResult};
+use normalize::Pipeline;
 
-pub use handle_impls::AsArrayBuffer;
+pub use address::Address;
+pub use args::{Args, ArgsSpec, ExpectArgs};
+pub use bounded::{Bounded, InRange};
+pub use bytes::{
+    with_bytes, with_bytes_mut, AsArrayBuffer, AsNodeBuffer, ByteArray, Bytes, HexBytes, HexFormat,
+    PrefixedHex,
+};
+pub use coercion::Coerce;
+pub use collections::{AsJsMap, AsJsObject, AsJsSet};
+pub use decimal::Decimal;
+pub use freeze::{freeze, Freeze};
+pub(crate) use handle_impls::with_str_as;
+pub use handle_impls::{with_str, AsHexQuantity};
+pub use inspect::{expect, type_name_of, Candidates};
+pub use iter::{marshal_iter, GeneratorInternals, JsGenerator};
+pub use limits::MarshalLimits;
+pub use named_tuple::NamedTuple;
+pub use neon_utils_derive::{FromHandle, IntoHandle};
+pub use object_ext::ObjectExt;
+pub use option_policy::{AsUndefined, SkipIfNone};
+#[doc(hidden)]
+pub use option_policy::{Field, Keep, LeaveOut};
+pub use path::{AbsolutePath, ExistingPath};
+pub use plain_object::PlainObject;
+pub use scope::{with_scope, ITEMS_PER_SCOPE};
+pub use secret::Secret;
+#[cfg(feature = "crypto")]
+pub use secret::SigningKey;
+#[cfg(feature = "crypto")]
+pub use signature::{normalize_s, AsCompactHex, LowS, Signature, VFormat};
+pub use symbols::{well_known_symbol, SymbolKey, WellKnownSymbol};
+#[cfg(feature = "bigint")]
+pub use time::Nanos;
+pub use try_into::{Checked, HandleError, TryIntoHandle};
+pub use zeroize::Zeroize;
 
 pub trait IntoHandle {
     type Handle: Value;
+    #[allow(clippy::wrong_self_convention)]
     fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle>;
 }
 
@@ -16,10 +84,41 @@ pub trait FromHandle {
         Self: Sized;
 }
 
+/// For properties of an options object which may be left out. Missing
+/// and undefined properties give a default instead of an error.
+/// This is what #[neon(default)] uses when deriving FromHandle.
+pub trait FromHandleWithDefault: FromHandle + Sized {
+    fn from_handle_or_else<'a, V: Value>(
+        handle: Handle<V>,
+        cx: &mut impl Context<'a>,
+        default: impl FnOnce() -> Self,
+    ) -> SafeResult<Self> {
+        if handle.is_a::<JsUndefined>() {
+            Ok(default())
+        } else {
+            Self::from_handle(handle, cx)
+        }
+    }
+
+    fn from_handle_or_default<'a, V: Value>(
+        handle: Handle<V>,
+        cx: &mut impl Context<'a>,
+    ) -> SafeResult<Self>
+    where
+        Self: Default,
+    {
+        Self::from_handle_or_else(handle, cx, Self::default)
+    }
+}
+
+impl<T: FromHandle> FromHandleWithDefault for T {}
+
 /// A helper to conveniently do things like:
 /// let v: Duration = fn_ctx.get(0)?;
 pub trait Arg<K> {
     fn arg<T: FromHandle>(&mut self, key: K) -> SafeResult<T>;
+    /// Like arg, but runs the value through a normalization Pipeline first
+    fn arg_normalized<T: FromHandle>(&mut self, key: K, pipeline: &Pipeline) -> SafeResult<T>;
 }
 
 impl<O: neon::object::This> Arg<i32> for CallContext<'_, O> {
@@ -27,18 +126,300 @@ impl<O: neon::object::This> Arg<i32> for CallContext<'_, O> {
         let arg = self.argument::<JsValue>(key)?;
         T::from_handle(arg, self)
     }
+
+    fn arg_normalized<T: FromHandle>(&mut self, key: i32, pipeline: &Pipeline) -> SafeResult<T> {
+        let arg = self.argument::<JsValue>(key)?;
+        pipeline.decode(arg, self)
+    }
 }
 
+/// Builds a JS object from Rust values. Keys are used as written unless
+/// a leading #[js(casing = ...)] respells them, eg: max_fee to maxFee.
+/// Besides idents, a key may be a string literal, which is never respelled,
+/// or in brackets to compute it from anything IntoHandle, eg: a symbol
+/// like [WellKnownSymbol::ToStringTag]: "Pool". Also:
+///   fee: { max: max_fee }            a nested object, with the same casing
+///   memo?: memo                      an Option, left out when None
+///   ..defaults                       the properties of another object
+/// A leading #[js(skip_none)], or #[js(casing = ..., skip_none)], leaves out
+/// every field that is a None Option, as if each were written memo?: memo.
+/// A SkipIfNone field is always left out when None.
+/// Eg: js_object!(cx => { id: id, "content-type": mime, ..extra })
 #[macro_export]
 macro_rules! js_object {
-    ($cx:expr => {$($k:ident: $v:expr,)*}) => {
+    ($cx:expr => #[js(casing = $casing:expr, skip_none)] {$($fields:tt)*}) => {
         {
             let js = JsObject::new($cx);
-            $(
-                let handle = ($v).into_handle($cx)?;
-                js.set($cx, stringify!($k), handle)?;
-            )*
+            $crate::__js_object_fields!($crate; $cx, js, Some($casing), true; $($fields)*);
             Ok(js)
         }
-    }
+    };
+    ($cx:expr => #[js(casing = $casing:expr)] {$($fields:tt)*}) => {
+        {
+            let js = JsObject::new($cx);
+            $crate::__js_object_fields!($crate; $cx, js, Some($casing), false; $($fields)*);
+            Ok(js)
+        }
+    };
+    ($cx:expr => #[js(skip_none)] {$($fields:tt)*}) => {
+        {
+            let js = JsObject::new($cx);
+            $crate::__js_object_fields!($crate; $cx, js, None, true; $($fields)*);
+            Ok(js)
+        }
+    };
+    ($cx:expr => {$($fields:tt)*}) => {
+        {
+            let js = JsObject::new($cx);
+            $crate::__js_object_fields!($crate; $cx, js, None, false; $($fields)*);
+            Ok(js)
+        }
+    };
+}
+
+/// Builds a JS array from Rust values of any mix of IntoHandle types, eg:
+/// js_array![cx => address, amount, memo]. Each index comes from where
+/// the value is written, so none can be set twice or skipped.
+#[macro_export]
+macro_rules! js_array {
+    ($cx:expr => $($v:expr),* $(,)?) => {
+        {
+            let js = JsArray::new($cx, 0);
+            let items: &[::neon::handle::Handle<::neon::types::JsValue>] = &[
+                $($crate::marshalling::IntoHandle::into_handle(&$v, $cx)?.upcast()),*
+            ];
+            for (i, item) in items.iter().enumerate() {
+                js.set($cx, i as u32, *item)?;
+            }
+            Ok(js)
+        }
+    };
+}
+
+/// Like js_object!, but the object and everything in it is frozen
+/// so that JS can't change it. Eg: for config exported as a constant.
+/// It takes the same #[js(...)] options.
+#[macro_export]
+macro_rules! js_const_object {
+    ($cx:expr => #[js(casing = $casing:expr, skip_none)] {$($fields:tt)*}) => {
+        {
+            let js = JsObject::new($cx);
+            $crate::__js_object_fields!($crate; $cx, js, Some($casing), true; $($fields)*);
+            $crate::marshalling::freeze(js, $cx, $crate::marshalling::Freeze::Deep)?;
+            Ok(js)
+        }
+    };
+    ($cx:expr => #[js(casing = $casing:expr)] {$($fields:tt)*}) => {
+        {
+            let js = JsObject::new($cx);
+            $crate::__js_object_fields!($crate; $cx, js, Some($casing), false; $($fields)*);
+            $crate::marshalling::freeze(js, $cx, $crate::marshalling::Freeze::Deep)?;
+            Ok(js)
+        }
+    };
+    ($cx:expr => #[js(skip_none)] {$($fields:tt)*}) => {
+        {
+            let js = JsObject::new($cx);
+            $crate::__js_object_fields!($crate; $cx, js, None, true; $($fields)*);
+            $crate::marshalling::freeze(js, $cx, $crate::marshalling::Freeze::Deep)?;
+            Ok(js)
+        }
+    };
+    ($cx:expr => {$($fields:tt)*}) => {
+        {
+            let js = JsObject::new($cx);
+            $crate::__js_object_fields!($crate; $cx, js, None, false; $($fields)*);
+            $crate::marshalling::freeze(js, $cx, $crate::marshalling::Freeze::Deep)?;
+            Ok(js)
+        }
+    };
+}
+
+// A key of js_object! as a JsValue. Only ident keys are respelled.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __js_object_key {
+    ($cx:expr, $casing:expr, [$k:expr]) => {
+        $crate::marshalling::IntoHandle::into_handle(&$k, $cx)?.upcast::<JsValue>()
+    };
+    ($cx:expr, $casing:expr, $k:ident) => {{
+        let casing: Option<$crate::marshalling::casing::Casing> = $casing;
+        let key = match casing {
+            Some(casing) => $crate::marshalling::casing::rename_key(stringify!($k), casing),
+            None => ::std::borrow::Cow::Borrowed(stringify!($k)),
+        };
+        $cx.string(key.as_ref()).upcast::<JsValue>()
+    }};
+    ($cx:expr, $casing:expr, $k:literal) => {
+        $cx.string($k).upcast::<JsValue>()
+    };
+}
+
+/// Copies the own enumerable properties of source onto target, like
+/// { ...source } in JS. Used by js_object! for ..source.
+#[doc(hidden)]
+pub fn spread<'a>(
+    cx: &mut impl Context<'a>,
+    target: Handle<'a, JsObject>,
+    source: Handle<'a, JsValue>,
+) -> NeonResult<()> {
+    let global = cx.global();
+    let object: Handle<JsObject> = global.get(cx, "Object")?;
+    let assign: Handle<JsFunction> = object.get(cx, "assign")?;
+    assign.call(cx, object, vec![target.upcast(), source])?;
+    Ok(())
+}
+
+/// Names the elements of a tuple, so it is marshalled as an object which
+/// JS can destructure. The names come first, then the tuple, eg:
+/// Ok(NamedTuple!(gas_used, logs, status = execute(&tx)?))
+/// A leading #[js(casing = ...)] respells the names like js_object! does.
+#[macro_export]
+#[allow(non_snake_case)]
+macro_rules! NamedTuple {
+    (#[js(casing = $casing:expr)] $($k:ident),+ $(,)? = $v:expr) => {
+        $crate::marshalling::NamedTuple {
+            names: [$(stringify!($k)),+],
+            values: $v,
+            casing: Some($casing),
+        }
+    };
+    ($($k:ident),+ $(,)? = $v:expr) => {
+        $crate::marshalling::NamedTuple {
+            names: [$(stringify!($k)),+],
+            values: $v,
+            casing: None,
+        }
+    };
+}
+
+/// Declares a fieldless enum that is marshalled to and from JS as a string tag.
+/// The tag is the variant name respelled with a Casing, which defaults to camelCase
+/// and may be given with a leading #[js(casing = ...)] attribute. Variants
+/// may have doc comments. Eg:
+///
+/// js_enum! {
+///     #[js(casing = Casing::Lower)]
+///     #[derive(Clone, Copy, Debug)]
+///     pub enum Network { Mainnet, Testnet }
+/// }
+#[macro_export]
+macro_rules! js_enum {
+    (
+        #[js(casing = $casing:expr)]
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident { $($(#[$vmeta:meta])* $variant:ident),+ $(,)? }
+    ) => {
+        $(#[$meta])*
+        $vis enum $name {
+            $($(#[$vmeta])* $variant,)+
+        }
+
+        impl $crate::marshalling::FromHandle for $name {
+            fn from_handle<'a, V: ::neon::types::Value>(
+                handle: ::neon::handle::Handle<V>,
+                cx: &mut impl ::neon::context::Context<'a>,
+            ) -> $crate::errors::SafeResult<Self>
+            where
+                Self: Sized,
+            {
+                let casing: $crate::marshalling::casing::Casing = $casing;
+                let s = <String as $crate::marshalling::FromHandle>::from_handle(handle, cx)?;
+                $(
+                    if s == casing.apply(stringify!($variant)) {
+                        return Ok($name::$variant);
+                    }
+                )+
+                let accepted: Vec<String> = vec![
+                    $(format!("\"{}\"", casing.apply(stringify!($variant))),)+
+                ];
+                Err(format!(
+                    "Expected one of {} for {} but got \"{}\"",
+                    accepted.join(", "),
+                    stringify!($name),
+                    s
+                ))?
+            }
+        }
+
+        impl $crate::marshalling::IntoHandle for $name {
+            type Handle = ::neon::types::JsString;
+            fn into_handle<'c>(
+                &self,
+                cx: &mut impl ::neon::context::Context<'c>,
+            ) -> $crate::errors::SafeJsResult<'c, Self::Handle> {
+                let casing: $crate::marshalling::casing::Casing = $casing;
+                let tag = match self {
+                    $($name::$variant => casing.apply(stringify!($variant)),)+
+                };
+                Ok(::neon::types::JsString::new(cx, tag))
+            }
+        }
+    };
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident { $($(#[$vmeta:meta])* $variant:ident),+ $(,)? }
+    ) => {
+        $crate::js_enum! {
+            #[js(casing = $crate::marshalling::casing::Casing::Camel)]
+            $(#[$meta])*
+            $vis enum $name { $($(#[$vmeta])* $variant),+ }
+        }
+    };
+}
+
+/// Decodes a Box<dyn Trait> from a JS object with a string discriminant,
+/// eg: { type: "file", path } | { type: "s3", bucket }. Each tag names the
+/// type decoded from the whole object, which is then boxed. The discriminant
+/// is read from "type" unless given with a leading #[js(tag = ...)] attribute. Eg:
+///
+/// tagged_union! {
+///     Box<dyn Storage> {
+///         "file" => FileStorage,
+///         "s3" => S3Storage,
+///     }
+/// }
+#[macro_export]
+macro_rules! tagged_union {
+    (
+        #[js(tag = $key:literal)]
+        $target:ty { $($tag:literal => $variant:ty),+ $(,)? }
+    ) => {
+        impl $crate::marshalling::FromHandle for $target {
+            fn from_handle<'a, V: ::neon::types::Value>(
+                handle: ::neon::handle::Handle<V>,
+                cx: &mut impl ::neon::context::Context<'a>,
+            ) -> $crate::errors::SafeResult<Self>
+            where
+                Self: Sized,
+            {
+                let object: ::neon::handle::Handle<::neon::types::JsObject> =
+                    $crate::marshalling::expect(handle, cx, "object")?;
+                let tag: String = $crate::marshalling::ObjectExt::get_field(&*object, cx, $key)?;
+                $(
+                    if tag == $tag {
+                        let value = <$variant as $crate::marshalling::FromHandle>::from_handle(handle, cx)
+                            .map_err(|e| e.with_context(format!("Invalid {} \"{}\"", $key, tag)))?;
+                        let boxed: Self = Box::new(value);
+                        return Ok(boxed);
+                    }
+                )+
+                let accepted: Vec<String> = vec![$(format!("\"{}\"", $tag),)+];
+                Err(format!(
+                    "Expected one of {} for field '{}' but got \"{}\"",
+                    accepted.join(", "),
+                    $key,
+                    tag
+                ))?
+            }
+        }
+    };
+    (
+        $target:ty { $($tag:literal => $variant:ty),+ $(,)? }
+    ) => {
+        $crate::tagged_union! {
+            #[js(tag = "type")]
+            $target { $($tag => $variant),+ }
+        }
+    };
 }
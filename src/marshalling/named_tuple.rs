@@ -0,0 +1,46 @@
+use super::casing::{rename_key, Casing};
+use super::*;
+use std::borrow::Cow;
+
+/// A tuple marshalled as an object, with a name for each element so JS can
+/// destructure it, eg: const { gasUsed, logs } = estimate(tx).
+/// Usually made with the NamedTuple! macro rather than by hand.
+pub struct NamedTuple<N, T> {
+    pub names: N,
+    pub values: T,
+    pub casing: Option<Casing>,
+}
+
+fn key<'n>(names: &[&'n str], i: usize, casing: Option<Casing>) -> Cow<'n, str> {
+    match casing {
+        Some(casing) => rename_key(names[i], casing),
+        None => Cow::Borrowed(names[i]),
+    }
+}
+
+// The names are an array so that giving the wrong number of them
+// is a compile error instead of a missing property.
+macro_rules! impl_named_tuple {
+    ($n:literal; $($i:tt $t:ident),+) => {
+        impl<$($t: IntoHandle),+> IntoHandle for NamedTuple<[&'static str; $n], ($($t,)+)> {
+            type Handle = JsObject;
+            fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+                let js = cx.empty_object();
+                $(
+                    let handle = self.values.$i.into_handle(cx)?;
+                    js.set(cx, key(&self.names, $i, self.casing).as_ref(), handle)?;
+                )+
+                Ok(js)
+            }
+        }
+    };
+}
+
+impl_named_tuple!(1; 0 T0);
+impl_named_tuple!(2; 0 T0, 1 T1);
+impl_named_tuple!(3; 0 T0, 1 T1, 2 T2);
+impl_named_tuple!(4; 0 T0, 1 T1, 2 T2, 3 T3);
+impl_named_tuple!(5; 0 T0, 1 T1, 2 T2, 3 T3, 4 T4);
+impl_named_tuple!(6; 0 T0, 1 T1, 2 T2, 3 T3, 4 T4, 5 T5);
+impl_named_tuple!(7; 0 T0, 1 T1, 2 T2, 3 T3, 4 T4, 5 T5, 6 T6);
+impl_named_tuple!(8; 0 T0, 1 T1, 2 T2, 3 T3, 4 T4, 5 T5, 6 T6, 7 T7);
@@ -0,0 +1,44 @@
+use super::*;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+// Parsed with std, which takes only the canonical forms, eg: no leading
+// zeros in an IPv4 octet, and brackets around an IPv6 host with a port.
+// They're written back the way Display writes them, eg: "::1" not "0:0:0:0:0:0:0:1".
+macro_rules! net_impls {
+    ($($t:ty => $expected:literal,)*) => {
+        $(
+            impl FromHandle for $t {
+                fn from_handle<'a, V: Value>(
+                    handle: Handle<V>,
+                    cx: &mut impl Context<'a>,
+                ) -> SafeResult<Self>
+                where
+                    Self: Sized,
+                {
+                    let parsed = with_str_as(handle, cx, $expected, 1, |s| {
+                        s.parse::<$t>()
+                            .map_err(|_| format!("Invalid {} '{}'", $expected, s))
+                    })?;
+                    Ok(parsed?)
+                }
+            }
+
+            impl IntoHandle for $t {
+                type Handle = JsString;
+                fn into_handle<'c>(
+                    &self,
+                    cx: &mut impl Context<'c>,
+                ) -> SafeJsResult<'c, Self::Handle> {
+                    Ok(cx.string(self.to_string()))
+                }
+            }
+        )*
+    };
+}
+
+net_impls! {
+    IpAddr => "IP address",
+    Ipv4Addr => "IPv4 address",
+    Ipv6Addr => "IPv6 address",
+    SocketAddr => "socket address",
+}
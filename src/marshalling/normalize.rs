@@ -0,0 +1,104 @@
+use super::codecs::strip_hex_prefix;
+use super::FromHandle;
+use crate::errors::{SafeJsResult, SafeResult};
+use neon::prelude::*;
+
+/// A step that rewrites a JS value before it is parsed.
+///
+/// Like SafeErr these are enumerated rather than generic so that
+/// a Pipeline can be built once and stored alongside a binding.
+#[derive(Clone, Debug)]
+pub enum Normalizer {
+    /// Trims leading and trailing whitespace from strings
+    Trim,
+    /// Removes a leading 0x from strings
+    StripHexPrefix,
+    /// Lowercases strings which are entirely hex (after an optional 0x)
+    LowercaseHex,
+    /// Replaces an object with one of its properties, if present.
+    /// Eg: UnwrapEnvelope("value") turns { value: "1" } into "1"
+    UnwrapEnvelope(&'static str),
+    /// Any other string rewrite
+    Custom(fn(&str) -> String),
+}
+
+impl Normalizer {
+    fn apply<'a>(
+        &self,
+        handle: Handle<'a, JsValue>,
+        cx: &mut impl Context<'a>,
+    ) -> SafeJsResult<'a, JsValue> {
+        if let Normalizer::UnwrapEnvelope(key) = self {
+            if handle.is_a::<JsArray>() {
+                return Ok(handle);
+            }
+            if let Ok(obj) = handle.downcast::<JsObject>() {
+                let inner: Handle<JsValue> = obj.get(cx, *key)?;
+                if !inner.is_a::<JsUndefined>() {
+                    return Ok(inner);
+                }
+            }
+            return Ok(handle);
+        }
+
+        let js_str = match handle.downcast::<JsString>() {
+            Ok(s) => s,
+            Err(_) => return Ok(handle),
+        };
+        let s = js_str.value();
+        let normalized = match self {
+            Normalizer::Trim => s.trim().to_owned(),
+            Normalizer::StripHexPrefix => strip_hex_prefix(&s).to_owned(),
+            Normalizer::LowercaseHex => {
+                if strip_hex_prefix(&s).bytes().all(|b| b.is_ascii_hexdigit()) {
+                    s.to_ascii_lowercase()
+                } else {
+                    s
+                }
+            }
+            Normalizer::Custom(f) => f(&s),
+            Normalizer::UnwrapEnvelope(_) => unreachable!(),
+        };
+        Ok(cx.string(normalized).upcast())
+    }
+}
+
+/// An ordered list of Normalizers that run before FromHandle,
+/// so that shims for old JS call conventions live in one place
+/// per binding instead of inside each FromHandle impl.
+#[derive(Clone, Debug, Default)]
+pub struct Pipeline {
+    normalizers: Vec<Normalizer>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, normalizer: Normalizer) -> Self {
+        self.normalizers.push(normalizer);
+        self
+    }
+
+    pub fn normalize<'a, V: Value>(
+        &self,
+        handle: Handle<'a, V>,
+        cx: &mut impl Context<'a>,
+    ) -> SafeJsResult<'a, JsValue> {
+        let mut handle = handle.upcast();
+        for normalizer in &self.normalizers {
+            handle = normalizer.apply(handle, cx)?;
+        }
+        Ok(handle)
+    }
+
+    pub fn decode<'a, T: FromHandle, V: Value>(
+        &self,
+        handle: Handle<'a, V>,
+        cx: &mut impl Context<'a>,
+    ) -> SafeResult<T> {
+        let handle = self.normalize(handle, cx)?;
+        T::from_handle(handle, cx)
+    }
+}
@@ -0,0 +1,63 @@
+use super::{FromHandle, IntoHandle, SymbolKey};
+use crate::errors::{MaybeThrown, SafeResult};
+use neon::prelude::*;
+
+/// Helpers for reading (possibly nested) fields off of JS objects
+pub trait ObjectExt {
+    /// Reads a field by a dot separated path, eg: "network.rpc.timeout".
+    /// Errors name the path up to the segment that was missing or invalid.
+    fn get_field<'a, T: FromHandle>(&self, cx: &mut impl Context<'a>, path: &str) -> SafeResult<T>;
+
+    /// Sets a property keyed by a symbol, eg: Symbol.toStringTag
+    fn set_symbol_property<'a>(
+        &self,
+        cx: &mut impl Context<'a>,
+        key: impl Into<SymbolKey>,
+        value: impl IntoHandle,
+    ) -> SafeResult<()>;
+}
+
+impl<O: Object> ObjectExt for O {
+    fn get_field<'a, T: FromHandle>(&self, cx: &mut impl Context<'a>, path: &str) -> SafeResult<T> {
+        let mut segments = path.split('.');
+        // split always yields at least one segment
+        let first = segments.next().unwrap();
+        let mut value: Handle<JsValue> = self.get(cx, first)?;
+        let mut walked = first.len();
+
+        for segment in segments {
+            if value.is_a::<JsUndefined>() || value.is_a::<JsNull>() {
+                return Err(format!("Missing field '{}'", &path[..walked]))?;
+            }
+            let obj: Handle<JsObject> = value
+                .downcast()
+                .map_err(|_| format!("Expected field '{}' to be an object", &path[..walked]))?;
+            value = obj.get(cx, segment)?;
+            walked += 1 + segment.len();
+        }
+
+        match T::from_handle(value, cx) {
+            Ok(t) => Ok(t),
+            Err(MaybeThrown::Thrown(t)) => Err(MaybeThrown::Thrown(t)),
+            Err(MaybeThrown::Unthrown(e)) => {
+                if value.is_a::<JsUndefined>() {
+                    Err(format!("Missing field '{}'", path))?
+                } else {
+                    Err(e.with_context(format!("Invalid field '{}'", path)))?
+                }
+            }
+        }
+    }
+
+    fn set_symbol_property<'a>(
+        &self,
+        cx: &mut impl Context<'a>,
+        key: impl Into<SymbolKey>,
+        value: impl IntoHandle,
+    ) -> SafeResult<()> {
+        let key = key.into().into_handle(cx)?;
+        let value = value.into_handle(cx)?;
+        self.set(cx, key, value)?;
+        Ok(())
+    }
+}
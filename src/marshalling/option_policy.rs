@@ -0,0 +1,115 @@
+//! Option<T> is written as null when None. Some JS APIs tell undefined
+//! (not given) from null (cleared), so these write None differently:
+//!   AsUndefined(memo)    undefined rather than null
+//!   SkipIfNone(memo)     as an object field, left out when None
+//! js_object! and #[derive(IntoHandle)] take #[js(skip_none)] or
+//! #[neon(skip_none)] to leave out every None field instead.
+
+use super::{FromHandle, IntoHandle};
+use crate::errors::{SafeJsResult, SafeResult};
+use neon::prelude::*;
+
+/// None as undefined. Reads null or undefined as None, as Option<T> does.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AsUndefined<T>(pub Option<T>);
+
+/// Left out of js_object! and #[derive(IntoHandle)] objects when None.
+/// Anywhere else, eg: in an Array, None is undefined.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SkipIfNone<T>(pub Option<T>);
+
+fn some_or_undefined<'c, T: IntoHandle>(
+    value: &Option<T>,
+    cx: &mut impl Context<'c>,
+) -> SafeJsResult<'c, JsValue> {
+    Ok(match value {
+        Some(t) => t.into_handle(cx)?.upcast(),
+        None => cx.undefined().upcast(),
+    })
+}
+
+impl<T: IntoHandle> IntoHandle for AsUndefined<T> {
+    type Handle = JsValue;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        some_or_undefined(&self.0, cx)
+    }
+}
+
+impl<T: IntoHandle> IntoHandle for SkipIfNone<T> {
+    type Handle = JsValue;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        some_or_undefined(&self.0, cx)
+    }
+}
+
+impl<T: FromHandle> FromHandle for AsUndefined<T> {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        Option::from_handle(handle, cx).map(AsUndefined)
+    }
+}
+
+impl<T: FromHandle> FromHandle for SkipIfNone<T> {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        Option::from_handle(handle, cx).map(SkipIfNone)
+    }
+}
+
+// Whether a field is left out of an object. The macros call
+// (&Field(&value)).left_out(skip_none), which finds LeaveOut for an Option
+// or SkipIfNone and Keep for anything else. Only a field whose type is
+// known where the macro is used can be left out: a generic T is kept.
+#[doc(hidden)]
+pub struct Field<'v, T: ?Sized>(pub &'v T);
+
+#[doc(hidden)]
+pub trait LeaveOut {
+    fn left_out(&self, skip_none: bool) -> bool;
+}
+
+impl<T> LeaveOut for Field<'_, Option<T>> {
+    fn left_out(&self, skip_none: bool) -> bool {
+        skip_none && self.0.is_none()
+    }
+}
+
+impl<T> LeaveOut for Field<'_, SkipIfNone<T>> {
+    fn left_out(&self, _skip_none: bool) -> bool {
+        self.0 .0.is_none()
+    }
+}
+
+#[doc(hidden)]
+pub trait Keep {
+    fn left_out(&self, skip_none: bool) -> bool;
+}
+
+impl<T: ?Sized> Keep for &Field<'_, T> {
+    fn left_out(&self, _skip_none: bool) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The borrow is what picks Keep for anything else
+    #[allow(clippy::needless_borrow)]
+    #[test]
+    fn left_out() {
+        let none: Option<u32> = None;
+        assert!(!(&Field(&none)).left_out(false));
+        assert!((&Field(&none)).left_out(true));
+        assert!(!(&Field(&Some(1))).left_out(true));
+        assert!((&Field(&SkipIfNone::<u32>(None))).left_out(false));
+        assert!(!(&Field(&SkipIfNone(Some(1)))).left_out(false));
+        assert!(!(&Field(&AsUndefined::<u32>(None))).left_out(true));
+        assert!(!(&Field(&0u32)).left_out(true));
+    }
+}
@@ -0,0 +1,150 @@
+use super::bytes::with_binary;
+use super::*;
+use crate::decls::ts_type;
+use crate::errors::SafeErr;
+use std::path::{Path, PathBuf};
+
+// Paths are read as strings, or as a Buffer of the raw bytes like fs
+// takes, which on Unix needn't be UTF-8. Either is checked for what no
+// platform allows, so that a bad path fails here and not in some fs call
+// later with a vaguer error.
+impl FromHandle for PathBuf {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        let path = match with_binary(handle, cx, path_from_bytes)? {
+            Some(path) => path?,
+            None => PathBuf::from(with_str_as(handle, cx, "path string or Buffer", 1, |s| {
+                s.to_string()
+            })?),
+        };
+        check(&path)?;
+        Ok(path)
+    }
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> Result<PathBuf, SafeErr> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(std::ffi::OsStr::from_bytes(bytes).into())
+}
+
+// Elsewhere paths aren't bytes, so only UTF-8 can be told apart
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> Result<PathBuf, SafeErr> {
+    let path = std::str::from_utf8(bytes).map_err(|_| "Invalid path: Buffer is not UTF-8")?;
+    Ok(path.into())
+}
+
+fn check(path: &Path) -> Result<(), SafeErr> {
+    if path.as_os_str().is_empty() {
+        return Err("Invalid path: empty".into());
+    }
+    // No OS takes a NUL in a path, and Node throws on one too
+    if path.to_string_lossy().contains('\0') {
+        return Err(format!("Invalid path '{}': contains a NUL", path.display()).into());
+    }
+    Ok(())
+}
+
+impl IntoHandle for Path {
+    type Handle = JsString;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        // JS strings can't hold what isn't Unicode, and writing it lossily
+        // would name some other file
+        let s = self.to_str().ok_or_else(|| {
+            format!(
+                "Path '{}' is not valid UTF-8, so can't be a JS string",
+                self.display()
+            )
+        })?;
+        s.into_handle(cx)
+    }
+}
+
+ts_type!(Path => "string | Buffer", "string");
+
+impl IntoHandle for PathBuf {
+    type Handle = JsString;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        self.as_path().into_handle(cx)
+    }
+}
+
+ts_type!(PathBuf => "string | Buffer", "string");
+
+/// A path which is absolute on this platform, eg: /etc/app.toml or
+/// C:\app.toml on Windows.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AbsolutePath(pub PathBuf);
+
+impl FromHandle for AbsolutePath {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        let path = PathBuf::from_handle(handle, cx)?;
+        if !path.is_absolute() {
+            return Err(format!("Expected an absolute path, got '{}'", path.display()).into());
+        }
+        Ok(Self(path))
+    }
+}
+
+/// A path to something which existed when it was read. It may not by the
+/// time it is used, so opening it can still fail.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExistingPath(pub PathBuf);
+
+impl FromHandle for ExistingPath {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        let path = PathBuf::from_handle(handle, cx)?;
+        match path.try_exists() {
+            Ok(true) => Ok(Self(path)),
+            Ok(false) => Err(format!("Path '{}' does not exist", path.display()).into()),
+            Err(e) => Err(format!("Can't tell if path '{}' exists: {}", path.display(), e).into()),
+        }
+    }
+}
+
+impl IntoHandle for AbsolutePath {
+    type Handle = JsString;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        self.0.into_handle(cx)
+    }
+}
+
+ts_type!(AbsolutePath => "string | Buffer", "string");
+
+impl IntoHandle for ExistingPath {
+    type Handle = JsString;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        self.0.into_handle(cx)
+    }
+}
+
+ts_type!(ExistingPath => "string | Buffer", "string");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks() {
+        assert!(check(Path::new("a/b")).is_ok());
+        assert!(check(Path::new("")).is_err());
+        assert!(check(Path::new("a\0b")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_bytes() {
+        let path = path_from_bytes(b"caf\xe9").unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(path.as_os_str().len(), 4);
+        assert!(path.to_str().is_none());
+    }
+}
@@ -0,0 +1,102 @@
+use super::*;
+use crate::registry;
+
+/// Decodes T only from a plain object: one made by an object literal,
+/// JSON.parse or Object.create(null), not a class instance. Only its own
+/// enumerable data properties are read, so no getter runs and nothing
+/// comes from the prototype chain, eg: for config which a polluted
+/// Object.prototype mustn't reach. Objects held by its fields aren't
+/// checked, unless they are PlainObjects too.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PlainObject<T>(pub T);
+
+impl<T> PlainObject<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: FromHandle> FromHandle for PlainObject<T> {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        let object: Handle<JsObject> = expect(handle, cx, "plain object")?;
+        let copy = plain_copy(object, cx)?;
+        T::from_handle(copy, cx).map(Self)
+    }
+}
+
+impl<T: IntoHandle> IntoHandle for PlainObject<T> {
+    type Handle = T::Handle;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        self.0.into_handle(cx)
+    }
+}
+
+// Copies the own enumerable data properties of object onto one with no
+// prototype, for T to read from. It only calls the intrinsics, and only
+// reads the descriptors' own properties, so that a script replacing
+// Object.keys or adding Object.prototype.get can't change what's read.
+fn plain_copy<'a>(
+    object: Handle<JsObject>,
+    cx: &mut impl Context<'a>,
+) -> SafeJsResult<'a, JsObject> {
+    let get_prototype_of: Handle<JsFunction> = registry::intrinsic(cx, "getPrototypeOf")?;
+    let undefined = cx.undefined();
+    let prototype = get_prototype_of.call(cx, undefined, vec![object.upcast()])?;
+    if !prototype.is_a::<JsNull>() {
+        let object_prototype: Handle<JsValue> = registry::intrinsic(cx, "prototype")?;
+        let is: Handle<JsFunction> = registry::intrinsic(cx, "is")?;
+        let same: Handle<JsBoolean> = is
+            .call(cx, undefined, vec![prototype, object_prototype])?
+            .downcast_or_throw(cx)?;
+        if !same.value() {
+            let got = match type_name_of(object, cx) {
+                "object" => "class instance",
+                got => got,
+            };
+            return Err(format!("Expected plain object, got {}", got).into());
+        }
+    }
+
+    let create: Handle<JsFunction> = registry::intrinsic(cx, "create")?;
+    let null = cx.null().upcast();
+    let copy: Handle<JsObject> = create
+        .call(cx, undefined, vec![null])?
+        .downcast_or_throw(cx)?;
+    let keys: Handle<JsFunction> = registry::intrinsic(cx, "keys")?;
+    let keys: Handle<JsArray> = keys
+        .call(cx, undefined, vec![object.upcast()])?
+        .downcast_or_throw(cx)?;
+    let describe: Handle<JsFunction> = registry::intrinsic(cx, "getOwnPropertyDescriptor")?;
+    let has_own: Handle<JsFunction> = registry::intrinsic(cx, "hasOwnProperty")?;
+    for key in keys.to_vec(cx)? {
+        let descriptor: Handle<JsObject> = describe
+            .call(cx, undefined, vec![object.upcast(), key])?
+            .downcast_or_throw(cx)?;
+        let field = cx.string("value").upcast();
+        let is_data: Handle<JsBoolean> = has_own
+            .call(cx, descriptor, vec![field])?
+            .downcast_or_throw(cx)?;
+        if !is_data.value() {
+            // An accessor, which has an own get even if it's only a setter
+            let getter: Handle<JsValue> = descriptor.get(cx, "get")?;
+            let got = if getter.is_a::<JsUndefined>() {
+                "setter"
+            } else {
+                "getter"
+            };
+            let key: Handle<JsString> = key.downcast_or_throw(cx)?;
+            return Err(format!(
+                "Invalid field '{}': Expected a value, got a {}",
+                key.value(),
+                got
+            )
+            .into());
+        }
+        let value: Handle<JsValue> = descriptor.get(cx, "value")?;
+        copy.set(cx, key, value)?;
+    }
+    Ok(copy)
+}
@@ -0,0 +1,52 @@
+//! Every handle made in a HandleScope lives until the scope closes, which
+//! for a function is when it returns. Marshalling a million items would
+//! keep a million handles (and whatever they point to) alive at once, so
+//! big loops make their handles in a scope of their own every so often.
+
+use super::*;
+
+/// Arrays are written this many items at a time in a scope of their own.
+/// Smaller ones stay in the caller's scope, which costs nothing extra.
+pub const ITEMS_PER_SCOPE: usize = 1024;
+
+/// Runs f in a new HandleScope, closed when it returns, so any handles f
+/// makes are let go. Only what f puts somewhere from outside the scope,
+/// eg: into an array made before, outlives it.
+///
+///   for chunk in rows.chunks(ITEMS_PER_SCOPE) {
+///       with_scope(cx, |cx| append(cx, table, chunk))?;
+///   }
+pub fn with_scope<'a, T>(
+    cx: &mut impl Context<'a>,
+    f: impl for<'b> FnOnce(&mut ExecuteContext<'b>) -> T,
+) -> T {
+    cx.execute_scoped(|mut cx| f(&mut cx))
+}
+
+// Sets each item at its index of arr, which is where IntoHandle for slices
+// and marshal_iter spend their handles
+pub(crate) fn fill_array<'a, I>(
+    cx: &mut impl Context<'a>,
+    arr: Handle<'a, JsArray>,
+    items: I,
+) -> SafeResult<()>
+where
+    I: IntoIterator,
+    I::Item: IntoHandle,
+{
+    let mut items = items.into_iter().enumerate().peekable();
+    for (i, item) in items.by_ref().take(ITEMS_PER_SCOPE) {
+        let value = item.into_handle(cx)?;
+        arr.set(cx, i as u32, value)?;
+    }
+    while items.peek().is_some() {
+        with_scope(cx, |cx| {
+            for (i, item) in items.by_ref().take(ITEMS_PER_SCOPE) {
+                let value = item.into_handle(cx)?;
+                arr.set(cx, i as u32, value)?;
+            }
+            SafeResult::Ok(())
+        })?;
+    }
+    Ok(())
+}
@@ -0,0 +1,150 @@
+use super::*;
+use std::fmt;
+#[cfg(feature = "crypto")]
+use std::ops::Deref;
+use zeroize::Zeroize;
+#[cfg(feature = "crypto")]
+use zeroize::Zeroizing;
+
+#[cfg(feature = "crypto")]
+use super::codecs::strip_hex_prefix;
+#[cfg(feature = "crypto")]
+use super::handle_impls::read_str;
+#[cfg(feature = "crypto")]
+use secp256k1::SecretKey;
+
+/// A secp256k1 SecretKey which can be held in a Secret. SecretKey isn't
+/// Zeroize, and being from another crate can't be made so.
+#[cfg(feature = "crypto")]
+pub struct SigningKey(SecretKey);
+
+#[cfg(feature = "crypto")]
+impl Deref for SigningKey {
+    type Target = SecretKey;
+    fn deref(&self) -> &SecretKey {
+        &self.0
+    }
+}
+
+#[cfg(feature = "crypto")]
+impl Zeroize for SigningKey {
+    fn zeroize(&mut self) {
+        self.0.non_secure_erase();
+    }
+}
+
+/// Key material which can't leak by mistake:
+///   * Debug and Display print [REDACTED]
+///   * It isn't IntoHandle, so can't be returned to JS
+///   * It's zeroized on drop, as is what it was decoded from
+///
+/// The JS string a key came from is out of reach of Rust, and a copy
+/// taken out of expose isn't zeroized, so keep keys in a Secret for as
+/// long as they are held. Eg: Secret<SigningKey> rather than SecretKey.
+pub struct Secret<T: Zeroize>(T);
+
+impl<T: Zeroize> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Zeroize> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl<T: Zeroize> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
+
+impl<T: Zeroize> fmt::Display for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
+
+// Errors never include any of the input
+#[cfg(feature = "crypto")]
+fn decode_key(hex: &str, key: &mut [u8; 32]) -> Result<(), &'static str> {
+    const INVALID: &str = "Failed to parse secret key";
+    let hex = strip_hex_prefix(hex).as_bytes();
+    if hex.len() != 64 {
+        return Err(INVALID);
+    }
+    for (byte, pair) in key.iter_mut().zip(hex.chunks(2)) {
+        let nibble = |c: u8| (c as char).to_digit(16).ok_or(INVALID);
+        *byte = (nibble(pair[0])? << 4 | nibble(pair[1])?) as u8;
+    }
+    Ok(())
+}
+
+/// Reads a hex string, zeroizing the copies made along the way
+#[cfg(feature = "crypto")]
+pub(crate) fn read_key<'a, V: Value>(
+    handle: Handle<V>,
+    cx: &mut impl Context<'a>,
+) -> SafeResult<SecretKey> {
+    let hex = Zeroizing::new(read_str(handle, cx, "hex string", 2)?);
+    let mut bytes = Zeroizing::new([0u8; 32]);
+    decode_key(&hex, &mut bytes)?;
+    Ok(SecretKey::from_slice(&*bytes).map_err(|_| "Failed to parse secret key")?)
+}
+
+#[cfg(feature = "crypto")]
+impl FromHandle for Secret<SigningKey> {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        read_key(handle, cx).map(|key| Secret::new(SigningKey(key)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacted() {
+        let secret = Secret::new(String::from("hunter2"));
+        assert_eq!(format!("{:?} {}", secret, secret), "[REDACTED] [REDACTED]");
+    }
+
+    #[test]
+    fn zeroized() {
+        let mut bytes = [7u8; 4];
+        bytes.zeroize();
+        assert_eq!(bytes, [0; 4]);
+
+        let mut s = String::with_capacity(16);
+        s.push_str("hunter2");
+        s.zeroize();
+        assert!(s.is_empty());
+        assert!(s.capacity() >= 16);
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn decodes_keys() {
+        let mut key = [0u8; 32];
+        decode_key(&format!("0x{}", "0a".repeat(32)), &mut key).unwrap();
+        assert_eq!(key, [10; 32]);
+        let bad = "zz".repeat(32);
+        assert_eq!(
+            decode_key(&bad, &mut key),
+            Err("Failed to parse secret key")
+        );
+        assert_eq!(
+            decode_key("0a0a", &mut key),
+            Err("Failed to parse secret key")
+        );
+    }
+}
@@ -0,0 +1,333 @@
+use super::bytes::with_binary;
+use super::inspect::expect;
+use super::*;
+use crate::errors::SafeJsResult;
+use crate::prelude::*;
+use rustc_hex::ToHex as _;
+use secp256k1::ecdsa::{self, RecoverableSignature, RecoveryId};
+use std::convert::{TryFrom, TryInto};
+
+/// How the recovery id is written as v
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VFormat {
+    /// 0 or 1, eg: typed transactions
+    Parity,
+    /// 27 or 28, eg: personal_sign. This is the default.
+    Legacy,
+}
+
+impl VFormat {
+    fn v(&self, recovery_id: RecoveryId) -> u8 {
+        let parity = recovery_id.to_i32() as u8;
+        match self {
+            VFormat::Parity => parity,
+            VFormat::Legacy => parity + 27,
+        }
+    }
+}
+
+/// Accepts either format
+fn recovery_id(v: u8) -> Result<RecoveryId, &'static str> {
+    match v {
+        0 | 1 => Ok(RecoveryId::from_i32(v as i32).unwrap()),
+        27 | 28 => Ok(RecoveryId::from_i32((v - 27) as i32).unwrap()),
+        _ => Err("Invalid recovery id"),
+    }
+}
+
+/// A v as a number, in either format
+impl FromHandle for RecoveryId {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        let v: u64 = FromHandle::from_handle(handle, cx)?;
+        let v = u8::try_from(v).map_err(|_| "Invalid recovery id")?;
+        Ok(recovery_id(v)?)
+    }
+}
+
+/// Written as 0 or 1
+impl IntoHandle for RecoveryId {
+    type Handle = JsNumber;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        Ok(cx.number(self.to_i32()))
+    }
+}
+
+fn to_bytes(signature: &RecoverableSignature, format: VFormat) -> [u8; 65] {
+    let (recovery_id, compact) = signature.serialize_compact();
+    let mut bytes = [0; 65];
+    bytes[..64].copy_from_slice(&compact);
+    bytes[64] = format.v(recovery_id);
+    bytes
+}
+
+/// Tells the format from the bytes: DER followed by v, or else 65 bytes
+/// of r, s and v
+fn parse(bytes: &[u8]) -> Result<RecoverableSignature, &'static str> {
+    let (v, signature) = bytes.split_last().ok_or("Empty signature")?;
+    let compact = if is_der(signature) {
+        match (ecdsa::Signature::from_der(signature), signature.try_into()) {
+            (Ok(der), _) => der.serialize_compact(),
+            // r and s which happen to start like DER
+            (Err(_), Ok(compact)) => compact,
+            (Err(_), Err(_)) => return Err("Invalid DER signature"),
+        }
+    } else {
+        signature
+            .try_into()
+            .map_err(|_| "Expected 65 bytes, or DER followed by v")?
+    };
+    RecoverableSignature::from_compact(&compact, recovery_id(*v)?)
+        .map_err(|_| "Failed to parse RecoverableSignature")
+}
+
+// A SEQUENCE whose length is the rest of the bytes. 1 in 65536 compact
+// signatures start that way too, so parse falls back to compact.
+fn is_der(bytes: &[u8]) -> bool {
+    matches!(bytes, [0x30, len, rest @ ..] if *len as usize == rest.len())
+}
+
+/// Flips s to the lower half of the curve order if it isn't, which some
+/// verifiers require (eg: EIP-2). The recovery id flips with it, so the
+/// same key is recovered.
+pub fn normalize_s(signature: &RecoverableSignature) -> RecoverableSignature {
+    let (recovery_id, compact) = signature.serialize_compact();
+    let mut standard = signature.to_standard();
+    standard.normalize_s();
+    let normalized = standard.serialize_compact();
+    if normalized == compact {
+        return *signature;
+    }
+    let flipped = RecoveryId::from_i32(recovery_id.to_i32() ^ 1).unwrap();
+    RecoverableSignature::from_compact(&normalized, flipped).unwrap()
+}
+
+/// Reads 65 bytes of r, s and v, DER followed by v, { r, s, v } or
+/// { der, v }, from hex or binary. v is 0/1 or 27/28. s is kept as given,
+/// which LowS normalizes.
+impl FromHandle for RecoverableSignature {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        if let Some(signature) = with_binary(handle, cx, parse)? {
+            return Ok(signature?);
+        }
+        if handle.is_a::<JsString>() {
+            let bytes = HexBytes::from_handle(handle, cx)?;
+            return Ok(parse(&bytes.0)?);
+        }
+        if type_name_of(handle, cx) != "object" {
+            return Err(Candidates::binary()
+                .or("hex string")
+                .or("{ r, s, v }")
+                .error(handle, cx)
+                .into());
+        }
+        let object: Handle<JsObject> = expect(handle, cx, "object")?;
+        let der: Option<Bytes> = object.get_field(cx, "der")?;
+        match der {
+            Some(der) if is_der(&der) => {
+                let v: u8 = object.get_field(cx, "v")?;
+                let mut bytes = der.to_vec();
+                bytes.push(v);
+                Ok(parse(&bytes)?)
+            }
+            Some(_) => Err("Invalid DER signature".into()),
+            None => Ok(Signature::from_handle(object, cx)?.try_into()?),
+        }
+    }
+}
+
+/// A RecoverableSignature with s normalized to the lower half when read.
+/// It is written as a RecoverableSignature.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LowS(pub RecoverableSignature);
+
+impl FromHandle for LowS {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        RecoverableSignature::from_handle(handle, cx).map(|signature| LowS(normalize_s(&signature)))
+    }
+}
+
+impl IntoHandle for LowS {
+    type Handle = JsString;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        self.0.into_handle(cx)
+    }
+}
+
+// Specify the v of a signature written as 65 bytes of hex. Without this it's Legacy.
+pub struct AsCompactHex(pub RecoverableSignature, pub VFormat);
+
+impl IntoHandle for AsCompactHex {
+    type Handle = JsString;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        let hex: String = to_bytes(&self.0, self.1).to_hex();
+        format!("0x{}", hex).into_handle(cx)
+    }
+}
+
+impl IntoHandle for RecoverableSignature {
+    type Handle = JsString;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        AsCompactHex(*self, VFormat::Legacy).into_handle(cx)
+    }
+}
+
+/// A signature split into { r, s, v }, as some JS libraries expect.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Signature {
+    pub r: Bytes32,
+    pub s: Bytes32,
+    pub v: u8,
+}
+
+impl Signature {
+    pub fn new(signature: &RecoverableSignature, format: VFormat) -> Self {
+        let bytes = to_bytes(signature, format);
+        Self {
+            r: bytes[..32].try_into().unwrap(),
+            s: bytes[32..64].try_into().unwrap(),
+            v: bytes[64],
+        }
+    }
+}
+
+impl From<RecoverableSignature> for Signature {
+    fn from(signature: RecoverableSignature) -> Self {
+        Self::new(&signature, VFormat::Legacy)
+    }
+}
+
+impl TryFrom<Signature> for RecoverableSignature {
+    type Error = &'static str;
+    fn try_from(signature: Signature) -> Result<Self, Self::Error> {
+        let mut compact = [0; 64];
+        compact[..32].copy_from_slice(&signature.r);
+        compact[32..].copy_from_slice(&signature.s);
+        RecoverableSignature::from_compact(&compact, recovery_id(signature.v)?)
+            .map_err(|_| "Failed to parse RecoverableSignature")
+    }
+}
+
+impl FromHandle for Signature {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        let object: Handle<JsObject> = expect(handle, cx, "object")?;
+        let v: u64 = object.get_field(cx, "v")?;
+        let v = v
+            .try_into()
+            .ok()
+            .filter(|v| recovery_id(*v).is_ok())
+            .ok_or("Invalid recovery id")?;
+        Ok(Self {
+            r: object.get_field(cx, "r")?,
+            s: object.get_field(cx, "s")?,
+            v,
+        })
+    }
+}
+
+impl IntoHandle for Signature {
+    type Handle = JsObject;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        let object = cx.empty_object();
+        let r = self.r.into_handle(cx)?;
+        object.set(cx, "r", r)?;
+        let s = self.s.into_handle(cx)?;
+        object.set(cx, "s", s)?;
+        let v = cx.number(self.v);
+        object.set(cx, "v", v)?;
+        Ok(object)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use primitive_types::U256;
+    use secp256k1::{Message, Secp256k1, SecretKey};
+
+    fn sign() -> RecoverableSignature {
+        let secp = Secp256k1::signing_only();
+        let key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let message = Message::from_slice(&[9; 32]).unwrap();
+        secp.sign_ecdsa_recoverable(&message, &key)
+    }
+
+    #[test]
+    fn v_formats() {
+        let signature = sign();
+        let parity = to_bytes(&signature, VFormat::Parity);
+        let legacy = to_bytes(&signature, VFormat::Legacy);
+        assert_eq!(parity[..64], legacy[..64]);
+        assert_eq!(parity[64] + 27, legacy[64]);
+        assert!(recovery_id(2).is_err());
+    }
+
+    #[test]
+    fn formats() {
+        let signature = sign();
+        let bytes = to_bytes(&signature, VFormat::Legacy);
+        assert_eq!(parse(&bytes), Ok(signature));
+        let mut der = signature.to_standard().serialize_der().to_vec();
+        der.push(bytes[64]);
+        assert_eq!(parse(&der), Ok(signature));
+        assert!(parse(&bytes[1..]).is_err());
+        assert!(parse(&[]).is_err());
+    }
+
+    #[test]
+    fn compact_like_der() {
+        let mut bytes = [9; 65];
+        bytes[..2].copy_from_slice(&[0x30, 0x3e]);
+        bytes[64] = 27;
+        assert!(is_der(&bytes[..64]));
+        let signature =
+            RecoverableSignature::from_compact(&bytes[..64], RecoveryId::from_i32(0).unwrap())
+                .unwrap();
+        assert_eq!(parse(&bytes), Ok(signature));
+    }
+
+    #[test]
+    fn low_s() {
+        let signature = sign();
+        let (recovery_id, compact) = signature.serialize_compact();
+        // The same signature with s negated, mod the curve order
+        let order = U256::from_str_radix(
+            "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEBAAEDCE6AF48A03BBFD25E8CD0364141",
+            16,
+        )
+        .unwrap();
+        let mut high = compact;
+        (order - U256::from_big_endian(&compact[32..])).to_big_endian(&mut high[32..]);
+        let flipped = RecoveryId::from_i32(recovery_id.to_i32() ^ 1).unwrap();
+        let high = RecoverableSignature::from_compact(&high, flipped).unwrap();
+        assert_ne!(high, signature);
+        assert_eq!(normalize_s(&high), signature);
+        assert_eq!(normalize_s(&signature), signature);
+        let secp = Secp256k1::verification_only();
+        let message = Message::from_slice(&[9; 32]).unwrap();
+        assert_eq!(
+            secp.recover_ecdsa(&message, &high),
+            secp.recover_ecdsa(&message, &signature)
+        );
+    }
+
+    #[test]
+    fn split_round_trip() {
+        let signature = sign();
+        for format in [VFormat::Parity, VFormat::Legacy] {
+            let split = Signature::new(&signature, format);
+            assert_eq!(RecoverableSignature::try_from(split), Ok(signature));
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use super::*;
+
+/// The symbols JS defines on Symbol, eg: Symbol.toStringTag
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WellKnownSymbol {
+    AsyncIterator,
+    HasInstance,
+    IsConcatSpreadable,
+    Iterator,
+    Match,
+    MatchAll,
+    Replace,
+    Search,
+    Species,
+    Split,
+    ToPrimitive,
+    ToStringTag,
+    Unscopables,
+}
+
+impl WellKnownSymbol {
+    /// The property of Symbol it is found at, eg: "toStringTag"
+    pub fn name(&self) -> &'static str {
+        match self {
+            WellKnownSymbol::AsyncIterator => "asyncIterator",
+            WellKnownSymbol::HasInstance => "hasInstance",
+            WellKnownSymbol::IsConcatSpreadable => "isConcatSpreadable",
+            WellKnownSymbol::Iterator => "iterator",
+            WellKnownSymbol::Match => "match",
+            WellKnownSymbol::MatchAll => "matchAll",
+            WellKnownSymbol::Replace => "replace",
+            WellKnownSymbol::Search => "search",
+            WellKnownSymbol::Species => "species",
+            WellKnownSymbol::Split => "split",
+            WellKnownSymbol::ToPrimitive => "toPrimitive",
+            WellKnownSymbol::ToStringTag => "toStringTag",
+            WellKnownSymbol::Unscopables => "unscopables",
+        }
+    }
+}
+
+fn symbol_constructor<'a>(cx: &mut impl Context<'a>) -> NeonResult<Handle<'a, JsObject>> {
+    let global = cx.global();
+    global.get(cx, "Symbol")
+}
+
+/// Gets a well-known symbol off of the global Symbol
+pub fn well_known_symbol<'a>(
+    cx: &mut impl Context<'a>,
+    which: WellKnownSymbol,
+) -> JsResult<'a, JsValue> {
+    let symbol = symbol_constructor(cx)?;
+    symbol.get(cx, which.name())
+}
+
+/// A symbol to use as a property key. The legacy runtime has no
+/// JsSymbol type, so it is marshalled as a plain JsValue.
+/// Eg: js_object!(cx => { [SymbolKey::For("pool.id".into())]: id, })
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SymbolKey {
+    WellKnown(WellKnownSymbol),
+    /// From the global registry, ie: Symbol.for(key)
+    For(String),
+}
+
+impl From<WellKnownSymbol> for SymbolKey {
+    fn from(which: WellKnownSymbol) -> Self {
+        SymbolKey::WellKnown(which)
+    }
+}
+
+impl IntoHandle for WellKnownSymbol {
+    type Handle = JsValue;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        Ok(well_known_symbol(cx, *self)?)
+    }
+}
+
+impl IntoHandle for SymbolKey {
+    type Handle = JsValue;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        match self {
+            SymbolKey::WellKnown(which) => Ok(well_known_symbol(cx, *which)?),
+            SymbolKey::For(key) => {
+                let symbol = symbol_constructor(cx)?;
+                let symbol_for: Handle<JsFunction> = symbol.get(cx, "for")?;
+                let key = cx.string(key).upcast();
+                Ok(symbol_for.call(cx, symbol, vec![key])?)
+            }
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use super::*;
+use crate::decls::ts_type;
+#[cfg(feature = "bigint")]
+use std::convert::TryFrom;
+use std::time::Duration;
+
+#[cfg(feature = "bigint")]
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+// Milliseconds, as Date and setTimeout count them
+fn to_millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1e6
+}
+
+fn from_millis(ms: f64) -> Result<Duration, &'static str> {
+    if ms.is_nan() {
+        return Err("Got NaN for Duration");
+    } else if ms.is_infinite() {
+        return Err("Got infinite for Duration");
+    } else if ms < 0.0 {
+        return Err("Got negative number for Duration");
+    } else if ms >= u64::MAX as f64 {
+        return Err("Got number too large for Duration");
+    }
+    // The whole and fractional parts separately, since going through
+    // seconds would round twice
+    let whole = ms.trunc();
+    let nanos = ((ms - whole) * 1e6).round() as u64;
+    Ok(Duration::from_millis(whole as u64) + Duration::from_nanos(nanos))
+}
+
+impl FromHandle for Duration {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        let ms = f64::from_handle(handle, cx)?;
+        Ok(from_millis(ms)?)
+    }
+}
+
+/// As fractional milliseconds. Through f64 and back a Duration is:
+///   * exact for whole milliseconds, up to 2^53 of them
+///   * exact to the nanosecond under 2^31 ms (about 24 days)
+///   * within 1µs under 100 years
+///
+/// Use Nanos where that isn't enough.
+impl IntoHandle for Duration {
+    type Handle = JsNumber;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        Ok(cx.number(to_millis(*self)))
+    }
+}
+
+ts_type!(Duration => "number", "number");
+
+/// A Duration as a BigInt of nanoseconds, for when it has to be exact.
+/// Also reads a number of nanoseconds, if it is a safe integer.
+#[cfg(feature = "bigint")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Nanos(pub Duration);
+
+#[cfg(feature = "bigint")]
+fn from_nanos(nanos: u128) -> Result<Duration, &'static str> {
+    let secs =
+        u64::try_from(nanos / NANOS_PER_SEC).map_err(|_| "Got BigInt too large for Duration")?;
+    Ok(Duration::new(secs, (nanos % NANOS_PER_SEC) as u32))
+}
+
+#[cfg(feature = "bigint")]
+impl FromHandle for Nanos {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        match type_name_of(handle, cx) {
+            "bigint" => {
+                // The legacy runtime has no BigInt, so String does the reading
+                let global = cx.global();
+                let to_string: Handle<JsFunction> = global.get(cx, "String")?;
+                let this = cx.undefined();
+                let digits: Handle<JsString> = to_string
+                    .call(cx, this, vec![handle.upcast()])?
+                    .downcast_or_throw(cx)?;
+                let digits = digits.value();
+                if digits.starts_with('-') {
+                    return Err("Got negative BigInt for Duration".into());
+                }
+                let nanos = digits
+                    .parse::<u128>()
+                    .map_err(|_| "Got BigInt too large for Duration")?;
+                Ok(Self(from_nanos(nanos)?))
+            }
+            "number" => Ok(Self(Duration::from_nanos(u64::from_handle(handle, cx)?))),
+            _ => Err(Candidates::default()
+                .or("bigint")
+                .or("number")
+                .error(handle, cx)
+                .into()),
+        }
+    }
+}
+
+#[cfg(feature = "bigint")]
+impl IntoHandle for Nanos {
+    type Handle = JsValue;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        let global = cx.global();
+        let big_int: Handle<JsFunction> = global.get(cx, "BigInt")?;
+        let this = cx.undefined();
+        let digits = cx.string(self.0.as_nanos().to_string());
+        Ok(big_int.call(cx, this, vec![digits.upcast()])?)
+    }
+}
+
+#[cfg(feature = "bigint")]
+ts_type!(Nanos => "bigint | number", "bigint");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const DAY_NANOS: u64 = 86_400 * 1_000_000_000;
+
+    fn round_trip(duration: Duration) -> Duration {
+        from_millis(to_millis(duration)).unwrap()
+    }
+
+    #[test]
+    fn rejects() {
+        assert_eq!(from_millis(f64::NAN), Err("Got NaN for Duration"));
+        assert_eq!(from_millis(-1.0), Err("Got negative number for Duration"));
+        assert_eq!(from_millis(1e20), Err("Got number too large for Duration"));
+        assert_eq!(from_millis(1.5), Ok(Duration::from_micros(1500)));
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn rejects_nanos() {
+        assert_eq!(
+            from_nanos(u128::MAX),
+            Err("Got BigInt too large for Duration")
+        );
+        assert_eq!(from_nanos(1_500_000_001), Ok(Duration::new(1, 500_000_001)));
+    }
+
+    proptest! {
+        #[test]
+        fn prop_whole_millis_exact(ms in 0u64..1 << 53) {
+            let duration = Duration::from_millis(ms);
+            prop_assert_eq!(round_trip(duration), duration);
+        }
+
+        #[test]
+        fn prop_nanos_exact_under_24_days(nanos in 0u64..(1 << 31) * 1_000_000) {
+            let duration = Duration::from_nanos(nanos);
+            prop_assert_eq!(round_trip(duration), duration);
+        }
+
+        #[test]
+        fn prop_within_1us_under_100_years(nanos in 0u64..36_525 * DAY_NANOS) {
+            let duration = Duration::from_nanos(nanos);
+            let back = round_trip(duration);
+            let error = back.abs_diff(duration);
+            prop_assert!(error <= Duration::from_micros(1), "{:?} came back as {:?}", duration, back);
+        }
+    }
+}
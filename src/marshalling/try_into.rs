@@ -0,0 +1,96 @@
+//! For values which can't always be marshalled, eg: a state machine mid
+//! transition. TryIntoHandle fails with a domain error, kept apart from
+//! failures to marshal, and Checked(value) bridges it to IntoHandle and
+//! Terminal, eg: Ok(Checked(state)).finish(cx).
+//!
+//! A domain error without a code is given INVALID_STATE, so that JS can
+//! tell it from a marshalling error.
+
+use super::IntoHandle;
+use crate::errors::{MaybeThrown, SafeErr, SafeJsResult, Terminal};
+use neon::prelude::*;
+use neon::result::Throw;
+
+pub trait TryIntoHandle {
+    type Handle: Value;
+    type Error: Into<SafeErr>;
+    fn try_into_handle<'c>(
+        &self,
+        cx: &mut impl Context<'c>,
+    ) -> Result<Handle<'c, Self::Handle>, HandleError<Self::Error>>;
+}
+
+pub enum HandleError<E> {
+    /// What the value says about itself, eg: "Transfer is in flight"
+    Invalid(E),
+    /// Marshalling what it holds failed, or JS threw
+    Marshal(MaybeThrown),
+}
+
+impl<E> From<MaybeThrown> for HandleError<E> {
+    fn from(e: MaybeThrown) -> Self {
+        HandleError::Marshal(e)
+    }
+}
+
+impl<E> From<Throw> for HandleError<E> {
+    fn from(t: Throw) -> Self {
+        HandleError::Marshal(t.into())
+    }
+}
+
+impl<E: Into<SafeErr>> From<HandleError<E>> for MaybeThrown {
+    fn from(e: HandleError<E>) -> Self {
+        match e {
+            HandleError::Invalid(e) => {
+                let e = e.into();
+                let e = match e.code() {
+                    Some(_) => e,
+                    None => e.with_code("INVALID_STATE"),
+                };
+                MaybeThrown::Unthrown(e)
+            }
+            HandleError::Marshal(e) => e,
+        }
+    }
+}
+
+/// IntoHandle for a TryIntoHandle, failing with its error
+pub struct Checked<T>(pub T);
+
+impl<T: TryIntoHandle> IntoHandle for Checked<T> {
+    type Handle = T::Handle;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        Ok(self.0.try_into_handle(cx)?)
+    }
+}
+
+impl<T: TryIntoHandle> Terminal for Checked<T> {
+    type Handle = T::Handle;
+    fn finish<'c>(self, cx: impl Context<'c>) -> JsResult<'c, Self::Handle> {
+        Ok::<_, MaybeThrown>(self).finish(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code(e: HandleError<SafeErr>) -> Option<&'static str> {
+        match MaybeThrown::from(e) {
+            MaybeThrown::Unthrown(e) => e.code(),
+            MaybeThrown::Thrown(_) => panic!("Expected an unthrown error"),
+        }
+    }
+
+    #[test]
+    fn codes() {
+        assert_eq!(
+            code(HandleError::Invalid("Moving".into())),
+            Some("INVALID_STATE")
+        );
+        let coded = SafeErr::from("Locked").with_code("LOCKED");
+        assert_eq!(code(HandleError::Invalid(coded)), Some("LOCKED"));
+        assert_eq!(code(HandleError::Marshal("Bad hex".into())), None);
+    }
+}
@@ -0,0 +1,26 @@
+use super::*;
+use ::url::Url;
+
+// Only absolute URLs with a host, eg: an RPC endpoint, and not relative
+// ones or the likes of "mailto:a@b.c". They're written back normalized,
+// eg: "HTTP://Example.com" as "http://example.com/".
+impl FromHandle for Url {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        let parsed = with_str_as(handle, cx, "URL", 1, |s| match Url::parse(s) {
+            Ok(url) if url.has_host() => Ok(url),
+            Ok(_) => Err(format!("Invalid URL '{}': expected a host", s)),
+            Err(e) => Err(format!("Invalid URL '{}': {}", s, e)),
+        })?;
+        Ok(parsed?)
+    }
+}
+
+impl IntoHandle for Url {
+    type Handle = JsString;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        Ok(cx.string(self.as_str()))
+    }
+}
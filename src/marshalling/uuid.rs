@@ -0,0 +1,34 @@
+use super::*;
+use ::uuid::fmt::Hyphenated;
+use ::uuid::Uuid;
+
+// Only the hyphenated form is read, eg: not the braced or urn: forms which
+// Uuid::parse_str also takes, so that what JS passes is what it gets back.
+// Either case is read, and lower case is written.
+impl FromHandle for Uuid {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        let parsed = with_str_as(handle, cx, "UUID", 1, |s| {
+            if s.len() != Hyphenated::LENGTH {
+                return Err(format!(
+                    "Invalid UUID '{}': expected {} characters, got {}",
+                    s,
+                    Hyphenated::LENGTH,
+                    s.len()
+                ));
+            }
+            Uuid::parse_str(s).map_err(|e| format!("Invalid UUID '{}': {}", s, e))
+        })?;
+        Ok(parsed?)
+    }
+}
+
+impl IntoHandle for Uuid {
+    type Handle = JsString;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        let mut buffer = Uuid::encode_buffer();
+        Ok(cx.string(self.hyphenated().encode_lower(&mut buffer)))
+    }
+}
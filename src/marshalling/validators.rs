@@ -0,0 +1,89 @@
+//! The FromHandle rules as functions JS can call without throwing, eg: so
+//! that a form is checked by the same rules the native layer enforces.
+//!
+//!   cx.export_value("validators", validators::js_validators(&mut cx)?)?;
+//!
+//! Then validators.u256("0x1") is { ok: true, error: null }, and
+//! validators.address("0x12") is { ok: false, error: "Expected an address..." }.
+//! Other types can be added with add.
+
+use super::*;
+use crate::errors::MaybeThrown;
+use primitive_types::{H256, U128, U256};
+
+/// Decodes handle as T only to check it, with what decoding failed or
+/// threw with as the message. A throwing getter is caught.
+pub fn check<'a, T: FromHandle, V: Value>(
+    handle: Handle<V>,
+    cx: &mut impl Context<'a>,
+) -> NeonResult<Result<(), String>> {
+    let result = cx.try_catch(|cx| match T::from_handle(handle, cx) {
+        Ok(_) => Ok(Ok(())),
+        Err(MaybeThrown::Thrown(t)) => Err(t),
+        Err(MaybeThrown::Unthrown(e)) => Ok(Err(e.to_string())),
+    });
+    match result {
+        Ok(checked) => Ok(checked),
+        Err(thrown) => Ok(Err(describe_thrown(thrown, cx)?)),
+    }
+}
+
+fn describe_thrown<'a>(thrown: Handle<JsValue>, cx: &mut impl Context<'a>) -> NeonResult<String> {
+    if let Ok(s) = thrown.downcast::<JsString>() {
+        return Ok(s.value());
+    }
+    if let Ok(object) = thrown.downcast::<JsObject>() {
+        let message: Handle<JsValue> = object.get(cx, "message")?;
+        if let Ok(message) = message.downcast::<JsString>() {
+            return Ok(message.value());
+        }
+    }
+    Ok(format!("Threw {}", type_name_of(thrown, cx)))
+}
+
+// Returns { ok, error } for the first argument, with error null when ok
+fn js_validate<T: FromHandle>(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let value = match cx.argument_opt(0) {
+        Some(value) => value,
+        None => cx.undefined().upcast(),
+    };
+    let checked = check::<T, _>(value, &mut cx)?;
+    let result = cx.empty_object();
+    let ok = cx.boolean(checked.is_ok());
+    result.set(&mut cx, "ok", ok)?;
+    let error = match checked {
+        Ok(()) => cx.null().upcast::<JsValue>(),
+        Err(message) => cx.string(message).upcast(),
+    };
+    result.set(&mut cx, "error", error)?;
+    Ok(result)
+}
+
+/// Sets validators[name] to a validator for T, eg: for a type of the addon's
+///   validators::add::<Order>(&mut cx, validators, "order")?;
+pub fn add<'a, T: FromHandle>(
+    cx: &mut impl Context<'a>,
+    validators: Handle<JsObject>,
+    name: &str,
+) -> NeonResult<()> {
+    let validate = JsFunction::new(cx, js_validate::<T>)?;
+    validators.set(cx, name, validate)?;
+    Ok(())
+}
+
+/// An object of validators for the common types, named as in JS
+pub fn js_validators<'a>(cx: &mut impl Context<'a>) -> JsResult<'a, JsObject> {
+    let validators = cx.empty_object();
+    add::<bool>(cx, validators, "boolean")?;
+    add::<f64>(cx, validators, "number")?;
+    add::<String>(cx, validators, "string")?;
+    add::<u32>(cx, validators, "u32")?;
+    add::<u64>(cx, validators, "u64")?;
+    add::<U128>(cx, validators, "u128")?;
+    add::<i128>(cx, validators, "i128")?;
+    add::<U256>(cx, validators, "u256")?;
+    add::<Address>(cx, validators, "address")?;
+    add::<H256>(cx, validators, "bytes32")?;
+    add::<HexBytes>(cx, validators, "bytes")?;
+    Ok(validators)
+}
@@ -0,0 +1,140 @@
+use crate::registry;
+use neon::prelude::*;
+use std::cell::Cell;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+
+static TRACKED: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    // The total this JS thread has told V8 about
+    static EXTERNAL: Cell<i64> = const { Cell::new(0) };
+    // What MemoryTracked values hinted from this thread have given back
+    // since, which they may do from any thread
+    static RELEASED: Arc<AtomicI64> = Arc::new(AtomicI64::new(0));
+}
+
+/// Wraps large Proxy contents with their approximate size,
+/// eg: Proxy<MemoryTracked<Index>>, so that it can be accounted for.
+/// The size is added to tracked_bytes on creation and removed on drop.
+pub struct MemoryTracked<T> {
+    value: T,
+    size: usize,
+    // Set once hinted, to give the size back to the thread which added it
+    hinted: OnceLock<Arc<AtomicI64>>,
+}
+
+impl<T> MemoryTracked<T> {
+    pub fn new(value: T, size: usize) -> Self {
+        TRACKED.fetch_add(size, Ordering::Relaxed);
+        Self {
+            value,
+            size,
+            hinted: OnceLock::new(),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Adds the size to what V8 is told about with adjust_external_memory,
+    /// once however many times this is called. It is taken off again after
+    /// this is dropped, the next time the same thread adjusts.
+    pub fn hint<'a>(&self, cx: &mut impl Context<'a>) -> NeonResult<()> {
+        let mut first = false;
+        self.hinted.get_or_init(|| {
+            first = true;
+            RELEASED.with(Arc::clone)
+        });
+        if first {
+            adjust_external_memory(cx, self.size as i64)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T> Deref for MemoryTracked<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> Drop for MemoryTracked<T> {
+    fn drop(&mut self) {
+        TRACKED.fetch_sub(self.size, Ordering::Relaxed);
+        if let Some(released) = self.hinted.get() {
+            released.fetch_add(self.size as i64, Ordering::SeqCst);
+        }
+    }
+}
+
+/// The total size of every live MemoryTracked
+pub fn tracked_bytes() -> usize {
+    TRACKED.load(Ordering::Relaxed)
+}
+
+// The ballast is kept in whole chunks of this size and one for the rest
+const CHUNK: i64 = 1 << 30;
+
+/// Tells V8's GC that change more bytes (or fewer, if negative) of native
+/// memory are kept alive by JS on this thread, and returns the new total,
+/// as napi_adjust_external_memory does. The total never goes below zero.
+///
+/// The legacy runtime has no adjust_external_memory, so instead this keeps
+/// ArrayBuffers adding up to the total on a hidden object. V8 counts
+/// ArrayBuffers as external memory, and ones that are never written to are
+/// backed by untouched zero pages, so they cost address space but not RSS.
+/// Only the difference is applied: whole chunks are added or dropped, and
+/// the buffer for the rest is replaced if its size changed.
+pub fn adjust_external_memory<'a>(cx: &mut impl Context<'a>, change: i64) -> NeonResult<i64> {
+    let released = RELEASED.with(|released| released.swap(0, Ordering::SeqCst));
+    let previous = EXTERNAL.with(Cell::get);
+    let total = previous
+        .saturating_add(change)
+        .saturating_sub(released)
+        .max(0);
+    if total == previous {
+        return Ok(total);
+    }
+
+    let ballast = registry::table(cx, "neon-utils.externalMemory")?;
+    for i in total / CHUNK..previous / CHUNK {
+        let undefined = cx.undefined();
+        ballast.set(cx, i as u32, undefined)?;
+    }
+    for i in previous / CHUNK..total / CHUNK {
+        let chunk = cx.array_buffer(CHUNK as u32)?;
+        ballast.set(cx, i as u32, chunk)?;
+    }
+    if total % CHUNK != previous % CHUNK {
+        let rest = cx.array_buffer((total % CHUNK) as u32)?;
+        ballast.set(cx, "rest", rest)?;
+    }
+    EXTERNAL.with(|external| external.set(total));
+    Ok(total)
+}
+
+/// adjust_external_memory(change) for JS, returning the new total
+pub fn js_adjust_external_memory(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    let change = cx.argument::<JsNumber>(0)?.value();
+    let total = adjust_external_memory(&mut cx, change as i64)?;
+    Ok(cx.number(total as f64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_until_drop() {
+        let before = tracked_bytes();
+        let tracked = MemoryTracked::new(vec![0u8; 1024], 1024);
+        assert_eq!(tracked_bytes(), before + 1024);
+        assert_eq!(tracked.len(), 1024);
+        drop(tracked);
+        assert_eq!(tracked_bytes(), before);
+    }
+}
@@ -0,0 +1,234 @@
+use crate::errors::{IntoError, MaybeThrown, SafeErr};
+use crate::instance;
+use crate::marshalling::{freeze, Freeze, IntoHandle};
+use crate::proxy::Proxy;
+use crate::shutdown;
+use neon::prelude::*;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, ThreadId};
+
+type Shared = Arc<dyn Any + Send + Sync>;
+type Finalizer = Box<dyn FnOnce() + Send>;
+
+enum Entry {
+    // The steps are running on this thread, without the lock held
+    Running(ThreadId),
+    Ready {
+        state: Shared,
+        // How many instances share it, for the finalizer to run after the last
+        instances: usize,
+        finalizer: Option<Finalizer>,
+    },
+}
+
+// Node loads the library once per process, but runs the module's
+// register function once per instantiation (eg: each worker_thread).
+// So, the state is kept here, keyed by type, rather than in the module.
+type States = Option<HashMap<TypeId, Entry>>;
+static STATES: Mutex<States> = Mutex::new(None);
+// Notified whenever steps finish running, whether or not they succeeded
+static FINISHED: Condvar = Condvar::new();
+
+type Step<S> = Box<dyn FnOnce(&mut S) -> Result<(), SafeErr>>;
+
+/// Sequences the native setup for an addon (initialize logging,
+/// start a runtime, etc) so that it only ever happens once per process
+/// no matter how many times the module is instantiated.
+pub struct Init<S> {
+    state: S,
+    steps: Vec<Step<S>>,
+    finalizer: Option<Box<dyn FnOnce(&S) + Send>>,
+}
+
+/// Starts building the setup for the shared state S.
+/// Eg:
+///   let state = module::init(State::default())
+///       .step(|s| s.start_logging())
+///       .step(|s| s.start_runtime())
+///       .finalizer(|s| s.flush_logs())
+///       .run(&mut cx)?;
+pub fn init<S: 'static + Send + Sync>(state: S) -> Init<S> {
+    Init {
+        state,
+        steps: Vec::new(),
+        finalizer: None,
+    }
+}
+
+impl<S: 'static + Send + Sync> Init<S> {
+    /// Adds a setup step. Steps run in the order they were added.
+    pub fn step<E: Into<SafeErr>>(
+        mut self,
+        f: impl 'static + FnOnce(&mut S) -> Result<(), E>,
+    ) -> Self {
+        self.steps.push(Box::new(move |s| f(s).map_err(Into::into)));
+        self
+    }
+
+    /// Sets a function to run with the state once every instance which
+    /// shares it has gone away, eg: to flush logs. The legacy runtime has
+    /// no cleanup hooks, so an instance goes away on its process 'exit'
+    /// event, as for shutdown::token.
+    pub fn finalizer(mut self, f: impl 'static + Send + FnOnce(&S)) -> Self {
+        self.finalizer = Some(Box::new(f));
+        self
+    }
+
+    /// Runs the steps if this is the first instantiation, otherwise
+    /// discards them and returns the state from the first.
+    ///
+    /// If a step fails the error is thrown and nothing is kept,
+    /// so the next instantiation will start over from the first step.
+    pub fn run<'a>(self, cx: &mut ModuleContext<'a>) -> NeonResult<Proxy<S>> {
+        // Every instantiation is an instance, whether or not it is the first
+        instance::current();
+
+        let key = TypeId::of::<S>();
+        let mut states = lock();
+        loop {
+            match states.get_or_insert_with(HashMap::new).get_mut(&key) {
+                Some(Entry::Ready {
+                    state, instances, ..
+                }) => {
+                    *instances += 1;
+                    let state = downcast(state.clone());
+                    drop(states);
+                    shutdown::on_shutdown(cx, move || detach(key))?;
+                    return Ok(state);
+                }
+                Some(Entry::Running(thread)) if *thread == thread::current().id() => {
+                    drop(states);
+                    return cx.throw_error("module::init was run again by one of its own steps");
+                }
+                // A second worker waits for setup to finish instead of racing it
+                Some(Entry::Running(_)) => {
+                    states = FINISHED.wait(states).unwrap_or_else(|e| e.into_inner());
+                }
+                None => break,
+            }
+        }
+        let running = Running::start(key, states);
+
+        // The lock isn't held while the steps run, so that they may
+        // use module::state
+        let Init {
+            mut state,
+            steps,
+            finalizer,
+        } = self;
+        for step in steps {
+            if let Err(e) = step(&mut state) {
+                drop(running);
+                let e = e.into_error(cx)?;
+                return cx.throw(e);
+            }
+        }
+
+        let state = Arc::new(state);
+        let finalizer = finalizer.map(|f| {
+            let state = state.clone();
+            Box::new(move || f(&state)) as Finalizer
+        });
+        running.finish(Entry::Ready {
+            state: state.clone(),
+            instances: 1,
+            finalizer,
+        });
+        shutdown::on_shutdown(cx, move || detach(key))?;
+        Ok(Proxy::from_arc(state))
+    }
+}
+
+fn lock() -> MutexGuard<'static, States> {
+    STATES.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// Marks the steps for a key as running until dropped. If they fail (or
+// panic) the key is removed, so that the next instantiation starts over.
+struct Running(TypeId);
+
+impl Running {
+    fn start(key: TypeId, mut states: MutexGuard<States>) -> Self {
+        states
+            .get_or_insert_with(HashMap::new)
+            .insert(key, Entry::Running(thread::current().id()));
+        Running(key)
+    }
+
+    fn finish(self, entry: Entry) {
+        lock()
+            .get_or_insert_with(HashMap::new)
+            .insert(self.0, entry);
+        // Drop notifies, and now leaves the entry alone
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        let mut states = lock();
+        let states = states.get_or_insert_with(HashMap::new);
+        if let Some(Entry::Running(_)) = states.get(&self.0) {
+            states.remove(&self.0);
+        }
+        FINISHED.notify_all();
+    }
+}
+
+// An instance sharing the state for key has gone away
+fn detach(key: TypeId) {
+    let mut guard = lock();
+    let states = guard.get_or_insert_with(HashMap::new);
+    match states.get_mut(&key) {
+        Some(Entry::Ready { instances, .. }) => {
+            *instances -= 1;
+            if *instances != 0 {
+                return;
+            }
+        }
+        _ => return,
+    }
+    let finalizer = match states.remove(&key) {
+        Some(Entry::Ready { finalizer, .. }) => finalizer,
+        _ => None,
+    };
+    drop(guard);
+    if let Some(finalizer) = finalizer {
+        finalizer();
+    }
+}
+
+/// Gets the state created by init, if it has run.
+pub fn state<S: 'static + Send + Sync>() -> Option<Proxy<S>> {
+    match lock().as_ref()?.get(&TypeId::of::<S>()) {
+        Some(Entry::Ready { state, .. }) => Some(downcast(state.clone())),
+        _ => None,
+    }
+}
+
+fn downcast<S: 'static + Send + Sync>(shared: Shared) -> Proxy<S> {
+    // Keyed by TypeId, so this can't fail
+    Proxy::from_arc(shared.downcast::<S>().unwrap())
+}
+
+/// Exports value as a constant, eg: chain IDs or a table of contract
+/// addresses, frozen as much as asked so that JS can't change it.
+pub fn export_const<'a>(
+    cx: &mut ModuleContext<'a>,
+    name: &str,
+    value: impl IntoHandle,
+    how: Freeze,
+) -> NeonResult<()> {
+    let handle = match value.into_handle(cx) {
+        Ok(handle) => handle,
+        Err(MaybeThrown::Thrown(t)) => return Err(t),
+        Err(MaybeThrown::Unthrown(e)) => {
+            let e = e.with_context(format!("Failed to export '{}'", name));
+            let e = e.into_error(cx)?;
+            return cx.throw(e);
+        }
+    };
+    freeze(handle, cx, how)?;
+    cx.export_value(name, handle)
+}
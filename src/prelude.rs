@@ -1,3 +1,24 @@
-pub(crate) use firestorm::profile_method;
-pub(crate) type Address = [u8; 20];
+//! The parts of neon-utils most addons need, eg: use neon_utils::prelude::*;
+
+pub use crate::errors::{
+    IntoError, MaybeThrown, SafeErr, SafeJsResult, SafeResult, SafeResultExt, Terminal,
+};
+pub use crate::marshalling::codecs::{
+    decode, decode_bytes, encode_hex, Decode, DecodeBytes, Encode,
+};
+pub use crate::marshalling::{
+    Arg, ArgsSpec, ExpectArgs, FromHandle, FromHandleWithDefault, IntoHandle, ObjectExt,
+};
+pub use crate::proxy::{neon_method, Proxy, ReentryLock, ThisProxy, WeakProxy};
+#[cfg(feature = "tokio")]
+pub use crate::task::run_future;
+pub use crate::task::{
+    join_all, run_async, run_async_serialized, run_async_then_on_main, run_async_with,
+    run_async_with_retry, run_async_with_timeout, run_with_progress, task, JoinMode, Progress,
+    TaskBuilder,
+};
+pub use crate::{
+    js_array, js_const_object, js_enum, js_object, tagged_union, terminal_fn, try_js, NamedTuple,
+};
+
 pub(crate) type Bytes32 = [u8; 32];
@@ -0,0 +1,257 @@
+//! Lightweight counters of native calls and how many of them threw,
+//! eg: for fleet-wide dashboards. Counted as results go through finish.
+
+use crate::config;
+use crate::errors::{DowncastFailure, SafeJsResult};
+use crate::marshalling::codecs::interned::InternStats;
+use crate::marshalling::{interned, IntoHandle};
+use neon::prelude::*;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+static CALLS: AtomicU64 = AtomicU64::new(0);
+static THROWS: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CallStats {
+    /// Results passed to finish
+    pub calls: u64,
+    /// Errors thrown from finish, including JS exceptions passed through
+    pub throws: u64,
+}
+
+/// The counts since the process started, or since the last reset
+pub fn call_stats() -> CallStats {
+    CallStats {
+        calls: CALLS.load(Ordering::Relaxed),
+        throws: THROWS.load(Ordering::Relaxed),
+    }
+}
+
+pub fn reset_call_stats() {
+    CALLS.store(0, Ordering::Relaxed);
+    THROWS.store(0, Ordering::Relaxed);
+}
+
+pub(crate) fn count_call() {
+    CALLS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn count_throw() {
+    THROWS.fetch_add(1, Ordering::Relaxed);
+}
+
+impl IntoHandle for CallStats {
+    type Handle = JsObject;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        let object = cx.empty_object();
+        let calls = cx.number(self.calls as f64);
+        object.set(cx, "calls", calls)?;
+        let throws = cx.number(self.throws as f64);
+        object.set(cx, "throws", throws)?;
+        Ok(object)
+    }
+}
+
+/// Returns { calls, throws }. Can be exported as is, eg:
+/// cx.export_function("callStats", profiling::js_call_stats)
+pub fn js_call_stats(mut cx: FunctionContext) -> JsResult<JsObject> {
+    match call_stats().into_handle(&mut cx) {
+        Ok(stats) => Ok(stats),
+        Err(e) => e.finish(cx),
+    }
+}
+
+/// Hits and misses of the caches set up by intern_capacity in the config
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InternedStats {
+    /// Byte arrays (eg: H256) as hex
+    pub hex: InternStats,
+    /// Checksummed Addresses
+    pub checksums: InternStats,
+}
+
+pub fn interned_stats() -> InternedStats {
+    InternedStats {
+        hex: interned::hex().stats(),
+        checksums: interned::checksums().stats(),
+    }
+}
+
+/// Empties the caches too, eg: between benchmark runs
+pub fn reset_interned_stats() {
+    interned::hex().clear();
+    interned::checksums().clear();
+}
+
+/// Empties the caches and changes their capacity from what the config
+/// says. 0 turns them off.
+pub fn set_intern_capacity(capacity: usize) {
+    interned::hex().set_capacity(capacity);
+    interned::checksums().set_capacity(capacity);
+}
+
+fn intern_stats_handle<'c>(
+    cx: &mut impl Context<'c>,
+    stats: &InternStats,
+) -> SafeJsResult<'c, JsObject> {
+    let object = cx.empty_object();
+    let hits = cx.number(stats.hits as f64);
+    object.set(cx, "hits", hits)?;
+    let misses = cx.number(stats.misses as f64);
+    object.set(cx, "misses", misses)?;
+    let entries = cx.number(stats.entries as f64);
+    object.set(cx, "entries", entries)?;
+    Ok(object)
+}
+
+impl IntoHandle for InternedStats {
+    type Handle = JsObject;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        let object = cx.empty_object();
+        let hex = intern_stats_handle(cx, &self.hex)?;
+        object.set(cx, "hex", hex)?;
+        let checksums = intern_stats_handle(cx, &self.checksums)?;
+        object.set(cx, "checksums", checksums)?;
+        Ok(object)
+    }
+}
+
+/// Returns { hex, checksums }, each { hits, misses, entries }
+pub fn js_interned_stats(mut cx: FunctionContext) -> JsResult<JsObject> {
+    match interned_stats().into_handle(&mut cx) {
+        Ok(stats) => Ok(stats),
+        Err(e) => e.finish(cx),
+    }
+}
+
+thread_local! {
+    // The export running on this thread, for marshal_stats
+    static EXPORT: Cell<&'static str> = const { Cell::new("") };
+}
+
+/// Runs f as the export named, so that values of the wrong type passed to
+/// it are counted against it. terminal_fn! and #[neon_method] do this with
+/// the function's name, and Type.method.
+pub fn in_export<T>(name: &'static str, f: impl FnOnce() -> T) -> T {
+    let _restore = RestoreExport(EXPORT.with(|export| export.replace(name)));
+    f()
+}
+
+// Puts back the export that was running, even if f panics
+struct RestoreExport(&'static str);
+
+impl Drop for RestoreExport {
+    fn drop(&mut self) {
+        EXPORT.with(|export| export.set(self.0));
+    }
+}
+
+type MarshalKey = (String, String, &'static str);
+
+static MARSHAL: Mutex<Option<HashMap<MarshalKey, u64>>> = Mutex::new(None);
+
+/// How many times export was passed a got where it expected expected,
+/// eg: a number for an "address string"
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MarshalFailures {
+    pub expected: String,
+    pub got: String,
+    /// Empty if it failed outside of a named export
+    pub export: &'static str,
+    pub count: u64,
+}
+
+static MARSHAL_STATS: OnceLock<AtomicBool> = OnceLock::new();
+
+fn marshal_stats_on() -> &'static AtomicBool {
+    MARSHAL_STATS.get_or_init(|| AtomicBool::new(config::get().marshal_stats))
+}
+
+/// Turns counting for marshal_stats on or off, whatever the config says,
+/// eg: around only the code being looked into
+pub fn set_marshal_stats(on: bool) {
+    marshal_stats_on().store(on, Ordering::Relaxed);
+}
+
+pub(crate) fn count_downcast_failure(failure: &DowncastFailure) {
+    if !marshal_stats_on().load(Ordering::Relaxed) {
+        return;
+    }
+    let export = EXPORT.with(Cell::get);
+    let key = (failure.expected.clone(), failure.got.clone(), export);
+    let mut counts = MARSHAL.lock().unwrap_or_else(|e| e.into_inner());
+    *counts
+        .get_or_insert_with(HashMap::new)
+        .entry(key)
+        .or_insert(0) += 1;
+}
+
+/// The wrong types passed, most common first, while marshal_stats is set
+/// in the config or by set_marshal_stats. Nothing is counted otherwise.
+pub fn marshal_stats() -> Vec<MarshalFailures> {
+    let counts = MARSHAL.lock().unwrap_or_else(|e| e.into_inner());
+    let mut stats: Vec<_> = counts
+        .iter()
+        .flatten()
+        .map(|((expected, got, export), count)| MarshalFailures {
+            expected: expected.clone(),
+            got: got.clone(),
+            export,
+            count: *count,
+        })
+        .collect();
+    stats.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| (a.export, &a.expected, &a.got).cmp(&(b.export, &b.expected, &b.got)))
+    });
+    stats
+}
+
+pub fn reset_marshal_stats() {
+    *MARSHAL.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+impl IntoHandle for MarshalFailures {
+    type Handle = JsObject;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        let object = cx.empty_object();
+        let expected = cx.string(&self.expected);
+        object.set(cx, "expected", expected)?;
+        let got = cx.string(&self.got);
+        object.set(cx, "got", got)?;
+        let export = cx.string(self.export);
+        object.set(cx, "export", export)?;
+        let count = cx.number(self.count as f64);
+        object.set(cx, "count", count)?;
+        Ok(object)
+    }
+}
+
+/// Returns [{ expected, got, export, count }], eg: exported as
+/// cx.export_function("getMarshalStats", profiling::js_marshal_stats)
+pub fn js_marshal_stats(mut cx: FunctionContext) -> JsResult<JsArray> {
+    match marshal_stats().into_handle(&mut cx) {
+        Ok(stats) => Ok(stats),
+        Err(e) => e.finish(cx),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic;
+
+    #[test]
+    fn export_restored_after_panic() {
+        in_export("outer", || {
+            let panicked = panic::catch_unwind(|| in_export("inner", || panic!("in inner")));
+            assert!(panicked.is_err());
+            assert_eq!(EXPORT.with(Cell::get), "outer");
+        });
+        assert_eq!(EXPORT.with(Cell::get), "");
+    }
+}
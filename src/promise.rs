@@ -0,0 +1,320 @@
+use crate::channel::{self, Slot};
+use crate::errors::{IntoError, MaybeThrown};
+use crate::marshalling::IntoHandle;
+use neon::prelude::*;
+use never::Never;
+
+// For waiting on a JS Promise from Rust
+#[cfg(feature = "tokio")]
+use crate::{marshalling::FromHandle, proxy};
+#[cfg(feature = "tokio")]
+use std::cell::{Cell, RefCell};
+#[cfg(feature = "tokio")]
+use std::collections::HashMap;
+#[cfg(feature = "tokio")]
+use std::future::Future;
+#[cfg(feature = "tokio")]
+use std::pin::Pin;
+#[cfg(feature = "tokio")]
+use std::task::Poll;
+#[cfg(feature = "tokio")]
+use tokio::sync::oneshot;
+
+/// A pending JS Promise, settled once from any thread, eg:
+///
+///   let (promise, oneshot) = JsOneshot::new(&mut cx)?;
+///   thread::spawn(move || oneshot.settle(fetch_block(number)));
+///   Ok(promise)
+///
+/// It resolves with any IntoHandle value or rejects with any IntoError,
+/// marshalled on the JS thread. Dropped without settling, eg: by a thread
+/// that panicked, it rejects rather than leaving JS waiting forever.
+///
+/// The legacy runtime has no native Promise support, so the Promise is
+/// built from the global constructor with an executor that stashes
+/// resolve and reject on a holder object. The Slot keeps the
+/// holder alive and gets us back onto the JS thread to settle it.
+pub struct JsOneshot(Option<Slot>);
+
+fn capture_settlers(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let holder = cx.this();
+    let resolve = cx.argument::<JsFunction>(0)?;
+    let reject = cx.argument::<JsFunction>(1)?;
+    holder.set(&mut cx, "resolve", resolve)?;
+    holder.set(&mut cx, "reject", reject)?;
+    Ok(cx.undefined())
+}
+
+/// Creates a pending Promise and the holder object with its resolve and reject,
+/// for when it is settled by something other than a JsOneshot.
+pub(crate) fn with_holder<'a>(
+    cx: &mut impl Context<'a>,
+) -> NeonResult<(Handle<'a, JsObject>, Handle<'a, JsObject>)> {
+    let holder = cx.empty_object();
+    let executor = JsFunction::new(cx, capture_settlers)?;
+    let bind: Handle<JsFunction> = executor.get(cx, "bind")?;
+    let executor = bind.call(cx, executor, vec![holder.upcast()])?;
+
+    let global = cx.global();
+    let constructor: Handle<JsFunction> = global.get(cx, "Promise")?;
+    let promise = constructor.construct(cx, vec![executor])?;
+    Ok((promise, holder))
+}
+
+impl JsOneshot {
+    /// The pending Promise for JS, and what settles it. Only on the JS thread.
+    pub fn new<'a>(cx: &mut impl Context<'a>) -> NeonResult<(Handle<'a, JsObject>, Self)> {
+        let (promise, holder) = with_holder(cx)?;
+        Ok((promise, Self(Some(channel::hold(cx, holder)?))))
+    }
+
+    pub fn settle<T, E>(mut self, result: Result<T, E>)
+    where
+        T: 'static + Send + IntoHandle,
+        E: 'static + Send + IntoError,
+    {
+        if let Some(slot) = self.0.take() {
+            settle(&slot, result);
+        }
+    }
+
+    pub fn resolve<T>(self, value: T)
+    where
+        T: 'static + Send + IntoHandle,
+    {
+        self.settle(Ok::<T, Never>(value))
+    }
+
+    pub fn reject<E>(self, error: E)
+    where
+        E: 'static + Send + IntoError,
+    {
+        self.settle(Err::<(), E>(error))
+    }
+}
+
+impl Drop for JsOneshot {
+    fn drop(&mut self) {
+        if let Some(slot) = self.0.take() {
+            settle(
+                &slot,
+                Err::<(), _>("Dropped without settling, eg: as its thread panicked"),
+            );
+        }
+    }
+}
+
+fn settle<T, E>(slot: &Slot, result: Result<T, E>)
+where
+    T: 'static + Send + IntoHandle,
+    E: 'static + Send + IntoError,
+{
+    slot.schedule(move |cx, holder| {
+        // Anything thrown while marshalling rejects the Promise
+        // rather than escaping into the event loop.
+        let _ = match cx.try_catch(|cx| marshal(cx, result)) {
+            Ok(Ok(value)) => call(cx, holder, "resolve", value),
+            Ok(Err(error)) | Err(error) => call(cx, holder, "reject", error),
+        };
+    });
+}
+
+// Ok(Ok) to resolve with, Ok(Err) to reject with.
+type Settlement<'a> = Result<Handle<'a, JsValue>, Handle<'a, JsValue>>;
+
+fn marshal<'a, T: IntoHandle, E: IntoError>(
+    cx: &mut TaskContext<'a>,
+    result: Result<T, E>,
+) -> NeonResult<Settlement<'a>> {
+    match result {
+        Ok(ok) => match ok.into_handle(cx) {
+            Ok(value) => Ok(Ok(value.upcast())),
+            Err(MaybeThrown::Thrown(t)) => Err(t),
+            Err(MaybeThrown::Unthrown(e)) => Ok(Err(e.into_error(cx)?.upcast())),
+        },
+        Err(e) => Ok(Err(e.into_error(cx)?.upcast())),
+    }
+}
+
+pub(crate) fn call<'a>(
+    cx: &mut TaskContext<'a>,
+    holder: Handle<'a, JsValue>,
+    settler: &str,
+    value: Handle<'a, JsValue>,
+) -> JsResult<'a, JsValue> {
+    let holder: Handle<JsObject> = holder.downcast_or_throw(cx)?;
+    let settler: Handle<JsFunction> = holder.get(cx, settler)?;
+    settler.call(cx, holder, vec![value])
+}
+
+// What waits on a JS Promise, by id. JsFunction::new can't take a
+// closure, so the then handlers are bound to an id to look them up by.
+#[cfg(feature = "tokio")]
+type Handler = Box<dyn for<'a> FnOnce(&mut FunctionContext<'a>, Settlement<'a>)>;
+
+#[cfg(feature = "tokio")]
+thread_local! {
+    // Only used from the JS thread
+    static PENDING: RefCell<HashMap<u32, Handler>> = RefCell::new(HashMap::new());
+    static NEXT_ID: Cell<u32> = const { Cell::new(0) };
+}
+
+#[cfg(feature = "tokio")]
+fn on_settled(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let id = cx.argument::<JsNumber>(0)?.value() as u32;
+    let fulfilled = cx.argument::<JsBoolean>(1)?.value();
+    let value = match cx.argument_opt(2) {
+        Some(value) => value,
+        None => cx.undefined().upcast(),
+    };
+    if let Some(handler) = PENDING.with(|pending| pending.borrow_mut().remove(&id)) {
+        handler(&mut cx, if fulfilled { Ok(value) } else { Err(value) });
+    }
+    Ok(cx.undefined())
+}
+
+/// Waits for a JS Promise (or any value, which resolves right away, as with
+/// await) from Rust. The resolution is decoded as T on the JS thread, and a
+/// rejection becomes an error with its message, eg: from a signer callback
+/// that JS implements as an async function.
+///
+/// Await the result, eg: in task::run_future. Nothing blocks while it
+/// waits. If the Promise is collected without ever settling, this fails
+/// rather than waiting forever.
+#[cfg(feature = "tokio")]
+pub fn resolve_to_rust<'a, T, V>(
+    cx: &mut impl Context<'a>,
+    handle: Handle<V>,
+) -> NeonResult<PromiseFuture<T>>
+where
+    T: 'static + Send + FromHandle,
+    V: Value,
+{
+    let (future, settler) = oneshot();
+    settle_when_resolved(cx, handle.upcast(), settler)?;
+    Ok(future)
+}
+
+/// Settles settler once handle resolves, as for resolve_to_rust
+#[cfg(feature = "tokio")]
+pub(crate) fn settle_when_resolved<'a, T>(
+    cx: &mut impl Context<'a>,
+    handle: Handle<JsValue>,
+    settler: Settler<T>,
+) -> NeonResult<()>
+where
+    T: 'static + Send + FromHandle,
+{
+    let id = NEXT_ID.with(|next| {
+        let id = next.get();
+        next.set(id.wrapping_add(1));
+        id
+    });
+    let handler: Handler = Box::new(move |cx, settlement| {
+        let result = match settlement {
+            Ok(value) => match T::from_handle(value, cx) {
+                Ok(value) => Ok(value),
+                // A pending exception is the Promise's problem, not ours
+                Err(MaybeThrown::Thrown(_)) => Err("Failed to decode Promise result".to_owned()),
+                Err(MaybeThrown::Unthrown(e)) => {
+                    Err(format!("Failed to decode Promise result: {}", e))
+                }
+            },
+            Err(reason) => Err(format!("Promise rejected: {}", reason_message(cx, reason))),
+        };
+        settler.settle(result);
+    });
+    PENDING.with(|pending| pending.borrow_mut().insert(id, handler));
+
+    let global = cx.global();
+    let constructor: Handle<JsObject> = global.get(cx, "Promise")?;
+    let promise_resolve: Handle<JsFunction> = constructor.get(cx, "resolve")?;
+    let promise: Handle<JsObject> = promise_resolve
+        .call(cx, constructor, vec![handle.upcast()])?
+        .downcast_or_throw(cx)?;
+
+    let handler = JsFunction::new(cx, on_settled)?;
+    let bind: Handle<JsFunction> = handler.get(cx, "bind")?;
+    let mut bound = |fulfilled: bool| -> JsResult<'a, JsObject> {
+        let null = cx.null().upcast();
+        let id = cx.number(id).upcast();
+        let fulfilled = cx.boolean(fulfilled).upcast();
+        bind.call(cx, handler, vec![null, id, fulfilled])?
+            .downcast_or_throw(cx)
+    };
+    let on_fulfilled = bound(true)?;
+    let on_rejected = bound(false)?;
+    // The handlers live as long as the Promise might still settle. Once
+    // they are collected it never will, and dropping the entry drops the
+    // Settler, which fails the future.
+    proxy::on_collected(cx, on_fulfilled, move || {
+        PENDING.with(|pending| pending.borrow_mut().remove(&id));
+    })?;
+    let then: Handle<JsFunction> = promise.get(cx, "then")?;
+    then.call(
+        cx,
+        promise,
+        vec![on_fulfilled.upcast(), on_rejected.upcast()],
+    )?;
+    Ok(())
+}
+
+// The message of an Error, or else String(reason)
+#[cfg(feature = "tokio")]
+pub(crate) fn reason_message<'a>(cx: &mut impl Context<'a>, reason: Handle<JsValue>) -> String {
+    let message = cx.try_catch(|cx| {
+        if let Ok(error) = reason.downcast::<JsObject>() {
+            let message: Handle<JsValue> = error.get(cx, "message")?;
+            if let Ok(message) = message.downcast::<JsString>() {
+                return Ok(message.value());
+            }
+        }
+        let global = cx.global();
+        let string: Handle<JsFunction> = global.get(cx, "String")?;
+        let undefined = cx.undefined();
+        let s: Handle<JsString> = string
+            .call(cx, undefined, vec![reason])?
+            .downcast_or_throw(cx)?;
+        Ok(s.value())
+    });
+    message.unwrap_or_else(|_| "unknown reason".to_owned())
+}
+
+/// Settles a PromiseFuture once. Dropped without settling it fails the
+/// future instead, eg: when the environment shuts down first, so that
+/// nothing waits forever on a call that was never made.
+#[cfg(feature = "tokio")]
+pub(crate) struct Settler<T>(oneshot::Sender<Result<T, String>>);
+
+#[cfg(feature = "tokio")]
+impl<T> Settler<T> {
+    pub(crate) fn settle(self, result: Result<T, String>) {
+        // Nobody is waiting if the future was dropped
+        let _ = self.0.send(result);
+    }
+}
+
+#[cfg(feature = "tokio")]
+pub(crate) fn oneshot<T>() -> (PromiseFuture<T>, Settler<T>) {
+    let (sender, receiver) = oneshot::channel();
+    (PromiseFuture(receiver), Settler(sender))
+}
+
+/// The settlement of a JS Promise, from resolve_to_rust. The error is
+/// a String rather than a SafeErr so that it can be sent between threads,
+/// eg: returned from a task.
+#[cfg(feature = "tokio")]
+pub struct PromiseFuture<T>(oneshot::Receiver<Result<T, String>>);
+
+#[cfg(feature = "tokio")]
+impl<T> Future for PromiseFuture<T> {
+    type Output = Result<T, String>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0)
+            .poll(cx)
+            .map(|received| match received {
+                Ok(result) => result,
+                Err(_) => Err("Dropped without settling, eg: as Node shut down".to_owned()),
+            })
+    }
+}
@@ -1,7 +1,19 @@
 use neon::{prelude::*, result::NeonResult};
-use std::sync::Arc;
+use std::any::Any;
+use std::cell::{Cell, RefCell, UnsafeCell};
+use std::collections::HashMap;
+#[cfg(feature = "tokio")]
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, Weak};
+use std::thread::{self, ThreadId};
 
-use crate::errors::{IntoError, MaybeThrown};
+use crate::errors::{IntoError, MaybeThrown, SafeErr, SafeJsResult, SafeResult};
+use crate::marshalling::{type_name_of, IntoHandle};
+#[cfg(feature = "tokio")]
+use crate::{promise::JsOneshot, runtime, task::Failure};
+
+pub use neon_utils_derive::neon_method;
 
 /// Provides a way to easily share data across
 /// threads when wrapped in a JavaScript class
@@ -27,9 +39,64 @@ impl<T> Proxy<T> {
         borrow.0.clone()
     }
 
+    /// Like this, but from any call (eg: a method passed around as a plain
+    /// function), with an error instead of a panic if the receiver isn't a K.
+    pub fn from_this<K, O>(cx: &mut CallContext<O>) -> SafeResult<Self>
+    where
+        K: Class<Internals = Self>,
+        O: neon::object::This + Value,
+    {
+        let this = cx.this().upcast::<JsValue>();
+        let this = match this.downcast::<K>() {
+            Ok(this) => this,
+            Err(_) => {
+                let got = type_name_of(this, cx);
+                let expected = std::any::type_name::<K>().rsplit("::").next().unwrap_or("");
+                return Err(format!("Expected this to be {}, got {}", expected, got).into());
+            }
+        };
+        let guard = cx.lock();
+        let borrow = this.borrow(&guard);
+        Ok(borrow.clone())
+    }
+
     pub fn new(value: T) -> Self {
         Self(Arc::new(value))
     }
+
+    pub(crate) fn from_arc(value: Arc<T>) -> Self {
+        Self(value)
+    }
+
+    /// A reference which doesn't keep the value alive, eg: for a background
+    /// thread which should stop once JS is done with the wrapper.
+    pub fn downgrade(&self) -> WeakProxy<T> {
+        WeakProxy(Arc::downgrade(&self.0))
+    }
+}
+
+/// A Proxy which doesn't keep its value alive. Once every Proxy is
+/// dropped (eg: the class wrapping it was collected) the value is
+/// dropped too, and upgrade gives None.
+pub struct WeakProxy<T>(Weak<T>);
+
+impl<T> Clone for WeakProxy<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> WeakProxy<T> {
+    pub fn upgrade(&self) -> Option<Proxy<T>> {
+        self.0.upgrade().map(Proxy)
+    }
+}
+
+impl<T> Deref for Proxy<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.0
+    }
 }
 
 impl<T> From<T> for Proxy<T> {
@@ -38,6 +105,340 @@ impl<T> From<T> for Proxy<T> {
     }
 }
 
+/// Links T to the class which wraps it, so that a Proxy<T> can be returned
+/// to JS as is, eg: Ok(Proxy::new(tally)) from a factory function.
+///   impl Wrapped for Tally { type Class = JsTally; }
+/// The class's init has to take the Proxy with Proxy::adopt_or.
+pub trait Wrapped: 'static + Sized {
+    type Class: Class<Internals = Proxy<Self>>;
+}
+
+// The Proxy into_handle is constructing a class around. Only used from
+// the JS thread.
+thread_local! {
+    static ADOPTING: RefCell<Option<Box<dyn Any>>> = RefCell::new(None);
+}
+
+impl<T: 'static> Proxy<T> {
+    /// For a class's init: the Proxy being returned to JS, if that is why
+    /// the class is being constructed, or else a new one from f, eg:
+    ///   init(_cx) { Ok(Proxy::adopt_or(Tally::default)) }
+    /// Call this before reading arguments, which there are none of when
+    /// adopting.
+    pub fn adopt_or(f: impl FnOnce() -> T) -> Self {
+        let adopting = ADOPTING.with(|adopting| adopting.borrow_mut().take());
+        match adopting.map(|proxy| proxy.downcast::<Self>()) {
+            Some(Ok(proxy)) => *proxy,
+            // Some other class's Proxy, which into_handle will report
+            Some(Err(other)) => {
+                ADOPTING.with(|adopting| *adopting.borrow_mut() = Some(other));
+                Self::new(f())
+            }
+            None => Self::new(f()),
+        }
+    }
+}
+
+impl<T: Wrapped> IntoHandle for Proxy<T> {
+    type Handle = T::Class;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        let constructor = T::Class::constructor(cx)?;
+        ADOPTING.with(|adopting| *adopting.borrow_mut() = Some(Box::new(self.clone())));
+        let args: Vec<Handle<JsValue>> = Vec::new();
+        let object = constructor.construct(cx, args);
+        // Not left for the next class to pick up if init didn't
+        let unadopted = ADOPTING.with(|adopting| adopting.borrow_mut().take());
+        let object = object?;
+        if unadopted.is_some() {
+            let class = std::any::type_name::<T::Class>()
+                .rsplit("::")
+                .next()
+                .unwrap_or("");
+            return Err(format!("The init of {} doesn't call Proxy::adopt_or", class).into());
+        }
+        Ok(object)
+    }
+}
+
+// Callbacks waiting on an object to be collected, by id. Only used from
+// the JS thread, like the promise handlers.
+thread_local! {
+    static FINALIZERS: RefCell<HashMap<u32, Box<dyn FnOnce()>>> = RefCell::new(HashMap::new());
+    static NEXT_ID: Cell<u32> = const { Cell::new(0) };
+}
+
+fn run_finalizer(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let id = cx.argument::<JsNumber>(0)?.value() as u32;
+    if let Some(f) = FINALIZERS.with(|finalizers| finalizers.borrow_mut().remove(&id)) {
+        f();
+    }
+    Ok(cx.undefined())
+}
+
+// One FinalizationRegistry per environment, hung off the global object
+fn finalization_registry<'a>(cx: &mut impl Context<'a>) -> NeonResult<Handle<'a, JsObject>> {
+    let global = cx.global();
+    let symbol: Handle<JsObject> = global.get(cx, "Symbol")?;
+    let symbol_for: Handle<JsFunction> = symbol.get(cx, "for")?;
+    let name = cx.string("neon-utils.finalizers").upcast();
+    let key: Handle<JsValue> = symbol_for.call(cx, symbol, vec![name])?;
+    let existing: Handle<JsValue> = global.get(cx, key)?;
+    if let Ok(registry) = existing.downcast::<JsObject>() {
+        return Ok(registry);
+    }
+    let constructor: Handle<JsFunction> = global.get(cx, "FinalizationRegistry")?;
+    let cleanup = JsFunction::new(cx, run_finalizer)?;
+    let registry = constructor.construct(cx, vec![cleanup.upcast()])?;
+    global.set(cx, key, registry)?;
+    Ok(registry)
+}
+
+/// Calls f on the JS thread some time after object is garbage collected,
+/// eg: to close a socket that the class wrapping a Proxy opened.
+/// Hold a WeakProxy in f rather than a Proxy, which would keep it alive.
+///
+/// This uses a JS FinalizationRegistry, so as with those f may run late,
+/// or never if the process exits first. Don't rely on it for anything
+/// which must happen, only to release resources sooner.
+pub fn on_collected<'a, V: Object>(
+    cx: &mut impl Context<'a>,
+    object: Handle<V>,
+    f: impl 'static + FnOnce(),
+) -> NeonResult<()> {
+    let registry = finalization_registry(cx)?;
+    let id = NEXT_ID.with(|next| {
+        let id = next.get();
+        next.set(id.wrapping_add(1));
+        id
+    });
+    FINALIZERS.with(|finalizers| finalizers.borrow_mut().insert(id, Box::new(f)));
+    let register: Handle<JsFunction> = registry.get(cx, "register")?;
+    let id = cx.number(id).upcast();
+    register.call(cx, registry, vec![object.upcast(), id])?;
+    Ok(())
+}
+
+/// Shared state which async code holds across awaits, eg: a nonce manager
+/// which awaits an RPC call between reading and bumping the nonce.
+/// A std Mutex can't be held across an await, so this is locked with
+/// Proxy::lock or Proxy::with_async instead.
+#[cfg(feature = "tokio")]
+pub type ProxyMut<T> = Proxy<AsyncMutex<T>>;
+
+/// tokio's Mutex, which is fair and is never poisoned: if a holder
+/// panics, the value is released as the panic left it.
+#[cfg(feature = "tokio")]
+pub type AsyncMutex<T> = tokio::sync::Mutex<T>;
+
+/// Holds the lock on a ProxyMut until dropped
+#[cfg(feature = "tokio")]
+pub type Guard<T> = tokio::sync::OwnedMutexGuard<T>;
+
+#[cfg(feature = "tokio")]
+impl<T> Proxy<AsyncMutex<T>> {
+    /// Waits for the lock. The guard owns a Proxy, so it may be moved
+    /// into an async block or another thread.
+    pub fn lock(&self) -> impl Future<Output = Guard<T>> {
+        self.0.clone().lock_owned()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T: 'static + Send> Proxy<AsyncMutex<T>> {
+    /// Runs the future from f with the lock held on the shared runtime,
+    /// and returns a Promise which settles with its result, eg:
+    ///   nonces.with_async(&mut cx, |mut nonces| async move {
+    ///       let next = rpc.next_nonce().await?;
+    ///       *nonces = next + 1;
+    ///       Ok::<_, SafeErr>(next)
+    ///   })
+    ///
+    /// The future may use tokio's timers and sockets. See runtime.
+    pub fn with_async<'a, F, Fut, Ok, Err>(
+        &self,
+        cx: &mut impl Context<'a>,
+        f: F,
+    ) -> NeonResult<Handle<'a, JsObject>>
+    where
+        F: 'static + Send + FnOnce(Guard<T>) -> Fut,
+        Fut: 'static + Send + Future<Output = Result<Ok, Err>>,
+        Ok: 'static + Send + IntoHandle,
+        Err: 'static + Send + IntoError,
+    {
+        let (promise, deferred) = JsOneshot::new(cx)?;
+        let lock = self.lock();
+        let runtime = runtime::get();
+        let run = runtime.spawn(async move { f(lock.await).await });
+        runtime.spawn(async move {
+            // Err only if the future panicked
+            let result = match run.await {
+                Ok(result) => result.map_err(Failure::Failed),
+                Err(_) => Err(Failure::Panicked),
+            };
+            deferred.settle(result);
+        });
+        Ok(promise)
+    }
+}
+
+/// A read/write lock for Proxy state whose methods call into JS, eg: a
+/// registry which runs listeners while it is being changed. If a listener
+/// calls back into the same object, a Mutex would wait for itself forever.
+/// This knows which thread holds it, so that instead fails with the code
+/// REENTRANT, which JS sees thrown from the inner call.
+///
+/// Reading again on a thread which is already reading is allowed.
+/// Writing while reading or writing, or reading while writing, is not.
+/// Other threads wait their turn as usual.
+pub struct ReentryLock<T> {
+    holders: Mutex<Holders>,
+    released: Condvar,
+    value: UnsafeCell<T>,
+}
+
+// SAFETY: value is only reached through the guards, which holders hands
+// out as a RwLock would: many readers or one writer
+unsafe impl<T: Send> Send for ReentryLock<T> {}
+unsafe impl<T: Send + Sync> Sync for ReentryLock<T> {}
+
+#[derive(Default)]
+struct Holders {
+    writer: Option<ThreadId>,
+    // One entry per read guard, so a thread may appear more than once
+    readers: Vec<ThreadId>,
+}
+
+impl<T: Default> Default for ReentryLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> ReentryLock<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            holders: Mutex::new(Holders::default()),
+            released: Condvar::new(),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    fn holders(&self) -> MutexGuard<'_, Holders> {
+        self.holders.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn reentered(action: &str) -> SafeErr {
+        SafeErr::from(format!(
+            "Can't {} this while a call further up the stack is using it, eg: from a callback it called",
+            action
+        ))
+        .with_code("REENTRANT")
+    }
+
+    /// Waits for any writer on another thread
+    pub fn read(&self) -> Result<ReadGuard<'_, T>, SafeErr> {
+        let me = thread::current().id();
+        let mut holders = self.holders();
+        if holders.writer == Some(me) {
+            return Err(Self::reentered("read"));
+        }
+        // A reader already here mustn't wait, in case a writer is waiting on it
+        if !holders.readers.contains(&me) {
+            while holders.writer.is_some() {
+                holders = self
+                    .released
+                    .wait(holders)
+                    .unwrap_or_else(|e| e.into_inner());
+            }
+        }
+        holders.readers.push(me);
+        Ok(ReadGuard(self, me))
+    }
+
+    /// Waits for readers and any writer on other threads
+    pub fn write(&self) -> Result<WriteGuard<'_, T>, SafeErr> {
+        let me = thread::current().id();
+        let mut holders = self.holders();
+        if holders.writer == Some(me) || holders.readers.contains(&me) {
+            return Err(Self::reentered("change"));
+        }
+        while holders.writer.is_some() || !holders.readers.is_empty() {
+            holders = self
+                .released
+                .wait(holders)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+        holders.writer = Some(me);
+        Ok(WriteGuard(self))
+    }
+}
+
+/// Reads a ReentryLock until dropped
+// The reader is kept, rather than looked up at drop, since the guard
+// may be dropped on another thread
+pub struct ReadGuard<'l, T>(&'l ReentryLock<T>, ThreadId);
+
+impl<T> Deref for ReadGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        // SAFETY: there is no writer while this is held
+        unsafe { &*self.0.value.get() }
+    }
+}
+
+impl<T> Drop for ReadGuard<'_, T> {
+    fn drop(&mut self) {
+        let mut holders = self.0.holders();
+        if let Some(i) = holders.readers.iter().position(|&r| r == self.1) {
+            holders.readers.swap_remove(i);
+        }
+        drop(holders);
+        self.0.released.notify_all();
+    }
+}
+
+/// Changes a ReentryLock until dropped
+pub struct WriteGuard<'l, T>(&'l ReentryLock<T>);
+
+impl<T> Deref for WriteGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        // SAFETY: this is the only guard while it is held
+        unsafe { &*self.0.value.get() }
+    }
+}
+
+impl<T> DerefMut for WriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: as for deref
+        unsafe { &mut *self.0.value.get() }
+    }
+}
+
+impl<T> Drop for WriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.0.holders().writer = None;
+        self.0.released.notify_all();
+    }
+}
+
+/// Proxy::from_this as a method on the context, eg:
+/// let pool = cx.this_proxy::<JsPool, _>()?;
+pub trait ThisProxy {
+    fn this_proxy<K, T>(&mut self) -> SafeResult<Proxy<T>>
+    where
+        K: Class<Internals = Proxy<T>>;
+}
+
+impl<O: neon::object::This + Value> ThisProxy for CallContext<'_, O> {
+    fn this_proxy<K, T>(&mut self) -> SafeResult<Proxy<T>>
+    where
+        K: Class<Internals = Proxy<T>>,
+    {
+        Proxy::from_this::<K, O>(self)
+    }
+}
+
 // This was supposed to be part of Terminal, but can't do it without GAT.
 pub trait ProxyTerminal: Sized {
     type Out;
@@ -59,3 +460,85 @@ impl<T> ProxyTerminal for Result<Proxy<T>, MaybeThrown> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weak() {
+        let proxy = Proxy::new(1);
+        let weak = proxy.downgrade();
+        assert_eq!(weak.upgrade().map(|p| *p), Some(1));
+        drop(proxy);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn reentry() {
+        let lock = ReentryLock::new(vec![1]);
+        {
+            let first = lock.read().ok().unwrap();
+            let again = lock.read().ok().unwrap();
+            assert_eq!(first.len() + again.len(), 2);
+            let e = lock.write().err().unwrap();
+            assert_eq!(e.code(), Some("REENTRANT"));
+            assert!(e.to_string().starts_with("Can't change this while"));
+        }
+        let mut writing = lock.write().ok().unwrap();
+        writing.push(2);
+        assert!(lock.read().is_err() && lock.write().is_err());
+        drop(writing);
+        assert_eq!(*lock.read().ok().unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn other_threads_wait() {
+        let lock = Arc::new(ReentryLock::new(0));
+        let writing = lock.write().ok().unwrap();
+        let reader = {
+            let lock = lock.clone();
+            thread::spawn(move || *lock.read().ok().unwrap())
+        };
+        thread::sleep(std::time::Duration::from_millis(20));
+        drop(writing);
+        assert_eq!(reader.join().unwrap(), 0);
+    }
+
+    #[test]
+    fn read_dropped_on_another_thread() {
+        let lock = ReentryLock::new(0);
+        let reading = lock.read().ok().unwrap();
+        thread::scope(|s| {
+            s.spawn(move || drop(reading));
+        });
+        assert!(lock.write().is_ok());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn held_across_await() {
+        let counter: ProxyMut<u32> = Proxy::new(AsyncMutex::new(0));
+        let runtime = runtime::get();
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let counter = counter.clone();
+                runtime.spawn(async move {
+                    for _ in 0..100 {
+                        let mut n = counter.lock().await;
+                        let read = *n;
+                        // Whoever holds the lock waits with it held
+                        tokio::task::yield_now().await;
+                        *n = read + 1;
+                    }
+                })
+            })
+            .collect();
+        runtime.block_on(async move {
+            for task in tasks {
+                task.await.unwrap();
+            }
+            assert_eq!(*counter.lock().await, 800);
+        });
+    }
+}
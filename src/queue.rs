@@ -0,0 +1,102 @@
+use crate::errors::SafeJsResult;
+use crate::marshalling::FromHandle;
+use crate::promise::JsOneshot;
+use crossbeam_channel::{Receiver, Sender};
+use neon::prelude::*;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::thread;
+
+/// Type erases the item type so that a single JS class can back every queue
+trait Sink: Send + Sync {
+    fn push<'a>(
+        &self,
+        cx: &mut MethodContext<'a, JsNativeQueue>,
+        item: Handle<'a, JsValue>,
+    ) -> SafeJsResult<'a, JsObject>;
+}
+
+struct Staging<T> {
+    items: Sender<(T, JsOneshot)>,
+}
+
+impl<T: 'static + Send + FromHandle> Sink for Staging<T> {
+    fn push<'a>(
+        &self,
+        cx: &mut MethodContext<'a, JsNativeQueue>,
+        item: Handle<'a, JsValue>,
+    ) -> SafeJsResult<'a, JsObject> {
+        let item = T::from_handle(item, cx)?;
+        let (promise, deferred) = JsOneshot::new(cx)?;
+        self.items
+            .send((item, deferred))
+            .map_err(|_| "NativeQueue is closed")?;
+        Ok(promise)
+    }
+}
+
+/// The internals of a JsNativeQueue. Empty unless created by NativeQueue::bounded.
+pub struct QueueInternals(Option<Arc<dyn Sink>>);
+
+declare_types! {
+    /// The JS side of a NativeQueue. push(item) returns a Promise
+    /// which resolves once the item has been accepted into the queue.
+    pub class JsNativeQueue for QueueInternals {
+        init(_cx) {
+            Ok(QueueInternals(None))
+        }
+
+        method push(mut cx) {
+            let this = cx.this();
+            let sink = {
+                let guard = cx.lock();
+                let internals = this.borrow(&guard);
+                internals.0.clone()
+            };
+            let item = cx.argument::<JsValue>(0)?;
+            let pushed = match sink {
+                Some(sink) => sink.push(&mut cx, item),
+                None => Err("NativeQueue must be created from Rust".into()),
+            };
+            match pushed {
+                Ok(promise) => Ok(promise.upcast()),
+                Err(e) => e.finish(cx),
+            }
+        }
+    }
+}
+
+/// A bounded queue which JS pushes items into and Rust consumes from.
+///
+/// Items are decoded on the JS thread when pushed, then handed to Rust
+/// in FIFO order. The Promise returned by push does not resolve until
+/// there is room in the queue, which gives JS natural backpressure.
+pub struct NativeQueue<T>(PhantomData<T>);
+
+impl<T: 'static + Send + FromHandle> NativeQueue<T> {
+    /// Creates the JS object to push to, and the Receiver to consume from.
+    /// Dropping the Receiver rejects further pushes.
+    pub fn bounded<'a>(
+        cx: &mut impl Context<'a>,
+        capacity: usize,
+    ) -> NeonResult<(Handle<'a, JsNativeQueue>, Receiver<T>)> {
+        let (staging, staged) = crossbeam_channel::unbounded::<(T, JsOneshot)>();
+        let (sender, receiver) = crossbeam_channel::bounded(capacity);
+
+        // Blocks on the bounded queue so that the JS thread never has to.
+        // Exits once the JS object has been collected.
+        thread::spawn(move || {
+            for (item, deferred) in staged {
+                match sender.send(item) {
+                    Ok(()) => deferred.resolve(()),
+                    Err(_) => deferred.reject("NativeQueue is closed"),
+                }
+            }
+        });
+
+        let mut queue = JsNativeQueue::new::<_, JsValue, _>(cx, vec![])?;
+        let sink: Arc<dyn Sink> = Arc::new(Staging { items: staging });
+        cx.borrow_mut(&mut queue, |mut internals| internals.0 = Some(sink));
+        Ok((queue, receiver))
+    }
+}
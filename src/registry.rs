@@ -0,0 +1,201 @@
+//! Constructors for JS classes which Rust needs to make instances of,
+//! eg: BigNumber from ethers. JS registers them by name at init with
+//! registerClass("BigNumber", BigNumber), then Rust constructs them with
+//! construct(cx, "BigNumber", (value,)) or returns an Instance.
+//!
+//! Error classes are registered by code in the same way, with
+//! registerError("RATE_LIMITED", RateLimitError), and are what errors
+//! with that code are thrown as. See errors::ErrorCode.
+//!
+//! Each environment (eg: worker_thread) has its own classes, so each
+//! needs to register them.
+
+use crate::errors::{SafeJsResult, SafeResult, Terminal};
+use crate::marshalling::{Arg, IntoHandle};
+use neon::prelude::*;
+
+fn classes<'a>(cx: &mut impl Context<'a>) -> NeonResult<Handle<'a, JsObject>> {
+    table(cx, "neon-utils.classes")
+}
+
+fn error_classes<'a>(cx: &mut impl Context<'a>) -> NeonResult<Handle<'a, JsObject>> {
+    table(cx, "neon-utils.errors")
+}
+
+// The tables hang off the global object, like the channel registry,
+// so that they live as long as the environment.
+pub(crate) fn table<'a>(cx: &mut impl Context<'a>, name: &str) -> NeonResult<Handle<'a, JsObject>> {
+    let global = cx.global();
+    let symbol: Handle<JsObject> = global.get(cx, "Symbol")?;
+    let symbol_for: Handle<JsFunction> = symbol.get(cx, "for")?;
+    let name = cx.string(name).upcast();
+    let key: Handle<JsValue> = symbol_for.call(cx, symbol, vec![name])?;
+    let existing: Handle<JsValue> = global.get(cx, key)?;
+    if let Ok(classes) = existing.downcast::<JsObject>() {
+        return Ok(classes);
+    }
+    // No prototype, so that eg: "toString" is never found as a class
+    let object: Handle<JsObject> = global.get(cx, "Object")?;
+    let create: Handle<JsFunction> = object.get(cx, "create")?;
+    let null = cx.null().upcast();
+    let classes: Handle<JsObject> = create.call(cx, object, vec![null])?.downcast_or_throw(cx)?;
+    global.set(cx, key, classes)?;
+    Ok(classes)
+}
+
+// Functions of the global Object which Rust calls. They are all kept the
+// first time any is needed, so that scripts replacing them afterwards
+// don't change what Rust calls. None of them use this.
+const INTRINSICS: &[&str] = &[
+    "assign",
+    "create",
+    "defineProperty",
+    "getOwnPropertyDescriptor",
+    "getPrototypeOf",
+    "is",
+    "keys",
+];
+
+// Kept along with them: Object.prototype, as "prototype", and its
+// hasOwnProperty, which is called with the object to check as this.
+pub(crate) fn intrinsic<'a, V: Value>(
+    cx: &mut impl Context<'a>,
+    name: &str,
+) -> NeonResult<Handle<'a, V>> {
+    let intrinsics = table(cx, "neon-utils.intrinsics")?;
+    let existing: Handle<JsValue> = intrinsics.get(cx, name)?;
+    if !existing.is_a::<JsUndefined>() {
+        return existing.downcast_or_throw(cx);
+    }
+    let global = cx.global();
+    let object: Handle<JsObject> = global.get(cx, "Object")?;
+    for key in INTRINSICS {
+        let function: Handle<JsFunction> = object.get(cx, *key)?;
+        intrinsics.set(cx, *key, function)?;
+    }
+    let prototype: Handle<JsObject> = object.get(cx, "prototype")?;
+    let has_own: Handle<JsFunction> = prototype.get(cx, "hasOwnProperty")?;
+    intrinsics.set(cx, "prototype", prototype)?;
+    intrinsics.set(cx, "hasOwnProperty", has_own)?;
+    intrinsics.get(cx, name)
+}
+
+/// Registers a constructor under name, replacing any registered before
+pub fn register_class<'a>(
+    cx: &mut impl Context<'a>,
+    name: &str,
+    constructor: Handle<JsFunction>,
+) -> NeonResult<()> {
+    let classes = classes(cx)?;
+    classes.set(cx, name, constructor)?;
+    Ok(())
+}
+
+/// The constructor registered under name
+pub fn class<'a>(cx: &mut impl Context<'a>, name: &str) -> SafeJsResult<'a, JsFunction> {
+    let classes = classes(cx)?;
+    let class: Handle<JsValue> = classes.get(cx, name)?;
+    class
+        .downcast::<JsFunction>()
+        .map_err(|_| format!("Class '{}' is not registered", name).into())
+}
+
+/// Arguments for a constructor, eg: () or (value, options)
+pub trait IntoArgs {
+    #[allow(clippy::wrong_self_convention)]
+    fn into_args<'c>(&self, cx: &mut impl Context<'c>) -> SafeResult<Vec<Handle<'c, JsValue>>>;
+}
+
+impl IntoArgs for () {
+    fn into_args<'c>(&self, _cx: &mut impl Context<'c>) -> SafeResult<Vec<Handle<'c, JsValue>>> {
+        Ok(Vec::new())
+    }
+}
+
+impl<A: IntoArgs + ?Sized> IntoArgs for &A {
+    fn into_args<'c>(&self, cx: &mut impl Context<'c>) -> SafeResult<Vec<Handle<'c, JsValue>>> {
+        (**self).into_args(cx)
+    }
+}
+
+macro_rules! impl_into_args {
+    ($($i:tt $t:ident),+) => {
+        impl<$($t: IntoHandle),+> IntoArgs for ($($t,)+) {
+            fn into_args<'c>(&self, cx: &mut impl Context<'c>) -> SafeResult<Vec<Handle<'c, JsValue>>> {
+                Ok(vec![$(self.$i.into_handle(cx)?.upcast()),+])
+            }
+        }
+    };
+}
+
+impl_into_args!(0 A0);
+impl_into_args!(0 A0, 1 A1);
+impl_into_args!(0 A0, 1 A1, 2 A2);
+impl_into_args!(0 A0, 1 A1, 2 A2, 3 A3);
+impl_into_args!(0 A0, 1 A1, 2 A2, 3 A3, 4 A4);
+impl_into_args!(0 A0, 1 A1, 2 A2, 3 A3, 4 A4, 5 A5);
+
+/// Calls new on the class registered under name. Errors if it isn't
+/// registered, and throws whatever the constructor throws.
+pub fn construct<'a>(
+    cx: &mut impl Context<'a>,
+    name: &str,
+    args: impl IntoArgs,
+) -> SafeJsResult<'a, JsObject> {
+    let class = class(cx, name)?;
+    let args = args.into_args(cx)?;
+    Ok(class.construct(cx, args)?)
+}
+
+/// An instance of a registered class, to return from marshalling code.
+/// Eg: Instance("BigNumber", (amount.to_string(),))
+pub struct Instance<A>(pub &'static str, pub A);
+
+impl<A: IntoArgs> IntoHandle for Instance<A> {
+    type Handle = JsObject;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, Self::Handle> {
+        construct(cx, self.0, &self.1)
+    }
+}
+
+/// registerClass(name, constructor)
+pub fn js_register_class(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let registered = (|| -> SafeResult<()> {
+        let name: String = cx.arg(0)?;
+        let constructor: Handle<JsFunction> = cx.argument(1)?;
+        Ok(register_class(&mut cx, &name, constructor)?)
+    })();
+    registered.finish(cx)
+}
+
+/// Registers the class which errors with code are thrown as, replacing
+/// any registered before. It is constructed with the message.
+pub fn register_error<'a>(
+    cx: &mut impl Context<'a>,
+    code: &str,
+    constructor: Handle<JsFunction>,
+) -> NeonResult<()> {
+    let errors = error_classes(cx)?;
+    errors.set(cx, code, constructor)?;
+    Ok(())
+}
+
+/// The error class registered for code, if any
+pub fn error_class<'a>(
+    cx: &mut impl Context<'a>,
+    code: &str,
+) -> NeonResult<Option<Handle<'a, JsFunction>>> {
+    let errors = error_classes(cx)?;
+    let class: Handle<JsValue> = errors.get(cx, code)?;
+    Ok(class.downcast::<JsFunction>().ok())
+}
+
+/// registerError(code, constructor)
+pub fn js_register_error(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let registered = (|| -> SafeResult<()> {
+        let code: String = cx.arg(0)?;
+        let constructor: Handle<JsFunction> = cx.argument(1)?;
+        Ok(register_error(&mut cx, &code, constructor)?)
+    })();
+    registered.finish(cx)
+}
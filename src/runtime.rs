@@ -0,0 +1,23 @@
+//! One tokio runtime for the futures neon-utils runs, eg: from
+//! Proxy::with_async, instead of a thread for each of them.
+
+use std::sync::OnceLock;
+use tokio::runtime::{Builder, Runtime};
+
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+/// The shared runtime, started the first time it's needed. Futures run
+/// on it may use tokio's timers and sockets, and it's there to spawn
+/// anything else on too, eg: runtime::get().spawn(poll_blocks()).
+///
+/// It's shared by every instance of the addon, and runs until the
+/// process exits.
+pub fn get() -> &'static Runtime {
+    RUNTIME.get_or_init(|| {
+        Builder::new_multi_thread()
+            .thread_name("neon-utils")
+            .enable_all()
+            .build()
+            .expect("Failed to start the tokio runtime")
+    })
+}
@@ -0,0 +1,82 @@
+//! Knowing when the JS environment is going away, so that work still in
+//! flight can stop and results can be dropped instead of sent to a
+//! thread that no longer exists.
+//!
+//! The legacy runtime has no cleanup hooks, so this listens for the
+//! process 'exit' event instead. That fires on the main thread when Node
+//! exits, and in a worker_thread when it exits or is terminated.
+
+use crate::channel;
+use neon::prelude::*;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+thread_local! {
+    // One per JS thread, made when first asked for
+    static FLAG: RefCell<Option<Arc<AtomicBool>>> = const { RefCell::new(None) };
+    // Run after the flag is set, eg: by module::init for its finalizer
+    static HOOKS: RefCell<Vec<Box<dyn FnOnce()>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Set once the JS environment it was made on starts shutting down.
+/// Cheap to clone and check from any thread, eg: between chunks of work.
+#[derive(Clone, Debug)]
+pub struct ShutdownToken(Arc<AtomicBool>);
+
+impl ShutdownToken {
+    pub fn is_shutting_down(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Gets the token for the current JS environment
+pub fn token<'a>(cx: &mut impl Context<'a>) -> NeonResult<ShutdownToken> {
+    if let Some(flag) = FLAG.with(|f| f.borrow().clone()) {
+        return Ok(ShutdownToken(flag));
+    }
+    let global = cx.global();
+    let process: Handle<JsObject> = global.get(cx, "process")?;
+    let once: Handle<JsFunction> = process.get(cx, "once")?;
+    let event = cx.string("exit").upcast();
+    let listener = JsFunction::new(cx, on_exit)?.upcast();
+    once.call(cx, process, vec![event, listener])?;
+
+    let flag = Arc::new(AtomicBool::new(false));
+    FLAG.with(|f| *f.borrow_mut() = Some(flag.clone()));
+    Ok(ShutdownToken(flag))
+}
+
+/// Runs f once the current JS environment starts shutting down
+pub(crate) fn on_shutdown<'a>(
+    cx: &mut impl Context<'a>,
+    f: impl 'static + FnOnce(),
+) -> NeonResult<()> {
+    token(cx)?;
+    HOOKS.with(|hooks| hooks.borrow_mut().push(Box::new(f)));
+    Ok(())
+}
+
+/// Whether the current JS environment is shutting down. False if
+/// nothing has asked for a token yet, since then nothing is listening.
+pub(crate) fn is_shutting_down() -> bool {
+    FLAG.with(|f| {
+        f.borrow()
+            .as_ref()
+            .is_some_and(|f| f.load(Ordering::SeqCst))
+    })
+}
+
+fn on_exit(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    FLAG.with(|f| {
+        if let Some(flag) = f.borrow().as_ref() {
+            flag.store(true, Ordering::SeqCst);
+        }
+    });
+    channel::close();
+    let hooks = HOOKS.with(|hooks| std::mem::take(&mut *hooks.borrow_mut()));
+    for hook in hooks {
+        hook();
+    }
+    Ok(cx.undefined())
+}
@@ -0,0 +1,88 @@
+//! A JS value made once and then returned every time, eg: a shared
+//! provider that every call to getProvider() should get the same one of.
+//!
+//!   static PROVIDER: LazyJsSingleton<JsObject> = LazyJsSingleton::new();
+//!   PROVIDER.get_or_init(&mut cx, |cx| make_provider(cx))
+//!
+//! Each environment (eg: worker_thread) has a value of its own, since JS
+//! values can't be shared between them. The legacy runtime can't root a
+//! handle, so it hangs off the global object, like the class registry.
+
+use crate::errors::{SafeJsResult, SafeResult};
+use crate::registry;
+use neon::prelude::*;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+pub struct LazyJsSingleton<T> {
+    // 0 until first used
+    id: AtomicU32,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T: Value> Default for LazyJsSingleton<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Value> LazyJsSingleton<T> {
+    pub const fn new() -> Self {
+        Self {
+            id: AtomicU32::new(0),
+            _value: PhantomData,
+        }
+    }
+
+    // Any thread may be first, so the id is settled by whichever is
+    fn id(&self) -> u32 {
+        let id = self.id.load(Ordering::Acquire);
+        if id != 0 {
+            return id;
+        }
+        let new = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        match self
+            .id
+            .compare_exchange(0, new, Ordering::AcqRel, Ordering::Acquire)
+        {
+            Ok(_) => new,
+            Err(existing) => existing,
+        }
+    }
+
+    /// The value, if init has made it in this environment
+    pub fn get<'a>(&self, cx: &mut impl Context<'a>) -> SafeResult<Option<Handle<'a, T>>> {
+        let singletons = registry::table(cx, "neon-utils.singletons")?;
+        let id = cx.number(self.id());
+        let value: Handle<JsValue> = singletons.get(cx, id)?;
+        if value.is_a::<JsUndefined>() {
+            return Ok(None);
+        }
+        Ok(Some(value.downcast_or_throw(cx)?))
+    }
+
+    /// The value, made by init if this is the first time in this
+    /// environment. If init fails, nothing is kept and the next call
+    /// tries again.
+    pub fn get_or_init<'a, C: Context<'a>>(
+        &self,
+        cx: &mut C,
+        init: impl FnOnce(&mut C) -> SafeJsResult<'a, T>,
+    ) -> SafeJsResult<'a, T> {
+        if let Some(value) = self.get(cx)? {
+            return Ok(value);
+        }
+        let value = init(cx)?;
+        // init may have made one already, by way of get_or_init. That one
+        // is what others got, so it stays.
+        if let Some(existing) = self.get(cx)? {
+            return Ok(existing);
+        }
+        let singletons = registry::table(cx, "neon-utils.singletons")?;
+        let id = cx.number(self.id());
+        singletons.set(cx, id, value)?;
+        Ok(value)
+    }
+}
@@ -0,0 +1,261 @@
+use super::run_async;
+use crate::channel::{self, ignore, Slot};
+use crate::errors::{IntoError, MaybeThrown, SafeJsResult, SafeResult};
+use crate::marshalling::IntoHandle;
+use crate::promise;
+use crate::uncaught;
+use neon::prelude::*;
+use never::Never;
+use std::any::Any;
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+
+// The output of each step, which is only known to the builder
+type Value = Box<dyn Any + Send>;
+
+// Errors can't be turned into JS until they get back to the JS thread,
+// so keep the conversion for later instead of every error type.
+type Failure = Box<dyn for<'a> FnOnce(&mut TaskContext<'a>) -> JsResult<'a, JsError> + Send>;
+
+fn failure<E: 'static + Send + IntoError>(e: E) -> Failure {
+    Box::new(move |cx| e.into_error(cx))
+}
+
+type Background = Box<dyn FnOnce(Value) -> Result<Value, Failure> + Send>;
+type Main = Box<dyn for<'a> FnOnce(&mut TaskContext<'a>, Value) -> SafeResult<Value> + Send>;
+
+enum Step {
+    Background(Background),
+    Main(Main),
+}
+
+/// Chains steps which run in the background with steps which run on the
+/// JS thread, eg: to load something, ask JS about it, then process it.
+/// Start one with task, then finish it with a callback or as a Promise.
+///
+/// Consecutive background steps run on the same thread. The first
+/// failure skips the remaining steps.
+pub struct TaskBuilder<T> {
+    steps: VecDeque<Step>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+/// Starts a TaskBuilder with f as its first background step
+pub fn task<F, T, E>(f: F) -> TaskBuilder<T>
+where
+    F: 'static + Send + FnOnce() -> Result<T, E>,
+    T: 'static + Send,
+    E: 'static + Send + IntoError,
+{
+    let builder = TaskBuilder {
+        steps: VecDeque::new(),
+        _marker: PhantomData,
+    };
+    builder.and_then(move |()| f())
+}
+
+impl<T: 'static + Send> TaskBuilder<T> {
+    fn push<U>(mut self, step: Step) -> TaskBuilder<U> {
+        self.steps.push_back(step);
+        TaskBuilder {
+            steps: self.steps,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Runs f on the output of the previous step, in the background
+    pub fn and_then<F, U, E>(self, f: F) -> TaskBuilder<U>
+    where
+        F: 'static + Send + FnOnce(T) -> Result<U, E>,
+        U: 'static + Send,
+        E: 'static + Send + IntoError,
+    {
+        let step: Background = Box::new(move |value| {
+            let value = *value.downcast::<T>().unwrap();
+            match panic::catch_unwind(AssertUnwindSafe(|| f(value))) {
+                Ok(Ok(value)) => Ok(Box::new(value) as Value),
+                Ok(Err(e)) => Err(failure(e)),
+                Err(_) => Err(failure("Task panicked")),
+            }
+        });
+        self.push(Step::Background(step))
+    }
+
+    /// Runs f on the output of the previous step, on the JS thread.
+    /// If f throws, the exception is what the task fails with.
+    pub fn and_then_on_main<F, U>(self, f: F) -> TaskBuilder<U>
+    where
+        F: 'static + Send + for<'a> FnOnce(&mut TaskContext<'a>, T) -> SafeResult<U>,
+        U: 'static + Send,
+    {
+        let step: Main = Box::new(move |cx, value| {
+            let value = *value.downcast::<T>().unwrap();
+            f(cx, value).map(|value| Box::new(value) as Value)
+        });
+        self.push(Step::Main(step))
+    }
+}
+
+impl<T: 'static + Send + IntoHandle> TaskBuilder<T> {
+    /// Calls callback with (err) or (null, output) once every step is done
+    pub fn finish_with_callback<'a>(
+        self,
+        cx: &mut impl Context<'a>,
+        callback: Handle<JsFunction>,
+    ) -> NeonResult<()> {
+        let holder = cx.empty_object();
+        holder.set(cx, "callback", callback)?;
+        self.start(cx, holder, Finish::Callback)
+    }
+
+    /// Returns a Promise which settles once every step is done
+    pub fn finish_as_promise<'a>(self, cx: &mut impl Context<'a>) -> JsResult<'a, JsObject> {
+        let (promise, holder) = promise::with_holder(cx)?;
+        self.start(cx, holder, Finish::Promise)?;
+        Ok(promise)
+    }
+
+    fn start<'a>(
+        self,
+        cx: &mut impl Context<'a>,
+        holder: Handle<JsObject>,
+        finish: Finish,
+    ) -> NeonResult<()> {
+        let ignore = JsFunction::new(cx, ignore)?;
+        let run = Run {
+            steps: self.steps,
+            slot: Arc::new(channel::hold(cx, holder)?),
+            finish,
+            marshal: marshal::<T>,
+        };
+        run.background(ignore, Box::new(()));
+        Ok(())
+    }
+}
+
+// How the outcome is delivered, using what was put on the holder
+enum Finish {
+    Callback,
+    Promise,
+}
+
+type Marshal = for<'a> fn(&mut TaskContext<'a>, Value) -> SafeJsResult<'a, JsValue>;
+
+fn marshal<'a, T: 'static + IntoHandle>(
+    cx: &mut TaskContext<'a>,
+    value: Value,
+) -> SafeJsResult<'a, JsValue> {
+    let value = value.downcast::<T>().unwrap();
+    Ok(value.into_handle(cx)?.upcast())
+}
+
+struct Run {
+    steps: VecDeque<Step>,
+    // Gets back onto the JS thread, and keeps the holder alive until then
+    slot: Arc<Slot>,
+    finish: Finish,
+    marshal: Marshal,
+}
+
+impl Run {
+    // Runs background steps from the JS thread until the next main step
+    fn background(self, ignore: Handle<JsFunction>, value: Value) {
+        run_async(ignore, move || {
+            self.work(value);
+            Ok::<_, Never>(())
+        });
+    }
+
+    fn work(mut self, mut value: Value) {
+        let outcome = loop {
+            // Nothing can be sent back after shutdown, so skip the rest
+            if self.slot.is_closed() {
+                return;
+            }
+            match self.steps.pop_front() {
+                Some(Step::Background(f)) => match f(value) {
+                    Ok(next) => value = next,
+                    Err(e) => break Err(e),
+                },
+                Some(Step::Main(f)) => {
+                    self.steps.push_front(Step::Main(f));
+                    break Ok(value);
+                }
+                None => break Ok(value),
+            }
+        };
+        let slot = self.slot.clone();
+        slot.schedule(move |cx, holder| match outcome {
+            Ok(value) => self.main(cx, holder, value),
+            Err(e) => {
+                let error = cx.try_catch(|cx| e(cx).map(|e| e.upcast()));
+                self.settle(cx, holder, Err(error.unwrap_or_else(|e| e)))
+            }
+        });
+    }
+
+    // Runs main steps on the JS thread until the next background step
+    fn main<'a>(mut self, cx: &mut TaskContext<'a>, holder: Handle<JsValue>, mut value: Value) {
+        loop {
+            match self.steps.pop_front() {
+                Some(Step::Main(f)) => match caught(cx, |cx| f(cx, value)) {
+                    Ok(next) => value = next,
+                    Err(error) => return self.settle(cx, holder, Err(error)),
+                },
+                Some(step) => {
+                    self.steps.push_front(step);
+                    return match JsFunction::new(cx, ignore) {
+                        Ok(ignore) => self.background(ignore, value),
+                        Err(_) => tracing::warn!("Failed to resume task"),
+                    };
+                }
+                None => {
+                    let marshal = self.marshal;
+                    let outcome = caught(cx, |cx| marshal(cx, value));
+                    return self.settle(cx, holder, outcome);
+                }
+            }
+        }
+    }
+
+    fn settle<'a>(
+        self,
+        cx: &mut TaskContext<'a>,
+        holder: Handle<JsValue>,
+        outcome: Result<Handle<'a, JsValue>, Handle<'a, JsValue>>,
+    ) {
+        let called = cx.try_catch(|cx| {
+            let holder: Handle<JsObject> = holder.downcast_or_throw(cx)?;
+            let undefined = cx.undefined();
+            let (f, args) = match (self.finish, outcome) {
+                (Finish::Callback, Ok(value)) => ("callback", vec![cx.null().upcast(), value]),
+                (Finish::Callback, Err(error)) => ("callback", vec![error]),
+                (Finish::Promise, Ok(value)) => ("resolve", vec![value]),
+                (Finish::Promise, Err(error)) => ("reject", vec![error]),
+            };
+            let f: Handle<JsFunction> = holder.get(cx, f)?;
+            f.call(cx, undefined, args)
+        });
+        if let Err(thrown) = called {
+            uncaught::report(cx, thrown, "Task callback");
+        }
+    }
+}
+
+// Runs f, catching anything it throws or fails with as a JS value
+fn caught<'a, T>(
+    cx: &mut TaskContext<'a>,
+    f: impl FnOnce(&mut TaskContext<'a>) -> SafeResult<T>,
+) -> Result<T, Handle<'a, JsValue>> {
+    let result = cx.try_catch(|cx| match f(cx) {
+        Ok(value) => Ok(Ok(value)),
+        Err(MaybeThrown::Thrown(t)) => Err(t),
+        Err(MaybeThrown::Unthrown(e)) => Ok(Err(e.into_error(cx)?.upcast())),
+    });
+    match result {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(error)) | Err(error) => Err(error),
+    }
+}
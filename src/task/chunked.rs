@@ -0,0 +1,73 @@
+use crate::channel::{self, Slot};
+use crate::errors::{IntoError, MaybeThrown};
+use crate::marshalling::IntoHandle;
+use crate::promise;
+use neon::prelude::*;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Marshals items into a JS array on the JS thread, a slice of time at a
+/// time, and returns a Promise of the array. Between slices the event loop
+/// gets on with other callbacks and I/O, eg: so a million row result
+/// doesn't stall the process for 200ms:
+///   task::chunked_main_thread(&mut cx, rows, Duration::from_millis(5))
+///
+/// Each slice marshals at least one item, then as many more as fit. If an
+/// item fails to marshal the Promise rejects and the rest are dropped.
+///
+/// Each slice schedules the next as it ends, so only one ever waits for
+/// the JS thread. That is as bounded as a BoundedTsfn with a max_queue of
+/// 1, without another thread to feed it.
+pub fn chunked_main_thread<'a, I>(
+    cx: &mut impl Context<'a>,
+    items: I,
+    slice: Duration,
+) -> NeonResult<Handle<'a, JsObject>>
+where
+    I: IntoIterator,
+    I::IntoIter: 'static + Send,
+    I::Item: IntoHandle,
+{
+    let (promise, holder) = promise::with_holder(cx)?;
+    let results = cx.empty_array();
+    holder.set(cx, "results", results)?;
+    let slot = Arc::new(channel::hold(cx, holder)?);
+    next_slice(slot, items.into_iter(), 0, slice);
+    Ok(promise)
+}
+
+fn next_slice<I>(slot: Arc<Slot>, mut items: I, mut done: u32, slice: Duration)
+where
+    I: 'static + Send + Iterator,
+    I::Item: IntoHandle,
+{
+    let held = slot.clone();
+    slot.schedule(move |cx, holder| {
+        let deadline = Instant::now() + slice;
+        // Ok(Some) once every item is in, Ok(None) when out of time
+        let step = cx.try_catch(|cx| {
+            let holder: Handle<JsObject> = holder.downcast_or_throw(cx)?;
+            let results: Handle<JsArray> = holder.get(cx, "results")?;
+            loop {
+                let item = match items.next() {
+                    Some(item) => item,
+                    None => return Ok(Ok(Some(results.upcast::<JsValue>()))),
+                };
+                match item.into_handle(cx) {
+                    Ok(value) => results.set(cx, done, value)?,
+                    Err(MaybeThrown::Thrown(t)) => return Err(t),
+                    Err(MaybeThrown::Unthrown(e)) => return Ok(Err(e.into_error(cx)?.upcast())),
+                };
+                done += 1;
+                if Instant::now() >= deadline {
+                    return Ok(Ok(None));
+                }
+            }
+        });
+        let _ = match step {
+            Ok(Ok(None)) => return next_slice(held, items, done, slice),
+            Ok(Ok(Some(results))) => promise::call(cx, holder, "resolve", results),
+            Ok(Err(error)) | Err(error) => promise::call(cx, holder, "reject", error),
+        };
+    });
+}
@@ -0,0 +1,112 @@
+//! One thread watches the deadlines of every task with a timeout, so a
+//! timeout costs an entry here rather than a thread of its own.
+
+use std::collections::BTreeMap;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+type Expire = Box<dyn FnOnce() + Send>;
+
+struct Pending {
+    // By when they expire, then by id to keep equal instants apart
+    expiring: BTreeMap<(Instant, u64), Expire>,
+    next_id: u64,
+    watching: bool,
+}
+
+static PENDING: Mutex<Pending> = Mutex::new(Pending {
+    expiring: BTreeMap::new(),
+    next_id: 0,
+    watching: false,
+});
+// Notified when a deadline is added ahead of the earliest
+static EARLIER: Condvar = Condvar::new();
+
+fn lock() -> MutexGuard<'static, Pending> {
+    PENDING.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Calls expire on the watching thread once timeout has passed, unless
+/// the Deadline is dropped first
+pub(crate) struct Deadline(Option<(Instant, u64)>);
+
+pub(crate) fn after(timeout: Duration, expire: impl 'static + Send + FnOnce()) -> Deadline {
+    // Too far off to ever come
+    let at = match Instant::now().checked_add(timeout) {
+        Some(at) => at,
+        None => return Deadline(None),
+    };
+    let mut pending = lock();
+    if !pending.watching {
+        pending.watching = true;
+        thread::spawn(watch);
+    }
+    let key = (at, pending.next_id);
+    pending.next_id += 1;
+    let earliest = match pending.expiring.keys().next() {
+        Some(first) => key < *first,
+        None => true,
+    };
+    pending.expiring.insert(key, Box::new(expire));
+    drop(pending);
+    if earliest {
+        EARLIER.notify_one();
+    }
+    Deadline(Some(key))
+}
+
+impl Drop for Deadline {
+    fn drop(&mut self) {
+        if let Some(key) = self.0 {
+            lock().expiring.remove(&key);
+        }
+    }
+}
+
+fn watch() {
+    let mut pending = lock();
+    loop {
+        let now = Instant::now();
+        pending = match pending.expiring.keys().next().copied() {
+            Some(key) if key.0 <= now => {
+                let expire = pending.expiring.remove(&key).unwrap();
+                // Not held while expiring, so that it may set deadlines
+                drop(pending);
+                expire();
+                lock()
+            }
+            Some((at, _)) => {
+                EARLIER
+                    .wait_timeout(pending, at - now)
+                    .unwrap_or_else(|e| e.into_inner())
+                    .0
+            }
+            None => EARLIER.wait(pending).unwrap_or_else(|e| e.into_inner()),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn expires_in_order_unless_dropped() {
+        let (sender, receiver) = mpsc::channel();
+        let expiring = |ms: u64| {
+            let sender = sender.clone();
+            after(Duration::from_millis(ms), move || sender.send(ms).unwrap())
+        };
+        let _late = expiring(40);
+        let _early = expiring(10);
+        drop(expiring(20));
+        let _never = after(Duration::MAX, || unreachable!());
+        let started = Instant::now();
+        assert_eq!(receiver.recv().unwrap(), 10);
+        assert_eq!(receiver.recv().unwrap(), 40);
+        assert!(started.elapsed() >= Duration::from_millis(30));
+        assert!(receiver.recv_timeout(Duration::from_millis(50)).is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use super::{call_back, run_async, Failure};
+use crate::channel::{self, Slot};
+use crate::errors::IntoError;
+use crate::marshalling::IntoHandle;
+use neon::prelude::*;
+use never::Never;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use tracing::trace_span;
+
+/// What join_all does when a function fails
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JoinMode {
+    /// Wait for every function, then pass the error of the first
+    /// (by position) to fail
+    WaitForAll,
+    /// Pass the first error as soon as it happens. The other functions
+    /// still run to completion, but their results are dropped.
+    FailFast,
+}
+
+struct Join<Ok, Err> {
+    results: Vec<Option<Ok>>,
+    remaining: usize,
+    error: Option<(usize, Failure<Err>)>,
+    settled: bool,
+}
+
+/// Runs the functions in parallel, then calls the callback once with an
+/// Array of their results in the same order as the functions.
+pub fn join_all<'a, F, Ok, Err>(
+    cx: &mut impl Context<'a>,
+    callback: Handle<JsFunction>,
+    fs: Vec<F>,
+    mode: JoinMode,
+) -> NeonResult<()>
+where
+    F: 'static + Send + FnOnce() -> Result<Ok, Err>,
+    Err: 'static + Send + IntoError,
+    Ok: 'static + Send + IntoHandle,
+{
+    let slot = Arc::new(channel::hold(cx, callback)?);
+    if fs.is_empty() {
+        settle(&slot, Ok::<Vec<Ok>, Failure<Err>>(Vec::new()));
+        return Ok(());
+    }
+
+    let join = Arc::new(Mutex::new(Join {
+        results: fs.iter().map(|_| None).collect(),
+        remaining: fs.len(),
+        error: None,
+        settled: false,
+    }));
+    let ignore = JsFunction::new(cx, channel::ignore)?;
+    for (i, f) in fs.into_iter().enumerate() {
+        let join = join.clone();
+        let slot = slot.clone();
+        let span = trace_span!("join_all", index = i);
+        run_async(ignore, move || {
+            let result = {
+                let _entered = span.enter();
+                match panic::catch_unwind(AssertUnwindSafe(f)) {
+                    Ok(result) => result.map_err(Failure::Failed),
+                    Err(_) => Err(Failure::Panicked),
+                }
+            };
+            complete(&join, &slot, i, result, mode);
+            Ok::<_, Never>(())
+        });
+    }
+    Ok(())
+}
+
+fn complete<Ok, Err>(
+    join: &Mutex<Join<Ok, Err>>,
+    slot: &Slot,
+    i: usize,
+    result: Result<Ok, Failure<Err>>,
+    mode: JoinMode,
+) where
+    Err: 'static + Send + IntoError,
+    Ok: 'static + Send + IntoHandle,
+{
+    let mut join = join.lock().unwrap_or_else(|e| e.into_inner());
+    join.remaining -= 1;
+    if join.settled {
+        return;
+    }
+    match result {
+        Ok(ok) => join.results[i] = Some(ok),
+        Err(e) if mode == JoinMode::FailFast => {
+            join.settled = true;
+            return settle(slot, Err::<Vec<Ok>, _>(e));
+        }
+        Err(e) => {
+            let earliest = match &join.error {
+                Some((first, _)) => i < *first,
+                None => true,
+            };
+            if earliest {
+                join.error = Some((i, e));
+            }
+        }
+    }
+    if join.remaining == 0 {
+        join.settled = true;
+        let result = match join.error.take() {
+            Some((_, e)) => Err(e),
+            // Every result is in, since none failed
+            None => Ok(join.results.drain(..).flatten().collect()),
+        };
+        settle(slot, result);
+    }
+}
+
+fn settle<Ok, Err>(slot: &Slot, result: Result<Vec<Ok>, Failure<Err>>)
+where
+    Err: 'static + Send + IntoError,
+    Ok: 'static + Send + IntoHandle,
+{
+    slot.schedule(move |cx, callback| call_back(cx, callback, result));
+}
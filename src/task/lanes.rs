@@ -0,0 +1,180 @@
+use super::{call_back, run_until, Failure, Job};
+use crate::channel;
+use crate::errors::{IntoError, SafeResult};
+use crate::instance;
+use crate::marshalling::{expect, FromHandle, IntoHandle, ObjectExt};
+use neon::prelude::*;
+use never::Never;
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::{Duration, Instant};
+use tracing::trace_span;
+
+crate::js_enum! {
+    /// Which lane of the pool a task waits in. High goes before Normal,
+    /// which goes before Low, eg: signing ahead of bulk processing.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum Priority {
+        High,
+        #[default]
+        Normal,
+        Low,
+    }
+}
+
+/// How long a task waits before it goes first regardless of its lane,
+/// so that a steady stream of High work can't starve Low work forever.
+pub const STARVATION_LIMIT: Duration = Duration::from_millis(500);
+
+/// Options for run_async_with. Eg: Options { priority: Priority::High, ..Default::default() }
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Options {
+    pub priority: Priority,
+    /// As with run_async_with_timeout. The time spent queued doesn't count.
+    pub timeout: Option<Duration>,
+}
+
+/// From { priority?, timeout? }, with the timeout in milliseconds
+impl FromHandle for Options {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        let object: Handle<JsObject> = expect(handle, cx, "object")?;
+        let priority: Option<Priority> = object.get_field(cx, "priority")?;
+        let timeout: Option<Duration> = object.get_field(cx, "timeout")?;
+        Ok(Self {
+            priority: priority.unwrap_or_default(),
+            timeout,
+        })
+    }
+}
+
+struct Queued {
+    job: Job,
+    since: Instant,
+}
+
+#[derive(Default)]
+pub(crate) struct Lanes {
+    // Indexed by Priority as usize
+    queues: [VecDeque<Queued>; 3],
+}
+
+impl Lanes {
+    fn push(&mut self, priority: Priority, job: Job, now: Instant) {
+        self.queues[priority as usize].push_back(Queued { job, since: now });
+    }
+
+    fn pop(&mut self, now: Instant) -> Option<Job> {
+        // The oldest overdue task goes first, then by lane
+        let overdue = self
+            .queues
+            .iter()
+            .enumerate()
+            .filter_map(|(lane, queue)| Some((queue.front()?.since, lane)))
+            .filter(|(since, _)| now.saturating_duration_since(*since) >= STARVATION_LIMIT)
+            .min();
+        let lane = match overdue {
+            Some((_, lane)) => lane,
+            None => self.queues.iter().position(|queue| !queue.is_empty())?,
+        };
+        self.queues[lane].pop_front().map(|queued| queued.job)
+    }
+}
+
+/// Like run_async, but the function waits in a lane by priority.
+///
+/// The legacy runtime has no way to reorder work already on the libuv pool,
+/// so each call queues its function here and puts a stand-in on the pool.
+/// Whichever stand-in starts first runs the most urgent function queued.
+/// Only functions run through here are ordered by priority. Anything else
+/// on the pool, eg: from run_async, still takes a thread in turn. Lanes are
+/// per instance of the addon, so each worker_thread has its own.
+pub fn run_async_with<'a, F, Ok, Err>(
+    cx: &mut impl Context<'a>,
+    callback: Handle<JsFunction>,
+    options: Options,
+    f: F,
+) -> NeonResult<()>
+where
+    F: 'static + Send + FnOnce() -> Result<Ok, Err>,
+    Err: 'static + Send + IntoError,
+    Ok: 'static + Send + IntoHandle,
+{
+    let span = trace_span!("run_async_with", priority = ?options.priority);
+    let slot = channel::hold(cx, callback)?;
+    let job: Job = Box::new(move || {
+        let _entered = span.enter();
+        if slot.is_closed() {
+            return;
+        }
+        if let Some(timeout) = options.timeout {
+            return run_until(slot, timeout, f);
+        }
+        let result = match panic::catch_unwind(AssertUnwindSafe(f)) {
+            Ok(result) => result.map_err(Failure::Failed),
+            Err(_) => Err(Failure::Panicked),
+        };
+        slot.schedule(move |cx, callback| call_back(cx, callback, result));
+    });
+
+    let instance = instance::current();
+    instance
+        .lanes
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(options.priority, job, Instant::now());
+
+    let ignore = JsFunction::new(cx, channel::ignore)?;
+    super::run_async(ignore, move || {
+        let job = instance
+            .lanes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .pop(Instant::now());
+        // There is a stand-in for every job, so there is always one here
+        if let Some(job) = job {
+            job();
+        }
+        Ok::<_, Never>(())
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    static RAN: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+    fn pops(lanes: &mut Lanes, now: Instant) -> Vec<u32> {
+        while let Some(job) = lanes.pop(now) {
+            job();
+        }
+        std::mem::take(&mut *RAN.lock().unwrap())
+    }
+
+    fn job(n: u32) -> Job {
+        Box::new(move || RAN.lock().unwrap().push(n))
+    }
+
+    #[test]
+    fn by_lane_then_age() {
+        let start = Instant::now();
+        let mut lanes = Lanes::default();
+        lanes.push(Priority::Low, job(1), start);
+        lanes.push(Priority::Normal, job(2), start);
+        lanes.push(Priority::High, job(3), start);
+        lanes.push(Priority::High, job(4), start);
+        assert_eq!(pops(&mut lanes, start), vec![3, 4, 2, 1]);
+
+        // Low has waited too long, so it goes ahead of High
+        lanes.push(Priority::Low, job(1), start);
+        let later = start + STARVATION_LIMIT;
+        lanes.push(Priority::High, job(2), later);
+        lanes.push(Priority::Normal, job(3), later);
+        assert_eq!(pops(&mut lanes, later), vec![1, 2, 3]);
+    }
+}
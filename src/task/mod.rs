@@ -0,0 +1,456 @@
+use crate::channel::{self, Slot};
+use crate::errors::{IntoError, MaybeThrown, SafeErr, SafeJsResult, Terminal, TimeoutError};
+use crate::instance::{self, Instance};
+use crate::keep_alive::{self, Refs};
+use crate::marshalling::IntoHandle;
+use crate::tsfn::{BoundedTsfn, Overflow, TsfnOptions};
+use crate::uncaught;
+use atomic_take::AtomicTake;
+use neon::prelude::*;
+use never::Never;
+use std::cell::RefCell;
+#[cfg(feature = "tokio")]
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+#[cfg(feature = "tokio")]
+use tracing::Instrument;
+use tracing::{trace_span, Span};
+
+mod builder;
+mod chunked;
+mod deadline;
+mod join;
+pub(crate) mod lanes;
+mod retry;
+pub use builder::{task, TaskBuilder};
+pub use chunked::chunked_main_thread;
+pub use join::{join_all, JoinMode};
+pub use lanes::{run_async_with, Options, Priority, STARVATION_LIMIT};
+pub use retry::{run_async_with_retry, Backoff, RetryError, RetryPolicy};
+
+thread_local! {
+    // Only the JS thread starts tasks, so each instance has its own
+    static REFS: RefCell<Refs> = const { RefCell::new(Refs::new()) };
+}
+
+/// refTasks(): holds Node open for the task pool until as many
+/// unrefTasks() calls, eg: for a service which starts tasks whenever a
+/// native event arrives. Tasks in flight hold it open regardless, since
+/// the legacy runtime can't unref them. Returns the count.
+pub fn js_ref(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    let count = keep_alive::local_ref(&REFS, &mut cx)?;
+    Ok(cx.number(count))
+}
+
+/// unrefTasks(): undoes a refTasks(). Returns the count.
+pub fn js_unref(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    let count = keep_alive::local_unref(&REFS);
+    Ok(cx.number(count))
+}
+
+struct TaskWrapper<F> {
+    f: AtomicTake<F>,
+    span: Span,
+}
+
+impl<F> TaskWrapper<F> {
+    pub fn new(f: F, span: Span) -> Self {
+        Self {
+            f: AtomicTake::new(f),
+            span,
+        }
+    }
+}
+
+impl<F, Ok, Err> Task for TaskWrapper<F>
+where
+    F: 'static + Send + FnOnce() -> Result<Ok, Err>,
+    Err: 'static + Send + IntoError,
+    Ok: 'static + Send + IntoHandle,
+    Result<Ok, Err>: Terminal<Handle = Ok::Handle>,
+{
+    type Output = Ok;
+    type Error = Err;
+    type JsEvent = <Ok as IntoHandle>::Handle;
+
+    fn perform(&self) -> Result<Self::Output, Self::Error> {
+        let _entered = self.span.enter();
+        let f = self.f.take().unwrap();
+        f()
+    }
+
+    fn complete(
+        self,
+        cx: TaskContext,
+        result: Result<Self::Output, Self::Error>,
+    ) -> JsResult<Self::JsEvent> {
+        let _entered = self.span.enter();
+        result.finish(cx)
+    }
+}
+
+/// Runs a function asynchronously then calls
+/// the callback with the result.
+pub fn run_async<F, Ok, Err>(callback: Handle<JsFunction>, f: F)
+where
+    F: 'static + Send + FnOnce() -> Result<Ok, Err>,
+    Err: 'static + Send + IntoError,
+    Ok: 'static + Send + IntoHandle,
+    Result<Ok, Err>: Terminal<Handle = Ok::Handle>,
+{
+    let span = trace_span!("run_async", output = std::any::type_name::<Ok>());
+    let task = TaskWrapper::new(f, span);
+    task.schedule(callback);
+}
+
+struct TwoPhase<F> {
+    f: AtomicTake<F>,
+    span: Span,
+}
+
+impl<F, I, G, V, Err> Task for TwoPhase<F>
+where
+    F: 'static + Send + FnOnce() -> Result<(I, G), Err>,
+    I: 'static + Send,
+    G: 'static + Send + for<'a> FnOnce(&mut TaskContext<'a>, I) -> SafeJsResult<'a, V>,
+    V: Value,
+    Err: 'static + Send + IntoError,
+{
+    type Output = (I, G);
+    type Error = Err;
+    type JsEvent = V;
+
+    fn perform(&self) -> Result<Self::Output, Self::Error> {
+        let _entered = self.span.enter();
+        let f = self.f.take().unwrap();
+        f()
+    }
+
+    fn complete(
+        self,
+        mut cx: TaskContext,
+        result: Result<Self::Output, Self::Error>,
+    ) -> JsResult<Self::JsEvent> {
+        let _entered = self.span.enter();
+        match result {
+            Ok((intermediate, g)) => match g(&mut cx, intermediate) {
+                Ok(value) => Ok(value),
+                Err(e) => e.finish(cx),
+            },
+            Err(e) => {
+                let e = e.into_error(&mut cx)?;
+                cx.throw(e)
+            }
+        }
+    }
+}
+
+/// Like run_async, for results which need JS to finish, eg: calling a
+/// formatter that JS passed in. The function returns an intermediate value
+/// and a second function, which is then called with it on the JS thread.
+/// What that returns (or throws) is passed to the callback.
+///
+/// Closures can't name the lifetime of the handle they return, so
+/// the second function is usually a fn item. Eg:
+///   fn format<'a>(cx: &mut TaskContext<'a>, n: u64) -> SafeJsResult<'a, JsValue>
+pub fn run_async_then_on_main<F, I, G, V, Err>(callback: Handle<JsFunction>, f: F)
+where
+    F: 'static + Send + FnOnce() -> Result<(I, G), Err>,
+    I: 'static + Send,
+    G: 'static + Send + for<'a> FnOnce(&mut TaskContext<'a>, I) -> SafeJsResult<'a, V>,
+    V: Value,
+    Err: 'static + Send + IntoError,
+{
+    let span = trace_span!(
+        "run_async_then_on_main",
+        intermediate = std::any::type_name::<I>()
+    );
+    let task = TwoPhase {
+        f: AtomicTake::new(f),
+        span,
+    };
+    task.schedule(callback);
+}
+
+pub(crate) enum Failure<Err> {
+    Failed(Err),
+    TimedOut(TimeoutError),
+    Panicked,
+}
+
+impl<Err: IntoError> IntoError for Failure<Err> {
+    fn into_error<'c>(&self, cx: &mut impl Context<'c>) -> JsResult<'c, JsError> {
+        match self {
+            Failure::Failed(e) => e.into_error(cx),
+            Failure::TimedOut(e) => e.into_error(cx),
+            Failure::Panicked => SafeErr::from("Task panicked").into_error(cx),
+        }
+    }
+
+    fn as_safe_err(&self) -> Option<&SafeErr> {
+        match self {
+            Failure::Failed(e) => e.as_safe_err(),
+            _ => None,
+        }
+    }
+}
+
+/// Like run_async, but calls the callback with a TimeoutError
+/// if the function has not completed within the timeout.
+///
+/// The function runs on the libuv pool like any other, and one thread
+/// shared by every timeout watches the time. A function that never
+/// returns still holds its pool thread, but JS is no longer left hanging.
+pub fn run_async_with_timeout<'a, F, Ok, Err>(
+    cx: &mut impl Context<'a>,
+    callback: Handle<JsFunction>,
+    timeout: Duration,
+    f: F,
+) -> NeonResult<()>
+where
+    F: 'static + Send + FnOnce() -> Result<Ok, Err>,
+    Err: 'static + Send + IntoError,
+    Ok: 'static + Send + IntoHandle,
+{
+    let span = trace_span!(
+        "run_async_with_timeout",
+        output = std::any::type_name::<Ok>()
+    );
+    let slot = channel::hold(cx, callback)?;
+    let ignore = JsFunction::new(cx, channel::ignore)?;
+    run_async(ignore, move || {
+        let _entered = span.enter();
+        if !slot.is_closed() {
+            run_until(slot, timeout, f);
+        }
+        Ok::<_, Never>(())
+    });
+    Ok(())
+}
+
+// Runs f, then calls back through slot with what it returned, or with a
+// TimeoutError as soon as timeout passes if that's first
+fn run_until<F, Ok, Err>(slot: Slot, timeout: Duration, f: F)
+where
+    F: FnOnce() -> Result<Ok, Err>,
+    Err: 'static + Send + IntoError,
+    Ok: 'static + Send + IntoHandle,
+{
+    // Whichever of the two takes it calls back
+    let slot = Arc::new(AtomicTake::new(slot));
+    let _deadline = {
+        let slot = slot.clone();
+        deadline::after(timeout, move || {
+            if let Some(slot) = slot.take() {
+                let result = Err::<Ok, _>(Failure::<Err>::TimedOut(TimeoutError { timeout }));
+                slot.schedule(move |cx, callback| call_back(cx, callback, result));
+            }
+        })
+    };
+    let result = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => result.map_err(Failure::Failed),
+        Err(_) => Err(Failure::Panicked),
+    };
+    if let Some(slot) = slot.take() {
+        slot.schedule(move |cx, callback| call_back(cx, callback, result));
+    }
+}
+
+/// Like run_async, for a future, eg: one awaiting what a JsCallback
+/// returns. It runs on the shared tokio runtime, so waiting on JS doesn't
+/// hold up a thread of the libuv pool. See runtime.
+#[cfg(feature = "tokio")]
+pub fn run_future<'a, Fut, Ok, Err>(
+    cx: &mut impl Context<'a>,
+    callback: Handle<JsFunction>,
+    future: Fut,
+) -> NeonResult<()>
+where
+    Fut: 'static + Send + Future<Output = Result<Ok, Err>>,
+    Err: 'static + Send + IntoError,
+    Ok: 'static + Send + IntoHandle,
+{
+    let span = trace_span!("run_future", output = std::any::type_name::<Ok>());
+    let slot = channel::hold(cx, callback)?;
+    let runtime = crate::runtime::get();
+    let run = runtime.spawn(future.instrument(span));
+    runtime.spawn(async move {
+        // Err only if the future panicked
+        let result = match run.await {
+            Ok(result) => result.map_err(Failure::Failed),
+            Err(_) => Err(Failure::Panicked),
+        };
+        slot.schedule(move |cx, callback| call_back(cx, callback, result));
+    });
+    Ok(())
+}
+
+pub(crate) type Job = Box<dyn FnOnce() + Send>;
+
+/// Like run_async, but functions with the same key run one at a time
+/// in the order they were submitted. Eg: to hand out nonces for an
+/// account while JS fires off transactions concurrently.
+///
+/// Keys are per instance of the addon, so a worker_thread using the same
+/// key as the main thread doesn't wait on it.
+///
+/// Functions with different keys still run in parallel. The legacy
+/// runtime can only schedule work on the libuv pool from the JS thread,
+/// so each key with pending work has a thread of its own instead,
+/// which exits once the queue for that key is empty. Functions still
+/// queued when the environment shuts down are skipped, and submitting
+/// any after throws, as for run_with_progress.
+pub fn run_async_serialized<'a, F, Ok, Err>(
+    cx: &mut impl Context<'a>,
+    key: impl Into<String>,
+    callback: Handle<JsFunction>,
+    f: F,
+) -> NeonResult<()>
+where
+    F: 'static + Send + FnOnce() -> Result<Ok, Err>,
+    Err: 'static + Send + IntoError,
+    Ok: 'static + Send + IntoHandle,
+{
+    let key = key.into();
+    let span = trace_span!("run_async_serialized", key = %key);
+    let slot = channel::hold(cx, callback)?;
+    let job: Job = Box::new(move || {
+        let _entered = span.enter();
+        // Jobs still queued at shutdown are cancelled
+        if slot.is_closed() {
+            return;
+        }
+        let result = match panic::catch_unwind(AssertUnwindSafe(f)) {
+            Ok(result) => result.map_err(Failure::Failed),
+            Err(_) => Err(Failure::Panicked),
+        };
+        slot.schedule(move |cx, callback| call_back(cx, callback, result));
+    });
+
+    let instance = instance::current();
+    let mut queues = instance
+        .serialized
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    if let Some(queue) = queues.get_mut(&key) {
+        queue.push_back(job);
+        return Ok(());
+    }
+    queues.insert(key.clone(), Default::default());
+    drop(queues);
+    thread::spawn(move || drain(&instance, key, job));
+    Ok(())
+}
+
+fn drain(instance: &Instance, key: String, mut job: Job) {
+    loop {
+        job();
+        let mut queues = instance
+            .serialized
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        match queues.get_mut(&key).and_then(|queue| queue.pop_front()) {
+            Some(next) => job = next,
+            None => {
+                queues.remove(&key);
+                return;
+            }
+        }
+    }
+}
+
+/// Sends intermediate values to the progress callback of run_with_progress.
+/// At most PROGRESS_QUEUE wait for JS at a time, and emit waits for room
+/// beyond that, so a task emitting in a tight loop can't swamp the event
+/// loop.
+pub struct Progress<P> {
+    calls: BoundedTsfn<P>,
+}
+
+pub const PROGRESS_QUEUE: usize = 1000;
+
+impl<P: 'static + Send + IntoHandle> Progress<P> {
+    pub fn emit(&self, value: P) {
+        // Fails only once JS has shut down, when nobody is listening
+        let _ = self.calls.call(value);
+    }
+}
+
+/// Like run_async, but the function may also emit values along the way
+/// which are passed to on_progress, eg: blocks synced so far.
+/// Every emitted value is delivered before the callback is called.
+///
+/// To emit on an EventEmitter instead pass emitter.emit.bind(emitter, "progress")
+pub fn run_with_progress<'a, F, P, Ok, Err>(
+    cx: &mut impl Context<'a>,
+    callback: Handle<JsFunction>,
+    on_progress: Handle<JsFunction>,
+    f: F,
+) -> NeonResult<()>
+where
+    F: 'static + Send + FnOnce(&Progress<P>) -> Result<Ok, Err>,
+    P: 'static + Send + IntoHandle,
+    Err: 'static + Send + IntoError,
+    Ok: 'static + Send + IntoHandle,
+{
+    let options = TsfnOptions {
+        max_queue: PROGRESS_QUEUE,
+        overflow: Overflow::Block,
+    };
+    let progress = Progress {
+        calls: BoundedTsfn::reported_as(cx, on_progress, options, "Progress callback")?,
+    };
+    let slot = channel::hold(cx, callback)?;
+
+    let ignore = JsFunction::new(cx, channel::ignore)?;
+    run_async(ignore, move || {
+        let result = match panic::catch_unwind(AssertUnwindSafe(|| f(&progress))) {
+            Ok(result) => result.map_err(Failure::Failed),
+            Err(_) => Err(Failure::Panicked),
+        };
+        // Calls on every Slot run in the order scheduled, and whatever
+        // was emitted has a drain scheduled already, so arrives first
+        slot.schedule(move |cx, callback| call_back(cx, callback, result));
+        Ok::<_, Never>(())
+    });
+    Ok(())
+}
+
+// Calls a node style callback with the result, as the legacy Task would
+fn call_back<'a, Ok: IntoHandle, Err: IntoError>(
+    cx: &mut TaskContext<'a>,
+    callback: Handle<'a, JsValue>,
+    result: Result<Ok, Err>,
+) {
+    let called = cx.try_catch(|cx| {
+        let callback: Handle<JsFunction> = callback.downcast_or_throw(cx)?;
+        let args = match cx.try_catch(|cx| callback_args(cx, result)) {
+            Ok(args) => args,
+            Err(error) => vec![error],
+        };
+        let this = cx.undefined();
+        callback.call(cx, this, args)
+    });
+    if let Err(thrown) = called {
+        uncaught::report(cx, thrown, "Task callback");
+    }
+}
+
+// The arguments for a node style callback, as the legacy Task would call it
+fn callback_args<'a, Ok: IntoHandle, Err: IntoError>(
+    cx: &mut TaskContext<'a>,
+    result: Result<Ok, Err>,
+) -> NeonResult<Vec<Handle<'a, JsValue>>> {
+    let error = match result {
+        Ok(ok) => match ok.into_handle(cx) {
+            Ok(value) => return Ok(vec![cx.null().upcast(), value.upcast()]),
+            Err(MaybeThrown::Thrown(t)) => return Err(t),
+            Err(MaybeThrown::Unthrown(e)) => e.into_error(cx)?,
+        },
+        Err(e) => e.into_error(cx)?,
+    };
+    Ok(vec![error.upcast()])
+}
@@ -0,0 +1,173 @@
+use super::run_async;
+use crate::errors::{IntoError, SafeResult};
+use crate::marshalling::{expect, FromHandle, IntoHandle, ObjectExt};
+use neon::prelude::*;
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+/// How long to wait before each retry
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backoff {
+    /// The same delay every time
+    Fixed(Duration),
+    /// Doubling from initial each time, up to max
+    Exponential { initial: Duration, max: Duration },
+}
+
+impl Backoff {
+    /// The delay before the given retry, counting from 0
+    pub fn delay(&self, retry: u32) -> Duration {
+        match *self {
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential { initial, max } => {
+                let factor = 1u32.checked_shl(retry).unwrap_or(u32::MAX);
+                initial.checked_mul(factor).unwrap_or(max).min(max)
+            }
+        }
+    }
+}
+
+/// Accepts a number of ms for Fixed, or { initial, max } in ms for Exponential
+impl FromHandle for Backoff {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        if handle.is_a::<JsNumber>() {
+            return Ok(Backoff::Fixed(Duration::from_handle(handle, cx)?));
+        }
+        let object: Handle<JsObject> = expect(handle, cx, "number or object")?;
+        Ok(Backoff::Exponential {
+            initial: object.get_field(cx, "initial")?,
+            max: object.get_field(cx, "max")?,
+        })
+    }
+}
+
+/// When and how often run_async_with_retry tries again. Only retry_if
+/// depends on the error type, and it retries every error unless replaced.
+pub struct RetryPolicy<E> {
+    /// Including the first, so 1 never retries
+    pub max_attempts: u32,
+    pub backoff: Backoff,
+    pub retry_if: fn(&E) -> bool,
+}
+
+fn always<E>(_: &E) -> bool {
+    true
+}
+
+impl<E> RetryPolicy<E> {
+    pub fn new(max_attempts: u32, backoff: Backoff) -> Self {
+        Self {
+            max_attempts,
+            backoff,
+            retry_if: always::<E>,
+        }
+    }
+}
+
+/// Accepts { maxAttempts, backoff }, where backoff may be left out to retry
+/// straight away. The JS side can't say which errors to retry, so retry_if
+/// is left retrying all of them.
+impl<E> FromHandle for RetryPolicy<E> {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        let object: Handle<JsObject> = expect(handle, cx, "object")?;
+        let max_attempts: u32 = object.get_field(cx, "maxAttempts")?;
+        if max_attempts == 0 {
+            return Err("Invalid field 'maxAttempts': Expected at least 1".into());
+        }
+        let backoff: Option<Backoff> = object.get_field(cx, "backoff")?;
+        Ok(Self::new(
+            max_attempts,
+            backoff.unwrap_or(Backoff::Fixed(Duration::ZERO)),
+        ))
+    }
+}
+
+/// The last error, once every attempt allowed has failed. In JS it is that
+/// error with the count prepended to its message and set as `attempts`.
+pub struct RetryError<E> {
+    pub attempts: u32,
+    pub error: E,
+}
+
+impl<E: fmt::Display> fmt::Display for RetryError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed after {} attempts: {}", self.attempts, self.error)
+    }
+}
+
+impl<E: IntoError> IntoError for RetryError<E> {
+    fn into_error<'c>(&self, cx: &mut impl Context<'c>) -> JsResult<'c, JsError> {
+        let error = self.error.into_error(cx)?;
+        let message: Handle<JsValue> = error.get(cx, "message")?;
+        let message = match message.downcast::<JsString>() {
+            Ok(message) => message.value(),
+            Err(_) => String::new(),
+        };
+        let message = cx.string(format!(
+            "Failed after {} attempts: {}",
+            self.attempts, message
+        ));
+        error.set(cx, "message", message)?;
+        let attempts = cx.number(self.attempts);
+        error.set(cx, "attempts", attempts)?;
+        Ok(error)
+    }
+}
+
+/// Like run_async, but calls f again when it fails, as allowed by the policy.
+/// The callback gets the first success, or a RetryError with the last error.
+///
+/// The backoff sleeps on the pool thread, so long delays tie it up.
+pub fn run_async_with_retry<F, Ok, Err>(
+    callback: Handle<JsFunction>,
+    policy: RetryPolicy<Err>,
+    mut f: F,
+) where
+    F: 'static + Send + FnMut() -> Result<Ok, Err>,
+    Err: 'static + Send + IntoError,
+    Ok: 'static + Send + IntoHandle,
+{
+    run_async(callback, move || {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            match f() {
+                Ok(value) => return Ok(value),
+                Err(error) if attempts >= policy.max_attempts || !(policy.retry_if)(&error) => {
+                    return Err(RetryError { attempts, error });
+                }
+                Err(_) => {
+                    tracing::debug!(attempts, "Retrying");
+                    thread::sleep(policy.backoff.delay(attempts - 1));
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays() {
+        let fixed = Backoff::Fixed(Duration::from_millis(5));
+        assert_eq!(fixed.delay(0), Duration::from_millis(5));
+        assert_eq!(fixed.delay(9), Duration::from_millis(5));
+
+        let exponential = Backoff::Exponential {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(1),
+        };
+        let delays: Vec<u128> = (0..6).map(|i| exponential.delay(i).as_millis()).collect();
+        assert_eq!(delays, vec![100, 200, 400, 800, 1000, 1000]);
+        assert_eq!(exponential.delay(200), Duration::from_secs(1));
+    }
+}
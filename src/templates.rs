@@ -0,0 +1,146 @@
+//! Many objects with the same keys, eg: { blockNumber, hash, timestamp }
+//! for every block in a range. js_object! makes each key string and walks
+//! the object through a shape transition per key, every time. A Template
+//! has its keys once, and fills in values by position:
+//!
+//!   let block = Template::new(&["blockNumber", "hash", "timestamp"]);
+//!   block.instantiate(cx, (number, hash, timestamp))
+//!
+//! Make each one once and keep it, eg: in a static OnceLock.
+//!
+//! Each environment keeps an object with the keys, and every object is
+//! made as a copy of it with Object.assign, so it has its final shape
+//! before Rust fills in the values. Nothing is compiled from a string, so
+//! this works where code generation is disallowed.
+
+use crate::errors::{SafeJsResult, SafeResult};
+use crate::registry::{self, IntoArgs};
+use neon::prelude::*;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+
+pub struct Template {
+    id: u32,
+    keys: Vec<String>,
+    proto: bool,
+}
+
+impl Template {
+    pub fn new(keys: &[&str]) -> Self {
+        Template {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            keys: keys.iter().map(|k| k.to_string()).collect(),
+            proto: keys.contains(&"__proto__"),
+        }
+    }
+
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// For making many objects at once, eg: a row at a time. This finds
+    /// the shape to copy once, where instantiate finds it every time.
+    pub fn factory<'a>(&self, cx: &mut impl Context<'a>) -> SafeResult<Factory<'a, '_>> {
+        let assign = registry::intrinsic(cx, "assign")?;
+        let define = registry::intrinsic(cx, "defineProperty")?;
+        let shapes = registry::table(cx, "neon-utils.templates")?;
+        let id = cx.number(self.id);
+        let existing: Handle<JsValue> = shapes.get(cx, id)?;
+        let shape = match existing.downcast::<JsObject>() {
+            Ok(shape) => shape,
+            Err(_) => {
+                let shape = JsObject::new(cx);
+                for key in &self.keys {
+                    let undefined = cx.undefined();
+                    define_property(cx, define, shape, key, undefined)?;
+                }
+                shapes.set(cx, id, shape)?;
+                shape
+            }
+        };
+        Ok(Factory {
+            template: self,
+            shape,
+            assign,
+            define,
+        })
+    }
+
+    /// An object with the keys in order, and values in the same order.
+    /// Errors unless there are as many values as keys.
+    pub fn instantiate<'a>(
+        &self,
+        cx: &mut impl Context<'a>,
+        values: impl IntoArgs,
+    ) -> SafeJsResult<'a, JsObject> {
+        self.factory(cx)?.instantiate(cx, values)
+    }
+}
+
+/// A Template ready to use in one environment
+pub struct Factory<'a, 't> {
+    template: &'t Template,
+    shape: Handle<'a, JsObject>,
+    assign: Handle<'a, JsFunction>,
+    define: Handle<'a, JsFunction>,
+}
+
+impl<'a> Factory<'a, '_> {
+    pub fn instantiate(
+        &self,
+        cx: &mut impl Context<'a>,
+        values: impl IntoArgs,
+    ) -> SafeJsResult<'a, JsObject> {
+        let values = values.into_args(cx)?;
+        let keys = &self.template.keys;
+        if values.len() != keys.len() {
+            return Err(format!(
+                "Expected {} values for the template, got {}",
+                keys.len(),
+                values.len()
+            )
+            .into());
+        }
+        let empty = JsObject::new(cx);
+        // Object.assign would set the prototype rather than copy __proto__,
+        // leaving it out of the key order, so those are filled in from empty
+        let js = if self.template.proto {
+            empty
+        } else {
+            let this = cx.undefined();
+            let copied = self
+                .assign
+                .call(cx, this, vec![empty.upcast(), self.shape.upcast()])?;
+            copied.downcast_or_throw(cx)?
+        };
+        for (key, value) in keys.iter().zip(values) {
+            if key == "__proto__" {
+                define_property(cx, self.define, js, key, value)?;
+            } else {
+                js.set(cx, key.as_str(), value)?;
+            }
+        }
+        Ok(js)
+    }
+}
+
+// As a plain property, as setting it would be for any key but __proto__
+fn define_property<'a>(
+    cx: &mut impl Context<'a>,
+    define: Handle<'a, JsFunction>,
+    target: Handle<'a, JsObject>,
+    key: &str,
+    value: Handle<'a, JsValue>,
+) -> NeonResult<()> {
+    let descriptor = JsObject::new(cx);
+    let yes = cx.boolean(true);
+    descriptor.set(cx, "value", value)?;
+    descriptor.set(cx, "writable", yes)?;
+    descriptor.set(cx, "enumerable", yes)?;
+    descriptor.set(cx, "configurable", yes)?;
+    let key = cx.string(key).upcast();
+    let this = cx.undefined();
+    define.call(cx, this, vec![target.upcast(), key, descriptor.upcast()])?;
+    Ok(())
+}
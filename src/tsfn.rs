@@ -0,0 +1,247 @@
+//! Calls a JS function from any thread, with a bound on how many calls
+//! may wait for the JS thread, for callers fast enough to swamp the event
+//! loop, eg: a price feed ticking thousands of times a second.
+//!
+//! The legacy runtime has no threadsafe functions, so this is built on the
+//! shared channel: calls queue here, and at most one drain of the queue is
+//! scheduled on the JS thread at a time. What happens when the queue is
+//! full is up to the Overflow policy.
+
+use crate::channel::{self, Slot};
+use crate::errors::{MaybeThrown, SafeResult};
+use crate::marshalling::{expect, FromHandle, IntoHandle, ObjectExt};
+use crate::uncaught;
+use neon::prelude::*;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, ThreadId};
+use std::time::Duration;
+
+crate::js_enum! {
+    /// What call does when the queue is full
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Overflow {
+        /// Waits for room, failing with TsfnError::WouldBlock on the JS thread, which makes it
+        Block,
+        /// Drops the oldest queued call, eg: for a value where only the latest matters
+        DropOldest,
+        /// Fails with TsfnError::Full
+        Error,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TsfnOptions {
+    pub max_queue: usize,
+    pub overflow: Overflow,
+}
+
+impl Default for TsfnOptions {
+    fn default() -> Self {
+        Self {
+            max_queue: 1000,
+            overflow: Overflow::Block,
+        }
+    }
+}
+
+/// From { maxQueue?, overflow? }, with overflow one of "block",
+/// "dropOldest" or "error"
+impl FromHandle for TsfnOptions {
+    fn from_handle<'a, V: Value>(handle: Handle<V>, cx: &mut impl Context<'a>) -> SafeResult<Self>
+    where
+        Self: Sized,
+    {
+        let object: Handle<JsObject> = expect(handle, cx, "object")?;
+        let defaults = Self::default();
+        let max_queue: Option<u32> = object.get_field(cx, "maxQueue")?;
+        if max_queue == Some(0) {
+            return Err("Invalid field 'maxQueue': Expected at least 1".into());
+        }
+        let overflow: Option<Overflow> = object.get_field(cx, "overflow")?;
+        Ok(Self {
+            max_queue: max_queue.map_or(defaults.max_queue, |n| n as usize),
+            overflow: overflow.unwrap_or(defaults.overflow),
+        })
+    }
+}
+
+/// Why a call wasn't queued. Either way the item is given back.
+pub enum TsfnError<T> {
+    /// The queue is full, with Overflow::Error
+    Full(T),
+    /// The queue is full, with Overflow::Block, on the JS thread
+    WouldBlock(T),
+    /// The environment has shut down
+    Closed(T),
+}
+
+impl<T> fmt::Display for TsfnError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TsfnError::Full(_) => write!(f, "Too many calls are waiting for JS"),
+            TsfnError::WouldBlock(_) => write!(
+                f,
+                "Too many calls are waiting for JS, which can't be waited on from the JS thread"
+            ),
+            TsfnError::Closed(_) => write!(f, "JS has shut down"),
+        }
+    }
+}
+
+impl<T> fmt::Debug for TsfnError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+struct Queue<T> {
+    state: Mutex<State<T>>,
+    room: Condvar,
+    options: TsfnOptions,
+    slot: Slot,
+    // Blocking there would wait for a drain which can't run until it returns
+    js_thread: ThreadId,
+    // What a thrown callback is reported as, see uncaught::report
+    source: &'static str,
+}
+
+struct State<T> {
+    items: VecDeque<T>,
+    // Whether a drain is scheduled on the JS thread and yet to run
+    draining: bool,
+    dropped: u64,
+}
+
+/// A JS function to call with each item, from any thread. Calls are made
+/// in the order they were queued. Node is kept running until every clone
+/// is dropped.
+pub struct BoundedTsfn<T> {
+    queue: Arc<Queue<T>>,
+}
+
+impl<T> Clone for BoundedTsfn<T> {
+    fn clone(&self) -> Self {
+        Self {
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+impl<T: 'static + Send + IntoHandle> BoundedTsfn<T> {
+    pub fn new<'a>(
+        cx: &mut impl Context<'a>,
+        callback: Handle<JsFunction>,
+        options: TsfnOptions,
+    ) -> NeonResult<Self> {
+        Self::reported_as(cx, callback, options, "Threadsafe function")
+    }
+
+    pub(crate) fn reported_as<'a>(
+        cx: &mut impl Context<'a>,
+        callback: Handle<JsFunction>,
+        options: TsfnOptions,
+        source: &'static str,
+    ) -> NeonResult<Self> {
+        Ok(Self {
+            queue: Arc::new(Queue {
+                state: Mutex::new(State {
+                    items: VecDeque::new(),
+                    draining: false,
+                    dropped: 0,
+                }),
+                room: Condvar::new(),
+                options,
+                slot: channel::hold(cx, callback)?,
+                js_thread: thread::current().id(),
+                source,
+            }),
+        })
+    }
+
+    /// Queues a call with item, as the Overflow policy allows
+    pub fn call(&self, item: T) -> Result<(), TsfnError<T>> {
+        let queue = &self.queue;
+        let mut state = queue.state.lock().unwrap_or_else(|e| e.into_inner());
+        while state.items.len() >= queue.options.max_queue {
+            if queue.slot.is_closed() {
+                return Err(TsfnError::Closed(item));
+            }
+            match queue.options.overflow {
+                Overflow::Block if thread::current().id() == queue.js_thread => {
+                    return Err(TsfnError::WouldBlock(item));
+                }
+                Overflow::Block => {
+                    // Wakes now and then to notice a shutdown, when no drain will come
+                    let (s, _) = queue
+                        .room
+                        .wait_timeout(state, Duration::from_millis(100))
+                        .unwrap_or_else(|e| e.into_inner());
+                    state = s;
+                }
+                Overflow::DropOldest => {
+                    state.items.pop_front();
+                    state.dropped += 1;
+                }
+                Overflow::Error => return Err(TsfnError::Full(item)),
+            }
+        }
+        if queue.slot.is_closed() {
+            return Err(TsfnError::Closed(item));
+        }
+        state.items.push_back(item);
+        if !state.draining {
+            state.draining = true;
+            let drain = self.queue.clone();
+            queue
+                .slot
+                .schedule(move |cx, callback| drain.drain(cx, callback));
+        }
+        Ok(())
+    }
+
+    /// How many calls are waiting for the JS thread
+    pub fn queued(&self) -> usize {
+        self.lock().items.len()
+    }
+
+    /// How many calls Overflow::DropOldest has dropped
+    pub fn dropped(&self) -> u64 {
+        self.lock().dropped
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State<T>> {
+        self.queue.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<T: 'static + Send + IntoHandle> Queue<T> {
+    // On the JS thread. Takes what is queued now, so that calls queued
+    // while these run schedule another drain rather than starve the loop.
+    fn drain<'a>(&self, cx: &mut TaskContext<'a>, callback: Handle<'a, JsValue>) {
+        let items = {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            state.draining = false;
+            std::mem::take(&mut state.items)
+        };
+        self.room.notify_all();
+        for item in items {
+            let this = cx.undefined();
+            let called = cx.try_catch(|cx| {
+                let callback: Handle<JsFunction> = callback.downcast_or_throw(cx)?;
+                match item.into_handle(cx) {
+                    Ok(item) => callback.call(cx, this, vec![item.upcast()]),
+                    Err(MaybeThrown::Thrown(t)) => Err(t),
+                    Err(MaybeThrown::Unthrown(e)) => {
+                        tracing::warn!(error = %e, "Dropped call");
+                        Ok(this.upcast())
+                    }
+                }
+            });
+            if let Err(thrown) = called {
+                uncaught::report(cx, thrown, self.source);
+            }
+        }
+    }
+}
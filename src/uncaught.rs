@@ -0,0 +1,132 @@
+//! What happens to an exception thrown by a JS callback that Rust called
+//! from another thread, eg: the callback of run_async or an events batch.
+//! There is no JS caller for it to go up to, so by default it is emitted
+//! as process 'uncaughtException' for the app's usual handling. An addon
+//! can have it passed to a hook instead, or only logged and dropped.
+//!
+//! The setting is per JS thread, since hooks are JS functions.
+
+use crate::errors::{SafeResult, Terminal};
+use crate::marshalling::FromHandle;
+use neon::prelude::*;
+use std::cell::Cell;
+
+crate::js_enum! {
+    /// What to do with an exception from a callback
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum CallbackErrors { Swallow, Hook, Uncaught }
+}
+
+thread_local! {
+    static POLICY: Cell<CallbackErrors> = const { Cell::new(CallbackErrors::Uncaught) };
+}
+
+// The legacy runtime can't root a handle from Rust, so the hook hangs off
+// the global, like the channel registry. It's kept as hook.call bound to
+// hook, which calls it but can't give it back, on a property which can't
+// be written, deleted or redefined, so no script can swap in its own.
+fn hook_key<'a>(cx: &mut impl Context<'a>) -> NeonResult<Handle<'a, JsValue>> {
+    let global = cx.global();
+    let symbol: Handle<JsObject> = global.get(cx, "Symbol")?;
+    let symbol_for: Handle<JsFunction> = symbol.get(cx, "for")?;
+    let name = cx.string("neon-utils.callbackErrorHook").upcast();
+    symbol_for.call(cx, symbol, vec![name])
+}
+
+/// Sets the policy for this JS thread. With Hook but no hook set,
+/// exceptions are only logged.
+pub fn set_policy(policy: CallbackErrors) {
+    POLICY.with(|p| p.set(policy));
+}
+
+/// Calls hook(error, source) with each exception, and sets the policy to Hook.
+/// source says where it came from, eg: "Task callback". A hook can only be
+/// set once for each JS thread, after which the policy can still change.
+pub fn set_hook<'a>(cx: &mut impl Context<'a>, hook: Handle<JsFunction>) -> NeonResult<()> {
+    let key = hook_key(cx)?;
+    let global = cx.global();
+    let existing: Handle<JsValue> = global.get(cx, key)?;
+    if !existing.is_a::<JsUndefined>() {
+        return cx.throw_error("A callback error hook is already set");
+    }
+    let function: Handle<JsObject> = global.get(cx, "Function")?;
+    let prototype: Handle<JsObject> = function.get(cx, "prototype")?;
+    let call: Handle<JsFunction> = prototype.get(cx, "call")?;
+    let bind: Handle<JsFunction> = prototype.get(cx, "bind")?;
+    let invoke = bind.call(cx, call, vec![hook.upcast()])?;
+    // Not writable, enumerable or configurable, as those default to false
+    let descriptor = cx.empty_object();
+    descriptor.set(cx, "value", invoke)?;
+    let object: Handle<JsObject> = global.get(cx, "Object")?;
+    let define: Handle<JsFunction> = object.get(cx, "defineProperty")?;
+    define.call(cx, object, vec![global.upcast(), key, descriptor.upcast()])?;
+    set_policy(CallbackErrors::Hook);
+    Ok(())
+}
+
+/// onCallbackError("swallow" | "uncaught") or onCallbackError(hook)
+pub fn js_on_callback_error(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let result = (|| -> SafeResult<()> {
+        let arg: Handle<JsValue> = cx.argument(0)?;
+        if let Ok(hook) = arg.downcast::<JsFunction>() {
+            set_hook(&mut cx, hook)?;
+            return Ok(());
+        }
+        match CallbackErrors::from_handle(arg, &mut cx)? {
+            CallbackErrors::Hook => Err("Pass the hook itself to set one".into()),
+            policy => {
+                set_policy(policy);
+                Ok(())
+            }
+        }
+    })();
+    result.finish(cx)
+}
+
+/// Deals with what a callback threw as the policy says. source says what
+/// threw, eg: "Progress callback". Whatever is done, it is logged.
+pub(crate) fn report<'a>(cx: &mut TaskContext<'a>, thrown: Handle<'a, JsValue>, source: &str) {
+    tracing::warn!("{} threw", source);
+    let handled = match POLICY.with(Cell::get) {
+        CallbackErrors::Swallow => return,
+        CallbackErrors::Hook => cx.try_catch(|cx| {
+            let key = hook_key(cx)?;
+            let global = cx.global();
+            let invoke: Handle<JsValue> = global.get(cx, key)?;
+            let invoke = match invoke.downcast::<JsFunction>() {
+                Ok(invoke) => invoke,
+                Err(_) => return Ok(false),
+            };
+            let source = cx.string(source).upcast();
+            let undefined = cx.undefined();
+            // As hook.call(undefined, thrown, source)
+            invoke.call(cx, undefined, vec![undefined.upcast(), thrown, source])?;
+            Ok(true)
+        }),
+        CallbackErrors::Uncaught => cx.try_catch(|cx| {
+            let global = cx.global();
+            let process: Handle<JsObject> = global.get(cx, "process")?;
+            let emit: Handle<JsFunction> = process.get(cx, "emit")?;
+            let event = cx.string("uncaughtException").upcast();
+            let origin = event;
+            // Whether there was a listener
+            let heard: Handle<JsValue> = emit.call(cx, process, vec![event, thrown, origin])?;
+            Ok(heard.downcast::<JsBoolean>().is_ok_and(|b| b.value()))
+        }),
+    };
+    match handled {
+        Ok(true) => {}
+        Ok(false) => tracing::warn!("Nothing was listening for what {} threw", source),
+        Err(_) => tracing::warn!("Handling what {} threw threw too", source),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uncaught_by_default() {
+        assert_eq!(POLICY.with(Cell::get), CallbackErrors::Uncaught);
+    }
+}
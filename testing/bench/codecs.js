@@ -0,0 +1,20 @@
+// Compares passing records to and from Rust as objects against as one
+// CBOR or MessagePack Buffer. Build the addon, then:
+//   node testing/bench/codecs.js target/debug/neon_utils_testing.node
+// Use a release build for numbers worth comparing.
+const addon = require(process.argv[2]);
+const { time } = require("./time");
+
+const FORMATS = ["objects", "cbor", "msgpack"];
+
+for (const n of [1000, 100000]) {
+  for (const format of FORMATS) {
+    const [encoded, toJs] = time(() => addon.encodeTrades(n, format));
+    const [, fromJs] = time(() => addon.sumTrades(encoded, format));
+    const size = Buffer.isBuffer(encoded) ? `${encoded.length} bytes` : "-";
+    console.log(
+      `${String(n).padStart(6)} ${format.padEnd(8)} to JS ${toJs.toFixed(2).padStart(8)}ms` +
+        `  from JS ${fromJs.toFixed(2).padStart(8)}ms  ${size}`
+    );
+  }
+}
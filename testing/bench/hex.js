@@ -0,0 +1,21 @@
+// Compares the codecs::hex backends on short inputs, like an address, and
+// long ones. Build the addon, then:
+//   node testing/bench/hex.js target/debug/neon_utils_testing.node
+// Use a release build for numbers worth comparing.
+const addon = require(process.argv[2]);
+const { time } = require("./time");
+
+const BACKENDS = ["fasterHex", "scalar", "table"];
+const TIMES = 10000;
+
+for (const size of [20, 32, 1024, 65536]) {
+  const bytes = Buffer.alloc(size, 0xa5);
+  for (const backend of BACKENDS) {
+    const [, ms] = time(() => addon.encodeHex(backend, bytes, TIMES));
+    const perByte = (ms * 1e6) / (TIMES * size);
+    console.log(
+      `${String(size).padStart(6)} bytes ${backend.padEnd(9)} ${ms.toFixed(2).padStart(8)}ms` +
+        `  ${perByte.toFixed(3).padStart(7)}ns/byte`
+    );
+  }
+}
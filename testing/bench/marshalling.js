@@ -0,0 +1,44 @@
+// Times the marshalling hot paths: hex, U256, Vec<T> at a few sizes and
+// objects from js_object! against a Template. Each case round trips in
+// Rust many times per call, so the JS call itself is noise. Build the
+// addon, then:
+//   node testing/bench/marshalling.js target/release/neon_utils_testing.node
+// Save a baseline before a change and compare against it after:
+//   node testing/bench/marshalling.js <addon> --save baseline.json
+//   node testing/bench/marshalling.js <addon> --compare baseline.json
+// Numbers are only comparable on the same machine and build profile.
+// core/benches/codecs.rs times the hex and U256 codecs alone, in criterion.
+const fs = require("fs");
+const addon = require(process.argv[2]);
+const { time } = require("./time");
+
+const flag = (name) => {
+  const i = process.argv.indexOf(name);
+  return i === -1 ? undefined : process.argv[i + 1];
+};
+
+const hex = (n) => "ab".repeat(n);
+const CASES = [
+  ["hex 32 bytes", () => addon.repeatHex(hex(32), 10000)],
+  ["hex 4096 bytes", () => addon.repeatHex(hex(4096), 1000)],
+  ["U256 decimal", () => addon.repeatU256("123456789012345678901234567890", 10000)],
+  ["U256 hex", () => addon.repeatU256("0x" + "f".repeat(64), 10000)],
+  ["Vec<f64> 10", () => addon.repeatNumbers(Array.from({ length: 10 }, (_, i) => i), 1000)],
+  ["Vec<f64> 10000", () => addon.repeatNumbers(Array.from({ length: 10000 }, (_, i) => i), 10)],
+  ["Vec<String> 1000", () => addon.repeatStrings(Array.from({ length: 1000 }, (_, i) => `s${i}`), 10)],
+  ["js_object! 10000", () => addon.makeBlocks(10000, "object")],
+  ["Template 10000", () => addon.makeBlocks(10000, "template")],
+];
+
+const baseline = flag("--compare") ? JSON.parse(fs.readFileSync(flag("--compare"), "utf8")) : {};
+const results = {};
+for (const [name, f] of CASES) {
+  const [, ms] = time(f);
+  results[name] = ms;
+  const before = baseline[name];
+  const change = before === undefined ? "" : `  ${(((ms - before) / before) * 100).toFixed(1).padStart(6)}%`;
+  console.log(`${name.padEnd(18)} ${ms.toFixed(2).padStart(9)}ms${change}`);
+}
+if (flag("--save")) {
+  fs.writeFileSync(flag("--save"), JSON.stringify(results, null, 2) + "\n");
+}
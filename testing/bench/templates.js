@@ -0,0 +1,13 @@
+// Compares making objects from a templates::Template against js_object!.
+// Build the addon, then:
+//   node testing/bench/templates.js target/debug/neon_utils_testing.node
+// Use a release build for numbers worth comparing.
+const addon = require(process.argv[2]);
+const { time } = require("./time");
+
+for (const n of [1000, 100000]) {
+  for (const how of ["object", "template"]) {
+    const [, ms] = time(() => addon.makeBlocks(n, how));
+    console.log(`${String(n).padStart(6)} ${how.padEnd(8)} ${ms.toFixed(2).padStart(8)}ms`);
+  }
+}
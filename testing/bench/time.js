@@ -0,0 +1,17 @@
+// Shared by the benches. Runs f once to warm up, so that the JIT is done
+// before timing, then a few more times, and returns the median in ms with
+// the last result.
+const RUNS = 7;
+
+function time(f) {
+  let result = f();
+  const runs = [];
+  for (let i = 0; i < RUNS; i++) {
+    const start = process.hrtime.bigint();
+    result = f();
+    runs.push(Number(process.hrtime.bigint() - start) / 1e6);
+  }
+  return [result, runs.sort((a, b) => a - b)[RUNS >> 1]];
+}
+
+module.exports = { time };
@@ -0,0 +1,1365 @@
+use neon::prelude::*;
+use neon_utils::abi;
+use neon_utils::callback::JsCallback;
+use neon_utils::config;
+use neon_utils::crypto::hash;
+use neon_utils::decls::{self, Decls};
+use neon_utils::errors::{
+    self, ErrorCode, SafeErr, SafeJsResult, SafeResult, SafeResultExt, Terminal,
+};
+use neon_utils::events;
+use neon_utils::instance;
+use neon_utils::json;
+use neon_utils::keep_alive;
+use neon_utils::logging;
+use neon_utils::marshalling::casing::Casing;
+use neon_utils::marshalling::codecs;
+use neon_utils::marshalling::limits::with_limits;
+use neon_utils::marshalling::{
+    marshal_iter, validators, AbsolutePath, Address, Arg, ArgsSpec, AsArrayBuffer, AsHexQuantity,
+    AsJsMap, AsJsObject, AsJsSet, AsNodeBuffer, AsUndefined, Bounded, ByteArray, Bytes, Checked,
+    Coerce, Decimal, ExistingPath, ExpectArgs, Freeze, FromHandle, HandleError, HexBytes,
+    IntoHandle, JsGenerator, LowS, MarshalLimits, Nanos, ObjectExt, PlainObject, PrefixedHex,
+    Secret, Signature, SigningKey, SkipIfNone, SymbolKey, TryIntoHandle, WellKnownSymbol,
+};
+use neon_utils::marshalling::{with_scope, ITEMS_PER_SCOPE};
+use neon_utils::memory;
+use neon_utils::module;
+use neon_utils::prelude::{
+    js_array, js_const_object, js_object, tagged_union, terminal_fn, try_js, NamedTuple,
+};
+use neon_utils::profiling;
+use neon_utils::promise::{self, JsOneshot};
+use neon_utils::proxy::{
+    self, neon_method, AsyncMutex, Proxy, ProxyMut, ReentryLock, ThisProxy, Wrapped,
+};
+use neon_utils::registry::{self, Instance};
+use neon_utils::singleton::LazyJsSingleton;
+use neon_utils::task;
+use neon_utils::templates::Template;
+use neon_utils::tsfn::{BoundedTsfn, TsfnOptions};
+use neon_utils::uncaught;
+use primitive_types::{H160, H256, U128, U256};
+use secp256k1::ecdsa::{RecoverableSignature, RecoveryId};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+/// Decodes the first argument and encodes it straight back,
+/// so that JS can check FromHandle and IntoHandle agree.
+fn round_trip<T: FromHandle + IntoHandle>(mut cx: FunctionContext) -> JsResult<T::Handle> {
+    let value: SafeResult<T> = cx.arg(0);
+    value.finish(cx)
+}
+
+/// round_trip, the number of times in the second argument, for bench/marshalling.js
+fn round_trip_repeated<T: FromHandle + IntoHandle>(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let result = (|| -> SafeJsResult<JsValue> {
+        let value = cx.argument::<JsValue>(0)?;
+        let times: u32 = cx.arg(1)?;
+        let mut out = cx.undefined().upcast();
+        for _ in 0..times {
+            out = T::from_handle(value, &mut cx)?
+                .into_handle(&mut cx)?
+                .upcast();
+        }
+        Ok(out)
+    })();
+    match result {
+        Ok(out) => Ok(out),
+        Err(e) => e.finish(cx),
+    }
+}
+
+fn to_array_buffer(mut cx: FunctionContext) -> JsResult<JsArrayBuffer> {
+    let bytes: SafeResult<HexBytes> = cx.arg(0);
+    bytes.map(|b| AsArrayBuffer(b.0)).finish(cx)
+}
+
+fn to_node_buffer(mut cx: FunctionContext) -> JsResult<JsBuffer> {
+    let bytes: SafeResult<HexBytes> = cx.arg(0);
+    bytes.map(|b| AsNodeBuffer(b.0)).finish(cx)
+}
+
+#[derive(FromHandle)]
+#[neon(ts)]
+struct Options {
+    name: String,
+    #[neon(default)]
+    retries: u64,
+    #[neon(default = 30.0)]
+    timeout_secs: f64,
+}
+
+#[derive(FromHandle)]
+#[neon(rename_all = "snake_case")]
+struct SnakeOptions {
+    chain_id: u64,
+    #[neon(rename = "RPC")]
+    rpc_url: String,
+}
+
+fn describe_snake_options(mut cx: FunctionContext) -> JsResult<JsString> {
+    let options: SafeResult<SnakeOptions> = cx.arg(0);
+    options
+        .map(|o| format!("{} {}", o.chain_id, o.rpc_url))
+        .finish(cx)
+}
+
+/// describeSnakeOptions, but only from a plain object
+fn describe_plain_options(mut cx: FunctionContext) -> JsResult<JsString> {
+    let options: SafeResult<PlainObject<SnakeOptions>> = cx.arg(0);
+    options
+        .map(|PlainObject(o)| format!("{} {}", o.chain_id, o.rpc_url))
+        .finish(cx)
+}
+
+#[derive(FromHandle, IntoHandle)]
+struct Profile {
+    display_name: String,
+    #[neon(skip_none)]
+    nickname: Option<String>,
+    avatar: Option<String>,
+    status: AsUndefined<String>,
+    bio: SkipIfNone<String>,
+}
+
+/// Reads a Profile and writes it back, which is only the same object if
+/// nothing is None
+fn echo_profile(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let profile: SafeJsResult<JsObject> = (|| {
+        let profile: Profile = cx.arg(0)?;
+        profile.into_handle(&mut cx)
+    })();
+    match profile {
+        Ok(profile) => Ok(profile),
+        Err(e) => e.finish(cx),
+    }
+}
+
+#[derive(FromHandle, IntoHandle)]
+#[neon(transparent, ts, validate = BlockNumber::check)]
+struct BlockNumber(u64);
+
+impl BlockNumber {
+    fn check(&self) -> Result<(), &'static str> {
+        if self.0 == 0 {
+            return Err("Block 0 is the genesis block, which has no parent");
+        }
+        Ok(())
+    }
+}
+
+#[derive(FromHandle, IntoHandle)]
+#[neon(transparent)]
+struct Labelled<T> {
+    label: T,
+}
+
+/// The block before, as a transparent newtype
+fn parent_block(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    let parent: SafeResult<BlockNumber> = cx.arg(0);
+    parent.map(|b| BlockNumber(b.0 - 1)).finish(cx)
+}
+
+/// A mix of types in one js_array!, with a pair inside
+fn mixed(mut cx: FunctionContext) -> JsResult<JsArray> {
+    let mixed: SafeJsResult<JsArray> = (|| {
+        let memo: Option<String> = cx.arg(0)?;
+        js_array![&mut cx => 1u32, "two", memo, (3u32, "four"), U256::from(5)]
+    })();
+    match mixed {
+        Ok(mixed) => Ok(mixed),
+        Err(e) => e.finish(cx),
+    }
+}
+
+enum Transfer {
+    Pending,
+    Moving,
+    Done(Vec<String>),
+}
+
+// Only settled transfers have a shape JS can use
+impl TryIntoHandle for Transfer {
+    type Handle = JsObject;
+    type Error = &'static str;
+    fn try_into_handle<'c>(
+        &self,
+        cx: &mut impl Context<'c>,
+    ) -> Result<Handle<'c, JsObject>, HandleError<Self::Error>> {
+        match self {
+            Transfer::Pending => {
+                let pending: SafeJsResult<JsObject> = js_object!(cx => { state: "pending" });
+                Ok(pending?)
+            }
+            Transfer::Moving => Err(HandleError::Invalid("Transfer is in flight")),
+            Transfer::Done(amount) => {
+                let amounts: Vec<U256> = amount
+                    .iter()
+                    .map(|a| U256::from_dec_str(a).map_err(|_| "Not an amount"))
+                    .collect::<Result<_, _>>()
+                    .map_err(|e| HandleError::Marshal(e.into()))?;
+                let done: SafeJsResult<JsObject> =
+                    js_object!(cx => { state: "done", amounts: amounts });
+                Ok(done?)
+            }
+        }
+    }
+}
+
+fn transfer(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let transfer = (|| -> SafeResult<Transfer> {
+        let state: String = cx.arg(0)?;
+        Ok(match state.as_str() {
+            "pending" => Transfer::Pending,
+            "moving" => Transfer::Moving,
+            _ => Transfer::Done(cx.arg(1)?),
+        })
+    })();
+    transfer.map(Checked).finish(cx)
+}
+
+/// Every way of writing None in one js_object!, with skip_none if asked
+fn nones(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let nones: SafeJsResult<JsObject> = (|| {
+        let skip_none: bool = cx.arg(0)?;
+        let none: Option<u32> = None;
+        if skip_none {
+            js_object!(&mut cx => #[js(skip_none)] {
+                option: none,
+                some: Some(1u32),
+                undefined: AsUndefined::<u32>(None),
+                skipped: SkipIfNone::<u32>(None),
+            })
+        } else {
+            js_object!(&mut cx => {
+                option: none,
+                some: Some(1u32),
+                undefined: AsUndefined::<u32>(None),
+                skipped: SkipIfNone::<u32>(None),
+            })
+        }
+    })();
+    match nones {
+        Ok(nones) => Ok(nones),
+        Err(e) => e.finish(cx),
+    }
+}
+
+fn fees(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let mut by_account = HashMap::new();
+    by_account.insert("base_fee".to_string(), 1u64);
+    by_account.insert("0xAbC".to_string(), 2);
+    let fees: SafeJsResult<JsObject> = (|| {
+        js_object!(&mut cx => #[js(casing = Casing::Camel)] {
+            max_fee: 3u64,
+            by_account: AsJsObject(by_account, Casing::Camel),
+        })
+    })();
+    match fees {
+        Ok(fees) => Ok(fees),
+        Err(e) => e.finish(cx),
+    }
+}
+
+/// Nested, optional and spread fields in one js_object!
+fn receipt(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let receipt: SafeJsResult<JsObject> = (|| {
+        let memo: Option<String> = cx.arg(0)?;
+        let mut extra = HashMap::new();
+        extra.insert("chain_id".to_string(), 1u64);
+        js_object!(&mut cx => #[js(casing = Casing::Camel)] {
+            block_number: 12u64,
+            "tx-hash": "0x01",
+            gas: { gas_used: 21000u64, gas_limit: 30000u64 },
+            memo?: memo,
+            ..AsJsObject(extra, Casing::Camel)
+        })
+    })();
+    match receipt {
+        Ok(receipt) => Ok(receipt),
+        Err(e) => e.finish(cx),
+    }
+}
+
+/// Made up gas estimate, as (gas_used, logs, status)
+fn estimate(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let gas: SafeResult<u64> = cx.arg(0);
+    gas.map(|gas| {
+        NamedTuple!(
+            #[js(casing = Casing::Camel)]
+            gas_used,
+            logs,
+            status = (gas, vec![gas], true)
+        )
+    })
+    .finish(cx)
+}
+
+type Counter = Proxy<AtomicU32>;
+
+declare_types! {
+    pub class JsCounter for Counter {
+        init(_cx) {
+            Ok(Proxy::new(AtomicU32::new(0)))
+        }
+
+        method increment(mut cx) {
+            let counter = cx.this_proxy::<JsCounter, _>();
+            counter
+                .map(|counter| counter.fetch_add(1, Ordering::SeqCst) + 1)
+                .finish(cx)
+                .map(|n| n.upcast())
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Tally {
+    total: AtomicU64,
+}
+
+#[neon_method]
+impl Tally {
+    #[neon_method]
+    fn add(&self, amount: u32, times: Option<u32>) -> SafeResult<u64> {
+        let amount = amount as u64 * times.unwrap_or(1) as u64;
+        Ok(self.total.fetch_add(amount, Ordering::SeqCst) + amount)
+    }
+
+    #[neon_method]
+    fn total(&self) -> Result<u64, &'static str> {
+        Ok(self.total.load(Ordering::SeqCst))
+    }
+}
+
+type SharedTally = Proxy<Tally>;
+
+declare_types! {
+    pub class JsTally for SharedTally {
+        init(_cx) {
+            Ok(Proxy::adopt_or(Tally::default))
+        }
+
+        method add(cx) { Tally::js_add(cx) }
+        method total(cx) { Tally::js_total(cx) }
+    }
+}
+
+impl Wrapped for Tally {
+    type Class = JsTally;
+}
+
+/// A Tally which starts from total
+fn tally_from(mut cx: FunctionContext) -> JsResult<JsTally> {
+    let total: SafeResult<u64> = cx.arg(0);
+    total
+        .map(|total| {
+            Proxy::new(Tally {
+                total: AtomicU64::new(total),
+            })
+        })
+        .finish(cx)
+}
+
+/// Names, with methods which call back into JS while holding the lock
+#[derive(Default)]
+pub struct Roster {
+    names: ReentryLock<Vec<String>>,
+}
+
+#[neon_method]
+impl Roster {
+    #[neon_method]
+    fn add(&self, name: String) -> SafeResult<u32> {
+        let mut names = self.names.write()?;
+        names.push(name);
+        Ok(names.len() as u32)
+    }
+
+    #[neon_method]
+    fn names(&self) -> SafeResult<Vec<String>> {
+        Ok(self.names.read()?.clone())
+    }
+
+    /// Calls f with each name, reading
+    fn js_each(mut cx: MethodContext<JsRoster>) -> JsResult<JsValue> {
+        let roster = Proxy::this(&mut cx);
+        let f = cx.argument::<JsFunction>(0)?;
+        let each = (|| -> SafeResult<()> {
+            let names = roster.names.read()?;
+            for name in names.iter() {
+                let name = cx.string(name).upcast();
+                let this = cx.undefined();
+                f.call(&mut cx, this, vec![name])?;
+            }
+            Ok(())
+        })();
+        each.finish(cx).map(|u| u.upcast())
+    }
+
+    /// Replaces each name with what f returns for it, writing
+    fn js_rename(mut cx: MethodContext<JsRoster>) -> JsResult<JsValue> {
+        let roster = Proxy::this(&mut cx);
+        let f = cx.argument::<JsFunction>(0)?;
+        let renamed = (|| -> SafeResult<()> {
+            let mut names = roster.names.write()?;
+            for name in names.iter_mut() {
+                let old = cx.string(&*name).upcast();
+                let this = cx.undefined();
+                let new = f.call(&mut cx, this, vec![old])?;
+                *name = String::from_handle(new, &mut cx)?;
+            }
+            Ok(())
+        })();
+        renamed.finish(cx).map(|u| u.upcast())
+    }
+}
+
+type SharedRoster = Proxy<Roster>;
+
+declare_types! {
+    pub class JsRoster for SharedRoster {
+        init(_cx) {
+            Ok(Proxy::new(Roster::default()))
+        }
+
+        method add(cx) { Roster::js_add(cx) }
+        method names(cx) { Roster::js_names(cx) }
+        method each(cx) { Roster::js_each(cx) }
+        method rename(cx) { Roster::js_rename(cx) }
+    }
+}
+
+static COLLECTED: AtomicU32 = AtomicU32::new(0);
+
+/// Counts the Counter as collected once JS is done with it
+fn watch_counter(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let counter = cx.argument::<JsCounter>(0)?;
+    proxy::on_collected(&mut cx, counter, || {
+        COLLECTED.fetch_add(1, Ordering::SeqCst);
+    })?;
+    Ok(cx.undefined())
+}
+
+fn collected_count(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    Ok(cx.number(COLLECTED.load(Ordering::SeqCst)))
+}
+
+/// Reads a Counter passed as this, eg: counterValue.call(counter)
+fn counter_value(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    let counter = Proxy::from_this::<JsCounter, _>(&mut cx);
+    counter
+        .map(|counter| counter.load(Ordering::SeqCst))
+        .finish(cx)
+}
+
+/// A record to compare passing as objects with passing as one Buffer
+#[derive(FromHandle, Serialize, Deserialize)]
+struct Trade {
+    id: u64,
+    price: f64,
+    side: String,
+    tags: Vec<String>,
+}
+
+impl IntoHandle for Trade {
+    type Handle = JsObject;
+    fn into_handle<'c>(&self, cx: &mut impl Context<'c>) -> SafeJsResult<'c, JsObject> {
+        js_object!(cx => {
+            id: self.id,
+            price: self.price,
+            side: self.side,
+            tags: self.tags,
+        })
+    }
+}
+
+/// Makes n trades as "objects", "cbor" or "msgpack"
+fn encode_trades(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let n = cx.argument::<JsNumber>(0)?.value() as u64;
+    let format = cx.argument::<JsString>(1)?.value();
+    let trades: Vec<Trade> = (0..n)
+        .map(|id| Trade {
+            id,
+            price: id as f64 / 4.0,
+            side: if id % 2 == 0 { "buy" } else { "sell" }.to_string(),
+            tags: vec!["spot".to_string(), format!("batch-{}", id / 100)],
+        })
+        .collect();
+    let encoded: SafeJsResult<JsValue> = match format.as_str() {
+        "objects" => trades.into_handle(&mut cx).map(|h| h.upcast()),
+        "cbor" => codecs::cbor::to_buffer(&mut cx, &trades).map(|h| h.upcast()),
+        "msgpack" => codecs::msgpack::to_buffer(&mut cx, &trades).map(|h| h.upcast()),
+        _ => Err("Unknown format".into()),
+    };
+    match encoded {
+        Ok(encoded) => Ok(encoded),
+        Err(e) => e.finish(cx),
+    }
+}
+
+/// Reads trades made by encodeTrades, and sums their ids
+fn sum_trades(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    let trades = cx.argument::<JsValue>(0)?;
+    let format = cx.argument::<JsString>(1)?.value();
+    let decoded: SafeResult<Vec<Trade>> = match format.as_str() {
+        "objects" => Vec::from_handle(trades, &mut cx),
+        "cbor" => codecs::cbor::from_buffer(trades, &mut cx),
+        "msgpack" => codecs::msgpack::from_buffer(trades, &mut cx),
+        _ => Err("Unknown format".into()),
+    };
+    decoded
+        .map(|trades| trades.iter().map(|t| t.id).sum::<u64>())
+        .finish(cx)
+}
+
+/// Hex encodes bytes `times` times with "fasterHex", "scalar" or "table",
+/// and returns the result. For comparing the backends from bench/hex.js
+fn encode_hex(mut cx: FunctionContext) -> JsResult<JsString> {
+    let encoded = (|| -> SafeResult<String> {
+        let backend = match String::from_handle(cx.argument::<JsValue>(0)?, &mut cx)?.as_str() {
+            "fasterHex" => codecs::hex::Backend::FasterHex,
+            "scalar" => codecs::hex::Backend::Scalar,
+            "table" => codecs::hex::Backend::Table,
+            _ => return Err("Unknown backend".into()),
+        };
+        let bytes = Bytes::from_handle(cx.argument::<JsValue>(1)?, &mut cx)?;
+        let times = u32::from_handle(cx.argument::<JsValue>(2)?, &mut cx)?;
+        let mut out = vec![0; bytes.len() * 2];
+        for _ in 0..times {
+            backend
+                .encode_to(&bytes, &mut out)
+                .map_err(|()| "Hex buffer too small")?;
+        }
+        Ok(String::from_utf8(out).unwrap_or_default())
+    })();
+    encoded.finish(cx)
+}
+
+static BLOCK: OnceLock<Template> = OnceLock::new();
+static ODD: OnceLock<Template> = OnceLock::new();
+
+/// Makes n blocks with a "template" or "object" by object, to compare them.
+/// The last block has a __proto__ key, which has to stay a key.
+fn make_blocks(mut cx: FunctionContext) -> JsResult<JsArray> {
+    let blocks = (|| -> SafeJsResult<JsArray> {
+        let n: u32 = cx.arg(0)?;
+        let how: String = cx.arg(1)?;
+        let block = BLOCK.get_or_init(|| Template::new(&["blockNumber", "hash", "timestamp"]));
+        let blocks = JsArray::new(&mut cx, n);
+        let factory = block.factory(&mut cx)?;
+        for i in 0..n {
+            let hash = [i as u8; 32];
+            let timestamp = 1_600_000_000 + i;
+            let block: Handle<JsObject> = match how.as_str() {
+                "template" => factory.instantiate(&mut cx, (i, hash, timestamp))?,
+                "object" => {
+                    let block: SafeJsResult<JsObject> = js_object!(&mut cx => {
+                        blockNumber: i,
+                        hash: hash,
+                        timestamp: timestamp,
+                    });
+                    block?
+                }
+                _ => return Err("Unknown way to make blocks".into()),
+            };
+            blocks.set(&mut cx, i, block)?;
+        }
+        let odd = ODD.get_or_init(|| Template::new(&["__proto__", "a\"b"]));
+        let last = odd.instantiate(&mut cx, (1u32, "x"))?;
+        blocks.set(&mut cx, n, last)?;
+        if odd.instantiate(&mut cx, (1u32,)).is_ok() {
+            return Err("Too few values were accepted".into());
+        }
+        Ok(blocks)
+    })();
+    match blocks {
+        Ok(blocks) => Ok(blocks),
+        Err(e) => e.finish(cx),
+    }
+}
+
+static PROVIDER: LazyJsSingleton<JsObject> = LazyJsSingleton::new();
+static PROVIDERS_MADE: AtomicU32 = AtomicU32::new(0);
+
+/// The same object every time, with an id counting the ones made in
+/// every environment
+fn shared_provider(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let provider = PROVIDER.get_or_init(&mut cx, |cx| {
+        let id = PROVIDERS_MADE.fetch_add(1, Ordering::SeqCst);
+        js_object!(cx => { id: id })
+    });
+    match provider {
+        Ok(provider) => Ok(provider),
+        Err(e) => e.finish(cx),
+    }
+}
+
+/// An object tagged with symbols
+fn pool(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let pool: SafeJsResult<JsObject> = (|| {
+        let pool: SafeJsResult<JsObject> = js_object!(&mut cx => {
+            [WellKnownSymbol::ToStringTag]: "Pool",
+            size: 3u64,
+        });
+        let pool = pool?;
+        let id = SymbolKey::For("neon-utils.test.id".to_string());
+        pool.set_symbol_property(&mut cx, id, 7u64)?;
+        Ok(pool)
+    })();
+    match pool {
+        Ok(pool) => Ok(pool),
+        Err(e) => e.finish(cx),
+    }
+}
+
+/// A Money instance, if JS registered the class
+fn money(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let cents: SafeResult<u64> = cx.arg(0);
+    cents
+        .map(|cents| Instance("Money", (cents, "USD")))
+        .finish(cx)
+}
+
+fn describe_options(mut cx: FunctionContext) -> JsResult<JsString> {
+    let options: SafeResult<Options> = cx.arg(0);
+    options
+        .map(|o| format!("{} {} {}", o.name, o.retries, o.timeout_secs))
+        .finish(cx)
+}
+
+trait Storage {
+    fn describe(&self) -> String;
+}
+
+#[derive(FromHandle)]
+struct FileStorage {
+    path: String,
+}
+
+#[derive(FromHandle)]
+struct S3Storage {
+    bucket: String,
+}
+
+impl Storage for FileStorage {
+    fn describe(&self) -> String {
+        format!("file {}", self.path)
+    }
+}
+
+impl Storage for S3Storage {
+    fn describe(&self) -> String {
+        format!("s3 {}", self.bucket)
+    }
+}
+
+tagged_union! {
+    Box<dyn Storage> {
+        "file" => FileStorage,
+        "s3" => S3Storage,
+    }
+}
+
+fn describe_storage(mut cx: FunctionContext) -> JsResult<JsString> {
+    let storage: SafeResult<Box<dyn Storage>> = cx.arg(0);
+    storage.map(|s| s.describe()).finish(cx)
+}
+
+const PRIMES: [u64; 4] = [2, 3, 5, 7];
+
+/// Marshals a borrowed slice without copying it into a Vec first
+fn primes(cx: FunctionContext) -> JsResult<JsArray> {
+    let primes: SafeResult<&[u64]> = Ok(&PRIMES[..]);
+    primes.finish(cx)
+}
+
+/// The squares of 0..n as an Array
+fn squares(mut cx: FunctionContext) -> JsResult<JsArray> {
+    let n = cx.argument::<JsNumber>(0)?.value() as u32;
+    match marshal_iter(&mut cx, (0..n).map(|i| i * i)) {
+        Ok(array) => Ok(array),
+        Err(e) => e.finish(cx),
+    }
+}
+
+/// n objects { i } built by hand, a scope at a time
+fn points(mut cx: FunctionContext) -> JsResult<JsArray> {
+    let n = cx.argument::<JsNumber>(0)?.value() as u32;
+    let arr = JsArray::new(&mut cx, n);
+    let mut start = 0;
+    while start < n {
+        let end = n.min(start + ITEMS_PER_SCOPE as u32);
+        with_scope(&mut cx, |cx| {
+            for i in start..end {
+                let point = cx.empty_object();
+                let value = cx.number(i);
+                point.set(cx, "i", value)?;
+                arr.set(cx, i, point)?;
+            }
+            NeonResult::Ok(())
+        })?;
+        start = end;
+    }
+    Ok(arr)
+}
+
+/// The squares of 0.. as an endless iterator
+fn lazy_squares(mut cx: FunctionContext) -> JsResult<JsGenerator> {
+    JsGenerator::lazy(&mut cx, (0u32..).map(|i| i * i))
+}
+
+fn to_hex_quantity(mut cx: FunctionContext) -> JsResult<JsString> {
+    let n: SafeResult<U256> = cx.arg(0);
+    n.map(AsHexQuantity).finish(cx)
+}
+
+fn to_map(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let map: SafeResult<HashMap<String, U256>> = cx.arg(0);
+    map.map(AsJsMap).finish(cx)
+}
+
+fn to_set(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let set: SafeResult<HashSet<U256>> = cx.arg(0);
+    set.map(AsJsSet).finish(cx)
+}
+
+/// Returns the second argument after a delay which is shorter for later
+/// values, so that results come back out of order unless serialized.
+fn run_serialized(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let key = cx.argument::<JsString>(0)?.value();
+    let value = cx.argument::<JsNumber>(1)?.value() as u64;
+    let callback = cx.argument::<JsFunction>(2)?;
+    task::run_async_serialized(&mut cx, key, callback, move || {
+        thread::sleep(Duration::from_millis(20u64.saturating_sub(value)));
+        Ok::<_, &'static str>(value)
+    })?;
+    Ok(cx.undefined())
+}
+
+/// Emits 1..=n as progress, then calls back with n
+fn count_to(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let n = cx.argument::<JsNumber>(0)?.value() as u32;
+    let on_progress = cx.argument::<JsFunction>(1)?;
+    let callback = cx.argument::<JsFunction>(2)?;
+    task::run_with_progress(&mut cx, callback, on_progress, move |progress| {
+        for i in 1..=n {
+            progress.emit(i);
+        }
+        Ok::<_, &'static str>(n)
+    })?;
+    Ok(cx.undefined())
+}
+
+/// Parses a number in the background, passes it through globalThis.chainStep
+/// on the JS thread, then adds one in the background again
+fn chain(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let s = cx.argument::<JsString>(0)?.value();
+    task::task(move || s.parse::<f64>().map_err(|_| "Not a number"))
+        .and_then_on_main(|cx, n| {
+            let global = cx.global();
+            let step: Handle<JsFunction> = global.get(cx, "chainStep")?;
+            let n = cx.number(n).upcast();
+            let n: Handle<JsNumber> = step.call(cx, global, vec![n])?.downcast_or_throw(cx)?;
+            Ok(n.value())
+        })
+        .and_then(|n| Ok::<_, &'static str>(n + 1.0))
+        .finish_as_promise(&mut cx)
+}
+
+fn step<'a>(cx: &mut TaskContext<'a>, n: f64) -> SafeJsResult<'a, JsValue> {
+    let global = cx.global();
+    let step: Handle<JsFunction> = global.get(cx, "chainStep")?;
+    let n = cx.number(n).upcast();
+    Ok(step.call(cx, global, vec![n])?)
+}
+
+/// Doubles a number in the background, then formats it with globalThis.chainStep
+fn double_then_step(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let s = cx.argument::<JsString>(0)?.value();
+    let callback = cx.argument::<JsFunction>(1)?;
+    task::run_async_then_on_main(callback, move || {
+        let n = s.parse::<f64>().map_err(|_| "Not a number")?;
+        Ok::<_, &'static str>((n * 2.0, step))
+    });
+    Ok(cx.undefined())
+}
+
+/// Squares 0..n in parallel, in reverse so that later ones finish first.
+/// Squaring fail_at fails.
+/// Fails until the given attempt, retrying as the policy allows
+fn flaky(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let succeed_on = cx.argument::<JsNumber>(0)?.value() as u32;
+    let policy: SafeResult<task::RetryPolicy<String>> = cx.arg(1);
+    let callback = cx.argument::<JsFunction>(2)?;
+    let policy = match policy {
+        Ok(policy) => policy,
+        Err(e) => return e.finish(cx),
+    };
+    let mut attempt = 0;
+    task::run_async_with_retry(callback, policy, move || {
+        attempt += 1;
+        if attempt < succeed_on {
+            Err(format!("Attempt {} failed", attempt))
+        } else {
+            Ok(attempt)
+        }
+    });
+    Ok(cx.undefined())
+}
+
+static NONCES: OnceLock<ProxyMut<u64>> = OnceLock::new();
+
+/// Hands out nonces with the lock held across a wait, eg: for a node's reply
+fn next_nonce(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let nonces = NONCES.get_or_init(|| Proxy::new(AsyncMutex::new(0)));
+    nonces.with_async(&mut cx, |mut nonce| async move {
+        let next = *nonce;
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        *nonce = next + 1;
+        Ok::<_, String>(next)
+    })
+}
+
+/// The squares of 0..n, marshalled in 1ms slices
+fn chunked_squares(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let n = match u64::from_handle(cx.argument::<JsValue>(0)?, &mut cx) {
+        Ok(n) => n,
+        Err(e) => return e.finish(cx),
+    };
+    let squares = (0..n).map(|i| i * i);
+    task::chunked_main_thread(&mut cx, squares, Duration::from_millis(1))
+}
+
+/// Decodes a secret key, and returns how it would print
+fn describe_secret_key(mut cx: FunctionContext) -> JsResult<JsString> {
+    let key: SafeResult<Secret<SigningKey>> = cx.arg(0);
+    key.map(|key| format!("{:?}", key)).finish(cx)
+}
+
+/// Asks fetch for each page from another thread, and sums what it returns
+fn sum_pages(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let fetch = cx.argument::<JsFunction>(0)?;
+    let pages = cx.argument::<JsNumber>(1)?.value() as u32;
+    let callback = cx.argument::<JsFunction>(2)?;
+    let fetch = JsCallback::new(&mut cx, fetch)?;
+    task::run_future(&mut cx, callback, async move {
+        let mut sum = 0;
+        for page in 0..pages {
+            let items = fetch.call_for::<Vec<u32>, _>((page,)).await?;
+            sum += items.iter().sum::<u32>();
+        }
+        Ok::<_, String>(sum)
+    })?;
+    Ok(cx.undefined())
+}
+
+/// A Promise settled from another thread as asked: "resolve", "reject",
+/// or "drop" to let the thread drop it unsettled
+fn settle_later(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let how: String = match cx.arg(0) {
+        Ok(how) => how,
+        Err(e) => return e.finish(cx),
+    };
+    let (promise, oneshot) = JsOneshot::new(&mut cx)?;
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(5));
+        match how.as_str() {
+            "resolve" => oneshot.resolve(vec![how.clone()]),
+            "reject" => oneshot.reject(format!("Rejected from {:?}", thread::current().id())),
+            _ => drop(oneshot),
+        }
+    });
+    Ok(promise)
+}
+
+fn double_when_resolved(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let value = cx.argument::<JsValue>(0)?;
+    let callback = cx.argument::<JsFunction>(1)?;
+    let resolved = promise::resolve_to_rust::<u32, _>(&mut cx, value)?;
+    task::run_future(
+        &mut cx,
+        callback,
+        async move { resolved.await.map(|n| n * 2) },
+    )?;
+    Ok(cx.undefined())
+}
+
+fn prioritized(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let label: SafeResult<String> = cx.arg(0);
+    let options: SafeResult<task::Options> = cx.arg(1);
+    let callback = cx.argument::<JsFunction>(2)?;
+    let (label, options) = match label.and_then(|l| Ok((l, options?))) {
+        Ok(ok) => ok,
+        Err(e) => return e.finish(cx),
+    };
+    task::run_async_with(&mut cx, callback, options, move || {
+        thread::sleep(Duration::from_millis(20));
+        Ok::<_, String>(label)
+    })?;
+    Ok(cx.undefined())
+}
+
+/// Calls back with ms after sleeping that long, unless timeout passes first
+fn sleep_within(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let ms = cx.argument::<JsNumber>(0)?.value() as u64;
+    let timeout = cx.argument::<JsNumber>(1)?.value() as u64;
+    let callback = cx.argument::<JsFunction>(2)?;
+    task::run_async_with_timeout(
+        &mut cx,
+        callback,
+        Duration::from_millis(timeout),
+        move || {
+            thread::sleep(Duration::from_millis(ms));
+            Ok::<_, String>(ms)
+        },
+    )?;
+    Ok(cx.undefined())
+}
+
+struct RateLimited(u32);
+
+impl std::fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Retry in {}s", self.0)
+    }
+}
+
+impl ErrorCode for RateLimited {
+    fn code(&self) -> &'static str {
+        "RATE_LIMITED"
+    }
+}
+
+fn rate_limited(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let context = cx.argument_opt(0).is_some();
+    let failed = || -> SafeResult<()> { Err(RateLimited(3))? };
+    let result = if context {
+        failed().context("Fetching blocks")
+    } else {
+        failed()
+    };
+    result.finish(cx)
+}
+
+/// rateLimited from a run_async task, which should throw the same
+fn rate_limited_async(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let context = cx.len() > 1;
+    let callback = cx.argument::<JsFunction>(cx.len() - 1)?;
+    task::run_async(callback, move || -> Result<(), SafeErr> {
+        let e = RateLimited(3).into_safe_err();
+        Err(if context {
+            e.with_context("Fetching blocks")
+        } else {
+            e
+        })
+    });
+    Ok(cx.undefined())
+}
+
+fn reset_marshal_stats(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    profiling::reset_marshal_stats();
+    Ok(cx.undefined())
+}
+
+fn reset_interned_stats(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    profiling::reset_interned_stats();
+    Ok(cx.undefined())
+}
+
+fn emit_batched(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let n = cx.argument::<JsNumber>(0)?.value() as u32;
+    let options: SafeResult<events::BatchOptions> = cx.arg(1);
+    let on_batch = cx.argument::<JsFunction>(2)?;
+    let options = match options {
+        Ok(options) => options,
+        Err(e) => return e.finish(cx),
+    };
+    let sender = events::batched(&mut cx, on_batch, options)?;
+    thread::spawn(move || {
+        for i in 0..n {
+            sender.send(i);
+        }
+    });
+    Ok(cx.undefined())
+}
+
+/// Calls back with 0..n from another thread as fast as it can. Resolves
+/// with [rejected, dropped] once every call has been made.
+fn tsfn_flood(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let n = cx.argument::<JsNumber>(0)?.value() as u32;
+    let options: SafeResult<TsfnOptions> = cx.arg(1);
+    let callback = cx.argument::<JsFunction>(2)?;
+    let options = match options {
+        Ok(options) => options,
+        Err(e) => return e.finish(cx),
+    };
+    let tsfn = BoundedTsfn::new(&mut cx, callback, options)?;
+    let (promise, oneshot) = JsOneshot::new(&mut cx)?;
+    thread::spawn(move || {
+        let rejected = (0..n).filter(|&i| tsfn.call(i).is_err()).count() as u32;
+        oneshot.resolve((rejected, tsfn.dropped() as u32));
+    });
+    Ok(promise)
+}
+
+/// As tsfnFlood, but calls from the JS thread. Returns the message of the
+/// first call which fails, if any.
+fn tsfn_flood_here(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let n = cx.argument::<JsNumber>(0)?.value() as u32;
+    let options: SafeResult<TsfnOptions> = cx.arg(1);
+    let callback = cx.argument::<JsFunction>(2)?;
+    let options = match options {
+        Ok(options) => options,
+        Err(e) => return e.finish(cx),
+    };
+    let tsfn = BoundedTsfn::new(&mut cx, callback, options)?;
+    match (0..n).find_map(|i| tsfn.call(i).err()) {
+        Some(e) => Ok(cx.string(e.to_string()).upcast()),
+        None => Ok(cx.undefined().upcast()),
+    }
+}
+
+fn join_squares(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let n = cx.argument::<JsNumber>(0)?.value() as u64;
+    let fail_at = cx.argument::<JsNumber>(1)?.value() as u64;
+    let mode = if cx.argument::<JsBoolean>(2)?.value() {
+        task::JoinMode::FailFast
+    } else {
+        task::JoinMode::WaitForAll
+    };
+    let callback = cx.argument::<JsFunction>(3)?;
+    let fs = (0..n)
+        .map(|i| {
+            move || {
+                thread::sleep(Duration::from_millis((n - i) * 5));
+                if i == fail_at {
+                    Err(format!("Failed {}", i))
+                } else {
+                    Ok(i * i)
+                }
+            }
+        })
+        .collect();
+    task::join_all(&mut cx, callback, fs, mode)?;
+    Ok(cx.undefined())
+}
+
+/// Round trips nested arrays of numbers under tight MarshalLimits
+fn round_trip_limited(mut cx: FunctionContext) -> JsResult<JsArray> {
+    let limits = MarshalLimits {
+        max_depth: 2,
+        max_items: 3,
+        max_bytes: 4,
+    };
+    let value: SafeResult<Vec<Vec<f64>>> = with_limits(limits, || cx.arg(0));
+    value.finish(cx)
+}
+
+terminal_fn! {
+    /// An amount of ETH as wei, and back as ETH with 6 places
+    fn to_wei(cx: FunctionContext) -> SafeResult<String> {
+        let eth: Decimal = cx.arg(0)?;
+        let wei = eth.with_decimals(18)?;
+        let shown = Decimal(wei.0 / U256::exp10(12), 6);
+        Ok(format!("{} wei is {} ETH", wei.0, shown))
+    }
+}
+
+terminal_fn! {
+    /// keccak256 and sha256 of the same bytes, each hashed on its own thread
+    fn hash_shared(cx: FunctionContext) -> SafeResult<Vec<HexBytes>> {
+        let data: Bytes = cx.arg(0)?;
+        let shared = data.clone();
+        let keccak = std::thread::spawn(move || hash::keccak256(&shared));
+        let sha = hash::sha256(&data);
+        let keccak = keccak.join().map_err(|_| "Hashing panicked")?;
+        Ok(vec![HexBytes(keccak.to_vec()), HexBytes(sha.to_vec())])
+    }
+}
+
+/// Half of an even number
+fn halve(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    let n: u32 = try_js!(cx, cx.arg(0));
+    let half = if n.is_multiple_of(2) {
+        Ok(n / 2)
+    } else {
+        Err(format!("Expected an even number, got {}", n))
+    };
+    let half = try_js!(cx, half);
+    Ok(cx.number(half))
+}
+
+/// Takes a string, an optional number, then options and a callback
+fn describe_call(mut cx: FunctionContext) -> JsResult<JsString> {
+    let described: SafeResult<String> = (|| {
+        let args = ArgsSpec::new(1)
+            .optional(1)
+            .options()
+            .callback()
+            .check(&mut cx)?;
+        let to: String = args.get(&mut cx, 0)?;
+        let amount: Option<u64> = args.get_opt(&mut cx, 1)?;
+        let options: Option<Options> = args.options(&mut cx)?;
+        let callback = args.callback(&mut cx)?;
+        Ok(format!(
+            "{} {:?} options={:?} callback={}",
+            to,
+            amount,
+            options.map(|o| o.name),
+            callback.is_some()
+        ))
+    })();
+    described.finish(cx)
+}
+
+/// Takes one or two arguments of any kind
+fn count_args(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    cx.expect_args(1..=2).map(|n| n as u32).finish(cx)
+}
+
+/// Decodes bytes as the given kind with max_bytes of 4
+fn round_trip_limited_bytes(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let limits = MarshalLimits {
+        max_bytes: 4,
+        ..MarshalLimits::UNLIMITED
+    };
+    let kind: SafeResult<String> = cx.arg(1);
+    let bytes = with_limits(limits, || -> SafeResult<Vec<u8>> {
+        Ok(match kind?.as_str() {
+            "hex" => cx.arg::<HexBytes>(0)?.0,
+            "array" => cx.arg::<ByteArray>(0)?.0,
+            _ => cx.arg::<AsArrayBuffer>(0)?.0,
+        })
+    });
+    match bytes.and_then(|bytes| AsArrayBuffer(bytes).into_handle(&mut cx)) {
+        Ok(buffer) => Ok(buffer.upcast()),
+        Err(e) => e.finish(cx),
+    }
+}
+
+#[derive(Default)]
+struct State {
+    init_runs: AtomicU32,
+}
+
+fn constants(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let constants: SafeJsResult<JsObject> = (|| {
+        js_const_object!(&mut cx => #[js(skip_none)] {
+            name: "test".to_string(),
+            chainIds: vec![vec![1u64], vec![5]],
+            memo: None::<String>,
+        })
+    })();
+    match constants {
+        Ok(constants) => Ok(constants),
+        Err(e) => e.finish(cx),
+    }
+}
+
+static OBSERVED: AtomicU32 = AtomicU32::new(0);
+
+fn count_observed(_: &SafeErr) {
+    OBSERVED.fetch_add(1, Ordering::SeqCst);
+}
+
+fn observed_errors(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    Ok(cx.number(OBSERVED.load(Ordering::SeqCst)))
+}
+
+fn instance_id(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    Ok(cx.number(instance::current().id() as f64))
+}
+
+static FINALIZED: AtomicU32 = AtomicU32::new(0);
+
+fn finalized(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    Ok(cx.number(FINALIZED.load(Ordering::SeqCst)))
+}
+
+fn init_runs(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    let runs = module::state::<State>().map_or(0, |s| s.init_runs.load(Ordering::SeqCst));
+    Ok(cx.number(runs))
+}
+
+register_module!(mut cx, {
+    module::init(State::default())
+        .step(|s| -> Result<(), &'static str> {
+            s.init_runs.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        })
+        // Steps run without the lock, so this doesn't deadlock
+        .step(|_| match module::state::<State>() {
+            Some(_) => Err("State was shared before its steps finished"),
+            None => Ok(()),
+        })
+        .finalizer(|_| {
+            FINALIZED.fetch_add(1, Ordering::SeqCst);
+        })
+        .run(&mut cx)?;
+
+    cx.export_function("roundTripString", round_trip::<String>)?;
+    cx.export_function("roundTripF64", round_trip::<f64>)?;
+    cx.export_function("roundTripBool", round_trip::<bool>)?;
+    cx.export_function("roundTripU64", round_trip::<u64>)?;
+    cx.export_function("roundTripCoercedU64", round_trip::<Coerce<u64>>)?;
+    cx.export_function("roundTripCoercedString", round_trip::<Coerce<String>>)?;
+    cx.export_function("roundTripU256", round_trip::<U256>)?;
+    cx.export_function("roundTripU128", round_trip::<U128>)?;
+    cx.export_function("roundTripPrimitiveU128", round_trip::<u128>)?;
+    cx.export_function("roundTripI128", round_trip::<i128>)?;
+    cx.export_function("roundTripH160", round_trip::<H160>)?;
+    cx.export_function("roundTripH256", round_trip::<H256>)?;
+    cx.export_function("roundTripBytes", round_trip::<HexBytes>)?;
+    cx.export_function("roundTripPrefixedHex", round_trip::<PrefixedHex>)?;
+    cx.export_function("roundTripVecU8", round_trip::<Vec<u8>>)?;
+    cx.export_function("roundTripVecU16", round_trip::<Vec<u16>>)?;
+    cx.export_function("roundTripAddress", round_trip::<[u8; 20]>)?;
+    cx.export_function("roundTripChecksumAddress", round_trip::<Address>)?;
+    cx.export_function("roundTripIpAddr", round_trip::<std::net::IpAddr>)?;
+    cx.export_function("roundTripIpv4Addr", round_trip::<std::net::Ipv4Addr>)?;
+    cx.export_function("roundTripSocketAddr", round_trip::<std::net::SocketAddr>)?;
+    cx.export_function("roundTripUuid", round_trip::<uuid::Uuid>)?;
+    cx.export_function("roundTripUrl", round_trip::<url::Url>)?;
+    cx.export_function("roundTripChar", round_trip::<char>)?;
+    cx.export_function("roundTripDuration", round_trip::<Duration>)?;
+    cx.export_function("roundTripNanos", round_trip::<Nanos>)?;
+    cx.export_function("roundTripDecimal", round_trip::<Decimal>)?;
+    cx.export_function("toWei", to_wei)?;
+    cx.export_function("halve", halve)?;
+    cx.export_function("hashShared", hash_shared)?;
+    cx.export_function("describeCall", describe_call)?;
+    cx.export_function("countArgs", count_args)?;
+    cx.export_function("roundTripNonZeroU32", round_trip::<std::num::NonZeroU32>)?;
+    cx.export_function("roundTripPort", round_trip::<Bounded<u16, 1, 65535>>)?;
+    cx.export_function("roundTripPercent", round_trip::<Bounded<f64, 0, 100>>)?;
+    cx.export_function("roundTripSignature", round_trip::<RecoverableSignature>)?;
+    cx.export_function("roundTripSplitSignature", round_trip::<Signature>)?;
+    cx.export_function("roundTripLowS", round_trip::<LowS>)?;
+    cx.export_function("roundTripPath", round_trip::<PathBuf>)?;
+    cx.export_function("roundTripVecDeque", round_trip::<VecDeque<f64>>)?;
+    cx.export_function("roundTripBTreeSet", round_trip::<BTreeSet<String>>)?;
+    cx.export_function("roundTripHashSet", round_trip::<HashSet<u32>>)?;
+    cx.export_function(
+        "roundTripSmallVec",
+        round_trip::<smallvec::SmallVec<[U256; 4]>>,
+    )?;
+    cx.export_function("roundTripAbsolutePath", round_trip::<AbsolutePath>)?;
+    cx.export_function("roundTripExistingPath", round_trip::<ExistingPath>)?;
+    cx.export_function("roundTripRecoveryId", round_trip::<RecoveryId>)?;
+    cx.export_function("roundTripBytes32", round_trip::<[u8; 32]>)?;
+    cx.export_function("roundTripOptionU256", round_trip::<Option<U256>>)?;
+    cx.export_function("roundTripVecU256", round_trip::<Vec<U256>>)?;
+    cx.export_function("toArrayBuffer", to_array_buffer)?;
+    cx.export_function("toNodeBuffer", to_node_buffer)?;
+    cx.export_function("describeOptions", describe_options)?;
+    cx.export_function("describeStorage", describe_storage)?;
+    cx.export_function("describeSnakeOptions", describe_snake_options)?;
+    cx.export_function("describePlainOptions", describe_plain_options)?;
+    cx.export_function("fees", fees)?;
+    cx.export_function("receipt", receipt)?;
+    cx.export_function("echoProfile", echo_profile)?;
+    cx.export_function("nones", nones)?;
+    cx.export_function("mixed", mixed)?;
+    cx.export_function("transfer", transfer)?;
+    cx.export_function("parentBlock", parent_block)?;
+    cx.export_function("roundTripLabelled", round_trip::<Labelled<String>>)?;
+    cx.export_function("estimate", estimate)?;
+    cx.export_function("squares", squares)?;
+    cx.export_function("points", points)?;
+    cx.export_function("primes", primes)?;
+    cx.export_function("lazySquares", lazy_squares)?;
+    cx.export_function("toHexQuantity", to_hex_quantity)?;
+    cx.export_function("toMap", to_map)?;
+    cx.export_function("toSet", to_set)?;
+    cx.export_function("initRuns", init_runs)?;
+    cx.export_function("finalized", finalized)?;
+    cx.export_function("instanceId", instance_id)?;
+    errors::set_error_observer(count_observed);
+    cx.export_function("observedErrors", observed_errors)?;
+    cx.export_function("callStats", profiling::js_call_stats)?;
+    cx.export_function("internedStats", profiling::js_interned_stats)?;
+    cx.export_function("getMarshalStats", profiling::js_marshal_stats)?;
+    cx.export_function("resetMarshalStats", reset_marshal_stats)?;
+    cx.export_function("resetInternedStats", reset_interned_stats)?;
+    let validators = validators::js_validators(&mut cx)?;
+    validators::add::<Bounded<u16, 1, 65535>>(&mut cx, validators, "port")?;
+    validators::add::<Options>(&mut cx, validators, "options")?;
+    cx.export_value("validators", validators)?;
+    cx.export_function("configure", config::js_configure)?;
+    cx.export_function("registerClass", registry::js_register_class)?;
+    cx.export_function("money", money)?;
+    cx.export_function("flaky", flaky)?;
+    cx.export_function("emitBatched", emit_batched)?;
+    cx.export_function("tsfnFlood", tsfn_flood)?;
+    cx.export_function("tsfnFloodHere", tsfn_flood_here)?;
+    cx.export_function("rateLimited", rate_limited)?;
+    cx.export_function("rateLimitedAsync", rate_limited_async)?;
+    cx.export_function("registerError", registry::js_register_error)?;
+    cx.export_function("prioritized", prioritized)?;
+    cx.export_function("sleepWithin", sleep_within)?;
+    cx.export_function("doubleWhenResolved", double_when_resolved)?;
+    cx.export_function("settleLater", settle_later)?;
+    cx.export_function("sumPages", sum_pages)?;
+    cx.export_function("chunkedSquares", chunked_squares)?;
+    cx.export_function("nextNonce", next_nonce)?;
+    cx.export_function("encodeTrades", encode_trades)?;
+    cx.export_function("sumTrades", sum_trades)?;
+    cx.export_function("encodeHex", encode_hex)?;
+    cx.export_function("makeBlocks", make_blocks)?;
+    cx.export_function("sharedProvider", shared_provider)?;
+    cx.export_function("ref", keep_alive::js_ref)?;
+    cx.export_function("adjustExternalMemory", memory::js_adjust_external_memory)?;
+    cx.export_function("unref", keep_alive::js_unref)?;
+    cx.export_function("refTasks", task::js_ref)?;
+    cx.export_function("unrefTasks", task::js_unref)?;
+    cx.export_function("repeatHex", round_trip_repeated::<HexBytes>)?;
+    cx.export_function("repeatU256", round_trip_repeated::<U256>)?;
+    cx.export_function("repeatNumbers", round_trip_repeated::<Vec<f64>>)?;
+    cx.export_function("repeatStrings", round_trip_repeated::<Vec<String>>)?;
+    cx.export_function("jsonStream", json::js_json_stream)?;
+    cx.export_class::<JsCounter>("Counter")?;
+    cx.export_class::<JsTally>("Tally")?;
+    cx.export_class::<JsRoster>("Roster")?;
+    cx.export_function("tallyFrom", tally_from)?;
+    cx.export_function("counterValue", counter_value)?;
+    cx.export_function("watchCounter", watch_counter)?;
+    cx.export_function("collectedCount", collected_count)?;
+    cx.export_function("pool", pool)?;
+    cx.export_function("constants", constants)?;
+    module::export_const(
+        &mut cx,
+        "CHAIN_IDS",
+        vec![vec![1u64], vec![5]],
+        Freeze::Deep,
+    )?;
+    module::export_const(&mut cx, "LIMITS", vec![vec![1u64]], Freeze::Shallow)?;
+    cx.export_function("setLogLevel", logging::js_set_log_level)?;
+    cx.export_function("setLogger", logging::js_set_logger)?;
+    cx.export_function("runSerialized", run_serialized)?;
+    cx.export_function("onCallbackError", uncaught::js_on_callback_error)?;
+    cx.export_function("countTo", count_to)?;
+    cx.export_function("chain", chain)?;
+    cx.export_function("joinSquares", join_squares)?;
+    cx.export_function("doubleThenStep", double_then_step)?;
+    cx.export_function("roundTripLimited", round_trip_limited)?;
+    cx.export_function("roundTripLimitedBytes", round_trip_limited_bytes)?;
+    cx.export_function("keccak256", hash::js_keccak256)?;
+    cx.export_function("encodeParams", abi::js_encode_params)?;
+    cx.export_function("decodeParams", abi::js_decode_params)?;
+    cx.export_function("sha256", hash::js_sha256)?;
+    cx.export_function("describeSecretKey", describe_secret_key)?;
+    let decls = Decls::new()
+        .function::<(Options,), String>("describeOptions", &["options"])
+        .function::<(Decimal,), String>("toWei", &["eth"])
+        .function::<(Nanos,), Nanos>("roundTripNanos", &["nanos"])
+        .function::<(BlockNumber,), BlockNumber>("parentBlock", &["block"]);
+    decls::export_schema(&mut cx, decls)?;
+    Ok(())
+});
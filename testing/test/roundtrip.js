@@ -0,0 +1,879 @@
+// Round trips random values through the addon given as the first argument.
+const assert = require("assert");
+const crypto = require("crypto");
+const addon = require(process.argv[2]);
+
+const ITERATIONS = 1000;
+const U256_MAX = (1n << 256n) - 1n;
+
+function randomU256() {
+  const bits = 1 + Math.floor(Math.random() * 256);
+  const bytes = crypto.randomBytes(32);
+  return BigInt("0x" + bytes.toString("hex")) >> BigInt(256 - bits);
+}
+
+function randomHex(length) {
+  return crypto.randomBytes(length).toString("hex");
+}
+
+// Small, so that the random values below keep evicting
+addon.configure({ internCapacity: 64 });
+for (let i = 0; i < ITERATIONS; i++) {
+  const s = crypto.randomBytes(16).toString("base64");
+  assert.strictEqual(addon.roundTripString(s), s);
+
+  const f = (Math.random() - 0.5) * Number.MAX_SAFE_INTEGER;
+  assert.strictEqual(addon.roundTripF64(f), f);
+
+  const u = Math.floor(Math.random() * Number.MAX_SAFE_INTEGER);
+  assert.strictEqual(addon.roundTripU64(u), u);
+
+  const big = randomU256();
+  assert.strictEqual(addon.roundTripU256(big.toString()), big.toString());
+  assert.strictEqual(addon.roundTripU256("0x" + big.toString(16)), big.toString());
+  const bigBytes = Buffer.from(big.toString(16).padStart(64, "0"), "hex");
+  assert.strictEqual(addon.roundTripU256(bigBytes), big.toString());
+  assert.strictEqual(addon.toHexQuantity(big.toString()), "0x" + big.toString(16));
+
+  const u128 = big >> 128n;
+  assert.strictEqual(addon.roundTripU128(u128.toString()), u128.toString());
+  assert.strictEqual(addon.roundTripPrimitiveU128(u128.toString()), u128.toString());
+  const i128 = Math.random() < 0.5 ? -u128 / 2n : u128 / 2n;
+  assert.strictEqual(addon.roundTripI128(i128.toString()), i128.toString());
+
+  const bytes = randomHex(Math.floor(Math.random() * 64));
+  assert.strictEqual(addon.roundTripBytes(bytes), bytes);
+  assert.strictEqual(addon.roundTripBytes(Buffer.from(bytes, "hex")), bytes);
+  const arrayBuffer = Uint8Array.from(Buffer.from(bytes, "hex")).buffer;
+  assert.strictEqual(addon.roundTripBytes(arrayBuffer), bytes);
+
+  const buffer = addon.toNodeBuffer(bytes);
+  assert.ok(Buffer.isBuffer(buffer));
+  assert.strictEqual(buffer.toString("hex"), bytes);
+  const fromArrayBuffer = addon.toArrayBuffer(bytes);
+  assert.ok(fromArrayBuffer instanceof ArrayBuffer);
+  assert.strictEqual(Buffer.from(fromArrayBuffer).toString("hex"), bytes);
+
+  const address = "0x" + randomHex(20);
+  assert.strictEqual(addon.roundTripAddress(address), address);
+
+  assert.strictEqual(addon.roundTripH160(address), address);
+  const addressBytes = Buffer.from(address.slice(2), "hex");
+  assert.strictEqual(addon.roundTripAddress(addressBytes), address);
+  assert.strictEqual(addon.roundTripH160(Uint8Array.from(addressBytes).buffer), address);
+  assert.strictEqual(addon.roundTripChecksumAddress(addressBytes).toLowerCase(), address);
+
+  const bytes32 = "0x" + randomHex(32);
+  assert.strictEqual(addon.roundTripBytes32(bytes32), bytes32);
+  assert.strictEqual(addon.roundTripH256(bytes32), bytes32);
+}
+addon.configure({ internCapacity: 0 });
+
+assert.strictEqual(addon.roundTripBool(true), true);
+assert.strictEqual(addon.roundTripBool(false), false);
+assert.strictEqual(addon.roundTripU256(U256_MAX.toString()), U256_MAX.toString());
+assert.strictEqual(addon.roundTripU256(12), "12");
+assert.strictEqual(addon.roundTripU256(Buffer.from([1, 0])), "256");
+assert.strictEqual(addon.roundTripIpAddr("0:0:0:0:0:0:0:1"), "::1");
+assert.strictEqual(addon.roundTripIpAddr("10.0.0.1"), "10.0.0.1");
+assert.throws(() => addon.roundTripIpv4Addr("::1"), /Invalid IPv4 address '::1'/);
+assert.throws(() => addon.roundTripIpv4Addr("010.0.0.1"), /Invalid IPv4 address/);
+assert.strictEqual(addon.roundTripSocketAddr("[::1]:8545"), "[::1]:8545");
+assert.strictEqual(addon.roundTripSocketAddr("127.0.0.1:8545"), "127.0.0.1:8545");
+assert.throws(() => addon.roundTripSocketAddr("localhost:8545"), /Invalid socket address/);
+assert.throws(() => addon.roundTripSocketAddr(8545), /Expected socket address, got number/);
+const UUID = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+assert.strictEqual(addon.roundTripUuid(UUID), UUID);
+assert.strictEqual(addon.roundTripUuid(UUID.toUpperCase()), UUID);
+assert.throws(() => addon.roundTripUuid(UUID.replace(/-/g, "")), /Invalid UUID '67e5.*': expected 36 characters, got 32/);
+assert.throws(() => addon.roundTripUuid(UUID.replace("6", "z")), /Invalid UUID 'z7e55044-.*': invalid character/);
+assert.throws(() => addon.roundTripUuid(1), /Expected UUID, got number/);
+assert.strictEqual(addon.roundTripUrl("HTTP://Example.com"), "http://example.com/");
+assert.strictEqual(addon.roundTripUrl("wss://rpc.example.com:8546/v1?key=a"), "wss://rpc.example.com:8546/v1?key=a");
+assert.throws(() => addon.roundTripUrl("/v1/rpc"), /Invalid URL '\/v1\/rpc': relative URL without a base/);
+assert.throws(() => addon.roundTripUrl("mailto:a@b.c"), /Invalid URL 'mailto:a@b.c': expected a host/);
+assert.throws(() => addon.roundTripUrl("http://exa mple.com"), /Invalid URL/);
+assert.strictEqual(addon.roundTripChar("😀"), "😀");
+assert.throws(() => addon.roundTripChar("ab"), /Expected a single character, got "ab"/);
+assert.throws(() => addon.roundTripChar(""), /Expected a single character/);
+assert.strictEqual(addon.roundTripNonZeroU32(7), 7);
+assert.throws(() => addon.roundTripNonZeroU32(0), /Got zero for NonZeroU32/);
+assert.strictEqual(addon.roundTripPort(8545), 8545);
+assert.throws(() => addon.roundTripPort(0), /Expected a number from 1 to 65535, got 0/);
+assert.throws(() => addon.roundTripPort(70000), /exceeding limits of u16/);
+assert.strictEqual(addon.roundTripPercent(12.5), 12.5);
+assert.throws(() => addon.roundTripPercent(101), /Expected a number from 0 to 100, got 101/);
+assert.throws(() => addon.roundTripU256(Buffer.alloc(33)), /Failed to parse U256/);
+assert.throws(() => addon.roundTripBytes32(Buffer.alloc(31)), /Failed to parse \[u8; N\]/);
+assert.strictEqual(addon.roundTripOptionU256(null), null);
+assert.strictEqual(addon.roundTripOptionU256(undefined), null);
+assert.deepStrictEqual(addon.roundTripVecU256(["1", 2]), ["1", "2"]);
+
+assert.throws(() => addon.roundTripU256((U256_MAX + 1n).toString()));
+assert.strictEqual(addon.roundTripI128(-12), "-12");
+assert.throws(() => addon.roundTripI128(NaN), /^Error: Got NaN for i128$/);
+assert.throws(() => addon.roundTripI128(-0.5), /^Error: Got fractional number for i128$/);
+assert.throws(() => addon.roundTripU128((1n << 128n).toString()));
+assert.throws(() => addon.roundTripPrimitiveU128("-1"));
+assert.throws(() => addon.roundTripU64(-1));
+assert.throws(() => addon.roundTripU64(0.5));
+assert.throws(() => addon.roundTripBytes("0g"));
+// One running total, however many times it's adjusted
+assert.strictEqual(addon.adjustExternalMemory(100), 100);
+assert.strictEqual(addon.adjustExternalMemory(2 ** 30), 2 ** 30 + 100);
+assert.strictEqual(addon.adjustExternalMemory(-(2 ** 30) - 40), 60);
+assert.strictEqual(addon.adjustExternalMemory(-1000), 0);
+// Long enough to be read a chunk at a time
+const longHex = randomHex(100000);
+assert.strictEqual(addon.roundTripBytes(longHex), longHex);
+assert.strictEqual(addon.roundTripBytes("0x" + longHex), longHex);
+assert.throws(() => addon.roundTripBytes(longHex + "0"), /Invalid hex/);
+assert.throws(() => addon.roundTripBytes(longHex.slice(1) + "g"), /Invalid hex/);
+assert.throws(() => addon.roundTripAddress("0x" + randomHex(19)));
+assert.throws(() => addon.roundTripBool("true"));
+assert.throws(() => addon.roundTripVecU256(["1", "x"]), {
+  message: "Invalid element at index 1: Failed to parse U256",
+});
+assert.strictEqual(addon.describeOptions({ name: "a" }), "a 0 30");
+assert.strictEqual(
+  addon.describeOptions({ name: "b", retries: 3, timeoutSecs: undefined }),
+  "b 3 30"
+);
+assert.strictEqual(addon.describeOptions({ name: "c", timeoutSecs: 1.5 }), "c 0 1.5");
+assert.throws(() => addon.describeOptions({ retries: 1 }), /Invalid field 'name'/);
+assert.throws(() => addon.describeOptions({ name: "d", retries: "x" }), /Invalid field 'retries'/);
+assert.throws(() => addon.describeOptions("e"), /^Error: Expected object, got string$/);
+assert.throws(() => addon.describeOptions(null), /^Error: Expected object, got null$/);
+assert.throws(() => addon.describeOptions({ retries: "x", timeoutSecs: "y" }), (e) => {
+  assert.deepStrictEqual(
+    e.errors.map((error) => error.field),
+    ["name", "retries", "timeoutSecs"]
+  );
+  assert.match(e.message, /^Invalid field 'name': .*; Invalid field 'retries': .*; Invalid field 'timeoutSecs'/);
+  return true;
+});
+assert.strictEqual(addon.describeSnakeOptions({ chain_id: 5, RPC: "http://rpc" }), "5 http://rpc");
+assert.throws(() => addon.describeSnakeOptions({ chainId: 5, RPC: "x" }), /Invalid field 'chain_id'/);
+// PlainObject reads own data properties of plain objects only
+assert.strictEqual(addon.describePlainOptions({ chain_id: 5, RPC: "http://rpc" }), "5 http://rpc");
+assert.strictEqual(addon.describePlainOptions(JSON.parse('{"chain_id":1,"RPC":"x"}')), "1 x");
+assert.strictEqual(
+  addon.describePlainOptions(Object.assign(Object.create(null), { chain_id: 2, RPC: "y" })),
+  "2 y"
+);
+class SnakeOptions {
+  constructor() {
+    this.chain_id = 5;
+    this.RPC = "x";
+  }
+}
+assert.strictEqual(addon.describeSnakeOptions(new SnakeOptions()), "5 x");
+assert.throws(() => addon.describePlainOptions(new SnakeOptions()), /^Error: Expected plain object, got class instance$/);
+assert.throws(() => addon.describePlainOptions([]), /^Error: Expected plain object, got array$/);
+assert.throws(() => addon.describePlainOptions("x"), /^Error: Expected plain object, got string$/);
+let getterRan = false;
+const withGetter = {
+  chain_id: 5,
+  get RPC() {
+    getterRan = true;
+    return "x";
+  },
+};
+assert.throws(() => addon.describePlainOptions(withGetter), /Invalid field 'RPC': Expected a value, got a getter/);
+assert.ok(!getterRan);
+Object.prototype.RPC = "polluted";
+try {
+  assert.strictEqual(addon.describeSnakeOptions({ chain_id: 5 }), "5 polluted");
+  assert.throws(() => addon.describePlainOptions({ chain_id: 5 }), /Invalid field 'RPC'/);
+} finally {
+  delete Object.prototype.RPC;
+}
+const setterOnly = { chain_id: 5, set RPC(_) {} };
+assert.throws(() => addon.describePlainOptions(setterOnly), /Invalid field 'RPC': Expected a value, got a setter/);
+// Descriptors are read by own property, and Object's functions are kept
+// before scripts can replace them
+Object.prototype.get = () => "polluted";
+const keys = Object.keys;
+Object.keys = () => ["chain_id"];
+try {
+  assert.strictEqual(addon.describePlainOptions({ chain_id: 5, RPC: "x" }), "5 x");
+  assert.throws(() => addon.describePlainOptions(withGetter), /got a getter/);
+} finally {
+  delete Object.prototype.get;
+  Object.keys = keys;
+}
+assert.deepStrictEqual(addon.fees(), { maxFee: 3, byAccount: { baseFee: 1, "0xAbC": 2 } });
+const receipt = { blockNumber: 12, "tx-hash": "0x01", gas: { gasUsed: 21000, gasLimit: 30000 }, chainId: 1 };
+assert.deepStrictEqual(addon.receipt(), receipt);
+assert.deepStrictEqual(addon.receipt("refund"), { ...receipt, memo: "refund" });
+const profile = { displayName: "Ada", nickname: "ada", avatar: "a.png", status: "away", bio: "Hi" };
+assert.deepStrictEqual(addon.echoProfile(profile), profile);
+const bare = addon.echoProfile({ displayName: "Ada" });
+assert.deepStrictEqual(Object.keys(bare), ["displayName", "avatar", "status"]);
+assert.strictEqual(bare.avatar, null);
+assert.strictEqual(bare.status, undefined);
+assert.deepStrictEqual(Object.entries(addon.nones(false)), [["option", null], ["some", 1], ["undefined", undefined]]);
+assert.deepStrictEqual(Object.entries(addon.nones(true)), [["some", 1], ["undefined", undefined]]);
+assert.deepStrictEqual(addon.mixed(), [1, "two", null, [3, "four"], "5"]);
+assert.deepStrictEqual(addon.mixed("memo")[2], "memo");
+assert.deepStrictEqual(addon.transfer("pending"), { state: "pending" });
+assert.deepStrictEqual(addon.transfer("done", ["1", "2"]), { state: "done", amounts: ["1", "2"] });
+assert.throws(() => addon.transfer("moving"), { message: "Transfer is in flight", code: "INVALID_STATE" });
+assert.throws(() => addon.transfer("done", ["x"]), (e) => e.message === "Not an amount" && e.code === undefined);
+assert.strictEqual(addon.parentBlock(12), 11);
+assert.throws(() => addon.parentBlock(0), /^Error: Block 0 is the genesis block, which has no parent$/);
+assert.throws(() => addon.parentBlock("x"), /Expected number/);
+assert.strictEqual(addon.roundTripLabelled("hi"), "hi");
+// Marshalling has already read the config by now
+assert.throws(() => addon.configure({ maxBytes: 1024 }), /already configured/);
+assert.throws(() => addon.configure({ logLevel: "loud" }), /Invalid log level 'loud'/);
+assert.throws(() => addon.money(5), /Class 'Money' is not registered/);
+class Money {
+  constructor(cents, currency) {
+    Object.assign(this, { cents, currency });
+  }
+}
+addon.registerClass("Money", Money);
+class RateLimitError extends Error {
+  constructor(message) {
+    super(message);
+    this.name = "RateLimitError";
+  }
+}
+assert.throws(() => addon.rateLimited(), { message: "Retry in 3s", code: "RATE_LIMITED", name: "Error" });
+addon.registerError("RATE_LIMITED", RateLimitError);
+assert.throws(() => addon.rateLimited(), (e) => {
+  assert.ok(e instanceof RateLimitError);
+  assert.strictEqual(e.message, "Retry in 3s");
+  assert.strictEqual(e.code, "RATE_LIMITED");
+  return true;
+});
+assert.throws(() => addon.rateLimited(true), (e) => {
+  assert.ok(e instanceof RateLimitError);
+  assert.strictEqual(e.message, "Fetching blocks: Retry in 3s");
+  assert.ok(e.cause instanceof RateLimitError);
+  return true;
+});
+const money = addon.money(5);
+assert.ok(money instanceof Money);
+assert.deepStrictEqual([money.cents, money.currency], [5, "USD"]);
+const counter = new addon.Counter();
+counter.increment();
+assert.strictEqual(counter.increment(), 2);
+assert.strictEqual(addon.counterValue.call(counter), 2);
+assert.throws(() => addon.counterValue.call({}), /Expected this to be JsCounter, got object/);
+for (const format of ["objects", "cbor", "msgpack"]) {
+  assert.strictEqual(addon.sumTrades(addon.encodeTrades(100, format), format), 4950);
+}
+assert.ok(addon.encodeTrades(3, "cbor") instanceof Buffer);
+const packed = addon.encodeTrades(1, "msgpack");
+assert.strictEqual(addon.sumTrades(packed.buffer.slice(packed.byteOffset, packed.byteOffset + packed.length), "msgpack"), 0);
+assert.throws(() => addon.sumTrades(Buffer.from([0xc1]), "msgpack"), /Invalid MessagePack at byte 1: Invalid marker/);
+assert.throws(() => addon.sumTrades(Buffer.from([0x01]), "cbor"), /Failed to decode CBOR: invalid type: integer `1`, expected a sequence/);
+assert.throws(() => addon.sumTrades("x", "cbor"), /Expected buffer, got string/);
+const hexInput = Buffer.from(Array.from({ length: 300 }, (_, i) => (i * 37) % 256));
+for (const backend of ["fasterHex", "scalar", "table"]) {
+  assert.strictEqual(addon.encodeHex(backend, hexInput, 2), hexInput.toString("hex"));
+}
+assert.throws(() => addon.encodeHex("simd", hexInput, 1), /Unknown backend/);
+
+const blocks = addon.makeBlocks(3, "template");
+assert.deepStrictEqual(blocks.slice(0, 3), addon.makeBlocks(3, "object").slice(0, 3));
+assert.deepStrictEqual(Object.keys(blocks[1]), ["blockNumber", "hash", "timestamp"]);
+assert.strictEqual(blocks[2].timestamp, 1600000002);
+assert.deepStrictEqual(Object.keys(blocks[3]), ["__proto__", 'a"b']);
+assert.strictEqual(Object.getPrototypeOf(blocks[3]), Object.prototype);
+assert.throws(() => addon.makeBlocks(1, "map"), /Unknown way to make blocks/);
+
+assert.strictEqual(addon.sharedProvider(), addon.sharedProvider());
+
+assert.strictEqual(addon.repeatHex("abcd", 3), "abcd");
+assert.strictEqual(addon.repeatU256("0x10", 2), "16");
+assert.deepStrictEqual(addon.repeatNumbers([1, 2.5], 2), [1, 2.5]);
+assert.deepStrictEqual(addon.repeatStrings(["a"], 0), undefined);
+assert.throws(() => addon.repeatU256("x", 1), /Failed to parse U256/);
+
+assert.strictEqual(addon.roundTripPath("a/b"), "a/b");
+assert.strictEqual(addon.roundTripPath(Buffer.from("x/y")), "x/y");
+assert.throws(() => addon.roundTripPath(Buffer.from([0x63, 0xe9])), /^Error: Path 'c\uFFFD' is not valid UTF-8, so can't be a JS string$/);
+assert.throws(() => addon.roundTripPath(""), /^Error: Invalid path: empty$/);
+assert.throws(() => addon.roundTripPath("a\0b"), /contains a NUL/);
+assert.throws(() => addon.roundTripPath(1), /Expected path string or Buffer, got number/);
+
+assert.deepStrictEqual(addon.roundTripVecDeque([3, 1, 2]), [3, 1, 2]);
+assert.deepStrictEqual(addon.roundTripBTreeSet(["b", "a", "b"]), ["a", "b"]);
+assert.deepStrictEqual(addon.roundTripBTreeSet(new Set(["c", "a"])), ["a", "c"]);
+assert.throws(() => addon.roundTripBTreeSet("ab"), /Expected Set or array, got string/);
+assert.deepStrictEqual(addon.roundTripHashSet(new Set([7, 7])), [7]);
+assert.deepStrictEqual(addon.roundTripHashSet([5, 6]).sort(), [5, 6]);
+assert.deepStrictEqual(addon.roundTripSmallVec(["1", "2"]), ["1", "2"]);
+assert.deepStrictEqual(addon.roundTripSmallVec(["1", "2", "3", "4", "5"]), ["1", "2", "3", "4", "5"]);
+assert.throws(() => addon.roundTripSmallVec(["1", {}]), /Invalid element at index 1/);
+assert.strictEqual(addon.roundTripAbsolutePath(__filename), __filename);
+assert.throws(() => addon.roundTripAbsolutePath("a/b"), /^Error: Expected an absolute path, got 'a\/b'$/);
+assert.strictEqual(addon.roundTripExistingPath(__dirname), __dirname);
+assert.throws(() => addon.roundTripExistingPath(__dirname + "/missing"), /^Error: Path '.*missing' does not exist$/);
+
+assert.strictEqual(addon.ref(), 1);
+assert.strictEqual(addon.ref(), 2);
+assert.strictEqual(addon.unref(), 1);
+// Only the hold keeps Node running until this timer, which doesn't
+let heldOpen = false;
+setTimeout(() => {
+  heldOpen = true;
+  assert.strictEqual(addon.unref(), 0);
+  assert.strictEqual(addon.unref(), 0);
+}, 50).unref();
+process.on("exit", () => assert(heldOpen, "ref() didn't hold Node open"));
+// The task pool's count is its own
+assert.strictEqual(addon.refTasks(), 1);
+assert.strictEqual(addon.unrefTasks(), 0);
+assert.strictEqual(addon.unrefTasks(), 0);
+
+const tally = new addon.Tally();
+// times is an Option, so it may be left out
+assert.strictEqual(tally.add(2), 2);
+assert.strictEqual(tally.add(3, 2), 8);
+assert.strictEqual(tally.total(), 8);
+assert.throws(() => tally.add("2"), /Expected number, got string/);
+assert.throws(() => tally.add(), /Expected number, got undefined/);
+const fromFactory = addon.tallyFrom(5);
+assert.ok(fromFactory instanceof addon.Tally);
+assert.strictEqual(fromFactory.add(1), 6);
+assert.strictEqual(new addon.Tally().total(), 0);
+// Calling back into a Roster from its own callback throws rather than hangs
+const roster = new addon.Roster();
+roster.add("a");
+roster.add("b");
+const seen = [];
+roster.each((name) => seen.push(name, roster.names().length));
+assert.deepStrictEqual(seen, ["a", 2, "b", 2]);
+const reentrant = (e) => e.code === "REENTRANT" && /^Can't change this while a call further up the stack/.test(e.message);
+assert.throws(() => roster.each(() => roster.add("c")), reentrant);
+assert.throws(() => roster.rename(() => roster.names()), (e) => e.code === "REENTRANT" && /^Can't read this/.test(e.message));
+roster.rename((name) => name.toUpperCase());
+assert.deepStrictEqual(roster.names(), ["A", "B"]);
+assert.strictEqual(roster.add("c"), 3);
+(async () => {
+  addon.watchCounter(new addon.Counter());
+  for (let i = 0; i < 10 && addon.collectedCount() === 0; i++) {
+    await new Promise(setImmediate);
+    global.gc();
+  }
+})();
+assert.strictEqual(addon.roundTripCoercedU64(" 42 "), 42);
+assert.strictEqual(addon.roundTripCoercedU64("0x2a"), 42);
+assert.throws(() => addon.roundTripCoercedU64("4 2"), /non-numeric string/);
+assert.throws(() => addon.roundTripU64("42"), /Expected number, got string/);
+assert.strictEqual(addon.roundTripCoercedString(1e21), "1e+21");
+assert.strictEqual(addon.roundTripCoercedString("a"), "a");
+const pool = addon.pool();
+assert.strictEqual(Object.prototype.toString.call(pool), "[object Pool]");
+assert.strictEqual(pool[Symbol.for("neon-utils.test.id")], 7);
+assert.strictEqual(pool.size, 3);
+const { gasUsed, logs, status } = addon.estimate(21000);
+assert.deepStrictEqual([gasUsed, logs, status], [21000, [21000], true]);
+assert.strictEqual(addon.describeStorage({ type: "file", path: "/tmp" }), "file /tmp");
+assert.strictEqual(addon.describeStorage({ type: "s3", bucket: "b" }), "s3 b");
+assert.throws(() => addon.describeStorage({ type: "s3" }), /Invalid type "s3": Invalid field 'bucket'/);
+assert.throws(
+  () => addon.describeStorage({ type: "ftp" }),
+  /Expected one of "file", "s3" for field 'type' but got "ftp"/
+);
+assert.throws(() => addon.roundTripBytes(() => {}), /^Error: Expected Buffer, ArrayBuffer or hex string, got function$/);
+assert.throws(() => addon.roundTripU256(true), /^Error: Expected Buffer, ArrayBuffer, string or number, got boolean$/);
+assert.throws(() => addon.roundTripBool(new Date()), /Expected boolean, got date/);
+assert.strictEqual(addon.roundTripDecimal("1.250"), "1.250");
+assert.strictEqual(addon.roundTripDecimal("1.5e-3"), "0.0015");
+assert.throws(() => addon.roundTripDecimal("-1"), /^Error: Invalid decimal '-1'$/);
+assert.strictEqual(addon.toWei("1.25"), "1250000000000000000 wei is 1.250000 ETH");
+assert.throws(() => addon.toWei("0.1234567890123456789"), /has more than 18 decimal places/);
+assert.throws(() => addon.toWei(), /^Error: Expected decimal string, got undefined$/);
+addon.configure({ marshalStats: true });
+addon.resetMarshalStats();
+assert.throws(() => addon.toWei(1), /Expected decimal string, got number/);
+for (let i = 0; i < 2; i++) assert.throws(() => tally.add("2"), /Expected number, got string/);
+assert.throws(() => addon.roundTripU64("1"), /Expected number, got string/);
+assert.deepStrictEqual(addon.getMarshalStats(), [
+  { expected: "number", got: "string", export: "Tally.add", count: 2 },
+  { expected: "number", got: "string", export: "", count: 1 },
+  { expected: "decimal string", got: "number", export: "to_wei", count: 1 },
+]);
+addon.configure({ marshalStats: false });
+assert.throws(() => addon.roundTripU64("1"), /Expected number, got string/);
+assert.strictEqual(addon.getMarshalStats()[1].count, 1);
+assert.strictEqual(addon.halve(10), 5);
+assert.throws(() => addon.halve(3), /^Error: Expected an even number, got 3$/);
+assert.throws(() => addon.halve("3"), /^Error: Expected number, got string$/);
+
+assert.strictEqual(addon.describeCall("a"), "a None options=None callback=false");
+assert.strictEqual(addon.describeCall("a", 2), "a Some(2) options=None callback=false");
+assert.strictEqual(addon.describeCall("a", { name: "x" }), 'a None options=Some("x") callback=false');
+assert.strictEqual(addon.describeCall("a", 2, { name: "x" }, () => {}), 'a Some(2) options=Some("x") callback=true');
+assert.strictEqual(addon.describeCall("a", undefined, () => {}), "a None options=None callback=true");
+assert.throws(() => addon.describeCall(), /^Error: Missing argument 1$/);
+assert.throws(() => addon.describeCall(undefined, 2), /^Error: Missing argument 1$/);
+assert.throws(() => addon.describeCall(1), /^Error: Invalid argument 1: Expected string, got number$/);
+assert.throws(() => addon.describeCall("a", 2, 3), /^Error: Expected 1 to 2 arguments, got 3$/);
+assert.throws(() => addon.describeCall("a", 2, {}), /^Error: Invalid options: /);
+assert.strictEqual(addon.countArgs(1, undefined), 2);
+assert.throws(() => addon.countArgs(), /^Error: Expected 1 to 2 arguments, got 0$/);
+
+assert.strictEqual(addon.__schema(), `// Generated by neon-utils. Do not edit.
+
+export interface Options {
+  name: string;
+  retries?: number;
+  timeoutSecs?: number;
+}
+
+export function describeOptions(options: Options): string;
+export function toWei(eth: string): string;
+export function roundTripNanos(nanos: bigint | number): bigint;
+export function parentBlock(block: number): number;
+`);
+assert.strictEqual(addon.roundTripDuration(1.5), 1.5);
+assert.strictEqual(addon.roundTripDuration(0.000001), 0.000001);
+assert.throws(() => addon.roundTripDuration(-1), /Got negative number for Duration/);
+assert.strictEqual(addon.roundTripNanos(123456789012345678901n), 123456789012345678901n);
+assert.strictEqual(addon.roundTripNanos(5), 5n);
+assert.throws(() => addon.roundTripNanos(-1n), /Got negative BigInt for Duration/);
+assert.throws(() => addon.roundTripNanos("5"), /^Error: Expected bigint or number, got string$/);
+assert.strictEqual(addon.describeSecretKey("0x" + "07".repeat(32)), "[REDACTED]");
+assert.throws(
+  () => addon.describeSecretKey("0x" + "7z".repeat(32)),
+  (e) => e.message === "Failed to parse secret key"
+);
+assert.deepStrictEqual(addon.validators.u256("0x1"), { ok: true, error: null });
+assert.deepStrictEqual(addon.validators.u256(true), {
+  ok: false,
+  error: "Expected Buffer, ArrayBuffer, string or number, got boolean",
+});
+assert.deepStrictEqual(addon.validators.address("0x12"), {
+  ok: false,
+  error: "Expected an address of 40 hex characters but got 2",
+});
+assert.deepStrictEqual(addon.validators.port(0), { ok: false, error: "Expected a number from 1 to 65535, got 0" });
+assert.deepStrictEqual(addon.validators.string(), { ok: false, error: "Expected string, got undefined" });
+assert.deepStrictEqual(
+  addon.validators.options({
+    get name() {
+      throw new Error("No name");
+    },
+  }),
+  { ok: false, error: "No name" }
+);
+assert.deepStrictEqual(addon.roundTripVecU8([0, 1, 255]), [0, 1, 255]);
+assert.throws(() => addon.roundTripVecU8([256]), /Invalid element at index 0: Got number exceeding limits of u8/);
+assert.deepStrictEqual(addon.roundTripVecU16([0, 65535]), [0, 65535]);
+assert.deepStrictEqual(addon.roundTripLimited([[1, 2, 3], []]), [[1, 2, 3], []]);
+assert.throws(() => addon.roundTripLimited([[1, 2, 3, 4]]), /Got 4 items, which exceeds the limit of 3/);
+assert.throws(() => addon.roundTripLimited([[[1]]]), /Expected number, got array/);
+assert.deepStrictEqual(Buffer.from(addon.roundTripLimitedBytes("01020304", "hex")), Buffer.from([1, 2, 3, 4]));
+assert.throws(() => addon.roundTripLimitedBytes("0102030405", "hex"), /Got 5 bytes, which exceeds the limit of 4/);
+assert.throws(() => addon.roundTripLimitedBytes(Buffer.alloc(5), "hex"), /Got 5 bytes, which exceeds the limit of 4/);
+assert.throws(() => addon.roundTripLimitedBytes([1, 2, 3, 4, 5], "array"), /Got 5 bytes, which exceeds the limit of 4/);
+assert.throws(() => addon.roundTripLimitedBytes(new ArrayBuffer(5), "buffer"), /Got 5 bytes, which exceeds the limit of 4/);
+assert.deepStrictEqual(Buffer.from(addon.roundTripLimitedBytes(new Uint8Array([9]), "buffer")), Buffer.from([9]));
+assert.throws(() => addon.roundTripLimitedBytes("09", "buffer"), /^Error: Expected Buffer or ArrayBuffer, got string$/);
+
+const bytes = Uint8Array.from([0, 1, 2, 3, 4, 5]);
+assert.strictEqual(addon.roundTripBytes(bytes.buffer), "000102030405");
+assert.strictEqual(addon.roundTripBytes(Buffer.from(bytes)), "000102030405");
+assert.strictEqual(addon.roundTripBytes(new Uint8Array(bytes.buffer, 2, 3)), "020304");
+assert.strictEqual(addon.roundTripBytes(new ArrayBuffer(0)), "");
+assert.strictEqual(addon.roundTripPrefixedHex(Buffer.from(bytes)), "0x000102030405");
+assert.strictEqual(addon.roundTripPrefixedHex("0x0001"), "0x0001");
+assert.strictEqual(addon.roundTripPrefixedHex(""), "0x");
+assert.strictEqual(addon.roundTripBytes(addon.roundTripPrefixedHex("abcd")), "abcd");
+const detached = new ArrayBuffer(4);
+const detachedView = new Uint8Array(detached, 1, 2);
+structuredClone(detached, { transfer: [detached] });
+if ("detached" in ArrayBuffer.prototype) {
+  assert.throws(() => addon.roundTripBytes(detached), /detached ArrayBuffer/);
+  assert.throws(() => addon.roundTripBytes(detachedView), /detached ArrayBuffer/);
+} else {
+  assert.strictEqual(addon.roundTripBytes(detached), "");
+  assert.strictEqual(addon.roundTripBytes(detachedView), "");
+}
+
+assert.deepStrictEqual(addon.squares(4), [0, 1, 4, 9]);
+// Past ITEMS_PER_SCOPE, items are written a scope at a time
+const manySquares = addon.squares(5000);
+assert.strictEqual(manySquares.length, 5000);
+assert.strictEqual(manySquares[4999], 4999 * 4999);
+const manyU16 = Array.from({ length: 3000 }, (_, i) => i);
+assert.deepStrictEqual(addon.roundTripVecU16(manyU16), manyU16);
+const manyPoints = addon.points(2500);
+assert.strictEqual(manyPoints.length, 2500);
+assert.deepStrictEqual(manyPoints[2049], { i: 2049 });
+assert.deepStrictEqual(addon.primes(), [2, 3, 5, 7]);
+const lazy = [];
+for (const square of addon.lazySquares()) {
+  if (square > 50) break;
+  lazy.push(square);
+}
+assert.deepStrictEqual(lazy, [0, 1, 4, 9, 16, 25, 36, 49]);
+const squares = addon.lazySquares();
+squares.return();
+assert.deepStrictEqual(squares.next(), { value: undefined, done: true });
+
+const r = "0x" + "11".repeat(32);
+const s = "0x" + "22".repeat(32);
+assert.strictEqual(addon.roundTripSignature(r + s.slice(2) + "00"), r + s.slice(2) + "1b");
+assert.deepStrictEqual(addon.roundTripSplitSignature({ r, s, v: 1 }), { r, s, v: 1 });
+assert.throws(() => addon.roundTripSplitSignature({ r, s, v: 2 }), /Invalid recovery id/);
+const der = "0x3044" + "0220" + r.slice(2) + "0220" + s.slice(2);
+assert.strictEqual(addon.roundTripSignature(der + "01"), r + s.slice(2) + "1c");
+assert.strictEqual(addon.roundTripSignature(Buffer.from(der.slice(2) + "1b", "hex")), r + s.slice(2) + "1b");
+assert.strictEqual(addon.roundTripSignature({ der, v: 28 }), r + s.slice(2) + "1c");
+assert.strictEqual(addon.roundTripSignature({ r, s, v: 0 }), r + s.slice(2) + "1b");
+assert.throws(() => addon.roundTripSignature(r + "00"), /Expected 65 bytes, or DER followed by v/);
+assert.throws(() => addon.roundTripSignature({ der: "0x3001", v: 0 }), /Invalid DER signature/);
+assert.throws(() => addon.roundTripSignature(1), /Expected Buffer, ArrayBuffer, hex string or \{ r, s, v \}, got number/);
+const curveOrder = 0xfffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141n;
+const highS = (curveOrder - BigInt(s)).toString(16).padStart(64, "0");
+assert.strictEqual(addon.roundTripLowS(r + highS + "00"), r + s.slice(2) + "1c");
+assert.strictEqual(addon.roundTripLowS(r + s.slice(2) + "00"), r + s.slice(2) + "1b");
+assert.strictEqual(addon.roundTripSignature(r + highS + "00"), r + highS + "1b");
+assert.strictEqual(addon.roundTripRecoveryId(28), 1);
+assert.throws(() => addon.roundTripRecoveryId(2), /Invalid recovery id/);
+
+const checksummed = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+assert.strictEqual(addon.roundTripChecksumAddress(checksummed.toLowerCase()), checksummed);
+assert.throws(() => addon.roundTripChecksumAddress(checksummed.replace("a", "A")), /Invalid checksum/);
+addon.configure({ internCapacity: 64 });
+addon.resetInternedStats();
+for (let i = 0; i < 3; i++) {
+  assert.strictEqual(addon.roundTripChecksumAddress(checksummed), checksummed);
+  assert.strictEqual(addon.roundTripH256("0x" + "ab".repeat(32)), "0x" + "ab".repeat(32));
+}
+assert.deepStrictEqual(addon.internedStats(), {
+  hex: { hits: 2, misses: 1, entries: 1 },
+  checksums: { hits: 2, misses: 1, entries: 1 },
+});
+addon.configure({ internCapacity: 0 });
+
+const map = addon.toMap(new Map([["a", "1"], ["b", 2]]));
+assert.ok(map instanceof Map);
+assert.deepStrictEqual([...map].sort(), [["a", "1"], ["b", "2"]]);
+assert.deepStrictEqual([...addon.toMap({ c: "3" })], [["c", "3"]]);
+assert.throws(() => addon.toMap(new Map([["a", "x"]])), {
+  message: "Invalid value at index 0: Failed to parse U256",
+});
+const set = addon.toSet(new Set(["1", "1", 2]));
+assert.ok(set instanceof Set);
+assert.deepStrictEqual([...set].sort(), ["1", "2"]);
+assert.strictEqual(addon.toSet(["3", "3"]).size, 1);
+
+assert.strictEqual(addon.initRuns(), 1);
+const statsBefore = addon.callStats();
+const observedBefore = addon.observedErrors();
+assert.strictEqual(addon.roundTripBool(true), true);
+assert.throws(() => addon.roundTripU256("x"));
+assert.deepStrictEqual(addon.callStats(), {
+  calls: statsBefore.calls + 2,
+  throws: statsBefore.throws + 1,
+});
+assert.strictEqual(addon.observedErrors(), observedBefore + 1);
+assert.deepStrictEqual(addon.CHAIN_IDS, [[1], [5]]);
+assert.ok(Object.isFrozen(addon.CHAIN_IDS) && Object.isFrozen(addon.CHAIN_IDS[1]));
+assert.ok(Object.isFrozen(addon.LIMITS) && !Object.isFrozen(addon.LIMITS[0]));
+const constants = addon.constants();
+assert.deepStrictEqual(constants, { name: "test", chainIds: [[1], [5]] });
+assert.ok(Object.isFrozen(constants) && Object.isFrozen(constants.chainIds[0]));
+assert.strictEqual(
+  addon.keccak256(Buffer.from("")),
+  "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+);
+const payload = crypto.randomBytes(100);
+assert.strictEqual(
+  addon.sha256(payload),
+  crypto.createHash("sha256").update(payload).digest("hex")
+);
+assert.deepStrictEqual(addon.hashShared(payload), [addon.keccak256(payload), addon.sha256(payload)]);
+assert.deepStrictEqual(addon.hashShared(payload.toString("hex")), addon.hashShared(payload.buffer.slice(payload.byteOffset, payload.byteOffset + 100)));
+assert.throws(() => addon.hashShared(1), /^Error: Expected Buffer, ArrayBuffer or hex string, got number$/);
+
+function address0x11() {
+  return "0x" + "11".repeat(20);
+}
+const abiTypes = "address,int8,(string,bytes2)[],uint256[2]";
+const abiValues = [address0x11(), "-128", [["hi", "0xabcd"]], ["1", "2"]];
+const encoded = addon.encodeParams(abiTypes, abiValues);
+assert.ok(encoded.startsWith("0x000000000000000000000000" + "11".repeat(20)));
+assert.deepStrictEqual(addon.decodeParams(abiTypes.split(","), encoded), abiValues);
+assert.deepStrictEqual(addon.decodeParams("address", Buffer.from(encoded.slice(2, 66), "hex")), [address0x11()]);
+assert.throws(() => addon.encodeParams("int8", [128]), /Invalid parameter at index 0: Value out of range for int8/);
+assert.throws(() => addon.encodeParams("uint7", [1]), /Unknown type 'uint7'/);
+assert.throws(() => addon.decodeParams("uint256", "0x00"), /Data too short/);
+
+const lines = [];
+assert.throws(() => addon.setLogLevel("loud"));
+addon.setLogLevel("debug");
+addon.setLogger((line) => {
+  lines.push(line);
+  addon.setLogger();
+});
+assert.throws(() => addon.roundTripU256("x"));
+addon.setLogLevel("warn");
+
+const order = [];
+for (let i = 0; i < 20; i++) {
+  addon.runSerialized("nonce", i, (err, value) => {
+    assert.ifError(err);
+    order.push(value);
+  });
+}
+// Each worker_thread is an instance of its own, with its own serialized keys
+const { Worker } = require("worker_threads");
+const workerResults = [];
+for (let w = 0; w < 2; w++) {
+  const worker = new Worker(
+    `
+    const assert = require("assert");
+    const { parentPort, workerData } = require("worker_threads");
+    const addon = require(workerData);
+    const order = [];
+    for (let i = 0; i < 10; i++) {
+      addon.runSerialized("nonce", i, (err, value) => {
+        if (err) throw err;
+        order.push(value);
+        if (order.length === 10) {
+          const provider = addon.sharedProvider();
+          assert(provider === addon.sharedProvider());
+          parentPort.postMessage({ id: addon.instanceId(), initRuns: addon.initRuns(), providerId: provider.id, order });
+        }
+      });
+    }
+    `,
+    { eval: true, workerData: process.argv[2] }
+  );
+  worker.once("message", (result) => workerResults.push(result));
+}
+(async () => {
+  // Emitted as uncaughtException until the addon says otherwise
+  const byDefault = await new Promise((resolve) => {
+    process.once("uncaughtException", (error) => resolve(error.message));
+    addon.runSerialized("hook", 0, () => {
+      throw new Error("unheard");
+    });
+  });
+  assert.strictEqual(byDefault, "unheard");
+  const hooked = await new Promise((resolve) => {
+    addon.onCallbackError((error, source) => resolve([error.message, source]));
+    addon.runSerialized("hook", 1, () => {
+      throw new Error("boom");
+    });
+  });
+  assert.deepStrictEqual(hooked, ["boom", "Task callback"]);
+  // The hook can't be read back or swapped, even by setting it again
+  const hookKey = Symbol.for("neon-utils.callbackErrorHook");
+  assert.strictEqual(globalThis[hookKey].name, "bound call");
+  assert.strictEqual(Reflect.set(globalThis, hookKey, () => {}), false);
+  assert.strictEqual(Reflect.deleteProperty(globalThis, hookKey), false);
+  assert.throws(() => addon.onCallbackError(() => {}), /^Error: A callback error hook is already set$/);
+  const uncaught = await new Promise((resolve) => {
+    addon.onCallbackError("uncaught");
+    process.once("uncaughtException", (error) => resolve(error.message));
+    addon.runSerialized("hook", 2, () => {
+      throw new Error("bang");
+    });
+  });
+  assert.strictEqual(uncaught, "bang");
+  addon.onCallbackError("swallow");
+  assert.throws(() => addon.onCallbackError("hook"), /^Error: Pass the hook itself to set one$/);
+  assert.throws(() => addon.onCallbackError("loud"), /Expected one of "swallow", "hook", "uncaught"/);
+})();
+const progress = [];
+let counted;
+addon.countTo(
+  50,
+  (i) => progress.push(i),
+  (err, n) => {
+    assert.ifError(err);
+    assert.deepStrictEqual(progress, Array.from({ length: n }, (_, i) => i + 1));
+    counted = n;
+  }
+);
+
+globalThis.chainStep = (n) => {
+  if (n < 0) throw new Error("Negative");
+  return n * 10;
+};
+const chained = [];
+addon.chain("4").then((n) => chained.push(n));
+addon.chain("x").catch((e) => chained.push(e.message));
+addon.chain("-1").catch((e) => chained.push(e.message));
+const joined = {};
+addon.joinSquares(5, -1, false, (err, squares) => (joined.all = err || squares));
+addon.joinSquares(0, -1, false, (err, squares) => (joined.none = err || squares));
+addon.joinSquares(5, 3, false, (err) => (joined.waited = err.message));
+addon.joinSquares(5, 1, true, (err) => (joined.failedFast = err.message));
+const retried = {};
+addon.flaky(3, { maxAttempts: 3, backoff: { initial: 1, max: 5 } }, (err, n) => (retried.ok = err || n));
+addon.flaky(5, { maxAttempts: 2, backoff: 1 }, (err) => (retried.failed = [err.message, err.attempts]));
+assert.throws(() => addon.flaky(1, { maxAttempts: 0 }, () => {}), /maxAttempts/);
+const prioritized = [];
+for (let i = 0; i < 8; i++) {
+  addon.prioritized(`low${i}`, { priority: "low" }, (err, label) => prioritized.push(err || label));
+}
+addon.prioritized("high", { priority: "high" }, (err, label) => prioritized.push(err || label));
+assert.throws(() => addon.prioritized("x", { priority: "urgent" }, () => {}), /Expected one of "high", "normal", "low"/);
+const timed = [];
+addon.sleepWithin(5, 1000, (err, ms) => timed.push(err || ms));
+addon.sleepWithin(200, 10, (err) => timed.push(`${err.name} ${err.code}`));
+addon.prioritized("late", { timeout: 5 }, (err) => timed.push(err.message));
+const batches = [];
+addon.emitBatched(2500, { window: 50, maxBatch: 1000 }, (batch) => batches.push(batch));
+assert.throws(() => addon.emitBatched(1, { maxBatch: 0 }, () => {}), /maxBatch/);
+let nonces;
+Promise.all([...Array(5)].map(() => addon.nextNonce())).then((n) => (nonces = n));
+const awaited = [];
+addon.doubleWhenResolved(Promise.resolve(21), (err, n) => awaited.push(err || n));
+addon.doubleWhenResolved(4, (err, n) => awaited.push(err || n));
+addon.doubleWhenResolved(Promise.reject(new Error("No signer")), (err) => awaited.push(err.message));
+addon.doubleWhenResolved(Promise.resolve("x"), (err) => awaited.push(err.message));
+const paged = [];
+addon.sumPages((page) => [page, 1], 3, (err, sum) => paged.push(err || sum));
+addon.sumPages(async (page) => [page * 10], 3, (err, sum) => paged.push(err || sum));
+addon.sumPages(
+  (page) => {
+    if (page === 1) throw new Error("No more pages");
+    return [page];
+  },
+  3,
+  (err) => paged.push(err.message)
+);
+addon.sumPages(() => ["x"], 1, (err) => paged.push(err.message));
+let slicedBetween = false;
+setImmediate(() => (slicedBetween = true));
+let chunked;
+addon.chunkedSquares(200000).then((squares) => (chunked = { length: squares.length, last: squares[199999], slicedBetween }));
+const streamed = [];
+(async () => {
+  const stream = addon.jsonStream("array");
+  // Held until end(), or Node wouldn't exit
+  assert.strictEqual(stream.ref(), 1);
+  assert.strictEqual(stream.ref(), 2);
+  assert.strictEqual(stream.unref(), 1);
+  const chunks = ['[{"id": 1, "tags": ["a"]}, {"id"', Buffer.from(': 2, "__proto__": 3}, 4'), "5]"];
+  for (const chunk of chunks) streamed.push(...(await stream.write(chunk)));
+  streamed.push(...(await stream.end()));
+  await assert.rejects(stream.write("[]"), /JSON stream has ended/);
+  assert.throws(() => stream.ref(), /JSON stream has ended/);
+  const broken = addon.jsonStream();
+  assert.deepStrictEqual(await broken.write("1 {"), [1]);
+  await assert.rejects(broken.write('"a" }'), /Invalid JSON at byte 7: expected `:`/);
+  await assert.rejects(broken.end(), /Invalid JSON at byte 7/);
+})();
+// Thrown from a task, errors keep the class, code and cause they have
+// when thrown on the JS thread
+const asyncErrors = [];
+addon.rateLimitedAsync((err) => asyncErrors.push(err));
+addon.rateLimitedAsync(true, (err) => asyncErrors.push(err));
+const settled = [];
+for (const how of ["resolve", "reject", "drop"]) {
+  addon.settleLater(how).then(
+    (value) => settled.push(value),
+    (err) => settled.push(err.message)
+  );
+}
+const flooded = {};
+for (const overflow of ["block", "dropOldest", "error"]) {
+  const received = [];
+  addon.tsfnFlood(20000, { maxQueue: 2, overflow }, (i) => received.push(i)).then(([rejected, dropped]) => {
+    flooded[overflow] = { received, rejected, dropped };
+  });
+}
+// Waiting for room on the JS thread would wait forever
+assert.strictEqual(
+  addon.tsfnFloodHere(3, { maxQueue: 2, overflow: "block" }, () => {}),
+  "Too many calls are waiting for JS, which can't be waited on from the JS thread"
+);
+const stepped = [];
+for (const s of ["2", "-1", "x"]) {
+  addon.doubleThenStep(s, (err, n) => stepped.push(err ? err.message : n));
+}
+
+process.on("exit", () => {
+  assert.deepStrictEqual(chained.sort(), [41, "Negative", "Not a number"]);
+  assert.deepStrictEqual(stepped.sort(), [40, "Negative", "Not a number"]);
+  assert.strictEqual(settled.length, 3);
+  const syncErrors = [];
+  for (const args of [[], [true]]) {
+    try {
+      addon.rateLimited(...args);
+    } catch (e) {
+      syncErrors.push(e);
+    }
+  }
+  const shape = (e) => ({
+    class: e.constructor.name,
+    message: e.message,
+    code: e.code,
+    cause: e.cause && { class: e.cause.constructor.name, message: e.cause.message, code: e.cause.code },
+  });
+  const byMessage = (a, b) => a.message.localeCompare(b.message);
+  assert.deepStrictEqual(asyncErrors.map(shape).sort(byMessage), syncErrors.map(shape).sort(byMessage));
+  assert.ok(asyncErrors.every((e) => e instanceof RateLimitError));
+  assert.ok(settled.some((s) => Array.isArray(s) && s[0] === "resolve"));
+  assert.ok(settled.some((s) => /^Rejected from ThreadId/.test(s)));
+  assert.ok(settled.includes("Dropped without settling, eg: as its thread panicked"));
+  assert.deepStrictEqual(joined, {
+    all: [0, 1, 4, 9, 16],
+    none: [],
+    waited: "Failed 3",
+    failedFast: "Failed 1",
+  });
+  assert.deepStrictEqual(retried, { ok: 3, failed: ["Failed after 2 attempts: Attempt 2 failed", 2] });
+  // The pool is busy with the first few low tasks, but high goes before the rest
+  assert.deepStrictEqual(batches.flat(), [...Array(2500).keys()]);
+  // Every call is delivered in order when blocking, else some are lost
+  const ascending = (xs) => xs.every((x, i) => i === 0 || x > xs[i - 1]);
+  assert.deepStrictEqual(flooded.block, { received: [...Array(20000).keys()], rejected: 0, dropped: 0 });
+  const { dropOldest, error } = flooded;
+  assert.ok(ascending(dropOldest.received) && ascending(error.received));
+  assert.strictEqual(dropOldest.received.length + dropOldest.dropped, 20000);
+  assert.strictEqual(dropOldest.received[dropOldest.received.length - 1], 19999);
+  assert.strictEqual(dropOldest.rejected, 0);
+  assert.strictEqual(error.received.length + error.rejected, 20000);
+  assert.strictEqual(error.dropped, 0);
+  assert.ok(batches.length >= 3 && batches.length < 2500);
+  assert.ok(batches.every((batch) => batch.length <= 1000));
+  assert.strictEqual(prioritized.length, 9);
+  assert.ok(prioritized.indexOf("high") < prioritized.indexOf("low7"));
+  assert.deepStrictEqual(timed.sort(), [5, "Task timed out after 5ms", "TimeoutError ETIMEDOUT"].sort());
+  assert.deepStrictEqual(awaited.sort(), [
+    42,
+    8,
+    "Failed to decode Promise result: Expected number, got string",
+    "Promise rejected: No signer",
+  ].sort());
+  assert.deepStrictEqual(streamed, [{ id: 1, tags: ["a"] }, JSON.parse('{"id": 2, "__proto__": 3}'), 45]);
+  assert.strictEqual(counted, 50);
+  assert.deepStrictEqual(nonces.sort(), [0, 1, 2, 3, 4]);
+  assert.deepStrictEqual(paged.sort(), [
+    30,
+    6,
+    "Callback threw: No more pages",
+    "Failed to decode Promise result: Invalid element at index 0: Expected number, got string",
+  ].sort());
+  assert.deepStrictEqual(chunked, { length: 200000, last: 199999 ** 2, slicedBetween: true });
+  assert.strictEqual(addon.collectedCount(), 1);
+  assert.deepStrictEqual(order, [...Array(20).keys()]);
+  assert.strictEqual(workerResults.length, 2);
+  const instanceIds = new Set([addon.instanceId(), ...workerResults.map((r) => r.id)]);
+  assert.strictEqual(instanceIds.size, 3);
+  const providerIds = new Set([addon.sharedProvider().id, ...workerResults.map((r) => r.providerId)]);
+  assert.strictEqual(providerIds.size, 3);
+  for (const result of workerResults) {
+    assert.deepStrictEqual(result.order, [...Array(10).keys()]);
+    assert.strictEqual(result.initRuns, 1);
+  }
+  // Once, when the last of the workers and this thread exited. The addon
+  // listened for 'exit' as it loaded, so before this listener.
+  assert.strictEqual(addon.finalized(), 1);
+  assert.match(lines[0], /Throwing error=Failed to parse U256/);
+  console.log("ok");
+});
@@ -0,0 +1,36 @@
+//! Runs test/roundtrip.js against the addon built from this crate.
+//!
+//! This needs Node on the PATH and an addon built against real Node
+//! headers, so it is ignored by default. To run it:
+//!   cargo build -p neon-utils-testing && cargo test -p neon-utils-testing -- --ignored
+use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn target_dir() -> PathBuf {
+    match std::env::var_os("CARGO_TARGET_DIR") {
+        Some(dir) => PathBuf::from(dir).join("debug"),
+        None => Path::new(env!("CARGO_MANIFEST_DIR")).join("../target/debug"),
+    }
+}
+
+#[test]
+#[ignore]
+fn node_round_trip() {
+    let target = target_dir();
+    let lib = target.join(format!("{}neon_utils_testing{}", DLL_PREFIX, DLL_SUFFIX));
+    // Node only loads addons with the .node extension
+    let addon = target.join("neon_utils_testing.node");
+    fs::copy(&lib, &addon).expect("Build the addon first with cargo build -p neon-utils-testing");
+
+    let script = Path::new(env!("CARGO_MANIFEST_DIR")).join("test/roundtrip.js");
+    let status = Command::new("node")
+        // For checking that finalizers run
+        .arg("--expose-gc")
+        .arg(script)
+        .arg(&addon)
+        .status()
+        .expect("Failed to run node");
+    assert!(status.success());
+}